@@ -0,0 +1,30 @@
+#!/usr/bin/env bash
+# Builds Hetu once per hash feature and benchmarks each build on the given datasets.
+#
+# Usage: scripts/bench_hashers.sh [-n RUNS] DATA_DIR [DATA_DIR...]
+set -euo pipefail
+
+runs=5
+if [[ "${1:-}" == "-n" ]]; then
+    runs="$2"
+    shift 2
+fi
+if [[ $# -eq 0 ]]; then
+    echo "Usage: $0 [-n RUNS] DATA_DIR [DATA_DIR...]" >&2
+    exit 2
+fi
+
+cd "$(dirname "$0")/.."
+features=(std use_ahash use_rustc_hash use_fxhash use_seahash use_wyhash)
+
+for feature in "${features[@]}"; do
+    feature_args=()
+    if [[ "$feature" != "std" ]]; then
+        feature_args=(--features "$feature")
+    fi
+    cargo build --release -q "${feature_args[@]}"
+    for data_dir in "$@"; do
+        ./target/release/Hetu "$data_dir" --bench "$runs" | sed -n '/^Benchmark:/,$p'
+        echo
+    done
+done
@@ -0,0 +1,79 @@
+//! Generates a synthetic fat-tree or Clos dataset with ECMP FIBs.
+//!
+//! Usage: gen_fabric OUT_DIR (--k K | --clos PODS,TORS,AGGS,SPINES)
+//!        [--subnets-per-tor N]
+
+use hetu::util::fabric::ClosSpec;
+use std::process;
+use std::time::Instant;
+
+struct Args {
+    out_dir: String,
+    spec: ClosSpec,
+}
+
+fn parse_args() -> Result<Args, String> {
+    let mut out_dir = String::new();
+    let mut spec: Option<ClosSpec> = None;
+    let mut subnets_per_tor = 1;
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--k" => {
+                let value = args.next().ok_or("--k requires a value")?;
+                let k = value
+                    .parse()
+                    .map_err(|_| format!("invalid --k value: {}", value))?;
+                spec = Some(ClosSpec::fat_tree(k)?);
+            }
+            "--clos" => {
+                let value = args.next().ok_or("--clos requires a value")?;
+                spec = Some(ClosSpec::parse(&value)?);
+            }
+            "--subnets-per-tor" => {
+                let value = args.next().ok_or("--subnets-per-tor requires a value")?;
+                subnets_per_tor = value
+                    .parse()
+                    .map_err(|_| format!("invalid --subnets-per-tor value: {}", value))?;
+            }
+            "-h" | "--help" => {
+                println!(
+                    "Usage: gen_fabric OUT_DIR (--k K | --clos PODS,TORS,AGGS,SPINES) \
+                     [--subnets-per-tor N]"
+                );
+                process::exit(0);
+            }
+            flag if flag.starts_with("--") => return Err(format!("unknown option: {}", flag)),
+            dir => out_dir = dir.to_string(),
+        }
+    }
+    if out_dir.is_empty() {
+        return Err("missing OUT_DIR".to_string());
+    }
+    let spec = spec
+        .ok_or("one of --k or --clos is required")?
+        .with_subnets_per_tor(subnets_per_tor)?;
+    Ok(Args { out_dir, spec })
+}
+
+fn main() {
+    let args = parse_args().unwrap_or_else(|err| {
+        eprintln!("{}", err);
+        process::exit(2);
+    });
+    let start = Instant::now();
+    let fabric = args.spec.build();
+    if let Err(err) = fabric.write(&args.out_dir) {
+        eprintln!("failed to write {}: {}", args.out_dir, err);
+        process::exit(1);
+    }
+    println!(
+        "Generated {} devices ({} edge), {} links and {} subnets in {} ({:?})",
+        fabric.routes.len(),
+        fabric.edge_devices.len(),
+        fabric.links.len(),
+        fabric.subnets.len(),
+        args.out_dir,
+        start.elapsed()
+    );
+}
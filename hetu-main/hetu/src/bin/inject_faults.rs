@@ -0,0 +1,116 @@
+//! Copies a dataset with injected faults and writes the violations they are
+//! expected to cause to `OUT_DIR/faults.json`.
+//!
+//! Usage: inject_faults IN_DIR OUT_DIR [--faults KIND[:COUNT],...] [--seed N]
+
+use hetu::util::fabric::Fabric;
+use hetu::util::fault::{FaultKind, FaultManifest};
+use std::fs;
+use std::path::Path;
+use std::process;
+
+struct Args {
+    in_dir: String,
+    out_dir: String,
+    kinds: Vec<FaultKind>,
+    seed: u64,
+}
+
+/// Parses `KIND[:COUNT],...` into one entry per fault.
+fn parse_faults(value: &str) -> Result<Vec<FaultKind>, String> {
+    let mut kinds = Vec::new();
+    for part in value.split(',') {
+        let (kind, count) = match part.split_once(':') {
+            Some((kind, count)) => (
+                kind,
+                count
+                    .parse()
+                    .map_err(|_| format!("invalid fault count: {}", part))?,
+            ),
+            None => (part, 1),
+        };
+        let kind: FaultKind = kind.parse()?;
+        kinds.extend(std::iter::repeat_n(kind, count));
+    }
+    Ok(kinds)
+}
+
+fn parse_args() -> Result<Args, String> {
+    let mut dirs: Vec<String> = Vec::new();
+    let mut kinds = FaultKind::ALL.to_vec();
+    let mut seed = 1;
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--faults" => {
+                let value = args.next().ok_or("--faults requires a value")?;
+                kinds = parse_faults(&value)?;
+            }
+            "--seed" => {
+                let value = args.next().ok_or("--seed requires a value")?;
+                seed = value
+                    .parse()
+                    .map_err(|_| format!("invalid --seed value: {}", value))?;
+            }
+            "-h" | "--help" => {
+                println!(
+                    "Usage: inject_faults IN_DIR OUT_DIR [--faults KIND[:COUNT],...] [--seed N]\n\
+                     Kinds: delete-route, wrong-port, loop, hijack, remove-link (default: one of each)"
+                );
+                process::exit(0);
+            }
+            flag if flag.starts_with("--") => return Err(format!("unknown option: {}", flag)),
+            dir => dirs.push(dir.to_string()),
+        }
+    }
+    let [in_dir, out_dir] =
+        <[String; 2]>::try_from(dirs).map_err(|_| "expected IN_DIR and OUT_DIR".to_string())?;
+    Ok(Args {
+        in_dir,
+        out_dir,
+        kinds,
+        seed,
+    })
+}
+
+fn main() {
+    let args = parse_args().unwrap_or_else(|err| {
+        eprintln!("{}", err);
+        process::exit(2);
+    });
+    let mut fabric = Fabric::load(&args.in_dir).unwrap_or_else(|err| {
+        eprintln!("failed to read {}: {}", args.in_dir, err);
+        process::exit(1);
+    });
+    let manifest = FaultManifest::inject(&mut fabric, &args.kinds, &args.in_dir, args.seed)
+        .unwrap_or_else(|err| {
+            eprintln!("{}", err);
+            process::exit(1);
+        });
+    let manifest_path = Path::new(&args.out_dir).join("faults.json");
+    let written = fabric.write(&args.out_dir).and_then(|()| {
+        let json = serde_json::to_vec_pretty(&manifest)?;
+        fs::write(&manifest_path, json)
+    });
+    if let Err(err) = written {
+        eprintln!("failed to write {}: {}", args.out_dir, err);
+        process::exit(1);
+    }
+    for report in &manifest.faults {
+        println!(
+            "Fault {} ({}): {}; {} unreachable, {} looping, {} blackholed pairs",
+            report.id,
+            report.fault.kind,
+            report.fault.description,
+            report.expected.reachability.len(),
+            report.expected.loop_freedom.len(),
+            report.expected.blackhole_freedom.len()
+        );
+    }
+    println!(
+        "Expected unreachable pairs: {} of {}; manifest in {}",
+        manifest.expected_unreachable_pairs,
+        manifest.total_pairs,
+        manifest_path.display()
+    );
+}
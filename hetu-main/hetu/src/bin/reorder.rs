@@ -0,0 +1,132 @@
+//! Encodes one dataset under every BDD variable order and compares the
+//! resulting predicate sizes.
+//!
+//! Usage: reorder DATA_DIR [--ip-bits N] [--orders ORDER,...] [--verify]
+
+use hetu::session::VerificationSession;
+use hetu::util::packet_set::PACKET_SET_NAME;
+use hetu::util::var_order::{PredicateSizes, VariableOrder};
+use std::process;
+use std::time::{Duration, Instant};
+
+struct Args {
+    data_dir: String,
+    ip_bits: usize,
+    orders: Vec<VariableOrder>,
+    verify: bool,
+}
+
+struct Trial {
+    sizes: PredicateSizes,
+    encode_time: Duration,
+    /// (reachable, unreachable) pairs, with `--verify`.
+    reachability: Option<(usize, usize)>,
+}
+
+fn parse_args() -> Result<Args, String> {
+    let mut parsed = Args {
+        data_dir: String::new(),
+        ip_bits: 144,
+        orders: VariableOrder::ALL.to_vec(),
+        verify: false,
+    };
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--ip-bits" => {
+                let value = args.next().ok_or("--ip-bits requires a value")?;
+                parsed.ip_bits = value
+                    .parse()
+                    .map_err(|_| format!("invalid --ip-bits value: {}", value))?;
+            }
+            "--orders" => {
+                let value = args.next().ok_or("--orders requires a value")?;
+                parsed.orders = value.split(',').map(str::parse).collect::<Result<_, _>>()?;
+            }
+            "--verify" => parsed.verify = true,
+            "-h" | "--help" => {
+                println!("Usage: reorder DATA_DIR [--ip-bits N] [--orders ORDER,...] [--verify]");
+                process::exit(0);
+            }
+            flag if flag.starts_with("--") => return Err(format!("unknown option: {}", flag)),
+            data_dir => parsed.data_dir = data_dir.to_string(),
+        }
+    }
+    if parsed.data_dir.is_empty() {
+        return Err("missing DATA_DIR".to_string());
+    }
+    Ok(parsed)
+}
+
+fn main() {
+    let args = parse_args().unwrap_or_else(|err| {
+        eprintln!("{}", err);
+        process::exit(2);
+    });
+    if PACKET_SET_NAME != "bdd" {
+        eprintln!(
+            "this build uses {} packet sets; variable orders only affect BDDs",
+            PACKET_SET_NAME
+        );
+        process::exit(2);
+    }
+
+    let mut trials: Vec<Trial> = Vec::new();
+    for &order in &args.orders {
+        let mut session = VerificationSession::builder()
+            .data_dir(&args.data_dir)
+            .ip_bits(args.ip_bits)
+            .variable_order(order)
+            .build()
+            .unwrap_or_else(|err| {
+                eprintln!("{}", err);
+                process::exit(1);
+            });
+        session.load().expect("load failed");
+        let start = Instant::now();
+        session.encode().expect("encode failed");
+        let encode_time = start.elapsed();
+        let sizes = session.predicate_sizes().expect("encode failed");
+        let reachability = args.verify.then(|| {
+            let report = session.verify().expect("verification failed");
+            (report.reachable, report.unreachable)
+        });
+        trials.push(Trial {
+            sizes,
+            encode_time,
+            reachability,
+        });
+    }
+
+    println!(
+        "{:<12} {:>12} {:>10} {:>12} {:>10} {:>12} {:>12}",
+        "order", "encode (s)", "distinct", "port nodes", "max", "space nodes", "reachable"
+    );
+    for trial in &trials {
+        let sizes = &trial.sizes;
+        let reachable = match trial.reachability {
+            Some((reachable, unreachable)) => format!("{}/{}", reachable, reachable + unreachable),
+            None => "-".to_string(),
+        };
+        println!(
+            "{:<12} {:>12.6} {:>10} {:>12} {:>10} {:>12} {:>12}",
+            sizes.order.to_string(),
+            trial.encode_time.as_secs_f64(),
+            sizes.distinct_predicates,
+            sizes.port_nodes,
+            sizes.max_port_nodes,
+            sizes.packet_space_nodes,
+            reachable
+        );
+    }
+    if let Some(best) = trials.iter().min_by_key(|trial| trial.sizes.port_nodes) {
+        println!("Smallest port predicates: {} order", best.sizes.order);
+    }
+    let mut outcomes = trials.iter().filter_map(|trial| trial.reachability);
+    if let Some(first) = outcomes.next() {
+        if outcomes.any(|outcome| outcome != first) {
+            println!("Reachability differs between orders");
+            process::exit(1);
+        }
+    }
+}
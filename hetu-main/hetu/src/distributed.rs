@@ -0,0 +1,348 @@
+//! Verification split across processes that share a work directory.
+//!
+//! The coordinator writes the encoded network as a snapshot and a
+//! [`WorkPlan`] naming the neighborhoods every worker verifies. Each worker
+//! opens the snapshot, verifies its neighborhoods and writes a
+//! [`WorkResult`]; the coordinator merges them once all workers are done.
+//! Files are renamed into place, so the directory can also live on a shared
+//! file system with workers started on other hosts.
+
+use crate::session::{SessionError, VerificationSession, VerificationSessionBuilder};
+use crate::util::work_plan::{WorkPlan, WorkResult};
+use crate::verifier::report::ReachabilityReport;
+use std::fmt;
+use std::fs::{self, File};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DistributedError {
+    Session(SessionError),
+    Io(PathBuf, String),
+    /// A worker exited unsuccessfully; its output is in the log file.
+    WorkerFailed {
+        worker: usize,
+        log: PathBuf,
+    },
+    UnknownWorker(usize),
+    /// The work plan does not match the snapshot next to it.
+    InvalidPlan(String),
+    /// Merged results do not cover every neighborhood exactly once.
+    Incomplete(String),
+}
+
+impl fmt::Display for DistributedError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DistributedError::Session(err) => write!(f, "{}", err),
+            DistributedError::Io(path, message) => write!(f, "{}: {}", path.display(), message),
+            DistributedError::WorkerFailed { worker, log } => {
+                write!(f, "worker {} failed, see {}", worker, log.display())
+            }
+            DistributedError::UnknownWorker(worker) => {
+                write!(f, "the work plan has no worker {}", worker)
+            }
+            DistributedError::InvalidPlan(message) => write!(f, "invalid work plan: {}", message),
+            DistributedError::Incomplete(message) => {
+                write!(f, "incomplete results: {}", message)
+            }
+        }
+    }
+}
+
+impl std::error::Error for DistributedError {}
+
+impl From<SessionError> for DistributedError {
+    fn from(err: SessionError) -> Self {
+        DistributedError::Session(err)
+    }
+}
+
+fn io_error(path: &Path) -> impl FnOnce(io::Error) -> DistributedError + '_ {
+    move |err| DistributedError::Io(path.to_path_buf(), err.to_string())
+}
+
+pub fn snapshot_path(dir: &Path) -> PathBuf {
+    dir.join("network.htsn")
+}
+
+pub fn plan_path(dir: &Path) -> PathBuf {
+    dir.join("plan.htwp")
+}
+
+pub fn result_path(dir: &Path, worker: usize) -> PathBuf {
+    dir.join(format!("worker-{}.htwr", worker))
+}
+
+pub fn log_path(dir: &Path, worker: usize) -> PathBuf {
+    dir.join(format!("worker-{}.log", worker))
+}
+
+pub struct Coordinator {
+    dir: PathBuf,
+    workers: usize,
+}
+
+impl Coordinator {
+    pub fn new(dir: impl AsRef<Path>, workers: usize) -> Self {
+        Coordinator {
+            dir: dir.as_ref().to_path_buf(),
+            workers: workers.max(1),
+        }
+    }
+
+    /// Writes the snapshot and the work plan of an encoded session and
+    /// removes results left over from an earlier run.
+    pub fn prepare(&self, session: &VerificationSession) -> Result<WorkPlan, DistributedError> {
+        fs::create_dir_all(&self.dir).map_err(io_error(&self.dir))?;
+        for worker in 0..self.workers {
+            let path = result_path(&self.dir, worker);
+            match fs::remove_file(&path) {
+                Err(err) if err.kind() != io::ErrorKind::NotFound => {
+                    return Err(io_error(&path)(err));
+                }
+                _ => {}
+            }
+        }
+        session.save_snapshot(snapshot_path(&self.dir))?;
+        let plan = WorkPlan::new(&session.plan_verification()?, self.workers);
+        let path = plan_path(&self.dir);
+        plan.save(&path).map_err(io_error(&path))?;
+        Ok(plan)
+    }
+
+    /// Starts `program` once per worker as
+    /// `program --worker DIR --worker-id N ARGS...` and waits for all of
+    /// them. Each worker's output goes to its log file in the directory.
+    pub fn run_local_workers(
+        &self,
+        program: &Path,
+        args: &[String],
+    ) -> Result<(), DistributedError> {
+        let mut children: Vec<(usize, Child)> = Vec::with_capacity(self.workers);
+        for worker in 0..self.workers {
+            let log = log_path(&self.dir, worker);
+            let stdout = File::create(&log).map_err(io_error(&log))?;
+            let stderr = stdout.try_clone().map_err(io_error(&log))?;
+            let child = Command::new(program)
+                .arg("--worker")
+                .arg(&self.dir)
+                .arg("--worker-id")
+                .arg(worker.to_string())
+                .args(args)
+                .stdout(stdout)
+                .stderr(stderr)
+                .spawn()
+                .map_err(io_error(program))?;
+            children.push((worker, child));
+        }
+        let mut failed = None;
+        for (worker, mut child) in children {
+            let status = child.wait().map_err(io_error(program))?;
+            if !status.success() && failed.is_none() {
+                failed = Some(worker);
+            }
+        }
+        match failed {
+            Some(worker) => Err(DistributedError::WorkerFailed {
+                worker,
+                log: log_path(&self.dir, worker),
+            }),
+            None => Ok(()),
+        }
+    }
+
+    /// Sums the workers' results. Every neighborhood of `plan` has to be
+    /// reported exactly once, by the worker it was assigned to.
+    pub fn merge(&self, plan: &WorkPlan) -> Result<ReachabilityReport, DistributedError> {
+        let mut assigned_to = vec![None; plan.neighborhoods.len()];
+        for (worker, ids) in plan.assignments.iter().enumerate() {
+            for &id in ids {
+                assigned_to[id as usize] = Some(worker);
+            }
+        }
+        let mut reported = vec![false; plan.neighborhoods.len()];
+        let mut report = ReachabilityReport::default();
+        for worker in 0..plan.assignments.len() {
+            let path = result_path(&self.dir, worker);
+            let result = WorkResult::load(&path).map_err(io_error(&path))?;
+            if result.worker != worker {
+                return Err(DistributedError::Incomplete(format!(
+                    "{} holds the results of worker {}",
+                    path.display(),
+                    result.worker
+                )));
+            }
+            for (id, neighborhood_report) in result.reports {
+                let id_index = id as usize;
+                if assigned_to.get(id_index) != Some(&Some(worker)) || reported[id_index] {
+                    return Err(DistributedError::Incomplete(format!(
+                        "worker {} reported neighborhood {} unexpectedly",
+                        worker, id
+                    )));
+                }
+                reported[id_index] = true;
+                report += neighborhood_report;
+            }
+        }
+        if let Some(id) = reported.iter().position(|&done| !done) {
+            return Err(DistributedError::Incomplete(format!(
+                "neighborhood {} was not verified",
+                id
+            )));
+        }
+        Ok(report)
+    }
+}
+
+/// Verifies the neighborhoods assigned to `worker` in `dir` and writes its
+/// result file. `builder` carries the verification options; the network
+/// comes from the coordinator's snapshot.
+pub fn run_worker(
+    builder: VerificationSessionBuilder,
+    dir: impl AsRef<Path>,
+    worker: usize,
+) -> Result<WorkResult, DistributedError> {
+    let dir = dir.as_ref();
+    let path = plan_path(dir);
+    let plan = WorkPlan::load(&path).map_err(io_error(&path))?;
+    let ids = plan
+        .assignments
+        .get(worker)
+        .ok_or(DistributedError::UnknownWorker(worker))?;
+    let session = builder.open_snapshot(snapshot_path(dir))?;
+    let neighborhoods = ids
+        .iter()
+        .map(|&id| {
+            let planned = &plan.neighborhoods[id as usize];
+            session
+                .simulator()
+                .neighborhood_from_names(
+                    &planned.marked_nodes,
+                    &planned.normal_nodes,
+                    planned.weight,
+                )
+                .map_err(|message| {
+                    DistributedError::InvalidPlan(format!("neighborhood {}: {}", id, message))
+                })
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    let reports = session.verify_neighborhoods(neighborhoods)?;
+    let result = WorkResult {
+        worker,
+        reports: ids.iter().copied().zip(reports).collect(),
+    };
+    let path = result_path(dir, worker);
+    result.save(&path).map_err(io_error(&path))?;
+    Ok(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::util::work_plan::PlannedNeighborhood;
+
+    /// A coordinator over a fresh directory holding a plan of three
+    /// neighborhoods dealt to two workers.
+    fn coordinator(test: &str) -> (Coordinator, WorkPlan) {
+        let dir = std::env::temp_dir().join(format!(
+            "hetu-distributed-unit-{}-{}",
+            test,
+            std::process::id()
+        ));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        let neighborhoods = (0..3)
+            .map(|i| PlannedNeighborhood {
+                marked_nodes: vec![format!("leaf{}", i)],
+                normal_nodes: Vec::new(),
+                weight: 1,
+            })
+            .collect();
+        let plan = WorkPlan {
+            neighborhoods,
+            assignments: vec![vec![0, 2], vec![1]],
+        };
+        (Coordinator::new(&dir, 2), plan)
+    }
+
+    fn write_result(coordinator: &Coordinator, worker: usize, ids: &[u32]) {
+        let reports = ids
+            .iter()
+            .map(|&id| (id, ReachabilityReport::new(id as usize + 1, 1, 2)))
+            .collect();
+        WorkResult { worker, reports }
+            .save(result_path(&coordinator.dir, worker))
+            .unwrap();
+    }
+
+    fn merge(
+        coordinator: &Coordinator,
+        plan: &WorkPlan,
+    ) -> Result<ReachabilityReport, DistributedError> {
+        let merged = coordinator.merge(plan);
+        fs::remove_dir_all(&coordinator.dir).unwrap();
+        merged
+    }
+
+    #[test]
+    fn merge_sums_every_assigned_report() {
+        let (coordinator, plan) = coordinator("sum");
+        write_result(&coordinator, 0, &[2, 0]);
+        write_result(&coordinator, 1, &[1]);
+        assert_eq!(
+            merge(&coordinator, &plan),
+            Ok(ReachabilityReport::new(6, 3, 6))
+        );
+    }
+
+    #[test]
+    fn merge_rejects_a_missing_result_file() {
+        let (coordinator, plan) = coordinator("missing");
+        write_result(&coordinator, 0, &[0, 2]);
+        let merged = merge(&coordinator, &plan);
+        assert!(
+            matches!(merged, Err(DistributedError::Io(..))),
+            "{:?}",
+            merged
+        );
+    }
+
+    #[test]
+    fn merge_rejects_unassigned_repeated_and_missing_neighborhoods() {
+        for (test, worker0, worker1) in [
+            ("unassigned", &[0, 1, 2][..], &[1][..]),
+            ("repeated", &[0, 2, 2][..], &[1][..]),
+            ("unverified", &[0][..], &[1][..]),
+        ] {
+            let (coordinator, plan) = coordinator(test);
+            write_result(&coordinator, 0, worker0);
+            write_result(&coordinator, 1, worker1);
+            let merged = merge(&coordinator, &plan);
+            assert!(
+                matches!(merged, Err(DistributedError::Incomplete(_))),
+                "{}: {:?}",
+                test,
+                merged
+            );
+        }
+    }
+
+    #[test]
+    fn merge_rejects_a_result_written_by_another_worker() {
+        let (coordinator, plan) = coordinator("swapped");
+        write_result(&coordinator, 0, &[0, 2]);
+        fs::copy(
+            result_path(&coordinator.dir, 0),
+            result_path(&coordinator.dir, 1),
+        )
+        .unwrap();
+        let merged = merge(&coordinator, &plan);
+        assert!(
+            matches!(merged, Err(DistributedError::Incomplete(_))),
+            "{:?}",
+            merged
+        );
+    }
+}
@@ -0,0 +1,19 @@
+//! HeTu data-plane verifier.
+//!
+//! The verifier can be driven step by step through [`Simulator`], or through a
+//! [`VerificationSession`] which owns a simulator and enforces the
+//! load → encode → verify/query order.
+
+pub mod distributed;
+pub mod session;
+pub mod simulator;
+pub mod util;
+pub mod verifier;
+
+pub use distributed::{Coordinator, DistributedError};
+pub use session::{SessionError, VerificationSession, VerificationSessionBuilder};
+pub use simulator::{Simulator, SubNet};
+pub use util::network::Network;
+pub use util::progress::{CancellationToken, ProgressEvent};
+pub use verifier::device::Device;
+pub use verifier::report::ReachabilityReport;
@@ -1,41 +1,506 @@
-mod simulator;
-mod util;
-mod verifier;
-use crate::simulator::Simulator;
+use hetu::distributed;
+use hetu::util::hash_utils::HASHER_NAME;
+use hetu::util::metrics::{Phase, StatsFormat};
+use hetu::util::npbdd::CacheBudget;
+use hetu::util::packet_set::PACKET_SET_NAME;
+use hetu::util::var_order::VariableOrder;
+use hetu::verifier::atomic::VerificationEngine;
+use hetu::verifier::lec_trie::LecBuilder;
+use hetu::verifier::npnet::DEFAULT_MAX_ITERATIONS;
+use hetu::verifier::partition::{parse_partitioner, Partitioner};
+use hetu::verifier::scheduler::{CostModel, Scheduler};
+use hetu::{Coordinator, ReachabilityReport, VerificationSession, VerificationSessionBuilder};
 use mimalloc::MiMalloc;
-use std::sync::atomic::{AtomicUsize, Ordering};
-use std::time::Instant;
+use std::process;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
 #[global_allocator]
 static GLOBAL: MiMalloc = MiMalloc;
-#[macro_use]
-extern crate lazy_static;
-lazy_static! {
-    pub static ref EXIST_COUNT: AtomicUsize = AtomicUsize::new(0);
-    pub static ref NONEXIST_COUNT: AtomicUsize = AtomicUsize::new(0);
-    pub static ref TRAVERSAL_COUNT: AtomicUsize = AtomicUsize::new(0);
+
+const DEFAULT_DATA_DIR: &str = "../data/fattree/fattree10";
+
+struct CliArgs {
+    data_dir: String,
+    ip_bits: usize,
+    cache_budget: CacheBudget,
+    stats_file: Option<String>,
+    stats_format: StatsFormat,
+    encoding_cache: Option<String>,
+    save_snapshot: Option<String>,
+    load_snapshot: Option<String>,
+    bench_runs: Option<usize>,
+    partitioner: Option<Arc<dyn Partitioner>>,
+    neighborhood_report: bool,
+    scheduler: Scheduler,
+    parallel_frontier: Option<usize>,
+    outer_summaries: bool,
+    lec_builder: LecBuilder,
+    engine: VerificationEngine,
+    symmetry: bool,
+    variable_order: VariableOrder,
+    predicate_sizes: bool,
+    coordinator_dir: Option<String>,
+    workers: usize,
+    worker_dir: Option<String>,
+    worker_id: usize,
+    progress: bool,
+    time_budget: Option<Duration>,
+    max_iterations: usize,
+    iteration_stats: bool,
+    cross_check: Option<usize>,
+    /// Options forwarded to spawned workers.
+    worker_args: Vec<String>,
 }
-fn main() {
-    println!("Starting the application");
-    let filedir = "../data/fattree/fattree10";
-    let mut simulator: Simulator = Simulator::new(144);
-    let start: Instant = Instant::now();
-    simulator.set_file_dir(filedir);
-    simulator.build();
-    let duration = start.elapsed();
-    println!("Build time: {:?}", duration);
-    simulator.verify_reachability_with_npnet();
-    let duration: std::time::Duration = start.elapsed();
-    println!("Total execution time: {:?}", duration);
+
+fn parse_args() -> Result<CliArgs, String> {
+    let mut cli_args = CliArgs {
+        data_dir: DEFAULT_DATA_DIR.to_string(),
+        ip_bits: 144,
+        cache_budget: CacheBudget::default(),
+        stats_file: None,
+        stats_format: StatsFormat::Json,
+        encoding_cache: None,
+        save_snapshot: None,
+        load_snapshot: None,
+        bench_runs: None,
+        partitioner: None,
+        neighborhood_report: false,
+        scheduler: Scheduler::default(),
+        parallel_frontier: None,
+        outer_summaries: false,
+        lec_builder: LecBuilder::default(),
+        engine: VerificationEngine::default(),
+        symmetry: false,
+        variable_order: VariableOrder::default(),
+        predicate_sizes: false,
+        coordinator_dir: None,
+        workers: 2,
+        worker_dir: None,
+        worker_id: 0,
+        progress: false,
+        time_budget: None,
+        max_iterations: DEFAULT_MAX_ITERATIONS,
+        iteration_stats: false,
+        cross_check: None,
+        worker_args: Vec::new(),
+    };
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--ip-bits" => {
+                let value = args.next().ok_or("--ip-bits requires a value")?;
+                cli_args.ip_bits = value
+                    .parse()
+                    .map_err(|_| format!("invalid --ip-bits value: {}", value))?;
+            }
+            "--cache-budget-mb" => {
+                let value = args.next().ok_or("--cache-budget-mb requires a value")?;
+                let megabytes: usize = value
+                    .parse()
+                    .map_err(|_| format!("invalid --cache-budget-mb value: {}", value))?;
+                cli_args.cache_budget = CacheBudget::from_total(megabytes * 1024 * 1024);
+                cli_args.worker_args.extend([arg, value]);
+            }
+            "--stats-file" => {
+                let value = args.next().ok_or("--stats-file requires a value")?;
+                cli_args.stats_file = Some(value);
+            }
+            "--stats-format" => {
+                let value = args.next().ok_or("--stats-format requires a value")?;
+                cli_args.stats_format = StatsFormat::parse(&value)
+                    .ok_or_else(|| format!("invalid --stats-format value: {}", value))?;
+            }
+            "--encoding-cache" => {
+                let value = args.next().ok_or("--encoding-cache requires a value")?;
+                cli_args.encoding_cache = Some(value);
+            }
+            "--save-snapshot" => {
+                let value = args.next().ok_or("--save-snapshot requires a value")?;
+                cli_args.save_snapshot = Some(value);
+            }
+            "--load-snapshot" => {
+                let value = args.next().ok_or("--load-snapshot requires a value")?;
+                cli_args.load_snapshot = Some(value);
+            }
+            "--bench" => {
+                let value = args.next().ok_or("--bench requires a value")?;
+                let runs: usize = value
+                    .parse()
+                    .map_err(|_| format!("invalid --bench value: {}", value))?;
+                if runs == 0 {
+                    return Err("--bench needs at least one run".to_string());
+                }
+                cli_args.bench_runs = Some(runs);
+            }
+            "--partition" => {
+                let value = args.next().ok_or("--partition requires a value")?;
+                cli_args.partitioner = Some(parse_partitioner(&value)?);
+            }
+            "--neighborhood-report" => cli_args.neighborhood_report = true,
+            "--max-neighborhood-cost" => {
+                let value = args
+                    .next()
+                    .ok_or("--max-neighborhood-cost requires a value")?;
+                let max_cost: f64 = value
+                    .parse()
+                    .ok()
+                    .filter(|max_cost: &f64| max_cost.is_finite() && *max_cost > 0.0)
+                    .ok_or_else(|| format!("invalid --max-neighborhood-cost value: {}", value))?;
+                cli_args.scheduler.max_cost = Some(max_cost);
+            }
+            "--cost-weights" => {
+                let value = args.next().ok_or("--cost-weights requires a value")?;
+                cli_args.scheduler.cost_model = CostModel::parse(&value)
+                    .ok_or_else(|| format!("invalid --cost-weights value: {}", value))?;
+            }
+            "--neighborhood-timings" => {
+                cli_args.scheduler.log_timings = true;
+                cli_args.worker_args.push(arg);
+            }
+            "--parallel-frontier" => {
+                let value = args.next().ok_or("--parallel-frontier requires a value")?;
+                let min_len: usize = value
+                    .parse()
+                    .map_err(|_| format!("invalid --parallel-frontier value: {}", value))?;
+                cli_args.parallel_frontier = Some(min_len);
+                cli_args.worker_args.extend([arg, value]);
+            }
+            "--outer-summaries" => {
+                cli_args.outer_summaries = true;
+                cli_args.worker_args.push(arg);
+            }
+            "--lec-builder" => {
+                let value = args.next().ok_or("--lec-builder requires a value")?;
+                cli_args.lec_builder = value.parse()?;
+            }
+            "--engine" => {
+                let value = args.next().ok_or("--engine requires a value")?;
+                cli_args.engine = value.parse()?;
+                cli_args.worker_args.extend([arg, value]);
+            }
+            "--symmetry" => cli_args.symmetry = true,
+            "--variable-order" => {
+                let value = args.next().ok_or("--variable-order requires a value")?;
+                cli_args.variable_order = value.parse()?;
+            }
+            "--predicate-sizes" => cli_args.predicate_sizes = true,
+            "--coordinator" => {
+                let value = args.next().ok_or("--coordinator requires a directory")?;
+                cli_args.coordinator_dir = Some(value);
+            }
+            "--workers" => {
+                let value = args.next().ok_or("--workers requires a value")?;
+                cli_args.workers = value
+                    .parse()
+                    .ok()
+                    .filter(|&workers| workers > 0)
+                    .ok_or_else(|| format!("invalid --workers value: {}", value))?;
+            }
+            "--worker" => {
+                let value = args.next().ok_or("--worker requires a directory")?;
+                cli_args.worker_dir = Some(value);
+            }
+            "--worker-id" => {
+                let value = args.next().ok_or("--worker-id requires a value")?;
+                cli_args.worker_id = value
+                    .parse()
+                    .map_err(|_| format!("invalid --worker-id value: {}", value))?;
+            }
+            "--progress" => {
+                cli_args.progress = true;
+                cli_args.worker_args.push(arg);
+            }
+            "--time-budget" => {
+                let value = args.next().ok_or("--time-budget requires a value")?;
+                let budget = value
+                    .parse()
+                    .ok()
+                    .and_then(|seconds| Duration::try_from_secs_f64(seconds).ok())
+                    .ok_or_else(|| format!("invalid --time-budget value: {}", value))?;
+                cli_args.time_budget = Some(budget);
+            }
+            "--max-iterations" => {
+                let value = args.next().ok_or("--max-iterations requires a value")?;
+                cli_args.max_iterations =
+                    value
+                        .parse()
+                        .ok()
+                        .filter(|&iterations| iterations > 0)
+                        .ok_or_else(|| format!("invalid --max-iterations value: {}", value))?;
+                cli_args.worker_args.extend([arg, value]);
+            }
+            "--iteration-stats" => {
+                cli_args.iteration_stats = true;
+                cli_args.worker_args.push(arg);
+            }
+            "--cross-check" => {
+                let value = args.next().ok_or("--cross-check requires a value")?;
+                cli_args.cross_check = Some(
+                    value
+                        .parse()
+                        .map_err(|_| format!("invalid --cross-check value: {}", value))?,
+                );
+            }
+            "-h" | "--help" => {
+                println!(
+                    "Usage: Hetu [DATA_DIR] [--ip-bits N] [--cache-budget-mb MB] \
+                     [--stats-file PATH] [--stats-format json|prometheus] \
+                     [--encoding-cache PATH] [--save-snapshot PATH] [--load-snapshot PATH] \
+                     [--bench RUNS] [--partition bfs[:DEPTH[:MAX]]|pod|mincut[:PARTS]] \
+                     [--neighborhood-report] [--max-neighborhood-cost COST] \
+                     [--cost-weights MARKED,RULE,TUNNEL] [--neighborhood-timings] \
+                     [--parallel-frontier MIN_LEN] [--outer-summaries] \
+                     [--lec-builder bdd|trie|check] [--engine npnet|atomic] \
+                     [--symmetry] [--variable-order reversed|msb-first|interleaved|device-last] \
+                     [--predicate-sizes] [--coordinator DIR [--workers N]] \
+                     [--worker DIR --worker-id N] [--progress] [--time-budget SECS] \
+                     [--max-iterations N] [--iteration-stats] [--cross-check SPREAD]"
+                );
+                process::exit(0);
+            }
+            _ if arg.starts_with('-') => return Err(format!("unknown option: {}", arg)),
+            _ => cli_args.data_dir = arg,
+        }
+    }
+    Ok(cli_args)
+}
+
+/// Runs the full load/encode/verify pipeline `runs` times and prints the
+/// fastest and median time per phase for the compiled-in hasher.
+fn run_benchmark(cli_args: &CliArgs, runs: usize) {
+    let mut totals: Vec<Duration> = Vec::new();
+    let mut phase_seconds: Vec<Vec<f64>> = vec![Vec::new(); Phase::ALL.len()];
+    for _ in 0..runs {
+        let start = Instant::now();
+        let mut session = configure_builder(cli_args).build().unwrap_or_else(|err| {
+            eprintln!("{}", err);
+            process::exit(1);
+        });
+        let report = session.run().unwrap_or_else(|err| {
+            eprintln!("{}", err);
+            process::exit(1);
+        });
+        totals.push(start.elapsed());
+        print_incomplete(&report);
+        let metrics = session.metrics();
+        for (i, phase) in Phase::ALL.iter().enumerate() {
+            phase_seconds[i].push(metrics.phase_seconds.get(*phase));
+        }
+    }
+
+    let min_and_median = |values: &mut Vec<f64>| {
+        values.sort_by(|a, b| a.total_cmp(b));
+        (values[0], values[values.len() / 2])
+    };
     println!(
-        "Reachable node pair count: {}",
-        EXIST_COUNT.load(Ordering::SeqCst)
+        "Benchmark: hasher={} packets={} runs={} data={}",
+        HASHER_NAME, PACKET_SET_NAME, runs, cli_args.data_dir
     );
+    println!("{:<24} {:>12} {:>12}", "phase", "min (s)", "median (s)");
+    for (i, phase) in Phase::ALL.iter().enumerate() {
+        let (min, median) = min_and_median(&mut phase_seconds[i]);
+        println!("{:<24} {:>12.6} {:>12.6}", phase.name(), min, median);
+    }
+    let mut total_seconds: Vec<f64> = totals.iter().map(|d| d.as_secs_f64()).collect();
+    let (min, median) = min_and_median(&mut total_seconds);
+    println!("{:<24} {:>12.6} {:>12.6}", "total", min, median);
+}
+
+/// Verifies this worker's share of a coordinator's plan and writes its result.
+fn run_worker(cli_args: &CliArgs, dir: &str) {
+    let start = Instant::now();
+    let builder = configure_builder(cli_args);
+    let result = distributed::run_worker(builder, dir, cli_args.worker_id).unwrap_or_else(|err| {
+        eprintln!("{}", err);
+        process::exit(1);
+    });
+    let report = result
+        .reports
+        .iter()
+        .fold(ReachabilityReport::default(), |mut acc, (_, cur)| {
+            acc += *cur;
+            acc
+        });
     println!(
-        "Unreachable node pair count: {}",
-        NONEXIST_COUNT.load(Ordering::SeqCst)
+        "Worker {}: {} neighborhoods, {} reachable, {} unreachable in {:?}",
+        result.worker,
+        result.reports.len(),
+        report.reachable,
+        report.unreachable,
+        start.elapsed()
     );
+    print_incomplete(&report);
+}
+
+/// The session options shared by every mode. A time budget counts from
+/// when the session is built or opened.
+fn configure_builder(cli_args: &CliArgs) -> VerificationSessionBuilder {
+    let mut builder = VerificationSession::builder()
+        .data_dir(&cli_args.data_dir)
+        .ip_bits(cli_args.ip_bits)
+        .variable_order(cli_args.variable_order)
+        .cache_budget(cli_args.cache_budget)
+        .scheduler(cli_args.scheduler.clone())
+        .outer_summaries(cli_args.outer_summaries)
+        .lec_builder(cli_args.lec_builder)
+        .engine(cli_args.engine)
+        .symmetry(cli_args.symmetry)
+        .max_iterations(cli_args.max_iterations)
+        .iteration_stats(cli_args.iteration_stats);
+    if let Some(path) = &cli_args.encoding_cache {
+        builder = builder.encoding_cache(path);
+    }
+    if let Some(partitioner) = &cli_args.partitioner {
+        builder = builder.partitioner(Arc::clone(partitioner));
+    }
+    if let Some(min_len) = cli_args.parallel_frontier {
+        builder = builder.parallel_frontier(min_len);
+    }
+    if cli_args.progress {
+        builder = builder.progress(Arc::new(|event| println!("{}", event)));
+    }
+    if let Some(budget) = cli_args.time_budget {
+        builder = builder.time_budget(budget);
+    }
+    builder
+}
+
+fn print_incomplete(report: &ReachabilityReport) {
+    if !report.is_complete() {
+        println!(
+            "Incomplete result: {} neighborhoods were not verified",
+            report.skipped_neighborhoods
+        );
+    }
+}
+
+/// Verifies through local worker processes of this binary sharing `dir`.
+/// Workers get what is left of the time budget when they start.
+fn run_coordinator(
+    cli_args: &CliArgs,
+    session: &VerificationSession,
+    dir: &str,
+    deadline: Option<Instant>,
+) -> ReachabilityReport {
+    let start = Instant::now();
+    let mut worker_args = cli_args.worker_args.clone();
+    let coordinator = Coordinator::new(dir, cli_args.workers);
+    let report = coordinator.prepare(session).and_then(|plan| {
+        println!(
+            "Distributed: {} neighborhoods over {} workers in {}",
+            plan.neighborhoods.len(),
+            cli_args.workers,
+            dir
+        );
+        let program = std::env::current_exe().expect("failed to locate the Hetu binary");
+        if let Some(deadline) = deadline {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            worker_args.extend([
+                "--time-budget".to_string(),
+                remaining.as_secs_f64().to_string(),
+            ]);
+        }
+        coordinator.run_local_workers(&program, &worker_args)?;
+        coordinator.merge(&plan)
+    });
+    let report = report.unwrap_or_else(|err| {
+        eprintln!("{}", err);
+        process::exit(1);
+    });
+    println!("Verification time: {:?}", start.elapsed());
+    report
+}
+
+fn main() {
+    let cli_args = parse_args().unwrap_or_else(|err| {
+        eprintln!("{}", err);
+        process::exit(2);
+    });
+    if let Some(runs) = cli_args.bench_runs {
+        run_benchmark(&cli_args, runs);
+        return;
+    }
+    if let Some(dir) = &cli_args.worker_dir {
+        run_worker(&cli_args, dir);
+        return;
+    }
     println!(
-        "Total node pair count: {}",
-        EXIST_COUNT.load(Ordering::SeqCst) + NONEXIST_COUNT.load(Ordering::SeqCst)
+        "Starting the application (hasher: {}, packets: {})",
+        HASHER_NAME, PACKET_SET_NAME
     );
+    let start: Instant = Instant::now();
+    let builder = configure_builder(&cli_args);
+    let session = match &cli_args.load_snapshot {
+        Some(path) => builder.open_snapshot(path),
+        None => builder.build(),
+    };
+    let mut session = session.unwrap_or_else(|err| {
+        eprintln!("{}", err);
+        process::exit(1);
+    });
+    session.load().expect("load failed");
+    session.encode().unwrap_or_else(|err| {
+        eprintln!("{}", err);
+        process::exit(1);
+    });
+    let duration = start.elapsed();
+    println!("Build time: {:?}", duration);
+    if let Some(path) = &cli_args.save_snapshot {
+        session.save_snapshot(path).unwrap_or_else(|err| {
+            eprintln!("{}", err);
+            process::exit(1);
+        });
+    }
+    if cli_args.predicate_sizes {
+        println!("{}", session.predicate_sizes().expect("encode failed"));
+    }
+    if cli_args.neighborhood_report {
+        print!(
+            "{}",
+            session.neighborhood_report().expect("partition failed")
+        );
+    }
+    let report = match &cli_args.coordinator_dir {
+        Some(dir) => run_coordinator(
+            &cli_args,
+            &session,
+            dir,
+            cli_args.time_budget.map(|budget| start + budget),
+        ),
+        None => session.verify().expect("verification failed"),
+    };
+    let duration: std::time::Duration = start.elapsed();
+    println!("Total execution time: {:?}", duration);
+    println!("Reachable node pair count: {}", report.reachable);
+    println!("Unreachable node pair count: {}", report.unreachable);
+    println!("Total node pair count: {}", report.total_pairs());
+    print_incomplete(&report);
+    if let Some(stats_file) = &cli_args.stats_file {
+        session
+            .metrics()
+            .write_to(stats_file, cli_args.stats_format)
+            .unwrap_or_else(|err| eprintln!("Failed to write stats file {}: {}", stats_file, err));
+    }
+    if let Some(spread) = cli_args.cross_check {
+        let report = session.cross_check(spread).unwrap_or_else(|err| {
+            eprintln!("{}", err);
+            process::exit(1);
+        });
+        println!("{}", report);
+        if !report.is_consistent() {
+            eprintln!(
+                "Cross-check failed: NPNet and the reference forwarder disagree on {} verdicts",
+                report.disagreements.len()
+            );
+            process::exit(1);
+        }
+    }
+    let lec_mismatches = session.lec_mismatches().expect("encode failed");
+    if lec_mismatches > 0 {
+        eprintln!(
+            "LEC cross-check failed: the trie and BDD builders disagree on {} ports",
+            lec_mismatches
+        );
+        process::exit(1);
+    }
 }
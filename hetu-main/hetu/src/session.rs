@@ -0,0 +1,454 @@
+use crate::simulator::Simulator;
+use crate::util::hash_utils::HashMap;
+use crate::util::metrics::MetricsSnapshot;
+use crate::util::network::Network;
+use crate::util::npbdd::CacheBudget;
+use crate::util::progress::{CancellationToken, ProgressCallback, RunControl};
+use crate::util::snapshot::NetworkSnapshot;
+use crate::util::var_order::{PredicateSizes, VariableOrder};
+use crate::verifier::atomic::VerificationEngine;
+use crate::verifier::device::Device;
+use crate::verifier::lec_trie::LecBuilder;
+use crate::verifier::neighborhood::Neighborhood;
+use crate::verifier::npnet::DEFAULT_MAX_ITERATIONS;
+use crate::verifier::partition::{NeighborhoodReport, Partitioner};
+use crate::verifier::reference::CrossCheckReport;
+use crate::verifier::report::ReachabilityReport;
+use crate::verifier::scheduler::{ScheduledNeighborhood, Scheduler};
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+
+const DEFAULT_IP_BITS: usize = 144;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SessionError {
+    MissingDataDir,
+    DataDirNotFound(PathBuf),
+    NotLoaded,
+    NotEncoded,
+    UnknownDevice(String),
+    Snapshot(String),
+    /// Encoding was cancelled or ran out of time; the session cannot continue.
+    Cancelled,
+    /// A query's traversal hit the iteration limit.
+    NotConverged(String),
+    /// The devices hold no rules, as in a session opened from a snapshot.
+    NoRules,
+}
+
+impl fmt::Display for SessionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SessionError::MissingDataDir => write!(f, "no data directory was configured"),
+            SessionError::DataDirNotFound(path) => {
+                write!(f, "data directory {} does not exist", path.display())
+            }
+            SessionError::NotLoaded => write!(f, "data has not been loaded yet"),
+            SessionError::NotEncoded => write!(f, "devices have not been encoded yet"),
+            SessionError::UnknownDevice(name) => {
+                write!(f, "device {} is unknown or has no packet space", name)
+            }
+            SessionError::Snapshot(message) => write!(f, "snapshot error: {}", message),
+            SessionError::Cancelled => write!(f, "encoding was cancelled"),
+            SessionError::NotConverged(message) => write!(f, "{}", message),
+            SessionError::NoRules => write!(
+                f,
+                "devices hold no rules; cross-checking needs the route files, not a snapshot"
+            ),
+        }
+    }
+}
+
+impl std::error::Error for SessionError {}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum Stage {
+    Configured,
+    Loaded,
+    Cancelled,
+    Encoded,
+}
+
+pub struct VerificationSessionBuilder {
+    data_dir: Option<PathBuf>,
+    ip_bits: usize,
+    variable_order: VariableOrder,
+    cache_budget: CacheBudget,
+    encoding_cache: Option<PathBuf>,
+    partitioner: Option<Arc<dyn Partitioner>>,
+    scheduler: Scheduler,
+    parallel_frontier: Option<usize>,
+    outer_summaries: bool,
+    lec_builder: LecBuilder,
+    engine: VerificationEngine,
+    symmetry: bool,
+    max_iterations: usize,
+    iteration_stats: bool,
+    cancellation: CancellationToken,
+    time_budget: Option<Duration>,
+    progress: Option<ProgressCallback>,
+}
+
+impl Default for VerificationSessionBuilder {
+    fn default() -> Self {
+        VerificationSessionBuilder {
+            data_dir: None,
+            ip_bits: DEFAULT_IP_BITS,
+            variable_order: VariableOrder::default(),
+            cache_budget: CacheBudget::default(),
+            encoding_cache: None,
+            partitioner: None,
+            scheduler: Scheduler::default(),
+            parallel_frontier: None,
+            outer_summaries: false,
+            lec_builder: LecBuilder::default(),
+            engine: VerificationEngine::default(),
+            symmetry: false,
+            max_iterations: DEFAULT_MAX_ITERATIONS,
+            iteration_stats: false,
+            cancellation: CancellationToken::default(),
+            time_budget: None,
+            progress: None,
+        }
+    }
+}
+
+impl VerificationSessionBuilder {
+    pub fn data_dir(mut self, data_dir: impl AsRef<Path>) -> Self {
+        self.data_dir = Some(data_dir.as_ref().to_path_buf());
+        self
+    }
+
+    pub fn ip_bits(mut self, ip_bits: usize) -> Self {
+        self.ip_bits = ip_bits;
+        self
+    }
+
+    /// Sets the BDD variable layout; snapshots keep the order they were
+    /// saved with.
+    pub fn variable_order(mut self, variable_order: VariableOrder) -> Self {
+        self.variable_order = variable_order;
+        self
+    }
+
+    /// Caps the memory held by the BDD table and operation caches.
+    pub fn cache_budget(mut self, cache_budget: CacheBudget) -> Self {
+        self.cache_budget = cache_budget;
+        self
+    }
+
+    /// Persists rule encodings to `path` and reuses them for devices whose
+    /// route file, ports and packet space are unchanged.
+    pub fn encoding_cache(mut self, path: impl AsRef<Path>) -> Self {
+        self.encoding_cache = Some(path.as_ref().to_path_buf());
+        self
+    }
+
+    /// Chooses how packet-space owners are grouped into neighborhoods; the
+    /// default merges owners within two hops of each other.
+    pub fn partitioner(mut self, partitioner: Arc<dyn Partitioner>) -> Self {
+        self.partitioner = Some(partitioner);
+        self
+    }
+
+    /// Controls the cost estimate, ordering and splitting of neighborhoods.
+    pub fn scheduler(mut self, scheduler: Scheduler) -> Self {
+        self.scheduler = scheduler;
+        self
+    }
+
+    /// Expands traversal frontiers of at least `min_len` nodes in parallel.
+    pub fn parallel_frontier(mut self, min_len: usize) -> Self {
+        self.parallel_frontier = Some(min_len);
+        self
+    }
+
+    /// Summarizes the devices shared by all outer areas once, instead of
+    /// traversing them again in every neighborhood.
+    pub fn outer_summaries(mut self, enabled: bool) -> Self {
+        self.outer_summaries = enabled;
+        self
+    }
+
+    /// Selects how rule LECs are computed during encoding.
+    pub fn lec_builder(mut self, lec_builder: LecBuilder) -> Self {
+        self.lec_builder = lec_builder;
+        self
+    }
+
+    /// Selects the engine used by `verify`; queries always use `NPNet`.
+    pub fn engine(mut self, engine: VerificationEngine) -> Self {
+        self.engine = engine;
+        self
+    }
+
+    /// Verifies one pod per class of symmetric pods and projects its results
+    /// to the rest; falls back to every destination when the check fails.
+    pub fn symmetry(mut self, enabled: bool) -> Self {
+        self.symmetry = enabled;
+        self
+    }
+
+    /// Caps the inner and outer area passes of each `NPNet` traversal.
+    pub fn max_iterations(mut self, max_iterations: usize) -> Self {
+        self.max_iterations = max_iterations;
+        self
+    }
+
+    /// Prints updated nodes and tunnel crossings per traversal iteration.
+    pub fn iteration_stats(mut self, enabled: bool) -> Self {
+        self.iteration_stats = enabled;
+        self
+    }
+
+    /// Stops encoding and verification once `token` is cancelled. Verifying
+    /// then returns a report of the neighborhoods finished so far.
+    pub fn cancellation(mut self, token: CancellationToken) -> Self {
+        self.cancellation = token;
+        self
+    }
+
+    /// Cancels the session once `budget` has passed since it was built or
+    /// opened.
+    pub fn time_budget(mut self, budget: Duration) -> Self {
+        self.time_budget = Some(budget);
+        self
+    }
+
+    /// Receives devices encoded and neighborhoods verified as they finish.
+    pub fn progress(mut self, callback: ProgressCallback) -> Self {
+        self.progress = Some(callback);
+        self
+    }
+
+    fn run_control(&self) -> RunControl {
+        let cancellation = match self.time_budget {
+            Some(budget) => self.cancellation.clone().with_budget(budget),
+            None => self.cancellation.clone(),
+        };
+        RunControl {
+            cancellation,
+            progress: self.progress.clone(),
+        }
+    }
+
+    pub fn build(self) -> Result<VerificationSession, SessionError> {
+        let control = self.run_control();
+        let data_dir = self.data_dir.ok_or(SessionError::MissingDataDir)?;
+        if !data_dir.is_dir() {
+            return Err(SessionError::DataDirNotFound(data_dir));
+        }
+        let mut simulator =
+            Simulator::with_variable_order(self.ip_bits, self.cache_budget, self.variable_order);
+        simulator.set_file_dir(&data_dir.to_string_lossy());
+        if let Some(path) = &self.encoding_cache {
+            simulator.set_encoding_cache_path(path);
+        }
+        if let Some(partitioner) = self.partitioner {
+            simulator.set_partitioner(partitioner);
+        }
+        simulator.set_scheduler(self.scheduler);
+        simulator.set_parallel_frontier(self.parallel_frontier);
+        simulator.set_outer_summaries(self.outer_summaries);
+        simulator.set_engine(self.engine);
+        simulator.set_symmetry(self.symmetry);
+        simulator.set_max_iterations(self.max_iterations);
+        simulator.set_iteration_stats(self.iteration_stats);
+        simulator.set_lec_builder(self.lec_builder);
+        simulator.set_run_control(control);
+        Ok(VerificationSession {
+            simulator,
+            stage: Stage::Configured,
+        })
+    }
+
+    /// Opens an encoded network saved with [`VerificationSession::save_snapshot`].
+    /// The data directory and IP width are ignored; the snapshot carries its own.
+    pub fn open_snapshot(
+        self,
+        path: impl AsRef<Path>,
+    ) -> Result<VerificationSession, SessionError> {
+        let control = self.run_control();
+        let path = path.as_ref();
+        let snapshot = NetworkSnapshot::load(path)
+            .map_err(|err| SessionError::Snapshot(format!("{}: {}", path.display(), err)))?;
+        let mut simulator = Simulator::from_snapshot(snapshot, self.cache_budget);
+        simulator.set_run_control(control);
+        if let Some(partitioner) = self.partitioner {
+            simulator.set_partitioner(partitioner);
+        }
+        simulator.set_scheduler(self.scheduler);
+        simulator.set_parallel_frontier(self.parallel_frontier);
+        simulator.set_outer_summaries(self.outer_summaries);
+        simulator.set_engine(self.engine);
+        simulator.set_symmetry(self.symmetry);
+        simulator.set_max_iterations(self.max_iterations);
+        simulator.set_iteration_stats(self.iteration_stats);
+        Ok(VerificationSession {
+            simulator,
+            stage: Stage::Encoded,
+        })
+    }
+}
+
+/// Owns a [`Simulator`] and drives it through load, encode and verify.
+pub struct VerificationSession {
+    simulator: Simulator,
+    stage: Stage,
+}
+
+impl VerificationSession {
+    pub fn builder() -> VerificationSessionBuilder {
+        VerificationSessionBuilder::default()
+    }
+
+    pub fn from_snapshot(path: impl AsRef<Path>) -> Result<Self, SessionError> {
+        VerificationSessionBuilder::default().open_snapshot(path)
+    }
+
+    /// Reads routes, topology, edge devices and packet space from the data directory.
+    /// Sessions opened from a snapshot are already encoded and skip this step.
+    pub fn load(&mut self) -> Result<(), SessionError> {
+        match self.stage {
+            Stage::Encoded => return Ok(()),
+            Stage::Cancelled => return Err(SessionError::Cancelled),
+            _ => {}
+        }
+        self.simulator.load();
+        self.stage = Stage::Loaded;
+        Ok(())
+    }
+
+    /// Encodes the loaded packet space and forwarding rules into BDDs.
+    pub fn encode(&mut self) -> Result<(), SessionError> {
+        match self.stage {
+            Stage::Configured => Err(SessionError::NotLoaded),
+            Stage::Loaded => {
+                if self.simulator.encode() {
+                    self.stage = Stage::Encoded;
+                    Ok(())
+                } else {
+                    self.stage = Stage::Cancelled;
+                    Err(SessionError::Cancelled)
+                }
+            }
+            Stage::Cancelled => Err(SessionError::Cancelled),
+            Stage::Encoded => Ok(()),
+        }
+    }
+
+    /// Checks reachability between every edge device and every packet-space owner.
+    /// If the session is cancelled meanwhile, the report covers the
+    /// neighborhoods finished so far and is not complete.
+    pub fn verify(&self) -> Result<ReachabilityReport, SessionError> {
+        self.ensure_encoded()?;
+        Ok(self.simulator.verify_reachability())
+    }
+
+    /// Neighborhoods `verify` would check, in the order it starts them.
+    pub fn plan_verification(&self) -> Result<Vec<ScheduledNeighborhood>, SessionError> {
+        self.ensure_encoded()?;
+        Ok(self.simulator.plan_verification())
+    }
+
+    /// Verifies neighborhoods planned elsewhere, e.g. by a coordinator, and
+    /// returns their weighted reports in the given order.
+    pub fn verify_neighborhoods(
+        &self,
+        neighborhoods: Vec<Neighborhood>,
+    ) -> Result<Vec<ReachabilityReport>, SessionError> {
+        self.ensure_encoded()?;
+        let plan = self.simulator.schedule_as_given(neighborhoods);
+        Ok(self.simulator.verify_plan(&plan))
+    }
+
+    /// Checks whether `src` can deliver the whole packet space owned by `dst`.
+    pub fn query(&self, src: &str, dst: &str) -> Result<bool, SessionError> {
+        self.ensure_encoded()?;
+        if !self.simulator.devices.contains_key(src) {
+            return Err(SessionError::UnknownDevice(src.to_string()));
+        }
+        self.simulator
+            .query_reachability(src, dst)
+            .ok_or_else(|| SessionError::UnknownDevice(dst.to_string()))?
+            .map_err(|err| SessionError::NotConverged(err.to_string()))
+    }
+
+    /// Compares every `NPNet` verdict with a concrete-packet forwarder; see
+    /// [`Simulator::cross_check`].
+    pub fn cross_check(&self, spread: usize) -> Result<CrossCheckReport, SessionError> {
+        self.ensure_encoded()?;
+        if !self.simulator.has_rules() {
+            return Err(SessionError::NoRules);
+        }
+        Ok(self.simulator.cross_check(spread))
+    }
+
+    /// Ports on which the `check` LEC builder found the trie and BDD
+    /// predicates to differ; always 0 with the other builders.
+    pub fn lec_mismatches(&self) -> Result<usize, SessionError> {
+        self.ensure_encoded()?;
+        Ok(self.simulator.lec_mismatches())
+    }
+
+    /// Partitions the network without verifying it and reports neighborhood sizes.
+    pub fn neighborhood_report(&self) -> Result<NeighborhoodReport, SessionError> {
+        self.ensure_encoded()?;
+        Ok(self.simulator.neighborhood_report())
+    }
+
+    /// Node counts of the encoded port predicates and packet spaces.
+    pub fn predicate_sizes(&self) -> Result<PredicateSizes, SessionError> {
+        self.ensure_encoded()?;
+        Ok(self.simulator.predicate_sizes())
+    }
+
+    /// Writes the encoded network to a versioned binary file.
+    pub fn save_snapshot(&self, path: impl AsRef<Path>) -> Result<(), SessionError> {
+        self.ensure_encoded()?;
+        let path = path.as_ref();
+        self.simulator
+            .to_snapshot()
+            .save(path)
+            .map_err(|err| SessionError::Snapshot(format!("{}: {}", path.display(), err)))
+    }
+
+    /// Loads, encodes and verifies in one call.
+    pub fn run(&mut self) -> Result<ReachabilityReport, SessionError> {
+        if self.stage < Stage::Loaded {
+            self.load()?;
+        }
+        self.encode()?;
+        self.verify()
+    }
+
+    /// Phase timings, cache statistics and traversal counters gathered so far.
+    /// Partition, schedule, traversal and check figures cover the last
+    /// `verify` only.
+    pub fn metrics(&self) -> MetricsSnapshot {
+        self.simulator.metrics_snapshot()
+    }
+
+    pub fn simulator(&self) -> &Simulator {
+        &self.simulator
+    }
+
+    pub fn network(&self) -> &Arc<Network> {
+        &self.simulator.network
+    }
+
+    pub fn devices(&self) -> &Arc<HashMap<String, Arc<Device>>> {
+        &self.simulator.devices
+    }
+
+    fn ensure_encoded(&self) -> Result<(), SessionError> {
+        match self.stage {
+            Stage::Configured => Err(SessionError::NotLoaded),
+            Stage::Loaded => Err(SessionError::NotEncoded),
+            Stage::Cancelled => Err(SessionError::Cancelled),
+            Stage::Encoded => Ok(()),
+        }
+    }
+}
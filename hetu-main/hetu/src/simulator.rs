@@ -1,9 +1,26 @@
+use crate::util::codec::{content_hash, ContentHasher};
+use crate::util::encoding_cache::EncodingCache;
+use crate::util::metrics::{Metrics, MetricsSnapshot, Phase};
 use crate::util::network::Network;
-use crate::util::npbdd::NPBDD;
+use crate::util::npbdd::{CacheBudget, SpaceId, NPBDD};
+use crate::util::packet_set::{prefix_bits, PacketSpace, PACKET_SET_NAME};
+use crate::util::progress::RunControl;
 use crate::util::rule::Rule;
+use crate::util::snapshot::{NetworkSnapshot, SnapshotDevice};
+use crate::util::var_order::{PredicateSizes, VariableOrder};
+use crate::verifier::atomic::{AtomicPredicates, VerificationEngine};
 use crate::verifier::device::Device;
+use crate::verifier::lec_trie::LecBuilder;
 use crate::verifier::neighborhood::{Neighborhood, PacketSpaceAwareDevice};
-use crate::verifier::npnet::{InvariantType, NPNet, TraversalType};
+use crate::verifier::npnet::{
+    ConvergenceError, InvariantType, NPNet, TraversalType, DEFAULT_MAX_ITERATIONS,
+};
+use crate::verifier::outer_summary::OuterSummaryCache;
+use crate::verifier::partition::{BfsMergePartitioner, NeighborhoodReport, Partitioner};
+use crate::verifier::reference::{CrossCheckReport, Disagreement, ReferenceForwarder};
+use crate::verifier::report::ReachabilityReport;
+use crate::verifier::scheduler::{ScheduledNeighborhood, Scheduler};
+use crate::verifier::symmetry::SymmetryReduction;
 use crate::verifier::toponet::Toponet;
 use rayon::prelude::*;
 use serde::{Deserialize, Serialize};
@@ -11,13 +28,14 @@ use serde_json::Value;
 use std::fs::{self, File};
 use std::io::{BufRead, BufReader};
 use std::net::IpAddr;
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
 use std::time::Instant;
 
 use crate::util::hash_utils::{HashMap, HashSet};
 
 #[derive(Debug, Serialize, Deserialize)]
-struct packet {
+pub struct Packet {
     prefix: String,
     prefix_len: usize,
     host_name: String,
@@ -44,31 +62,146 @@ pub struct Simulator {
     pub src_toponet: Toponet,
     ip_bits: usize,
     pub network: Arc<Network>,
+    pub npbdd: Arc<NPBDD>,
     common_prefix: String,
+    loaded_devices: HashMap<String, Device>,
+    pub metrics: Metrics,
+    encoding_cache_path: Option<PathBuf>,
+    route_hashes: HashMap<String, u64>,
+    context_hash: u64,
+    partitioner: Arc<dyn Partitioner>,
+    scheduler: Scheduler,
+    parallel_frontier: Option<usize>,
+    outer_summaries: bool,
+    lec_builder: LecBuilder,
+    lec_mismatches: usize,
+    engine: VerificationEngine,
+    symmetry: bool,
+    control: RunControl,
+    max_iterations: usize,
+    iteration_stats: bool,
 }
 
 impl Simulator {
     pub fn new(ip_bits: usize) -> Self {
+        Self::with_cache_budget(ip_bits, CacheBudget::default())
+    }
+
+    pub fn with_cache_budget(ip_bits: usize, cache_budget: CacheBudget) -> Self {
+        Self::with_variable_order(ip_bits, cache_budget, VariableOrder::default())
+    }
+
+    pub fn with_variable_order(
+        ip_bits: usize,
+        cache_budget: CacheBudget,
+        order: VariableOrder,
+    ) -> Self {
         let num_cpu = num_cpus::get();
         println!("Number of logical cores: {}", num_cpu);
-        NPBDD::init(ip_bits);
+        let npbdd = Arc::new(NPBDD::with_order(ip_bits, cache_budget, order));
         Simulator {
             file_dir: String::new(),
             devices_name: Vec::new(),
             devices: Arc::new(HashMap::default()),
             edge_devices: HashSet::default(),
             dst_devices: HashSet::default(),
-            src_toponet: Toponet::new(ip_bits),
+            src_toponet: Toponet::new(Arc::clone(&npbdd)),
             ip_bits,
             network: Arc::new(Network::new()),
+            npbdd,
             common_prefix: String::new(),
+            loaded_devices: HashMap::default(),
+            metrics: Metrics::new(),
+            encoding_cache_path: None,
+            route_hashes: HashMap::default(),
+            context_hash: 0,
+            partitioner: Arc::new(BfsMergePartitioner::default()),
+            scheduler: Scheduler::default(),
+            parallel_frontier: None,
+            outer_summaries: false,
+            lec_builder: LecBuilder::default(),
+            lec_mismatches: 0,
+            engine: VerificationEngine::default(),
+            symmetry: false,
+            control: RunControl::default(),
+            max_iterations: DEFAULT_MAX_ITERATIONS,
+            iteration_stats: false,
         }
     }
 
+    pub fn get_ip_bits(&self) -> usize {
+        self.ip_bits
+    }
+
+    pub fn get_file_dir(&self) -> &str {
+        &self.file_dir
+    }
+
+    pub fn get_edge_devices(&self) -> &HashSet<String> {
+        &self.edge_devices
+    }
+
     pub fn set_file_dir(&mut self, file_dir: &str) {
         self.file_dir = file_dir.to_string();
     }
 
+    /// Reuses per-device encodings from `path` when their inputs are unchanged,
+    /// and rewrites it after every encode.
+    pub fn set_encoding_cache_path(&mut self, path: &Path) {
+        self.encoding_cache_path = Some(path.to_path_buf());
+    }
+
+    pub fn set_partitioner(&mut self, partitioner: Arc<dyn Partitioner>) {
+        self.partitioner = partitioner;
+    }
+
+    pub fn set_scheduler(&mut self, scheduler: Scheduler) {
+        self.scheduler = scheduler;
+    }
+
+    /// Lets a single traversal expand frontier levels of at least `min_len`
+    /// nodes in parallel, on top of the per-neighborhood parallelism.
+    pub fn set_parallel_frontier(&mut self, min_len: Option<usize>) {
+        self.parallel_frontier = min_len;
+    }
+
+    /// Shares transfer summaries of the devices outside every inner area
+    /// between neighborhoods instead of traversing them in each one.
+    pub fn set_outer_summaries(&mut self, enabled: bool) {
+        self.outer_summaries = enabled;
+    }
+
+    /// Caps the area passes of each traversal; a neighborhood that has not
+    /// converged by then is reported and left unverified.
+    pub fn set_max_iterations(&mut self, max_iterations: usize) {
+        self.max_iterations = max_iterations;
+    }
+
+    /// Prints per-iteration traversal statistics of every neighborhood.
+    pub fn set_iteration_stats(&mut self, enabled: bool) {
+        self.iteration_stats = enabled;
+    }
+
+    pub fn set_lec_builder(&mut self, lec_builder: LecBuilder) {
+        self.lec_builder = lec_builder;
+    }
+
+    /// Ports on which `LecBuilder::Checked` found the trie and BDD
+    /// predicates to differ in the last encode.
+    pub fn lec_mismatches(&self) -> usize {
+        self.lec_mismatches
+    }
+
+    pub fn set_engine(&mut self, engine: VerificationEngine) {
+        self.engine = engine;
+    }
+
+    /// Verifies one pod per class of symmetric pods and counts its results
+    /// for the whole class.
+    pub fn set_symmetry(&mut self, enabled: bool) {
+        self.symmetry = enabled;
+    }
+
     pub fn get_devices_name(&mut self) {
         let routes_dir: String = format!("{}/routes", self.file_dir);
         let entries = fs::read_dir(routes_dir).expect("Failed to read directory");
@@ -84,11 +217,11 @@ impl Simulator {
 
     pub fn convert_subnet_devices_to_packets(
         subnet_devices: HashMap<String, Vec<SubNet>>,
-    ) -> Vec<packet> {
+    ) -> Vec<Packet> {
         let mut packets = Vec::new();
         for (host_name, subnets) in subnet_devices {
             for subnet in subnets {
-                let packet = packet {
+                let packet = Packet {
                     prefix: subnet.prefix.to_string(),
                     prefix_len: subnet.prefix_len as usize,
                     host_name: host_name.clone(),
@@ -99,35 +232,205 @@ impl Simulator {
         packets
     }
 
-    pub fn read_devices_files_and_encode(&mut self) {
+    pub fn read_devices_files(&mut self) {
         let mut tmp_devices = self.generate_devices_and_read_rules();
         let packets = self.read_packet_space();
         self.set_packet_space(&mut tmp_devices, packets);
+        self.loaded_devices = tmp_devices;
+        if self.encoding_cache_path.is_some() {
+            let packet_space_file_path = format!("{}/packet_space.json", self.file_dir);
+            let contents = fs::read(packet_space_file_path).expect("Error while reading the file");
+            let mut hasher = ContentHasher::new();
+            hasher
+                .update(&contents)
+                .update_u64(self.ip_bits as u64)
+                .update(PACKET_SET_NAME.as_bytes())
+                .update(self.npbdd.variable_order().to_string().as_bytes());
+            for devices in [&self.edge_devices, &self.dst_devices] {
+                let mut names: Vec<&String> = devices.iter().collect();
+                names.sort();
+                hasher.update_u64(names.len() as u64);
+                for name in names {
+                    hasher.update(name.as_bytes()).update(&[0]);
+                }
+            }
+            self.context_hash = hasher.finish();
+        }
+    }
+
+    /// Cancellation token and progress callback checked while encoding and
+    /// verifying.
+    pub fn set_run_control(&mut self, control: RunControl) {
+        self.control = control;
+    }
+
+    /// Returns false if the run was cancelled before every device was
+    /// encoded. The simulator then holds a partial network and cannot be
+    /// verified.
+    pub fn encode(&mut self) -> bool {
+        let mut tmp_devices = std::mem::take(&mut self.loaded_devices);
+        let start = Instant::now();
         self.src_toponet
             .encode_packet_space_group(&mut tmp_devices, &self.dst_devices);
-        self.src_toponet
-            .encode_rule_npbdd(&mut tmp_devices, &self.common_prefix, &self.network);
-        self.assign_device_id_and_encode(&mut tmp_devices);
+        self.metrics
+            .record(Phase::PacketSpaceEncode, start.elapsed());
+        let start = Instant::now();
+        let encoded = if self.encoding_cache_path.is_some() {
+            self.encode_rules_with_cache(&mut tmp_devices)
+        } else {
+            self.src_toponet.encode_rules(
+                &mut tmp_devices,
+                &self.common_prefix,
+                &self.network,
+                self.lec_builder,
+                &self.control,
+            )
+        };
+        if encoded.is_some() {
+            self.assign_device_id_and_encode(&mut tmp_devices);
+        }
+        self.metrics.record(Phase::RuleEncode, start.elapsed());
+        match encoded {
+            Some(mismatches) => self.lec_mismatches = mismatches,
+            None => return false,
+        }
         self.set_arc_devices(tmp_devices);
+        if self.npbdd.budget().is_bounded() {
+            println!("{}", self.npbdd.cache_stats());
+        }
+        true
     }
 
-    fn generate_devices_and_read_rules(&self) -> HashMap<String, Device> {
-        self.devices_name
+    fn generate_devices_and_read_rules(&mut self) -> HashMap<String, Device> {
+        let hash_routes = self.encoding_cache_path.is_some();
+        let parsed: Vec<(String, Device, u64)> = self
+            .devices_name
             .par_iter()
             .map(|device_name_arc| {
                 let device_name = Arc::clone(device_name_arc);
                 let mut tdevice: Device = Device::new((*device_name).clone());
                 let rule_file_path = format!("{}/routes/{}", self.file_dir, device_name);
-                tdevice.read_rules_file(&rule_file_path);
+                let contents =
+                    fs::read_to_string(&rule_file_path).expect("Error while reading the file");
+                tdevice.read_rules_str(&contents);
+                let route_hash = if hash_routes {
+                    content_hash(contents.as_bytes())
+                } else {
+                    0
+                };
                 (
                     Arc::try_unwrap(device_name).unwrap_or_else(|arc| (*arc).clone()),
                     tdevice,
+                    route_hash,
                 )
             })
-            .collect()
+            .collect();
+        let mut tmp_devices = HashMap::default();
+        for (device_name, device, route_hash) in parsed {
+            if hash_routes {
+                self.route_hashes.insert(device_name.clone(), route_hash);
+            }
+            tmp_devices.insert(device_name, device);
+        }
+        tmp_devices
     }
 
-    fn read_packet_space(&self) -> Vec<packet> {
+    fn device_encoding_key(&self, device_name: &str) -> u64 {
+        let mut hasher = ContentHasher::new();
+        hasher.update_u64(*self.route_hashes.get(device_name).unwrap_or(&0));
+        if let Some(ports) = self.network.get_device_ports().get(device_name) {
+            let mut port_names: Vec<String> =
+                ports.iter().map(|port| port.get_port_name()).collect();
+            port_names.sort();
+            for port_name in port_names {
+                hasher.update(port_name.as_bytes()).update(&[0]);
+            }
+        }
+        hasher.finish()
+    }
+
+    /// Returns the LEC mismatches of the re-encoded devices, or `None` if
+    /// encoding was cancelled; the cache is then left as is.
+    fn encode_rules_with_cache(
+        &mut self,
+        tmp_devices: &mut HashMap<String, Device>,
+    ) -> Option<usize> {
+        let cache_path = self.encoding_cache_path.clone().unwrap();
+        let previous = match EncodingCache::load(&cache_path) {
+            Ok(cache) if cache.context_hash == self.context_hash => Some(cache),
+            Ok(_) => {
+                println!("Encoding cache is stale, re-encoding all devices");
+                None
+            }
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => None,
+            Err(err) => {
+                println!("Ignoring unreadable encoding cache: {}", err);
+                None
+            }
+        };
+
+        let keys: HashMap<String, u64> = tmp_devices
+            .keys()
+            .map(|device_name| (device_name.clone(), self.device_encoding_key(device_name)))
+            .collect();
+        let mut changed_devices: HashMap<String, Device> = HashMap::default();
+        let mut reused_count = 0;
+        if let Some(mut previous) = previous {
+            previous.restore_npbdd(&self.npbdd);
+            let device_names: Vec<String> = tmp_devices.keys().cloned().collect();
+            for device_name in device_names {
+                match previous.devices.remove(&device_name) {
+                    Some((key, state)) if key == keys[&device_name] => {
+                        let device = tmp_devices.get_mut(&device_name).unwrap();
+                        device.restore_encoded_state(state, &self.npbdd);
+                        reused_count += 1;
+                    }
+                    _ => {
+                        let device = tmp_devices.remove(&device_name).unwrap();
+                        changed_devices.insert(device_name, device);
+                    }
+                }
+            }
+        } else {
+            changed_devices = std::mem::take(tmp_devices);
+        }
+        println!(
+            "Encoding cache: reused {} devices, re-encoding {}",
+            reused_count,
+            changed_devices.len()
+        );
+        let mismatches = if changed_devices.is_empty() {
+            0
+        } else {
+            self.src_toponet.encode_rules(
+                &mut changed_devices,
+                &self.common_prefix,
+                &self.network,
+                self.lec_builder,
+                &self.control,
+            )?
+        };
+        tmp_devices.extend(changed_devices);
+
+        let mut cache = EncodingCache::new(self.context_hash);
+        for (device_name, device) in tmp_devices.iter() {
+            cache.devices.insert(
+                device_name.clone(),
+                (keys[device_name], device.encoded_state(&self.npbdd)),
+            );
+        }
+        cache.capture_npbdd(&self.npbdd);
+        if let Err(err) = cache.save(&cache_path) {
+            println!(
+                "Failed to write encoding cache {}: {}",
+                cache_path.display(),
+                err
+            );
+        }
+        Some(mismatches)
+    }
+
+    fn read_packet_space(&self) -> Vec<Packet> {
         let packet_space_file_path = format!("{}/packet_space.json", self.file_dir);
         let contents =
             fs::read_to_string(packet_space_file_path).expect("Error while reading the file");
@@ -149,7 +452,7 @@ impl Simulator {
     fn set_packet_space(
         &mut self,
         tmp_devices: &mut HashMap<String, Device>,
-        packets: Vec<packet>,
+        packets: Vec<Packet>,
     ) {
         let mut prefixes: Vec<String> = Vec::new();
         for packet in packets {
@@ -157,7 +460,7 @@ impl Simulator {
             let packet_space = Rule::new_for_packet_space(packet.prefix_len, packet.prefix.clone());
             prefixes.push(packet.prefix);
             if let Some(device) = tmp_devices.get_mut(&tmp_device_name) {
-                device.set_packet_space_file(packet_space);
+                device.add_packet_space(packet_space);
             } else {
                 println!("Failed to find packet space device: {:?}", tmp_device_name);
             }
@@ -223,130 +526,631 @@ impl Simulator {
         self.src_toponet.set_arc_network(&self.network);
     }
 
-    pub fn build(&mut self) {
+    pub fn load(&mut self) {
+        let start = Instant::now();
         self.get_devices_name();
         self.get_edge_devices_name();
         self.get_dst_devices_name();
         self.init_network();
-        self.read_devices_files_and_encode();
+        self.read_devices_files();
+        self.metrics.record(Phase::Parse, start.elapsed());
+    }
+
+    pub fn metrics_snapshot(&self) -> MetricsSnapshot {
+        self.metrics.snapshot(&self.npbdd.cache_stats())
+    }
+
+    pub fn build(&mut self) {
+        self.load();
+        self.encode();
+    }
+
+    /// Captures the encoded network, with entries sorted by device name.
+    pub fn to_snapshot(&self) -> NetworkSnapshot {
+        let mut devices: Vec<SnapshotDevice> = self
+            .devices
+            .iter()
+            .map(|(device_name, device)| SnapshotDevice {
+                name: device_name.clone(),
+                device_id: device.device_id,
+                packet_space: device
+                    .get_packet_space()
+                    .iter()
+                    .map(|rule| (rule.get_ip().to_string(), rule.get_prefix_len()))
+                    .collect(),
+                subnet_space: self.npbdd.space(device.subnet_space).as_ref().clone(),
+                encoding: device.encoded_state(&self.npbdd),
+            })
+            .collect();
+        devices.sort_by(|a, b| a.name.cmp(&b.name));
+        let sorted = |names: &HashSet<String>| {
+            let mut names: Vec<String> = names.iter().cloned().collect();
+            names.sort();
+            names
+        };
+        let mut packet_space_bdds: Vec<(String, PacketSpace)> = self
+            .src_toponet
+            .map_device_packet_space_bdd
+            .iter()
+            .map(|(device_name, &space)| {
+                (
+                    device_name.clone(),
+                    self.npbdd.space(space).as_ref().clone(),
+                )
+            })
+            .collect();
+        packet_space_bdds.sort_by(|a, b| a.0.cmp(&b.0));
+        NetworkSnapshot {
+            ip_bits: self.ip_bits,
+            variable_order: self.npbdd.variable_order(),
+            common_prefix: self.common_prefix.clone(),
+            edge_devices: sorted(&self.edge_devices),
+            dst_devices: sorted(&self.dst_devices),
+            links: self.network.get_links().to_vec(),
+            devices,
+            packet_space_bdds,
+            all_space: self
+                .src_toponet
+                .all_space_map
+                .get("All")
+                .map(|&space| self.npbdd.space(space).as_ref().clone()),
+        }
+    }
+
+    /// Rebuilds an encoded simulator without touching any route file.
+    pub fn from_snapshot(snapshot: NetworkSnapshot, cache_budget: CacheBudget) -> Self {
+        let mut simulator =
+            Simulator::with_variable_order(snapshot.ip_bits, cache_budget, snapshot.variable_order);
+        simulator.common_prefix = snapshot.common_prefix;
+        simulator.edge_devices = snapshot.edge_devices.into_iter().collect();
+        simulator.dst_devices = snapshot.dst_devices.into_iter().collect();
+
+        let mut network = Network::new();
+        network.load_links(snapshot.links);
+        simulator.network = Arc::new(network);
+        simulator.src_toponet.set_arc_network(&simulator.network);
+
+        let npbdd = Arc::clone(&simulator.npbdd);
+        let tmp_devices: HashMap<String, Device> = snapshot
+            .devices
+            .into_par_iter()
+            .map(|snapshot_device| {
+                let mut device = Device::new(snapshot_device.name.clone());
+                device.device_id = snapshot_device.device_id;
+                device.device_id_bdd =
+                    npbdd.intern_pinned(npbdd.make_src_device_bdd(snapshot_device.device_id));
+                for (prefix, prefix_len) in snapshot_device.packet_space {
+                    device.add_packet_space(Rule::new_for_packet_space(prefix_len, prefix));
+                }
+                device.subnet_space = npbdd.intern_pinned(snapshot_device.subnet_space);
+                device.restore_encoded_state(snapshot_device.encoding, &npbdd);
+                (snapshot_device.name, device)
+            })
+            .collect();
+        simulator.devices_name = tmp_devices
+            .keys()
+            .map(|device_name| Arc::new(device_name.clone()))
+            .collect();
+        simulator.set_arc_devices(tmp_devices);
+
+        simulator.src_toponet.map_device_packet_space_bdd = Arc::new(
+            snapshot
+                .packet_space_bdds
+                .into_iter()
+                .map(|(device_name, space)| (device_name, npbdd.intern_pinned(space)))
+                .collect(),
+        );
+        let mut all_space_map: HashMap<String, SpaceId> = HashMap::default();
+        if let Some(all_space) = snapshot.all_space {
+            all_space_map.insert("All".to_string(), npbdd.intern_pinned(all_space));
+        }
+        simulator.src_toponet.all_space_map = Arc::new(all_space_map);
+        simulator
     }
 }
 
 impl Simulator {
     pub fn assign_device_id_and_encode(&self, tmp_devices: &mut HashMap<String, Device>) {
-        let mut device_id = 0;
-        for (_, device) in tmp_devices.iter_mut() {
+        for (device_id, device) in tmp_devices.values_mut().enumerate() {
             device.device_id = device_id;
-            device_id += 1;
         }
         tmp_devices.par_iter_mut().for_each(|(_, device)| {
-            device.device_id_bdd = NPBDD::make_src_device_bdd(device.device_id);
+            device.device_id_bdd = self
+                .npbdd
+                .intern_pinned(self.npbdd.make_src_device_bdd(device.device_id));
         });
     }
 
     pub fn find_neighborhood_from_subnet_space(&self) -> Vec<Neighborhood> {
+        self.partition_destinations(|_| true)
+    }
+
+    fn partition_destinations(&self, include: impl Fn(&str) -> bool) -> Vec<Neighborhood> {
         let mut marked_nodes = HashMap::default();
         self.src_toponet
             .map_device_packet_space_bdd
             .iter()
-            .for_each(|(device_name, space_bdd)| {
+            .filter(|(device_name, _)| include(device_name))
+            .for_each(|(device_name, &space)| {
                 let device_id = self.devices.get(device_name).unwrap().device_id;
                 let packet_space_aware_device =
-                    PacketSpaceAwareDevice::new(device_name.clone(), space_bdd.clone(), device_id);
+                    PacketSpaceAwareDevice::new(device_name.clone(), space, device_id);
                 marked_nodes.insert(device_name.clone(), packet_space_aware_device);
             });
-        let neighborhoods = self.two_hops_merge(marked_nodes);
-        neighborhoods
+
+        self.partitioner.partition(&self.network, marked_nodes)
     }
 
-    fn two_hops_merge(
+    /// Rebuilds a neighborhood from device names, e.g. one planned by
+    /// another process. Every marked node must own a packet space.
+    pub fn neighborhood_from_names(
         &self,
-        marked_nodes: HashMap<String, PacketSpaceAwareDevice>,
-    ) -> Vec<Neighborhood> {
-        let mut visited: HashMap<String, bool> = HashMap::default();
-        let mut components: Vec<Neighborhood> = Vec::new();
-        let proximity_depth = 2;
-        for (device_name, device) in marked_nodes.iter() {
-            match visited.get(device_name) {
-                Some(true) => continue,
-                _ => {
-                    let mut component = Neighborhood::new();
-                    self.bfs_explore(
-                        &marked_nodes,
-                        device,
-                        &mut visited,
-                        &mut component,
-                        proximity_depth,
-                        None,
-                    );
-                    components.push(component);
-                }
+        marked_nodes: &[String],
+        normal_nodes: &[String],
+        weight: usize,
+    ) -> Result<Neighborhood, String> {
+        let mut neighborhood = Neighborhood::new();
+        for device_name in marked_nodes {
+            let space = self
+                .src_toponet
+                .map_device_packet_space_bdd
+                .get(device_name)
+                .ok_or_else(|| format!("{} has no packet space", device_name))?;
+            let device_id = self.devices[device_name].device_id;
+            neighborhood.add_marked_node(PacketSpaceAwareDevice::new(
+                device_name.clone(),
+                *space,
+                device_id,
+            ));
+        }
+        for device_name in normal_nodes {
+            if !self.devices.contains_key(device_name) {
+                return Err(format!("unknown device {}", device_name));
             }
+            neighborhood.add_normal_node(device_name.clone());
         }
-        components
+        neighborhood.set_weight(weight);
+        Ok(neighborhood)
     }
 
-    fn bfs_explore(
+    /// Schedules neighborhoods planned elsewhere in the given order.
+    pub fn schedule_as_given(
         &self,
-        marked_nodes: &HashMap<String, PacketSpaceAwareDevice>,
-        start_device: &PacketSpaceAwareDevice,
-        visited: &mut HashMap<String, bool>,
-        component: &mut Neighborhood,
-        proximity_depth: i32,
-        max_neighborhood_size: Option<usize>,
-    ) {
-        let mut queue: Vec<(String, i32)> = Vec::new();
-        queue.push((start_device.device_name.clone(), proximity_depth));
-        let mut marked_count = 0;
-        while let Some((current_device_name, depth)) = queue.pop() {
-            if *visited.get(&current_device_name).unwrap_or(&false) || depth == 0 {
-                continue;
+        neighborhoods: Vec<Neighborhood>,
+    ) -> Vec<ScheduledNeighborhood> {
+        self.scheduler
+            .schedule_as_given(&self.network, &self.devices, neighborhoods)
+    }
+
+    /// Neighborhoods to verify. With symmetry reduction they only cover the
+    /// representative pods, weighted by the size of their class.
+    fn plan_neighborhoods(&self) -> Vec<Neighborhood> {
+        if !self.symmetry {
+            return self.find_neighborhood_from_subnet_space();
+        }
+        let start = Instant::now();
+        match self.detect_symmetry() {
+            Ok(reduction) => {
+                println!("{} in {:?}", reduction, start.elapsed());
+                reduction
+                    .groups()
+                    .into_iter()
+                    .flat_map(|(weight, owners)| {
+                        let mut neighborhoods =
+                            self.partition_destinations(|name| owners.contains(name));
+                        for neighborhood in &mut neighborhoods {
+                            neighborhood.set_weight(weight);
+                        }
+                        neighborhoods
+                    })
+                    .collect()
             }
-            visited.insert(current_device_name.clone(), true);
-            if let Some(bdd) = marked_nodes.get(&current_device_name) {
-                component.add_marked_node(bdd.clone());
-            } else {
-                component.add_normal_node(current_device_name.clone());
+            Err(reason) => {
+                println!(
+                    "Symmetry check failed: {}; verifying every destination",
+                    reason
+                );
+                self.find_neighborhood_from_subnet_space()
             }
-            if let Some(max_size) = max_neighborhood_size {
-                if marked_nodes.contains_key(&current_device_name) {
-                    marked_count += 1;
-                    if marked_count >= max_size {
-                        break;
-                    }
-                }
+        }
+    }
+
+    fn detect_symmetry(&self) -> Result<SymmetryReduction, String> {
+        let mut owners = Vec::new();
+        for device_name in self.src_toponet.map_device_packet_space_bdd.keys() {
+            let [packet_space] = self.devices[device_name].get_packet_space() else {
+                return Err(format!(
+                    "{} does not own exactly one packet space prefix",
+                    device_name
+                ));
+            };
+            let bits = prefix_bits(packet_space.get_ip(), packet_space.get_prefix_len())
+                .ok_or_else(|| format!("invalid packet space of {}", device_name))?;
+            owners.push((device_name.clone(), bits));
+        }
+        owners.sort_by(|a, b| a.1.cmp(&b.1));
+        SymmetryReduction::detect(
+            &self.src_toponet.topology_index(),
+            &self.network,
+            &owners,
+            &self.edge_devices,
+            &self.common_prefix,
+        )
+    }
+
+    /// Runs the configured partitioner and summarizes the neighborhood sizes.
+    pub fn neighborhood_report(&self) -> NeighborhoodReport {
+        NeighborhoodReport::new(
+            self.partitioner.name(),
+            &self.find_neighborhood_from_subnet_space(),
+        )
+    }
+
+    /// Node counts of every port predicate and destination packet space.
+    pub fn predicate_sizes(&self) -> PredicateSizes {
+        let port_predicates: Vec<Arc<PacketSpace>> = self
+            .devices
+            .values()
+            .flat_map(|device| {
+                device
+                    .get_space_id_to_space_port()
+                    .values()
+                    .map(|space_port| self.npbdd.space(space_port.get_predicate()))
+            })
+            .collect();
+        let packet_spaces: Vec<Arc<PacketSpace>> = self
+            .src_toponet
+            .map_device_packet_space_bdd
+            .values()
+            .map(|&space| self.npbdd.space(space))
+            .collect();
+        PredicateSizes::new(
+            self.npbdd.variable_order(),
+            port_predicates.iter().map(Arc::as_ref),
+            packet_spaces.iter().map(Arc::as_ref),
+        )
+    }
+
+    /// Verification metrics are reset first, so they describe this call only.
+    pub fn verify_reachability(&self) -> ReachabilityReport {
+        let start = Instant::now();
+        self.metrics.reset_verification();
+        let plan = self.plan_verification();
+        let report = self.verify_plan(&plan).into_iter().fold(
+            ReachabilityReport::default(),
+            |mut acc, cur| {
+                acc += cur;
+                acc
+            },
+        );
+        let duration = start.elapsed();
+        println!("Verification time: {:?}", duration);
+        report
+    }
+
+    /// Neighborhoods in the order `verify_reachability` starts them, split
+    /// by the scheduler's cost limit.
+    pub fn plan_verification(&self) -> Vec<ScheduledNeighborhood> {
+        let neighborhoods = self
+            .metrics
+            .time(Phase::NeighborhoodPartition, || self.plan_neighborhoods());
+        let plan = self.metrics.time(Phase::Schedule, || {
+            self.scheduler
+                .plan(&self.network, &self.devices, neighborhoods)
+        });
+        self.metrics.set_neighborhood_count(plan.len());
+        plan
+    }
+
+    /// Verifies every neighborhood of `plan` with the configured engine and
+    /// returns their weighted reports in plan order.
+    pub fn verify_plan(&self, plan: &[ScheduledNeighborhood]) -> Vec<ReachabilityReport> {
+        match self.engine {
+            VerificationEngine::NpNet => self.verify_plan_with_npnet(plan),
+            VerificationEngine::Atomic => self.verify_plan_with_atoms(plan),
+        }
+    }
+
+    /// Same checks as `verify_plan_with_npnet`, on bitsets of atomic
+    /// predicates computed once over every port predicate.
+    fn verify_plan_with_atoms(&self, plan: &[ScheduledNeighborhood]) -> Vec<ReachabilityReport> {
+        if self.control.is_cancelled() {
+            return plan.iter().map(|_| ReachabilityReport::skipped()).collect();
+        }
+        let start = Instant::now();
+        let index = self.src_toponet.topology_index();
+        let atoms = self.metrics.time(Phase::Traversal, || {
+            AtomicPredicates::compute(
+                &index,
+                self.src_toponet.all_space_map["All"],
+                &self.src_toponet.map_device_packet_space_bdd,
+                &self.npbdd,
+            )
+        });
+        println!(
+            "Atomic predicates: {} atoms for {} predicates in {:?}",
+            atoms.atom_count(),
+            atoms.predicate_count(),
+            start.elapsed()
+        );
+        let reports = self.scheduler.run(plan, &self.control, |neighborhood| {
+            let report = self.metrics.time(Phase::Traversal, || {
+                atoms.verify(&index, neighborhood, &self.edge_devices)
+            });
+            self.metrics.add_traversal_count(report.traversal_count);
+            report.weighted(neighborhood.weight())
+        });
+        fill_skipped(reports)
+    }
+
+    fn verify_plan_with_npnet(&self, plan: &[ScheduledNeighborhood]) -> Vec<ReachabilityReport> {
+        if self.control.is_cancelled() {
+            return plan.iter().map(|_| ReachabilityReport::skipped()).collect();
+        }
+        let outer_summaries = self.build_outer_summaries(plan);
+        let reports = self.scheduler.run(plan, &self.control, |neighborhood| {
+            let (npnet, converged) = self.metrics.time(Phase::Traversal, || {
+                let mut npnet =
+                    NPNet::new_with_src_toponet(&self.src_toponet, neighborhood.clone());
+                npnet.set_parallel_frontier(self.parallel_frontier);
+                npnet.set_outer_summaries(outer_summaries.clone());
+                npnet.set_max_iterations(self.max_iterations);
+                let converged =
+                    npnet.iterative_traversal(TraversalType::Backward, InvariantType::Reachability);
+                (npnet, converged)
+            });
+            self.metrics
+                .add_traversal_count(npnet.get_traversal_count());
+            if self.iteration_stats {
+                print!(
+                    "{}:\n{}",
+                    neighborhood_label(neighborhood),
+                    npnet.iteration_summary()
+                );
             }
-            if let Some(cur_ports) = self
-                .src_toponet
-                .network
-                .get_device_ports()
-                .get(&current_device_name)
-            {
-                for cur_port in cur_ports {
-                    if let Some((neighbor_name, _)) = cur_port.get_peer_port() {
-                        if !*visited.get(neighbor_name).unwrap_or(&false) {
-                            let new_depth = if marked_nodes.contains_key(neighbor_name) {
-                                proximity_depth
-                            } else {
-                                depth - 1
-                            };
-                            queue.push((neighbor_name.clone(), new_depth));
-                        }
-                    }
+            if let Err(err) = converged {
+                println!("{}: {}", neighborhood_label(neighborhood), err);
+                return ReachabilityReport::skipped();
+            }
+            self.metrics
+                .time(Phase::Check, || {
+                    npnet.check_reachability(self.edge_devices.clone(), TraversalType::Backward)
+                })
+                .weighted(neighborhood.weight())
+        });
+        if let Some(cache) = &outer_summaries {
+            let (computed, applied) = cache.stats();
+            println!(
+                "Outer summaries: {} devices, {} computed, {} applied",
+                cache.region_len(),
+                computed,
+                applied
+            );
+        }
+        fill_skipped(reports)
+    }
+
+    /// The shared region is every device outside the inner areas of `plan`.
+    fn build_outer_summaries(
+        &self,
+        plan: &[ScheduledNeighborhood],
+    ) -> Option<Arc<OuterSummaryCache>> {
+        if !self.outer_summaries {
+            return None;
+        }
+        let index = self.src_toponet.topology_index();
+        let mut in_region = vec![true; index.len()];
+        for scheduled in plan {
+            let neighborhood = &scheduled.neighborhood;
+            let inner_nodes = neighborhood
+                .get_marked_nodes()
+                .keys()
+                .chain(neighborhood.get_normal_nodes().iter());
+            for device_name in inner_nodes {
+                if let Some(node) = index.index_of(device_name) {
+                    in_region[node] = false;
                 }
             }
         }
+        if !in_region.contains(&true) {
+            return None;
+        }
+        let mut is_observed = vec![false; index.len()];
+        for device_name in &self.edge_devices {
+            if let Some(node) = index.index_of(device_name) {
+                is_observed[node] = true;
+            }
+        }
+        Some(Arc::new(OuterSummaryCache::new(
+            index,
+            Arc::clone(&self.npbdd),
+            TraversalType::Backward,
+            in_region,
+            is_observed,
+        )))
+    }
+
+    /// `None` if either device is unknown.
+    pub fn query_reachability(
+        &self,
+        src_name: &str,
+        dst_name: &str,
+    ) -> Option<Result<bool, ConvergenceError>> {
+        if !self.devices.contains_key(src_name) {
+            return None;
+        }
+        let dst_device = self.devices.get(dst_name)?;
+        let space = *self.src_toponet.map_device_packet_space_bdd.get(dst_name)?;
+        let mut neighborhood = Neighborhood::new();
+        neighborhood.add_marked_node(PacketSpaceAwareDevice::new(
+            dst_name.to_string(),
+            space,
+            dst_device.device_id,
+        ));
+        let (npnet, converged) = self.metrics.time(Phase::Traversal, || {
+            let mut npnet = NPNet::new_with_src_toponet(&self.src_toponet, neighborhood);
+            npnet.set_parallel_frontier(self.parallel_frontier);
+            npnet.set_max_iterations(self.max_iterations);
+            let converged =
+                npnet.iterative_traversal(TraversalType::Backward, InvariantType::Reachability);
+            (npnet, converged)
+        });
+        self.metrics
+            .add_traversal_count(npnet.get_traversal_count());
+        if let Err(err) = converged {
+            return Some(Err(err));
+        }
+        self.metrics
+            .time(Phase::Check, || npnet.is_reachable(src_name, dst_name))
+            .map(Ok)
     }
 
-    pub fn verify_reachability_with_npnet(&self) {
+    /// Whether devices still hold their rules; a simulator restored from a
+    /// snapshot only has their encodings.
+    pub fn has_rules(&self) -> bool {
+        self.devices
+            .values()
+            .any(|device| !device.get_rules().is_empty())
+    }
+
+    /// Verifies every destination with `NPNet` and compares its verdicts
+    /// with `ReferenceForwarder`'s, for each edge device and packet-space
+    /// owner: on every address `ReferenceForwarder::sample_addresses` picks
+    /// from each of the owner's packet-space entries, with `spread` evenly
+    /// spaced ones per entry, and on the owner's space as a whole. Symmetry reduction is not applied.
+    pub fn cross_check(&self, spread: usize) -> CrossCheckReport {
         let start = Instant::now();
+        let forwarder = ReferenceForwarder::new(&self.devices, &self.network);
+        let mut sources: Vec<&String> = self.edge_devices.iter().collect();
+        sources.sort();
         let neighborhoods = self.find_neighborhood_from_subnet_space();
-        neighborhoods.par_iter().for_each(|neighborhood| {
-            let mut npnet = NPNet::new_with_src_toponet(&self.src_toponet, neighborhood.clone());
-            npnet.iterative_traversal(TraversalType::Backward, InvariantType::Reachability);
-            npnet.check_reachability(self.edge_devices.clone(), TraversalType::Backward);
-        });
-        let duration = start.elapsed();
-        println!("Verification time: {:?}", duration);
+        let mut report = neighborhoods
+            .par_iter()
+            .map(|neighborhood| {
+                if self.control.is_cancelled() {
+                    return CrossCheckReport::skipped();
+                }
+                let mut npnet =
+                    NPNet::new_with_src_toponet(&self.src_toponet, neighborhood.clone());
+                npnet.set_max_iterations(self.max_iterations);
+                if let Err(err) =
+                    npnet.iterative_traversal(TraversalType::Backward, InvariantType::Reachability)
+                {
+                    println!("{}: {}", neighborhood_label(neighborhood), err);
+                    return CrossCheckReport::skipped();
+                }
+                let mut report = CrossCheckReport::default();
+                for owner in neighborhood.get_marked_nodes().keys() {
+                    let packet_spaces = self.devices[owner].get_packet_space();
+                    if packet_spaces.is_empty() {
+                        continue;
+                    }
+                    let mut addresses: Vec<IpAddr> = packet_spaces
+                        .iter()
+                        .flat_map(|packet_space| {
+                            forwarder.sample_addresses(
+                                packet_space.get_ip(),
+                                packet_space.get_prefix_len(),
+                                spread,
+                            )
+                        })
+                        .collect();
+                    addresses.sort_unstable();
+                    addresses.dedup();
+                    let samples: Vec<(IpAddr, SpaceId)> = addresses
+                        .into_iter()
+                        .map(|address| {
+                            let width = if address.is_ipv4() { 32 } else { 128 };
+                            let space = self
+                                .npbdd
+                                .intern(self.npbdd.make_prefix_bdd(&address.to_string(), width));
+                            (address, space)
+                        })
+                        .collect();
+                    for &src in sources.iter().filter(|&&src| src != owner) {
+                        let mut delivers_all = true;
+                        for &(address, space) in &samples {
+                            let reference = forwarder.delivers(src, owner, address);
+                            let npnet_verdict = npnet.delivers(src, space).unwrap_or(false);
+                            delivers_all &= reference;
+                            report.addresses_checked += 1;
+                            if reference != npnet_verdict {
+                                report.disagreements.push(Disagreement {
+                                    src: src.clone(),
+                                    dst: owner.clone(),
+                                    address: Some(address),
+                                    npnet: npnet_verdict,
+                                    reference,
+                                });
+                            }
+                        }
+                        let npnet_verdict = npnet.is_reachable(src, owner).unwrap_or(false);
+                        report.pairs_checked += 1;
+                        if delivers_all != npnet_verdict {
+                            report.disagreements.push(Disagreement {
+                                src: src.clone(),
+                                dst: owner.clone(),
+                                address: None,
+                                npnet: npnet_verdict,
+                                reference: delivers_all,
+                            });
+                        }
+                    }
+                }
+                report
+            })
+            .reduce(CrossCheckReport::default, |mut acc, cur| {
+                acc += cur;
+                acc
+            });
+        report.disagreements.sort();
+        println!("Cross-check time: {:?}", start.elapsed());
+        report
+    }
+}
+
+/// Names a neighborhood in log lines by its size and first marked node.
+fn neighborhood_label(neighborhood: &Neighborhood) -> String {
+    let marked_nodes = neighborhood.get_marked_nodes();
+    format!(
+        "Neighborhood of {} marked nodes from {}",
+        marked_nodes.len(),
+        marked_nodes.keys().min().map_or("", String::as_str)
+    )
+}
+
+fn fill_skipped(reports: Vec<Option<ReachabilityReport>>) -> Vec<ReachabilityReport> {
+    reports
+        .into_iter()
+        .map(|report| report.unwrap_or_else(ReachabilityReport::skipped))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn common_prefix(prefixes: &[&str]) -> String {
+        let prefixes: Vec<String> = prefixes.iter().map(|prefix| prefix.to_string()).collect();
+        Simulator::find_common_prefix(&prefixes)
+    }
+
+    #[test]
+    fn common_prefix_of_packet_spaces() {
+        assert_eq!(common_prefix(&[]), "");
+        assert_eq!(common_prefix(&["10.0.1.0"]), "10.0.1.0");
+        assert_eq!(
+            common_prefix(&["10.0.1.0", "10.0.2.0", "10.0.3.0"]),
+            "10.0."
+        );
+        assert_eq!(common_prefix(&["10.0.1.0", "11.0.0.0"]), "1");
+        assert_eq!(common_prefix(&["10.0.0.0", "20.0.0.0"]), "");
+    }
+
+    #[test]
+    fn common_prefix_compares_characters_not_bits() {
+        // Rules are filtered with `starts_with`, so "10.0.1" also keeps
+        // rules for 10.0.100.0/24.
+        assert_eq!(common_prefix(&["10.0.1.0", "10.0.10.0"]), "10.0.1");
     }
 }
@@ -0,0 +1,145 @@
+//! Concrete IP addresses and longest-prefix match over route prefixes, shared
+//! by the fault injector's ground truth and the reference forwarder.
+
+use crate::util::hash_utils::HashMap;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+
+/// An address as its family and value, so prefixes of one family never
+/// match addresses of the other.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Address {
+    v6: bool,
+    value: u128,
+}
+
+impl Address {
+    pub fn new(address: IpAddr) -> Self {
+        match address {
+            IpAddr::V4(addr) => Address {
+                v6: false,
+                value: u32::from(addr) as u128,
+            },
+            IpAddr::V6(addr) => Address {
+                v6: true,
+                value: u128::from(addr),
+            },
+        }
+    }
+
+    pub fn parse(prefix: &str) -> Option<Self> {
+        prefix.parse().ok().map(Address::new)
+    }
+
+    /// Address width in bits: 32 or 128.
+    pub fn width(&self) -> usize {
+        if self.v6 {
+            128
+        } else {
+            32
+        }
+    }
+
+    pub fn value(&self) -> u128 {
+        self.value
+    }
+
+    /// The address of the same family with `value`.
+    pub fn with_value(&self, value: u128) -> Address {
+        Address { value, ..*self }
+    }
+
+    pub fn to_ip(self) -> IpAddr {
+        if self.v6 {
+            IpAddr::V6(Ipv6Addr::from(self.value))
+        } else {
+            IpAddr::V4(Ipv4Addr::from(self.value as u32))
+        }
+    }
+
+    /// First address of the `len`-bit prefix holding this one.
+    pub fn first(&self, len: usize) -> Address {
+        let host_bits = (self.width() - len.min(self.width())) as u32;
+        self.with_value(self.value & u128::MAX.checked_shl(host_bits).unwrap_or(0))
+    }
+
+    /// Last address of the `len`-bit prefix holding this one.
+    pub fn last(&self, len: usize) -> Address {
+        let host_bits = (self.width() - len.min(self.width())) as u32;
+        let host_mask = u128::MAX.checked_shr(128 - host_bits).unwrap_or(0);
+        self.with_value(self.first(len).value | host_mask)
+    }
+}
+
+/// Longest-prefix match over one device's prefixes, giving the position of
+/// the matching prefix. Among equal prefixes, the first one listed wins, as
+/// in the encoders.
+#[derive(Default)]
+pub struct PrefixTable {
+    /// Position by first address, per prefix length, longest first.
+    lengths: Vec<(usize, HashMap<Address, usize>)>,
+}
+
+impl PrefixTable {
+    /// Builds the table from (prefix, prefix length) pairs. Prefixes that do
+    /// not parse keep their position but never match.
+    pub fn new<'a>(prefixes: impl IntoIterator<Item = (&'a str, usize)>) -> Self {
+        let mut by_len: HashMap<usize, HashMap<Address, usize>> = HashMap::default();
+        for (position, (prefix, len)) in prefixes.into_iter().enumerate() {
+            let Some(address) = Address::parse(prefix) else {
+                continue;
+            };
+            by_len
+                .entry(len)
+                .or_default()
+                .entry(address.first(len))
+                .or_insert(position);
+        }
+        let mut lengths: Vec<(usize, HashMap<Address, usize>)> = by_len.into_iter().collect();
+        lengths.sort_by_key(|(len, _)| std::cmp::Reverse(*len));
+        PrefixTable { lengths }
+    }
+
+    pub fn lookup(&self, address: Address) -> Option<usize> {
+        self.lengths
+            .iter()
+            .filter(|(len, _)| *len <= address.width())
+            .find_map(|(len, prefixes)| prefixes.get(&address.first(*len)).copied())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn address(spec: &str) -> Address {
+        Address::parse(spec).unwrap()
+    }
+
+    #[test]
+    fn prefix_bounds_stay_within_the_family() {
+        let v4 = address("10.1.2.3");
+        assert_eq!(v4.first(16), address("10.1.0.0"));
+        assert_eq!(v4.last(16), address("10.1.255.255"));
+        assert_eq!(v4.first(0), address("0.0.0.0"));
+        assert_eq!(v4.last(0), address("255.255.255.255"));
+        assert_eq!(v4.last(32), v4);
+        let v6 = address("2001:db8::1");
+        assert_eq!(v6.first(32), address("2001:db8::"));
+        assert_eq!(v6.last(0).value(), u128::MAX);
+    }
+
+    #[test]
+    fn lookup_prefers_the_longest_then_the_first_prefix() {
+        let table = PrefixTable::new([
+            ("10.0.0.0", 8),
+            ("10.1.0.0", 16),
+            ("not an address", 24),
+            ("10.1.0.0", 16),
+            ("::", 0),
+        ]);
+        assert_eq!(table.lookup(address("10.1.2.3")), Some(1));
+        assert_eq!(table.lookup(address("10.2.0.0")), Some(0));
+        assert_eq!(table.lookup(address("11.0.0.0")), None);
+        assert_eq!(table.lookup(address("2001:db8::1")), Some(4));
+    }
+}
@@ -0,0 +1,242 @@
+use crate::util::hash_utils::DashMap;
+use biodivine_lib_bdd::{Bdd, BddNode};
+use crossbeam::sync::ShardedLock;
+use std::borrow::Borrow;
+use std::hash::Hash;
+use std::mem;
+use std::ops::Add;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// Approximate heap + inline footprint of a cache key or value, in bytes.
+pub trait CacheWeight {
+    fn weight(&self) -> usize;
+}
+
+impl CacheWeight for u32 {
+    fn weight(&self) -> usize {
+        mem::size_of::<u32>()
+    }
+}
+
+impl CacheWeight for usize {
+    fn weight(&self) -> usize {
+        mem::size_of::<usize>()
+    }
+}
+
+impl CacheWeight for String {
+    fn weight(&self) -> usize {
+        mem::size_of::<String>() + self.capacity()
+    }
+}
+
+impl CacheWeight for Vec<u32> {
+    fn weight(&self) -> usize {
+        mem::size_of::<Vec<u32>>() + self.capacity() * mem::size_of::<u32>()
+    }
+}
+
+impl CacheWeight for Bdd {
+    fn weight(&self) -> usize {
+        mem::size_of::<Bdd>() + self.size() * mem::size_of::<BddNode>()
+    }
+}
+
+impl<A: CacheWeight, B: CacheWeight> CacheWeight for (A, B) {
+    fn weight(&self) -> usize {
+        self.0.weight() + self.1.weight()
+    }
+}
+
+impl<A: CacheWeight, B: CacheWeight, C: CacheWeight> CacheWeight for (A, B, C) {
+    fn weight(&self) -> usize {
+        self.0.weight() + self.1.weight() + self.2.weight()
+    }
+}
+
+/// Size accounting for one cache level, or several caches summed together.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CacheLevelStats {
+    pub hits: usize,
+    pub misses: usize,
+    pub entries: usize,
+    pub bytes: usize,
+    pub limit_bytes: usize,
+    pub evicted: usize,
+}
+
+impl CacheLevelStats {
+    pub fn hit_rate(&self) -> f64 {
+        let lookups = self.hits + self.misses;
+        if lookups == 0 {
+            0.0
+        } else {
+            self.hits as f64 / lookups as f64
+        }
+    }
+}
+
+impl Add for CacheLevelStats {
+    type Output = Self;
+
+    fn add(self, other: Self) -> Self {
+        CacheLevelStats {
+            hits: self.hits + other.hits,
+            misses: self.misses + other.misses,
+            entries: self.entries + other.entries,
+            bytes: self.bytes + other.bytes,
+            limit_bytes: self.limit_bytes.saturating_add(other.limit_bytes),
+            evicted: self.evicted + other.evicted,
+        }
+    }
+}
+
+struct Generations<K: Eq + Hash, V> {
+    young: DashMap<K, V>,
+    old: DashMap<K, V>,
+}
+
+/// Concurrent memo table with a byte budget and two-generation eviction.
+///
+/// New entries go to the young generation. When it outgrows half of the
+/// budget, the old generation is dropped and the young one takes its place.
+/// Hits in the old generation are promoted, so recently used entries survive
+/// a rotation while cold ones are evicted.
+pub struct GenerationalCache<K: Eq + Hash, V> {
+    generations: ShardedLock<Generations<K, V>>,
+    limit_bytes: usize,
+    young_bytes: AtomicUsize,
+    old_bytes: AtomicUsize,
+    evicted: AtomicUsize,
+}
+
+impl<K, V> GenerationalCache<K, V>
+where
+    K: Eq + Hash + CacheWeight,
+    V: Clone + CacheWeight,
+{
+    pub fn new(limit_bytes: usize) -> Self {
+        GenerationalCache {
+            generations: ShardedLock::new(Generations {
+                young: DashMap::default(),
+                old: DashMap::default(),
+            }),
+            limit_bytes,
+            young_bytes: AtomicUsize::new(0),
+            old_bytes: AtomicUsize::new(0),
+            evicted: AtomicUsize::new(0),
+        }
+    }
+
+    pub fn get<Q>(&self, key: &Q) -> Option<V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        let promoted = {
+            let generations = self.generations.read().unwrap();
+            if let Some(value) = generations.young.get(key) {
+                return Some(value.clone());
+            }
+            let (old_key, value) = generations.old.remove(key)?;
+            let weight = old_key.weight() + value.weight();
+            self.old_bytes.fetch_sub(
+                weight.min(self.old_bytes.load(Ordering::Relaxed)),
+                Ordering::Relaxed,
+            );
+            generations.young.insert(old_key, value.clone());
+            self.young_bytes.fetch_add(weight, Ordering::Relaxed);
+            value
+        };
+        self.rotate_if_needed();
+        Some(promoted)
+    }
+
+    pub fn insert(&self, key: K, value: V) {
+        let weight = key.weight() + value.weight();
+        {
+            let generations = self.generations.read().unwrap();
+            if generations.young.insert(key, value).is_none() {
+                self.young_bytes.fetch_add(weight, Ordering::Relaxed);
+            }
+        }
+        self.rotate_if_needed();
+    }
+
+    fn rotate_if_needed(&self) {
+        if self.young_bytes.load(Ordering::Relaxed) <= self.limit_bytes / 2 {
+            return;
+        }
+        let mut generations = self.generations.write().unwrap();
+        // Another thread may have rotated while we waited for the lock.
+        if self.young_bytes.load(Ordering::Relaxed) <= self.limit_bytes / 2 {
+            return;
+        }
+        self.evicted
+            .fetch_add(generations.old.len(), Ordering::Relaxed);
+        generations.old = mem::take(&mut generations.young);
+        self.old_bytes.store(
+            self.young_bytes.swap(0, Ordering::Relaxed),
+            Ordering::Relaxed,
+        );
+    }
+
+    pub fn clear(&self) {
+        let mut generations = self.generations.write().unwrap();
+        self.evicted.fetch_add(
+            generations.young.len() + generations.old.len(),
+            Ordering::Relaxed,
+        );
+        generations.young = DashMap::default();
+        generations.old = DashMap::default();
+        self.young_bytes.store(0, Ordering::Relaxed);
+        self.old_bytes.store(0, Ordering::Relaxed);
+    }
+
+    pub fn len(&self) -> usize {
+        let generations = self.generations.read().unwrap();
+        generations.young.len() + generations.old.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn bytes(&self) -> usize {
+        self.young_bytes.load(Ordering::Relaxed) + self.old_bytes.load(Ordering::Relaxed)
+    }
+
+    pub fn limit_bytes(&self) -> usize {
+        self.limit_bytes
+    }
+
+    pub fn evicted(&self) -> usize {
+        self.evicted.load(Ordering::Relaxed)
+    }
+
+    /// Copies every live entry out of both generations.
+    pub fn entries(&self) -> Vec<(K, V)>
+    where
+        K: Clone,
+    {
+        let generations = self.generations.read().unwrap();
+        generations
+            .old
+            .iter()
+            .chain(generations.young.iter())
+            .map(|entry| (entry.key().clone(), entry.value().clone()))
+            .collect()
+    }
+
+    /// Entry and byte counts; hits and misses are tracked by the owning cache.
+    pub fn stats(&self) -> CacheLevelStats {
+        CacheLevelStats {
+            hits: 0,
+            misses: 0,
+            entries: self.len(),
+            bytes: self.bytes(),
+            limit_bytes: self.limit_bytes,
+            evicted: self.evicted(),
+        }
+    }
+}
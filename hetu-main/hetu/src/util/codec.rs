@@ -0,0 +1,187 @@
+use crate::util::packet_set::{PacketSet, PacketSpace};
+use std::io::{self, ErrorKind};
+
+/// Little-endian binary writer shared by the on-disk formats.
+///
+/// Every file starts with a 4-byte magic and a `u16` format version, see
+/// [`Encoder::with_header`] and [`Decoder::expect_header`].
+#[derive(Default)]
+pub struct Encoder {
+    buf: Vec<u8>,
+}
+
+impl Encoder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_header(magic: &[u8; 4], version: u16) -> Self {
+        let mut encoder = Self::new();
+        encoder.buf.extend_from_slice(magic);
+        encoder.put_u16(version);
+        encoder
+    }
+
+    pub fn put_u8(&mut self, value: u8) {
+        self.buf.push(value);
+    }
+
+    pub fn put_u16(&mut self, value: u16) {
+        self.buf.extend_from_slice(&value.to_le_bytes());
+    }
+
+    pub fn put_u32(&mut self, value: u32) {
+        self.buf.extend_from_slice(&value.to_le_bytes());
+    }
+
+    pub fn put_u64(&mut self, value: u64) {
+        self.buf.extend_from_slice(&value.to_le_bytes());
+    }
+
+    pub fn put_len(&mut self, len: usize) {
+        self.put_u64(len as u64);
+    }
+
+    pub fn put_bytes(&mut self, bytes: &[u8]) {
+        self.put_len(bytes.len());
+        self.buf.extend_from_slice(bytes);
+    }
+
+    pub fn put_str(&mut self, value: &str) {
+        self.put_bytes(value.as_bytes());
+    }
+
+    pub fn put_bdd(&mut self, bdd: &PacketSpace) {
+        self.put_bytes(&PacketSet::to_bytes(bdd));
+    }
+
+    pub fn put_u32_slice(&mut self, values: &[u32]) {
+        self.put_len(values.len());
+        for &value in values {
+            self.put_u32(value);
+        }
+    }
+
+    pub fn into_bytes(self) -> Vec<u8> {
+        self.buf
+    }
+}
+
+pub struct Decoder<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> Decoder<'a> {
+    pub fn new(data: &'a [u8]) -> Self {
+        Decoder { data }
+    }
+
+    /// Checks the magic and returns the format version that follows it.
+    pub fn expect_header(&mut self, magic: &[u8; 4]) -> io::Result<u16> {
+        if self.take(4)? != magic {
+            return Err(invalid_data("unrecognized file magic"));
+        }
+        self.get_u16()
+    }
+
+    fn take(&mut self, len: usize) -> io::Result<&'a [u8]> {
+        if self.data.len() < len {
+            return Err(io::Error::new(
+                ErrorKind::UnexpectedEof,
+                "truncated binary file",
+            ));
+        }
+        let (head, tail) = self.data.split_at(len);
+        self.data = tail;
+        Ok(head)
+    }
+
+    pub fn get_u8(&mut self) -> io::Result<u8> {
+        Ok(self.take(1)?[0])
+    }
+
+    pub fn get_u16(&mut self) -> io::Result<u16> {
+        Ok(u16::from_le_bytes(self.take(2)?.try_into().unwrap()))
+    }
+
+    pub fn get_u32(&mut self) -> io::Result<u32> {
+        Ok(u32::from_le_bytes(self.take(4)?.try_into().unwrap()))
+    }
+
+    pub fn get_u64(&mut self) -> io::Result<u64> {
+        Ok(u64::from_le_bytes(self.take(8)?.try_into().unwrap()))
+    }
+
+    pub fn get_len(&mut self) -> io::Result<usize> {
+        let len = self.get_u64()?;
+        // A length can never exceed what is left in the buffer; this also
+        // keeps a corrupt file from triggering a huge allocation.
+        if len > self.data.len() as u64 {
+            return Err(invalid_data("length exceeds remaining data"));
+        }
+        Ok(len as usize)
+    }
+
+    pub fn get_bytes(&mut self) -> io::Result<&'a [u8]> {
+        let len = self.get_len()?;
+        self.take(len)
+    }
+
+    pub fn get_string(&mut self) -> io::Result<String> {
+        let bytes = self.get_bytes()?;
+        String::from_utf8(bytes.to_vec()).map_err(|_| invalid_data("invalid UTF-8 string"))
+    }
+
+    pub fn get_bdd(&mut self) -> io::Result<PacketSpace> {
+        <PacketSpace as PacketSet>::from_bytes(self.get_bytes()?)
+    }
+
+    pub fn get_u32_vec(&mut self) -> io::Result<Vec<u32>> {
+        let len = self.get_len()?;
+        (0..len).map(|_| self.get_u32()).collect()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.data.is_empty()
+    }
+}
+
+pub fn invalid_data(message: &str) -> io::Error {
+    io::Error::new(ErrorKind::InvalidData, message.to_string())
+}
+
+/// Stable 64-bit FNV-1a digest, used to detect changed input files across runs.
+#[derive(Clone, Copy)]
+pub struct ContentHasher(u64);
+
+impl Default for ContentHasher {
+    fn default() -> Self {
+        ContentHasher(0xcbf2_9ce4_8422_2325)
+    }
+}
+
+impl ContentHasher {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn update(&mut self, bytes: &[u8]) -> &mut Self {
+        for &byte in bytes {
+            self.0 ^= byte as u64;
+            self.0 = self.0.wrapping_mul(0x0100_0000_01b3);
+        }
+        self
+    }
+
+    pub fn update_u64(&mut self, value: u64) -> &mut Self {
+        self.update(&value.to_le_bytes())
+    }
+
+    pub fn finish(&self) -> u64 {
+        self.0
+    }
+}
+
+pub fn content_hash(bytes: &[u8]) -> u64 {
+    ContentHasher::new().update(bytes).finish()
+}
@@ -0,0 +1,199 @@
+use crate::util::codec::{invalid_data, Decoder, Encoder};
+use crate::util::hash_utils::HashMap;
+use crate::util::npbdd::{L3Entries, NPBDD};
+use crate::util::packet_set::PacketSpace;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+const MAGIC: &[u8; 4] = b"HTEC";
+const VERSION: u16 = 2;
+
+/// Per-device output of rule encoding: port to space-id assignment, the
+/// space-port predicates and the union of everything the device forwards.
+#[derive(Clone)]
+pub struct EncodedDevice {
+    pub port_space_ids: Vec<(String, i8)>,
+    pub space_ports: Vec<(i8, PacketSpace)>,
+    pub forwardable_space: PacketSpace,
+}
+
+impl EncodedDevice {
+    pub fn write(&self, encoder: &mut Encoder) {
+        encoder.put_len(self.port_space_ids.len());
+        for (port, space_id) in self.port_space_ids.iter() {
+            encoder.put_str(port);
+            encoder.put_u8(*space_id as u8);
+        }
+        encoder.put_len(self.space_ports.len());
+        for (space_id, space) in self.space_ports.iter() {
+            encoder.put_u8(*space_id as u8);
+            encoder.put_bdd(space);
+        }
+        encoder.put_bdd(&self.forwardable_space);
+    }
+
+    pub fn read(decoder: &mut Decoder) -> io::Result<Self> {
+        let port_count = decoder.get_len()?;
+        let mut port_space_ids = Vec::with_capacity(port_count);
+        for _ in 0..port_count {
+            port_space_ids.push((decoder.get_string()?, decoder.get_u8()? as i8));
+        }
+        let space_count = decoder.get_len()?;
+        let mut space_ports = Vec::with_capacity(space_count);
+        for _ in 0..space_count {
+            space_ports.push((decoder.get_u8()? as i8, decoder.get_bdd()?));
+        }
+        Ok(EncodedDevice {
+            port_space_ids,
+            space_ports,
+            forwardable_space: decoder.get_bdd()?,
+        })
+    }
+}
+
+/// Encoding results persisted between runs.
+///
+/// `context_hash` covers everything shared by all devices (packet space, IP
+/// width, edge and destination devices). Each device entry carries its own
+/// key, derived from its route file and its topology ports; a device is
+/// reused only if both match.
+#[derive(Default)]
+pub struct EncodingCache {
+    pub context_hash: u64,
+    pub devices: HashMap<String, (u64, EncodedDevice)>,
+    pub table: Vec<(u32, PacketSpace)>,
+    pub l3: L3Entries,
+}
+
+impl EncodingCache {
+    pub fn new(context_hash: u64) -> Self {
+        EncodingCache {
+            context_hash,
+            ..Default::default()
+        }
+    }
+
+    /// Captures the BDD table and L3 caches so ids stay valid on reload.
+    pub fn capture_npbdd(&mut self, npbdd: &NPBDD) {
+        self.table = npbdd.table().entries();
+        self.l3 = npbdd.cache().export_l3();
+    }
+
+    /// Seeds an `NPBDD` with the persisted table and L3 caches. Persisted ids
+    /// are remapped to the ids the sets get in `npbdd`, which may already hold
+    /// some of them. Cache entries that mention an id missing from the table
+    /// are dropped.
+    pub fn restore_npbdd(&mut self, npbdd: &NPBDD) {
+        let mut id_map: HashMap<u32, u32> = HashMap::default();
+        for (id, bdd) in std::mem::take(&mut self.table) {
+            id_map.insert(id, npbdd.intern(bdd));
+        }
+        let l3 = std::mem::take(&mut self.l3);
+        let remap = |id: &u32| id_map.get(id).copied();
+        let pair = |((a, b), id): ((u32, u32), u32)| Some(((remap(&a)?, remap(&b)?), remap(&id)?));
+        npbdd.cache().import_l3(L3Entries {
+            make: l3
+                .make
+                .into_iter()
+                .filter_map(|(key, id)| Some((key, remap(&id)?)))
+                .collect(),
+            and: l3.and.into_iter().filter_map(pair).collect(),
+            or: l3.or.into_iter().filter_map(pair).collect(),
+            not: l3
+                .not
+                .into_iter()
+                .filter_map(|(a, id)| Some((remap(&a)?, remap(&id)?)))
+                .collect(),
+        });
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> io::Result<Self> {
+        let data = fs::read(path)?;
+        let mut decoder = Decoder::new(&data);
+        if decoder.expect_header(MAGIC)? != VERSION {
+            return Err(invalid_data("unsupported encoding cache version"));
+        }
+        let mut cache = EncodingCache::new(decoder.get_u64()?);
+
+        let device_count = decoder.get_len()?;
+        for _ in 0..device_count {
+            let name = decoder.get_string()?;
+            let key = decoder.get_u64()?;
+            cache
+                .devices
+                .insert(name, (key, EncodedDevice::read(&mut decoder)?));
+        }
+
+        let table_count = decoder.get_len()?;
+        for _ in 0..table_count {
+            cache.table.push((decoder.get_u32()?, decoder.get_bdd()?));
+        }
+
+        let make_count = decoder.get_len()?;
+        for _ in 0..make_count {
+            cache
+                .l3
+                .make
+                .push((decoder.get_string()?, decoder.get_u32()?));
+        }
+        for entries in [&mut cache.l3.and, &mut cache.l3.or] {
+            let count = decoder.get_len()?;
+            for _ in 0..count {
+                let key = (decoder.get_u32()?, decoder.get_u32()?);
+                entries.push((key, decoder.get_u32()?));
+            }
+        }
+        let not_count = decoder.get_len()?;
+        for _ in 0..not_count {
+            cache.l3.not.push((decoder.get_u32()?, decoder.get_u32()?));
+        }
+        if !decoder.is_empty() {
+            return Err(invalid_data("trailing data after encoding cache"));
+        }
+        Ok(cache)
+    }
+
+    /// Writes to a temporary file first so a crash never leaves a torn cache.
+    pub fn save(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        let mut encoder = Encoder::with_header(MAGIC, VERSION);
+        encoder.put_u64(self.context_hash);
+
+        encoder.put_len(self.devices.len());
+        for (name, (key, device)) in self.devices.iter() {
+            encoder.put_str(name);
+            encoder.put_u64(*key);
+            device.write(&mut encoder);
+        }
+
+        encoder.put_len(self.table.len());
+        for (id, bdd) in self.table.iter() {
+            encoder.put_u32(*id);
+            encoder.put_bdd(bdd);
+        }
+
+        encoder.put_len(self.l3.make.len());
+        for (key, id) in self.l3.make.iter() {
+            encoder.put_str(key);
+            encoder.put_u32(*id);
+        }
+        for entries in [&self.l3.and, &self.l3.or] {
+            encoder.put_len(entries.len());
+            for ((a, b), id) in entries.iter() {
+                encoder.put_u32(*a);
+                encoder.put_u32(*b);
+                encoder.put_u32(*id);
+            }
+        }
+        encoder.put_len(self.l3.not.len());
+        for (key, id) in self.l3.not.iter() {
+            encoder.put_u32(*key);
+            encoder.put_u32(*id);
+        }
+
+        let path = path.as_ref();
+        let tmp_path = path.with_extension("tmp");
+        fs::write(&tmp_path, encoder.into_bytes())?;
+        fs::rename(tmp_path, path)
+    }
+}
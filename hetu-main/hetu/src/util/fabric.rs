@@ -0,0 +1,483 @@
+use crate::util::network::Topology;
+use serde::Deserialize;
+use serde_json::json;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+/// Subnets are /24s allocated in order from 10.0.0.0/8.
+const SUBNET_PREFIX_LEN: u8 = 24;
+const MAX_SUBNETS: usize = 1 << 16;
+
+/// Shape of a three-tier Clos fabric. Every pod has `tors_per_pod` ToRs
+/// (`pP_S0_I`) fully meshed with `aggs_per_pod` aggregation switches
+/// (`pP_S1_J`), and aggregation switch `J` of every pod connects to the
+/// `spines_per_plane` spines of plane `J` (`core_N`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ClosSpec {
+    pub pods: usize,
+    pub tors_per_pod: usize,
+    pub aggs_per_pod: usize,
+    pub spines_per_plane: usize,
+    pub subnets_per_tor: usize,
+}
+
+impl ClosSpec {
+    /// The k-ary fat tree: k pods of k/2 ToRs and k/2 aggregation switches,
+    /// and (k/2)^2 spines.
+    pub fn fat_tree(k: usize) -> Result<Self, String> {
+        if k < 2 || !k.is_multiple_of(2) {
+            return Err(format!("fat-tree k must be even and at least 2, got {}", k));
+        }
+        ClosSpec {
+            pods: k,
+            tors_per_pod: k / 2,
+            aggs_per_pod: k / 2,
+            spines_per_plane: k / 2,
+            subnets_per_tor: 1,
+        }
+        .validated()
+    }
+
+    /// Parses `PODS,TORS,AGGS,SPINES`, where `SPINES` counts the spines of
+    /// one plane.
+    pub fn parse(value: &str) -> Result<Self, String> {
+        let counts: Vec<usize> = value
+            .split(',')
+            .map(|part| part.trim().parse())
+            .collect::<Result<_, _>>()
+            .map_err(|_| format!("invalid Clos spec: {}", value))?;
+        let [pods, tors_per_pod, aggs_per_pod, spines_per_plane] = counts[..] else {
+            return Err(format!(
+                "Clos spec needs PODS,TORS,AGGS,SPINES, got {}",
+                value
+            ));
+        };
+        ClosSpec {
+            pods,
+            tors_per_pod,
+            aggs_per_pod,
+            spines_per_plane,
+            subnets_per_tor: 1,
+        }
+        .validated()
+    }
+
+    pub fn with_subnets_per_tor(mut self, subnets_per_tor: usize) -> Result<Self, String> {
+        self.subnets_per_tor = subnets_per_tor;
+        self.validated()
+    }
+
+    fn validated(self) -> Result<Self, String> {
+        if self.pods == 0
+            || self.tors_per_pod == 0
+            || self.aggs_per_pod == 0
+            || self.spines_per_plane == 0
+            || self.subnets_per_tor == 0
+        {
+            return Err("every Clos dimension must be at least 1".to_string());
+        }
+        if self.subnet_count() > MAX_SUBNETS {
+            return Err(format!(
+                "{} subnets do not fit in 10.0.0.0/8 as /{}s",
+                self.subnet_count(),
+                SUBNET_PREFIX_LEN
+            ));
+        }
+        Ok(self)
+    }
+
+    pub fn subnet_count(&self) -> usize {
+        self.pods * self.tors_per_pod * self.subnets_per_tor
+    }
+
+    pub fn device_count(&self) -> usize {
+        self.pods * (self.tors_per_pod + self.aggs_per_pod)
+            + self.aggs_per_pod * self.spines_per_plane
+    }
+
+    /// Builds the fabric with shortest-path ECMP FIBs: ToRs send foreign
+    /// subnets to every aggregation switch of their pod, aggregation switches
+    /// send other pods' subnets to every spine of their plane, and spines
+    /// send each pod's subnets down to it.
+    pub fn build(&self) -> Fabric {
+        let mut fabric = Fabric::default();
+        // (pod, tor) of every subnet, in subnet order.
+        let mut locations: Vec<(usize, usize)> = Vec::with_capacity(self.subnet_count());
+        for pod in 0..self.pods {
+            for tor in 0..self.tors_per_pod {
+                for _ in 0..self.subnets_per_tor {
+                    let id = locations.len();
+                    fabric.subnets.push(Subnet {
+                        prefix: format!("10.{}.{}.0", id >> 8, id & 0xff),
+                        prefix_len: SUBNET_PREFIX_LEN,
+                        host: tor_name(pod, tor),
+                    });
+                    locations.push((pod, tor));
+                }
+            }
+        }
+        let agg_ports: Vec<String> = (0..self.aggs_per_pod).map(|j| format!("up{}", j)).collect();
+        let spine_ports: Vec<String> = (0..self.spines_per_plane)
+            .map(|s| format!("up{}", s))
+            .collect();
+
+        for pod in 0..self.pods {
+            for tor in 0..self.tors_per_pod {
+                let name = tor_name(pod, tor);
+                for agg in 0..self.aggs_per_pod {
+                    fabric.links.push(link(
+                        &name,
+                        &format!("up{}", agg),
+                        &agg_name(pod, agg),
+                        &format!("down{}", tor),
+                    ));
+                }
+                let routes = fabric
+                    .subnets
+                    .iter()
+                    .map(|subnet| {
+                        if subnet.host == name {
+                            subnet.route(vec!["host0".to_string()])
+                        } else {
+                            subnet.route(agg_ports.clone())
+                        }
+                    })
+                    .collect();
+                fabric.routes.push((name, routes));
+                fabric.edge_devices.push(tor_name(pod, tor));
+            }
+            for agg in 0..self.aggs_per_pod {
+                let name = agg_name(pod, agg);
+                for spine in 0..self.spines_per_plane {
+                    fabric.links.push(link(
+                        &name,
+                        &format!("up{}", spine),
+                        &spine_name(agg * self.spines_per_plane + spine),
+                        &format!("pod{}", pod),
+                    ));
+                }
+                let routes = fabric
+                    .subnets
+                    .iter()
+                    .zip(&locations)
+                    .map(|(subnet, &(subnet_pod, subnet_tor))| {
+                        if subnet_pod == pod {
+                            subnet.route(vec![format!("down{}", subnet_tor)])
+                        } else {
+                            subnet.route(spine_ports.clone())
+                        }
+                    })
+                    .collect();
+                fabric.routes.push((name, routes));
+            }
+        }
+        for spine in 0..self.aggs_per_pod * self.spines_per_plane {
+            let routes = fabric
+                .subnets
+                .iter()
+                .zip(&locations)
+                .map(|(subnet, &(subnet_pod, _))| subnet.route(vec![format!("pod{}", subnet_pod)]))
+                .collect();
+            fabric.routes.push((spine_name(spine), routes));
+        }
+        fabric
+    }
+}
+
+pub fn tor_name(pod: usize, tor: usize) -> String {
+    format!("p{}_S0_{}", pod, tor)
+}
+
+pub fn agg_name(pod: usize, agg: usize) -> String {
+    format!("p{}_S1_{}", pod, agg)
+}
+
+pub fn spine_name(spine: usize) -> String {
+    format!("core_{}", spine)
+}
+
+fn link(src_node: &str, src_port: &str, dst_node: &str, dst_port: &str) -> Topology {
+    Topology {
+        src_node: src_node.to_string(),
+        src_port: src_port.to_string(),
+        dst_node: dst_node.to_string(),
+        dst_port: dst_port.to_string(),
+    }
+}
+
+/// A destination subnet and the ToR that owns it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Subnet {
+    pub prefix: String,
+    pub prefix_len: u8,
+    pub host: String,
+}
+
+impl Subnet {
+    pub fn route(&self, next_hops: Vec<String>) -> FabricRoute {
+        FabricRoute {
+            prefix: self.prefix.clone(),
+            prefix_len: self.prefix_len,
+            next_hops,
+        }
+    }
+}
+
+/// One FIB entry; an empty next-hop list drops the prefix.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FabricRoute {
+    pub prefix: String,
+    pub prefix_len: u8,
+    pub next_hops: Vec<String>,
+}
+
+/// Route file entry, with the same aliases the verifier accepts.
+#[derive(Deserialize)]
+struct RouteRecord {
+    #[serde(alias = "ip")]
+    prefix: String,
+    prefix_len: u8,
+    #[serde(alias = "ports")]
+    nexthop_infs: Vec<String>,
+}
+
+#[derive(Deserialize)]
+struct SubnetRecord {
+    prefix: String,
+    prefix_len: u8,
+    host_name: String,
+}
+
+/// A dataset in the layout `Simulator::load` reads.
+#[derive(Debug, Clone, Default)]
+pub struct Fabric {
+    /// FIB of every device, ToRs and aggregation switches pod by pod, then
+    /// the spines.
+    pub routes: Vec<(String, Vec<FabricRoute>)>,
+    pub links: Vec<Topology>,
+    pub edge_devices: Vec<String>,
+    pub subnets: Vec<Subnet>,
+}
+
+impl Fabric {
+    /// Reads a dataset in the layout `write` produces, generated or not.
+    /// Devices are sorted by name.
+    pub fn load(dir: impl AsRef<Path>) -> io::Result<Self> {
+        let dir = dir.as_ref();
+        let mut routes = Vec::new();
+        for entry in fs::read_dir(dir.join("routes"))? {
+            let entry = entry?;
+            let records: Vec<RouteRecord> = serde_json::from_slice(&fs::read(entry.path())?)?;
+            routes.push((
+                entry.file_name().to_string_lossy().into_owned(),
+                records
+                    .into_iter()
+                    .map(|record| FabricRoute {
+                        prefix: record.prefix,
+                        prefix_len: record.prefix_len,
+                        next_hops: record.nexthop_infs,
+                    })
+                    .collect(),
+            ));
+        }
+        routes.sort_by(|(a, _), (b, _)| a.cmp(b));
+        let links = serde_json::from_slice(&fs::read(dir.join("topology.json"))?)?;
+        let edge_devices = fs::read_to_string(dir.join("edge_devices"))?
+            .lines()
+            .filter(|line| !line.is_empty())
+            .map(str::to_string)
+            .collect();
+        let subnets: Vec<SubnetRecord> =
+            serde_json::from_slice(&fs::read(dir.join("packet_space.json"))?)?;
+        Ok(Fabric {
+            routes,
+            links,
+            edge_devices,
+            subnets: subnets
+                .into_iter()
+                .map(|record| Subnet {
+                    prefix: record.prefix,
+                    prefix_len: record.prefix_len,
+                    host: record.host_name,
+                })
+                .collect(),
+        })
+    }
+
+    pub fn routes(&self, device_name: &str) -> Option<&Vec<FabricRoute>> {
+        self.routes
+            .iter()
+            .find(|(name, _)| name == device_name)
+            .map(|(_, routes)| routes)
+    }
+
+    pub fn routes_mut(&mut self, device_name: &str) -> Option<&mut Vec<FabricRoute>> {
+        self.routes
+            .iter_mut()
+            .find(|(name, _)| name == device_name)
+            .map(|(_, routes)| routes)
+    }
+
+    /// Writes `routes/`, `topology.json`, `edge_devices` and
+    /// `packet_space.json` into `dir`. Devices are read from the route
+    /// files, so a non-empty `routes/` is refused rather than mixed with
+    /// another fabric.
+    pub fn write(&self, dir: impl AsRef<Path>) -> io::Result<()> {
+        let dir = dir.as_ref();
+        let routes_dir = dir.join("routes");
+        fs::create_dir_all(&routes_dir)?;
+        if fs::read_dir(&routes_dir)?.next().is_some() {
+            return Err(io::Error::new(
+                io::ErrorKind::AlreadyExists,
+                format!("{} is not empty", routes_dir.display()),
+            ));
+        }
+        for (device_name, routes) in &self.routes {
+            let records: Vec<_> = routes
+                .iter()
+                .map(|route| {
+                    json!({
+                        "action": if route.next_hops.is_empty() { "drop" } else { "fwd" },
+                        "prefix": route.prefix,
+                        "prefix_len": route.prefix_len,
+                        "nexthop_infs": route.next_hops,
+                    })
+                })
+                .collect();
+            fs::write(routes_dir.join(device_name), serde_json::to_vec(&records)?)?;
+        }
+        fs::write(dir.join("topology.json"), serde_json::to_vec(&self.links)?)?;
+        let mut edge_devices = self.edge_devices.join("\n");
+        edge_devices.push('\n');
+        fs::write(dir.join("edge_devices"), edge_devices)?;
+        let packet_space: Vec<_> = self
+            .subnets
+            .iter()
+            .map(|subnet| {
+                json!({
+                    "prefix": subnet.prefix,
+                    "prefix_len": subnet.prefix_len,
+                    "host_name": subnet.host,
+                })
+            })
+            .collect();
+        fs::write(
+            dir.join("packet_space.json"),
+            serde_json::to_vec(&packet_space)?,
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn next_hops<'a>(fabric: &'a Fabric, device: &str, prefix: &str) -> &'a [String] {
+        let (_, routes) = fabric
+            .routes
+            .iter()
+            .find(|(name, _)| name == device)
+            .unwrap();
+        &routes
+            .iter()
+            .find(|route| route.prefix == prefix)
+            .unwrap()
+            .next_hops
+    }
+
+    #[test]
+    fn specs_reject_empty_or_odd_shapes() {
+        assert!(ClosSpec::fat_tree(3).is_err());
+        assert!(ClosSpec::fat_tree(0).is_err());
+        assert!(ClosSpec::parse("2,2,2").is_err());
+        assert!(ClosSpec::parse("2,0,2,2").is_err());
+        assert!(ClosSpec::parse("2,x,2,2").is_err());
+        let spec = ClosSpec::parse("2, 3,4,5").unwrap();
+        assert!(spec.with_subnets_per_tor(0).is_err());
+        assert!(ClosSpec::parse("256,256,1,1").is_ok());
+        assert!(ClosSpec::parse("257,256,1,1").is_err());
+    }
+
+    #[test]
+    fn fat_tree_has_the_k_ary_shape() {
+        let spec = ClosSpec::fat_tree(4).unwrap();
+        assert_eq!(spec.device_count(), 20);
+        let fabric = spec.build();
+        assert_eq!(fabric.routes.len(), 20);
+        assert_eq!(fabric.edge_devices.len(), 8);
+        assert_eq!(fabric.subnets.len(), 8);
+        // ToR-agg and agg-spine links, one direction each.
+        assert_eq!(fabric.links.len(), 32);
+        for (device, routes) in &fabric.routes {
+            assert_eq!(routes.len(), 8, "{}", device);
+        }
+    }
+
+    #[test]
+    fn subnets_are_numbered_across_pods_and_tors() {
+        let fabric = ClosSpec::parse("2,2,1,1")
+            .unwrap()
+            .with_subnets_per_tor(2)
+            .unwrap()
+            .build();
+        let subnets: Vec<(&str, &str)> = fabric
+            .subnets
+            .iter()
+            .map(|subnet| (subnet.prefix.as_str(), subnet.host.as_str()))
+            .collect();
+        assert_eq!(
+            subnets,
+            vec![
+                ("10.0.0.0", "p0_S0_0"),
+                ("10.0.1.0", "p0_S0_0"),
+                ("10.0.2.0", "p0_S0_1"),
+                ("10.0.3.0", "p0_S0_1"),
+                ("10.0.4.0", "p1_S0_0"),
+                ("10.0.5.0", "p1_S0_0"),
+                ("10.0.6.0", "p1_S0_1"),
+                ("10.0.7.0", "p1_S0_1"),
+            ]
+        );
+    }
+
+    #[test]
+    fn routes_follow_the_shortest_paths() {
+        let fabric = ClosSpec::parse("2,2,2,2").unwrap().build();
+        let local = "10.0.0.0";
+        let same_pod = "10.0.1.0";
+        let other_pod = "10.0.2.0";
+        assert_eq!(next_hops(&fabric, "p0_S0_0", local), ["host0"]);
+        assert_eq!(next_hops(&fabric, "p0_S0_0", same_pod), ["up0", "up1"]);
+        assert_eq!(next_hops(&fabric, "p0_S1_1", same_pod), ["down1"]);
+        assert_eq!(next_hops(&fabric, "p0_S1_1", other_pod), ["up0", "up1"]);
+        assert_eq!(next_hops(&fabric, "core_3", other_pod), ["pod1"]);
+
+        // Aggregation switch 1 of pod 1 reaches the spines of plane 1.
+        let planes: Vec<&Topology> = fabric
+            .links
+            .iter()
+            .filter(|link| link.src_node == "p1_S1_1")
+            .collect();
+        assert_eq!(planes.len(), 2);
+        assert_eq!(planes[0].dst_node, "core_2");
+        assert_eq!(planes[1].dst_node, "core_3");
+        assert!(planes.iter().all(|link| link.dst_port == "pod1"));
+    }
+
+    #[test]
+    fn write_refuses_a_non_empty_routes_directory() {
+        let dir = std::env::temp_dir().join(format!("hetu-fabric-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        let fabric = ClosSpec::fat_tree(2).unwrap().build();
+        fabric.write(&dir).unwrap();
+        let written = fs::read_dir(dir.join("routes")).unwrap().count();
+        let rewritten = fabric.write(&dir);
+        fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(written, fabric.routes.len());
+        assert_eq!(
+            rewritten.map_err(|err| err.kind()),
+            Err(io::ErrorKind::AlreadyExists)
+        );
+    }
+}
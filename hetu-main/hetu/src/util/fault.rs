@@ -0,0 +1,738 @@
+use crate::util::address::{Address, PrefixTable};
+use crate::util::fabric::{Fabric, FabricRoute};
+use crate::util::hash_utils::{HashMap, HashSet};
+use rayon::prelude::*;
+use serde::Serialize;
+use std::collections::VecDeque;
+use std::fmt;
+use std::str::FromStr;
+
+/// Attempts at finding a target before a fault kind is given up on.
+const MAX_ATTEMPTS: usize = 1000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum FaultKind {
+    /// Removes one route, so the device drops the prefix.
+    DeleteRoute,
+    /// Sends a prefix out of a single port it should not use.
+    WrongPort,
+    /// Points a prefix across a link in both directions.
+    Loop,
+    /// Adds a more-specific route for half of a subnet towards a wrong port.
+    Hijack,
+    /// Removes a link from the topology.
+    RemoveLink,
+}
+
+impl FaultKind {
+    pub const ALL: [FaultKind; 5] = [
+        FaultKind::DeleteRoute,
+        FaultKind::WrongPort,
+        FaultKind::Loop,
+        FaultKind::Hijack,
+        FaultKind::RemoveLink,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            FaultKind::DeleteRoute => "delete-route",
+            FaultKind::WrongPort => "wrong-port",
+            FaultKind::Loop => "loop",
+            FaultKind::Hijack => "hijack",
+            FaultKind::RemoveLink => "remove-link",
+        }
+    }
+}
+
+impl fmt::Display for FaultKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for FaultKind {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        FaultKind::ALL
+            .into_iter()
+            .find(|kind| kind.name() == value)
+            .ok_or_else(|| {
+                format!(
+                    "unknown fault kind {} (expected delete-route, wrong-port, loop, hijack or remove-link)",
+                    value
+                )
+            })
+    }
+}
+
+/// One change to a dataset.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "edit", rename_all = "kebab-case")]
+pub enum Edit {
+    /// Replaces the next hops of a route, adding the route if it is missing.
+    SetRoute {
+        device: String,
+        prefix: String,
+        prefix_len: u8,
+        next_hops: Vec<String>,
+    },
+    RemoveRoute {
+        device: String,
+        prefix: String,
+        prefix_len: u8,
+    },
+    RemoveLink {
+        src_node: String,
+        src_port: String,
+        dst_node: String,
+        dst_port: String,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Fault {
+    pub kind: FaultKind,
+    pub description: String,
+    pub edits: Vec<Edit>,
+}
+
+impl Fault {
+    pub fn apply(&self, fabric: &mut Fabric) {
+        for edit in &self.edits {
+            match edit {
+                Edit::SetRoute {
+                    device,
+                    prefix,
+                    prefix_len,
+                    next_hops,
+                } => {
+                    let routes = match fabric.routes_mut(device) {
+                        Some(routes) => routes,
+                        None => {
+                            fabric.routes.push((device.clone(), Vec::new()));
+                            &mut fabric.routes.last_mut().unwrap().1
+                        }
+                    };
+                    match routes
+                        .iter_mut()
+                        .find(|route| route.prefix == *prefix && route.prefix_len == *prefix_len)
+                    {
+                        Some(route) => route.next_hops = next_hops.clone(),
+                        None => routes.push(FabricRoute {
+                            prefix: prefix.clone(),
+                            prefix_len: *prefix_len,
+                            next_hops: next_hops.clone(),
+                        }),
+                    }
+                }
+                Edit::RemoveRoute {
+                    device,
+                    prefix,
+                    prefix_len,
+                } => {
+                    if let Some(routes) = fabric.routes_mut(device) {
+                        routes.retain(|route| {
+                            route.prefix != *prefix || route.prefix_len != *prefix_len
+                        });
+                    }
+                }
+                Edit::RemoveLink {
+                    src_node,
+                    src_port,
+                    dst_node,
+                    dst_port,
+                } => fabric.links.retain(|link| {
+                    link.src_node != *src_node
+                        || link.src_port != *src_port
+                        || link.dst_node != *dst_node
+                        || link.dst_port != *dst_port
+                }),
+            }
+        }
+    }
+}
+
+/// Picks fault targets from `fabric` with a seeded generator, so the same
+/// seed always yields the same faults. Routes of a subnet at its own owner
+/// are never touched, since packets count as delivered once they get there.
+pub struct FaultInjector<'a> {
+    fabric: &'a Fabric,
+    ports: HashMap<&'a str, Vec<&'a str>>,
+    owners: HashSet<(&'a str, &'a str, u8)>,
+    touched: HashSet<(String, String)>,
+    state: u64,
+}
+
+impl<'a> FaultInjector<'a> {
+    pub fn new(fabric: &'a Fabric, seed: u64) -> Self {
+        let mut ports: HashMap<&str, Vec<&str>> = HashMap::default();
+        for link in &fabric.links {
+            ports
+                .entry(link.src_node.as_str())
+                .or_default()
+                .push(link.src_port.as_str());
+            ports
+                .entry(link.dst_node.as_str())
+                .or_default()
+                .push(link.dst_port.as_str());
+        }
+        let owners = fabric
+            .subnets
+            .iter()
+            .map(|subnet| {
+                (
+                    subnet.host.as_str(),
+                    subnet.prefix.as_str(),
+                    subnet.prefix_len,
+                )
+            })
+            .collect();
+        FaultInjector {
+            fabric,
+            ports,
+            owners,
+            touched: HashSet::default(),
+            state: seed,
+        }
+    }
+
+    /// SplitMix64.
+    fn next(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    fn pick<'b, T>(&mut self, items: &'b [T]) -> Option<&'b T> {
+        if items.is_empty() {
+            return None;
+        }
+        let index = (self.next() % items.len() as u64) as usize;
+        Some(&items[index])
+    }
+
+    /// A fault of `kind` on a device and prefix no earlier fault touched.
+    pub fn inject(&mut self, kind: FaultKind) -> Result<Fault, String> {
+        for _ in 0..MAX_ATTEMPTS {
+            let fault = match kind {
+                FaultKind::DeleteRoute => self.delete_route(),
+                FaultKind::WrongPort => self.wrong_port(),
+                FaultKind::Loop => self.make_loop(),
+                FaultKind::Hijack => self.hijack(),
+                FaultKind::RemoveLink => self.remove_link(),
+            };
+            let Some(fault) = fault else {
+                continue;
+            };
+            let keys: Vec<(String, String)> = fault.edits.iter().map(edit_key).collect();
+            if keys.iter().any(|key| self.touched.contains(key)) {
+                continue;
+            }
+            self.touched.extend(keys);
+            return Ok(fault);
+        }
+        Err(format!("found no target for a {} fault", kind))
+    }
+
+    /// A routed device and one of its routes that is not its own subnet.
+    fn pick_route(&mut self) -> Option<(&'a str, &'a FabricRoute)> {
+        let fabric = self.fabric;
+        let (device, routes) = self.pick(&fabric.routes)?;
+        let route = self.pick(routes)?;
+        if self
+            .owners
+            .contains(&(device.as_str(), route.prefix.as_str(), route.prefix_len))
+        {
+            return None;
+        }
+        Some((device.as_str(), route))
+    }
+
+    /// A linked port of `device` that `route` does not use.
+    fn pick_other_port(&mut self, device: &str, route: &FabricRoute) -> Option<&'a str> {
+        let candidates: Vec<&'a str> = self
+            .ports
+            .get(device)?
+            .iter()
+            .copied()
+            .filter(|port| !route.next_hops.iter().any(|hop| hop == port))
+            .collect();
+        self.pick(&candidates).copied()
+    }
+
+    fn delete_route(&mut self) -> Option<Fault> {
+        let (device, route) = self.pick_route()?;
+        Some(Fault {
+            kind: FaultKind::DeleteRoute,
+            description: format!(
+                "{} no longer routes {}/{}",
+                device, route.prefix, route.prefix_len
+            ),
+            edits: vec![Edit::RemoveRoute {
+                device: device.to_string(),
+                prefix: route.prefix.clone(),
+                prefix_len: route.prefix_len,
+            }],
+        })
+    }
+
+    fn wrong_port(&mut self) -> Option<Fault> {
+        let (device, route) = self.pick_route()?;
+        let port = self.pick_other_port(device, route)?;
+        Some(Fault {
+            kind: FaultKind::WrongPort,
+            description: format!(
+                "{} sends {}/{} out of {} instead of {}",
+                device,
+                route.prefix,
+                route.prefix_len,
+                port,
+                route.next_hops.join(",")
+            ),
+            edits: vec![Edit::SetRoute {
+                device: device.to_string(),
+                prefix: route.prefix.clone(),
+                prefix_len: route.prefix_len,
+                next_hops: vec![port.to_string()],
+            }],
+        })
+    }
+
+    fn make_loop(&mut self) -> Option<Fault> {
+        let fabric = self.fabric;
+        let link = self.pick(&fabric.links)?;
+        let subnet = self.pick(&fabric.subnets)?;
+        if subnet.host == link.src_node || subnet.host == link.dst_node {
+            return None;
+        }
+        let set_route = |device: &str, port: &str| Edit::SetRoute {
+            device: device.to_string(),
+            prefix: subnet.prefix.clone(),
+            prefix_len: subnet.prefix_len,
+            next_hops: vec![port.to_string()],
+        };
+        Some(Fault {
+            kind: FaultKind::Loop,
+            description: format!(
+                "{} and {} send {}/{} to each other",
+                link.src_node, link.dst_node, subnet.prefix, subnet.prefix_len
+            ),
+            edits: vec![
+                set_route(&link.src_node, &link.src_port),
+                set_route(&link.dst_node, &link.dst_port),
+            ],
+        })
+    }
+
+    fn hijack(&mut self) -> Option<Fault> {
+        let fabric = self.fabric;
+        let (device, routes) = self.pick(&fabric.routes)?;
+        let subnet = self.pick(&fabric.subnets)?;
+        let address = Address::parse(&subnet.prefix)?;
+        if subnet.host == *device || usize::from(subnet.prefix_len) >= address.width() {
+            return None;
+        }
+        let route = &routes[route_table(routes).lookup(address)?];
+        let port = self.pick_other_port(device, route)?;
+        Some(Fault {
+            kind: FaultKind::Hijack,
+            description: format!(
+                "{} sends the first half of {}/{} out of {} through a /{}",
+                device,
+                subnet.prefix,
+                subnet.prefix_len,
+                port,
+                subnet.prefix_len + 1
+            ),
+            edits: vec![Edit::SetRoute {
+                device: device.clone(),
+                prefix: subnet.prefix.clone(),
+                prefix_len: subnet.prefix_len + 1,
+                next_hops: vec![port.to_string()],
+            }],
+        })
+    }
+
+    fn remove_link(&mut self) -> Option<Fault> {
+        let fabric = self.fabric;
+        let link = self.pick(&fabric.links)?;
+        Some(Fault {
+            kind: FaultKind::RemoveLink,
+            description: format!(
+                "link {}:{} - {}:{} is removed",
+                link.src_node, link.src_port, link.dst_node, link.dst_port
+            ),
+            edits: vec![Edit::RemoveLink {
+                src_node: link.src_node.clone(),
+                src_port: link.src_port.clone(),
+                dst_node: link.dst_node.clone(),
+                dst_port: link.dst_port.clone(),
+            }],
+        })
+    }
+}
+
+/// What two faults must not share: a device and prefix, or a link.
+fn edit_key(edit: &Edit) -> (String, String) {
+    match edit {
+        Edit::SetRoute { device, prefix, .. } | Edit::RemoveRoute { device, prefix, .. } => {
+            (device.clone(), prefix.clone())
+        }
+        Edit::RemoveLink {
+            src_node, dst_node, ..
+        } => (src_node.clone(), dst_node.clone()),
+    }
+}
+
+/// Longest-prefix match on one device's routes.
+fn route_table(routes: &[FabricRoute]) -> PrefixTable {
+    PrefixTable::new(
+        routes
+            .iter()
+            .map(|route| (route.prefix.as_str(), usize::from(route.prefix_len))),
+    )
+}
+
+/// (source, destination) pairs violating each invariant, sorted. A pair
+/// violates an invariant if any address of the destination's packet space
+/// does, when sent from the source and following every ECMP branch.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct Violations {
+    /// No branch reaches the destination, which is what the reachability
+    /// check reports as unreachable.
+    pub reachability: Vec<(String, String)>,
+    /// Some branch runs into a forwarding loop.
+    pub loop_freedom: Vec<(String, String)>,
+    /// Some branch is dropped: no route, or a port without a link.
+    pub blackhole_freedom: Vec<(String, String)>,
+}
+
+impl Violations {
+    /// Follows concrete addresses through `fabric`: every subnet's first
+    /// address, plus the bounds of every more-specific route inside it, so
+    /// every address range forwarded alike is covered. Sources are the edge
+    /// devices, destinations the subnet owners.
+    pub fn evaluate(fabric: &Fabric) -> Self {
+        let mut names: Vec<&str> = Vec::new();
+        let mut index: HashMap<&str, usize> = HashMap::default();
+        let all_names = fabric
+            .routes
+            .iter()
+            .map(|(name, _)| name.as_str())
+            .chain(
+                fabric
+                    .links
+                    .iter()
+                    .flat_map(|link| [link.src_node.as_str(), link.dst_node.as_str()]),
+            )
+            .chain(fabric.edge_devices.iter().map(String::as_str))
+            .chain(fabric.subnets.iter().map(|subnet| subnet.host.as_str()));
+        for name in all_names {
+            index.entry(name).or_insert_with(|| {
+                names.push(name);
+                names.len() - 1
+            });
+        }
+
+        let mut peers: HashMap<(usize, &str), usize> = HashMap::default();
+        for link in &fabric.links {
+            let src = index[link.src_node.as_str()];
+            let dst = index[link.dst_node.as_str()];
+            peers.insert((src, link.src_port.as_str()), dst);
+            peers.insert((dst, link.dst_port.as_str()), src);
+        }
+        let mut routes: Vec<&[FabricRoute]> = vec![&[]; names.len()];
+        for (name, device_routes) in &fabric.routes {
+            routes[index[name.as_str()]] = device_routes;
+        }
+        let network = ForwardingGraph {
+            tables: routes.iter().map(|routes| route_table(routes)).collect(),
+            next_hops: routes
+                .iter()
+                .enumerate()
+                .map(|(device, device_routes)| {
+                    device_routes
+                        .iter()
+                        .map(|route| {
+                            route
+                                .next_hops
+                                .iter()
+                                .map(|port| peers.get(&(device, port.as_str())).copied())
+                                .collect()
+                        })
+                        .collect()
+                })
+                .collect(),
+        };
+
+        let prefixes: HashSet<(Address, usize)> = fabric
+            .routes
+            .iter()
+            .flat_map(|(_, routes)| routes)
+            .filter_map(|route| {
+                let len = usize::from(route.prefix_len);
+                Some((Address::parse(&route.prefix)?.first(len), len))
+            })
+            .collect();
+        let mut probes: Vec<(Address, usize)> = Vec::new();
+        for subnet in &fabric.subnets {
+            let Some(address) = Address::parse(&subnet.prefix) else {
+                continue;
+            };
+            let subnet_len = usize::from(subnet.prefix_len);
+            let first = address.first(subnet_len);
+            let last = address.last(subnet_len);
+            let mut addresses = vec![first];
+            for &(prefix, len) in &prefixes {
+                if len > subnet_len && prefix.first(subnet_len) == first {
+                    addresses.push(prefix);
+                    let end = prefix.last(len);
+                    if end < last {
+                        addresses.push(end.with_value(end.value() + 1));
+                    }
+                }
+            }
+            addresses.sort_unstable();
+            addresses.dedup();
+            let owner = index[subnet.host.as_str()];
+            probes.extend(addresses.into_iter().map(|address| (address, owner)));
+        }
+
+        let sources: Vec<usize> = fabric
+            .edge_devices
+            .iter()
+            .map(|name| index[name.as_str()])
+            .collect();
+        let outcomes: Vec<(usize, Vec<Outcome>)> = probes
+            .par_iter()
+            .map(|&(address, owner)| (owner, network.follow(address, owner)))
+            .collect();
+        let mut violated: [HashSet<(usize, usize)>; 3] = Default::default();
+        for (owner, outcome) in outcomes {
+            for &source in sources.iter().filter(|&&source| source != owner) {
+                let outcome = outcome[source];
+                for (set, holds) in
+                    violated
+                        .iter_mut()
+                        .zip([outcome.delivered, !outcome.looped, !outcome.dropped])
+                {
+                    if !holds {
+                        set.insert((source, owner));
+                    }
+                }
+            }
+        }
+        let [reachability, loop_freedom, blackhole_freedom] = violated.map(|pairs| {
+            let mut pairs: Vec<(String, String)> = pairs
+                .into_iter()
+                .map(|(source, owner)| (names[source].to_string(), names[owner].to_string()))
+                .collect();
+            pairs.sort_unstable();
+            pairs
+        });
+        Violations {
+            reachability,
+            loop_freedom,
+            blackhole_freedom,
+        }
+    }
+
+    /// Violations not already present in `baseline`.
+    pub fn difference(&self, baseline: &Violations) -> Violations {
+        let minus = |pairs: &[(String, String)], baseline: &[(String, String)]| {
+            let baseline: HashSet<&(String, String)> = baseline.iter().collect();
+            pairs
+                .iter()
+                .filter(|pair| !baseline.contains(pair))
+                .cloned()
+                .collect()
+        };
+        Violations {
+            reachability: minus(&self.reachability, &baseline.reachability),
+            loop_freedom: minus(&self.loop_freedom, &baseline.loop_freedom),
+            blackhole_freedom: minus(&self.blackhole_freedom, &baseline.blackhole_freedom),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.reachability.is_empty()
+            && self.loop_freedom.is_empty()
+            && self.blackhole_freedom.is_empty()
+    }
+}
+
+/// Where the branches of one address can end up from a device.
+#[derive(Debug, Clone, Copy, Default)]
+struct Outcome {
+    delivered: bool,
+    looped: bool,
+    dropped: bool,
+}
+
+/// Devices by index, with their route tables and each route's next hops
+/// resolved to peers; `None` is a port without a link.
+struct ForwardingGraph {
+    tables: Vec<PrefixTable>,
+    next_hops: Vec<Vec<Vec<Option<usize>>>>,
+}
+
+impl ForwardingGraph {
+    /// Outcomes of `address` from every device. Packets stop at `owner`.
+    fn follow(&self, address: Address, owner: usize) -> Vec<Outcome> {
+        let len = self.tables.len();
+        let mut predecessors: Vec<Vec<usize>> = vec![Vec::new(); len];
+        let mut out_degree = vec![0usize; len];
+        let mut drops: Vec<usize> = Vec::new();
+        for device in (0..len).filter(|&device| device != owner) {
+            let hops: &[Option<usize>] = match self.tables[device].lookup(address) {
+                Some(route) => &self.next_hops[device][route],
+                None => &[],
+            };
+            if hops.is_empty() || hops.contains(&None) {
+                drops.push(device);
+            }
+            for &peer in hops.iter().flatten() {
+                predecessors[peer].push(device);
+                out_degree[device] += 1;
+            }
+        }
+
+        let mut outcomes = vec![Outcome::default(); len];
+        for device in reach_back(&predecessors, [owner]) {
+            outcomes[device].delivered = true;
+        }
+        for device in reach_back(&predecessors, drops) {
+            outcomes[device].dropped = true;
+        }
+        // Peel off devices whose branches all end; the rest reach a cycle.
+        let mut remaining = out_degree;
+        let mut queue: VecDeque<usize> =
+            (0..len).filter(|&device| remaining[device] == 0).collect();
+        let mut looped = vec![true; len];
+        while let Some(device) = queue.pop_front() {
+            looped[device] = false;
+            for &predecessor in &predecessors[device] {
+                remaining[predecessor] -= 1;
+                if remaining[predecessor] == 0 {
+                    queue.push_back(predecessor);
+                }
+            }
+        }
+        for (outcome, looped) in outcomes.iter_mut().zip(looped) {
+            outcome.looped = looped;
+        }
+        outcomes
+    }
+}
+
+/// Devices with a forwarding path to any of `targets`, the targets included.
+fn reach_back(predecessors: &[Vec<usize>], targets: impl IntoIterator<Item = usize>) -> Vec<usize> {
+    let mut seen = vec![false; predecessors.len()];
+    let mut queue: VecDeque<usize> = VecDeque::new();
+    for target in targets {
+        if !seen[target] {
+            seen[target] = true;
+            queue.push_back(target);
+        }
+    }
+    let mut reached = Vec::new();
+    while let Some(device) = queue.pop_front() {
+        reached.push(device);
+        for &predecessor in &predecessors[device] {
+            if !seen[predecessor] {
+                seen[predecessor] = true;
+                queue.push_back(predecessor);
+            }
+        }
+    }
+    reached
+}
+
+/// Expected violations of one fault, beyond those of the source dataset.
+#[derive(Debug, Clone, Serialize)]
+pub struct FaultReport {
+    pub id: usize,
+    #[serde(flatten)]
+    pub fault: Fault,
+    pub expected: Violations,
+}
+
+/// Ground truth of a fault-injected dataset, written as `faults.json`.
+#[derive(Debug, Clone, Serialize)]
+pub struct FaultManifest {
+    pub source: String,
+    pub seed: u64,
+    /// Pairs the reachability check counts: edge devices to every other
+    /// subnet owner.
+    pub total_pairs: usize,
+    /// Unreachable pairs the verifier should report on the faulty dataset,
+    /// baseline included.
+    pub expected_unreachable_pairs: usize,
+    /// Violations already present in the source dataset.
+    pub baseline: Violations,
+    pub faults: Vec<FaultReport>,
+    /// Violations of all faults together, beyond the baseline. Faults can
+    /// mask or compound each other, so this is not the union of the
+    /// per-fault lists.
+    pub expected: Violations,
+}
+
+impl FaultManifest {
+    /// Injects one fault per entry of `kinds` into `fabric` and evaluates
+    /// every fault alone and all of them together.
+    pub fn inject(
+        fabric: &mut Fabric,
+        kinds: &[FaultKind],
+        source: &str,
+        seed: u64,
+    ) -> Result<Self, String> {
+        let original = fabric.clone();
+        let mut injector = FaultInjector::new(&original, seed);
+        let faults = kinds
+            .iter()
+            .map(|&kind| injector.inject(kind))
+            .collect::<Result<Vec<_>, _>>()?;
+        let baseline = Violations::evaluate(&original);
+        let reports = faults
+            .into_iter()
+            .enumerate()
+            .map(|(id, fault)| {
+                let mut faulty = original.clone();
+                fault.apply(&mut faulty);
+                let expected = Violations::evaluate(&faulty).difference(&baseline);
+                FaultReport {
+                    id,
+                    fault,
+                    expected,
+                }
+            })
+            .collect::<Vec<_>>();
+        for report in &reports {
+            report.fault.apply(fabric);
+        }
+        let violations = Violations::evaluate(fabric);
+        let owners: HashSet<&str> = fabric
+            .subnets
+            .iter()
+            .map(|subnet| subnet.host.as_str())
+            .collect();
+        let total_pairs = fabric
+            .edge_devices
+            .iter()
+            .map(|source| owners.len() - usize::from(owners.contains(source.as_str())))
+            .sum();
+        Ok(FaultManifest {
+            source: source.to_string(),
+            seed,
+            total_pairs,
+            expected_unreachable_pairs: violations.reachability.len(),
+            expected: violations.difference(&baseline),
+            baseline,
+            faults: reports,
+        })
+    }
+}
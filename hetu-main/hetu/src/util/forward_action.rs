@@ -11,7 +11,7 @@ impl ForwardAction {
     pub fn new(forward_type: String, ports: Vec<String>) -> Self {
         ForwardAction {
             forward_type,
-            ports: ports,
+            ports,
         }
     }
 
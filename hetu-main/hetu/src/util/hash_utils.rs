@@ -1,3 +1,59 @@
+//! Crate-wide hash containers. The hasher is picked at compile time by the
+//! `use_*` cargo features; with none enabled the std `RandomState` is used.
+//! If several features are enabled, the first one in this order wins:
+//! ahash, rustc_hash, fxhash, seahash, wyhash.
+//!
+//! Only `Default`-based constructors are available for every hasher, so create
+//! maps with `HashMap::default()` rather than `HashMap::new()`.
+
+#[cfg(feature = "use_ahash")]
+mod selected {
+    pub type BuildHasher = ahash::RandomState;
+    pub const NAME: &str = "ahash";
+}
+
+#[cfg(all(feature = "use_rustc_hash", not(feature = "use_ahash")))]
+mod selected {
+    pub type BuildHasher = rustc_hash::FxBuildHasher;
+    pub const NAME: &str = "rustc_hash";
+}
+
+#[cfg(all(
+    feature = "use_fxhash",
+    not(any(feature = "use_ahash", feature = "use_rustc_hash"))
+))]
+mod selected {
+    pub type BuildHasher = fxhash::FxBuildHasher;
+    pub const NAME: &str = "fxhash";
+}
+
+#[cfg(all(
+    feature = "use_seahash",
+    not(any(
+        feature = "use_ahash",
+        feature = "use_rustc_hash",
+        feature = "use_fxhash"
+    ))
+))]
+mod selected {
+    pub type BuildHasher = std::hash::BuildHasherDefault<seahash::SeaHasher>;
+    pub const NAME: &str = "seahash";
+}
+
+#[cfg(all(
+    feature = "use_wyhash",
+    not(any(
+        feature = "use_ahash",
+        feature = "use_rustc_hash",
+        feature = "use_fxhash",
+        feature = "use_seahash"
+    ))
+))]
+mod selected {
+    pub type BuildHasher = std::hash::BuildHasherDefault<wyhash::WyHash>;
+    pub const NAME: &str = "wyhash";
+}
+
 #[cfg(not(any(
     feature = "use_ahash",
     feature = "use_rustc_hash",
@@ -5,4 +61,17 @@
     feature = "use_seahash",
     feature = "use_wyhash"
 )))]
-pub use std::collections::{HashMap, HashSet};
+mod selected {
+    pub type BuildHasher = std::collections::hash_map::RandomState;
+    pub const NAME: &str = "std";
+}
+
+pub use selected::BuildHasher;
+
+/// Name of the hasher compiled into this build, for logs and benchmark output.
+pub const HASHER_NAME: &str = selected::NAME;
+
+pub type HashMap<K, V> = std::collections::HashMap<K, V, BuildHasher>;
+pub type HashSet<T> = std::collections::HashSet<T, BuildHasher>;
+pub type DashMap<K, V> = dashmap::DashMap<K, V, BuildHasher>;
+pub type DashSet<T> = dashmap::DashSet<T, BuildHasher>;
@@ -0,0 +1,274 @@
+use crate::util::bounded_cache::CacheLevelStats;
+use crate::util::npbdd::CacheStats;
+use serde::Serialize;
+use std::fmt::Write as _;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Phase {
+    Parse,
+    PacketSpaceEncode,
+    RuleEncode,
+    NeighborhoodPartition,
+    Schedule,
+    Traversal,
+    Check,
+}
+
+impl Phase {
+    pub const ALL: [Phase; 7] = [
+        Phase::Parse,
+        Phase::PacketSpaceEncode,
+        Phase::RuleEncode,
+        Phase::NeighborhoodPartition,
+        Phase::Schedule,
+        Phase::Traversal,
+        Phase::Check,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Phase::Parse => "parse",
+            Phase::PacketSpaceEncode => "packet_space_encode",
+            Phase::RuleEncode => "rule_encode",
+            Phase::NeighborhoodPartition => "neighborhood_partition",
+            Phase::Schedule => "schedule",
+            Phase::Traversal => "traversal",
+            Phase::Check => "check",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StatsFormat {
+    Json,
+    Prometheus,
+}
+
+impl StatsFormat {
+    pub fn parse(name: &str) -> Option<Self> {
+        match name {
+            "json" => Some(StatsFormat::Json),
+            "prometheus" | "prom" => Some(StatsFormat::Prometheus),
+            _ => None,
+        }
+    }
+}
+
+/// Session-wide counters, updated concurrently by the encode and verify phases.
+///
+/// Traversal and check run once per neighborhood in parallel, so their time is
+/// summed across neighborhoods rather than measured on the wall clock.
+#[derive(Default)]
+pub struct Metrics {
+    phase_nanos: [AtomicU64; Phase::ALL.len()],
+    neighborhoods: AtomicUsize,
+    traversal_count: AtomicUsize,
+}
+
+impl Metrics {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn record(&self, phase: Phase, elapsed: Duration) {
+        self.phase_nanos[phase as usize].fetch_add(elapsed.as_nanos() as u64, Ordering::Relaxed);
+    }
+
+    pub fn time<T>(&self, phase: Phase, f: impl FnOnce() -> T) -> T {
+        let start = Instant::now();
+        let result = f();
+        self.record(phase, start.elapsed());
+        result
+    }
+
+    pub fn phase_time(&self, phase: Phase) -> Duration {
+        Duration::from_nanos(self.phase_nanos[phase as usize].load(Ordering::Relaxed))
+    }
+
+    /// Zeroes the partition, schedule, traversal and check times and the
+    /// neighborhood and traversal counts, so they cover one verification.
+    pub fn reset_verification(&self) {
+        for phase in [
+            Phase::NeighborhoodPartition,
+            Phase::Schedule,
+            Phase::Traversal,
+            Phase::Check,
+        ] {
+            self.phase_nanos[phase as usize].store(0, Ordering::Relaxed);
+        }
+        self.neighborhoods.store(0, Ordering::Relaxed);
+        self.traversal_count.store(0, Ordering::Relaxed);
+    }
+
+    pub fn set_neighborhood_count(&self, count: usize) {
+        self.neighborhoods.store(count, Ordering::Relaxed);
+    }
+
+    pub fn add_traversal_count(&self, count: usize) {
+        self.traversal_count.fetch_add(count, Ordering::Relaxed);
+    }
+
+    pub fn snapshot(&self, cache_stats: &CacheStats) -> MetricsSnapshot {
+        let seconds = |phase| self.phase_time(phase).as_secs_f64();
+        MetricsSnapshot {
+            phase_seconds: PhaseSeconds {
+                parse: seconds(Phase::Parse),
+                packet_space_encode: seconds(Phase::PacketSpaceEncode),
+                rule_encode: seconds(Phase::RuleEncode),
+                neighborhood_partition: seconds(Phase::NeighborhoodPartition),
+                schedule: seconds(Phase::Schedule),
+                traversal: seconds(Phase::Traversal),
+                check: seconds(Phase::Check),
+            },
+            cache: CacheMetrics {
+                l1: LevelMetrics::from(&cache_stats.l1),
+                l2: LevelMetrics::from(&cache_stats.l2),
+                l3: LevelMetrics::from(&cache_stats.l3),
+            },
+            bdd_table_entries: cache_stats.table.entries,
+            bdd_table_bytes: cache_stats.table.bytes,
+            bdd_table_flushes: cache_stats.table_flushes,
+            neighborhoods: self.neighborhoods.load(Ordering::Relaxed),
+            traversal_count: self.traversal_count.load(Ordering::Relaxed),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct PhaseSeconds {
+    pub parse: f64,
+    pub packet_space_encode: f64,
+    pub rule_encode: f64,
+    pub neighborhood_partition: f64,
+    pub schedule: f64,
+    pub traversal: f64,
+    pub check: f64,
+}
+
+impl PhaseSeconds {
+    pub fn get(&self, phase: Phase) -> f64 {
+        match phase {
+            Phase::Parse => self.parse,
+            Phase::PacketSpaceEncode => self.packet_space_encode,
+            Phase::RuleEncode => self.rule_encode,
+            Phase::NeighborhoodPartition => self.neighborhood_partition,
+            Phase::Schedule => self.schedule,
+            Phase::Traversal => self.traversal,
+            Phase::Check => self.check,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct LevelMetrics {
+    pub hits: usize,
+    pub misses: usize,
+    pub hit_rate: f64,
+    pub entries: usize,
+    pub bytes: usize,
+    pub evicted: usize,
+}
+
+impl From<&CacheLevelStats> for LevelMetrics {
+    fn from(stats: &CacheLevelStats) -> Self {
+        LevelMetrics {
+            hits: stats.hits,
+            misses: stats.misses,
+            hit_rate: stats.hit_rate(),
+            entries: stats.entries,
+            bytes: stats.bytes,
+            evicted: stats.evicted,
+        }
+    }
+}
+
+type LevelValue = fn(&LevelMetrics) -> f64;
+
+#[derive(Debug, Clone, Serialize)]
+pub struct CacheMetrics {
+    pub l1: LevelMetrics,
+    pub l2: LevelMetrics,
+    pub l3: LevelMetrics,
+}
+
+/// Point-in-time copy of [`Metrics`] plus cache and BDD table statistics.
+#[derive(Debug, Clone, Serialize)]
+pub struct MetricsSnapshot {
+    pub phase_seconds: PhaseSeconds,
+    pub cache: CacheMetrics,
+    pub bdd_table_entries: usize,
+    pub bdd_table_bytes: usize,
+    pub bdd_table_flushes: usize,
+    pub neighborhoods: usize,
+    pub traversal_count: usize,
+}
+
+impl MetricsSnapshot {
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).expect("Failed to serialize metrics")
+    }
+
+    pub fn to_prometheus(&self) -> String {
+        let mut out = String::new();
+        out.push_str("# HELP hetu_phase_seconds Time spent per phase; traversal and check are summed over neighborhoods.\n");
+        out.push_str("# TYPE hetu_phase_seconds gauge\n");
+        for phase in Phase::ALL {
+            let _ = writeln!(
+                out,
+                "hetu_phase_seconds{{phase=\"{}\"}} {}",
+                phase.name(),
+                self.phase_seconds.get(phase)
+            );
+        }
+
+        let levels = [
+            ("l1", &self.cache.l1),
+            ("l2", &self.cache.l2),
+            ("l3", &self.cache.l3),
+        ];
+        let level_metrics: [(&str, &str, LevelValue); 6] = [
+            ("hetu_cache_hits_total", "counter", |l| l.hits as f64),
+            ("hetu_cache_misses_total", "counter", |l| l.misses as f64),
+            ("hetu_cache_hit_ratio", "gauge", |l| l.hit_rate),
+            ("hetu_cache_entries", "gauge", |l| l.entries as f64),
+            ("hetu_cache_bytes", "gauge", |l| l.bytes as f64),
+            ("hetu_cache_evicted_total", "counter", |l| l.evicted as f64),
+        ];
+        for (name, kind, value) in level_metrics {
+            let _ = writeln!(out, "# TYPE {} {}", name, kind);
+            for (level, metrics) in levels {
+                let _ = writeln!(out, "{}{{level=\"{}\"}} {}", name, level, value(metrics));
+            }
+        }
+
+        let scalars = [
+            ("hetu_bdd_table_entries", "gauge", self.bdd_table_entries),
+            ("hetu_bdd_table_bytes", "gauge", self.bdd_table_bytes),
+            (
+                "hetu_bdd_table_flushes_total",
+                "counter",
+                self.bdd_table_flushes,
+            ),
+            ("hetu_neighborhoods", "gauge", self.neighborhoods),
+            ("hetu_traversal_count", "counter", self.traversal_count),
+        ];
+        for (name, kind, value) in scalars {
+            let _ = writeln!(out, "# TYPE {} {}", name, kind);
+            let _ = writeln!(out, "{} {}", name, value);
+        }
+        out
+    }
+
+    pub fn write_to(&self, path: impl AsRef<Path>, format: StatsFormat) -> io::Result<()> {
+        let contents = match format {
+            StatsFormat::Json => self.to_json(),
+            StatsFormat::Prometheus => self.to_prometheus(),
+        };
+        fs::write(path, contents)
+    }
+}
@@ -1,8 +1,20 @@
+pub mod address;
+pub mod bounded_cache;
+pub mod codec;
 pub mod device_port;
+pub mod encoding_cache;
+pub mod fabric;
+pub mod fault;
 pub mod forward_action;
 pub mod hash_utils;
+pub mod metrics;
 pub mod network;
 pub mod npbdd;
+pub mod packet_set;
 pub mod pod;
+pub mod progress;
 pub mod rule;
+pub mod snapshot;
 pub mod space_port;
+pub mod var_order;
+pub mod work_plan;
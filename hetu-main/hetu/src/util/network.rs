@@ -1,7 +1,6 @@
 use super::pod::Pod;
 use crate::util::device_port::DevicePort;
-use crate::util::hash_utils::{HashMap, HashSet};
-use dashmap::DashMap;
+use crate::util::hash_utils::{DashMap, HashMap, HashSet};
 use rayon::iter::{IntoParallelRefIterator, IntoParallelRefMutIterator, ParallelIterator};
 use serde::{Deserialize, Serialize};
 use std::collections::VecDeque;
@@ -10,7 +9,7 @@ use std::io::BufReader;
 use std::io::Read;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
-struct Topology {
+pub struct Topology {
     pub dst_node: String,
     pub dst_port: String,
     pub src_node: String,
@@ -22,6 +21,13 @@ pub struct Network {
     topology: HashMap<DevicePort, DevicePort>,
     pods: HashMap<i32, Pod>,
     pod_device_names: HashSet<String>,
+    links: Vec<Topology>,
+}
+
+impl Default for Network {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl Network {
@@ -31,6 +37,7 @@ impl Network {
             topology: HashMap::default(),
             pods: HashMap::default(),
             pod_device_names: HashSet::default(),
+            links: Vec::new(),
         }
     }
 
@@ -43,7 +50,10 @@ impl Network {
             .expect("Error reading the file");
         let topologies: Vec<Topology> =
             serde_json::from_str(&content).expect("Error parsing the JSON");
+        self.load_links(topologies);
+    }
 
+    pub fn load_links(&mut self, topologies: Vec<Topology>) {
         let tmp_device_ports: DashMap<String, HashSet<DevicePort>> = DashMap::default();
         topologies.par_iter().for_each(|topology| {
             let d1 = topology.src_node.clone();
@@ -66,6 +76,11 @@ impl Network {
             pod.set_interfaces(&self.device_ports);
         });
         self.pods = pods;
+        self.links = topologies;
+    }
+
+    pub fn get_links(&self) -> &[Topology] {
+        &self.links
     }
 
     pub fn find_pods(&mut self, topologies: &Vec<Topology>) -> HashMap<i32, Pod> {
@@ -75,11 +90,11 @@ impl Network {
             if topology.dst_node.contains("S1") && topology.src_node.contains("S0") {
                 graph
                     .entry(topology.dst_node.clone())
-                    .or_insert_with(Vec::new)
+                    .or_default()
                     .push(topology.src_node.clone());
                 graph
                     .entry(topology.src_node.clone())
-                    .or_insert_with(Vec::new)
+                    .or_default()
                     .push(topology.dst_node.clone());
             }
         }
@@ -1,217 +1,606 @@
+use crate::util::bounded_cache::{CacheLevelStats, CacheWeight, GenerationalCache};
+use crate::util::hash_utils::{DashMap, DashSet};
+#[cfg(feature = "interval_packets")]
+use crate::util::packet_set::IntervalSet;
+use crate::util::packet_set::{prefix_bits, PacketSet, PacketSpace};
 use crate::util::rule::Rule;
+use crate::util::var_order::VariableOrder;
 use biodivine_lib_bdd::*;
-use dashmap::DashMap;
-use lazy_static::lazy_static;
-use once_cell::sync::OnceCell;
-use std::net::IpAddr;
+use dashmap::mapref::entry::Entry;
+use std::fmt;
+use std::mem;
 use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+/// Id of a packet set interned in the `BDDTable`. Equal sets share one id,
+/// so equality and emptiness are id comparisons.
+pub type SpaceId = u32;
+
+/// Ids of the empty and full sets, interned and pinned by every `NPBDD`.
+pub const NONE_SPACE_ID: SpaceId = 1;
+pub const ALL_SPACE_ID: SpaceId = 2;
+
+// Both directions of the table keep one map entry per BDD.
+const TABLE_ENTRY_OVERHEAD: usize =
+    2 * (mem::size_of::<u32>() + mem::size_of::<Arc<PacketSpace>>());
+
+/// Byte limits for the three cache levels and the canonical BDD table.
+/// The cache levels evict as they go. The table is only flushed between
+/// encode chunks, by [`NPBDD::maintain`], so it can outgrow its share while
+/// neighborhoods are verified.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CacheBudget {
+    pub l1_bytes: usize,
+    pub l2_bytes: usize,
+    pub l3_bytes: usize,
+    pub table_bytes: usize,
+}
+
+impl CacheBudget {
+    pub fn unbounded() -> Self {
+        CacheBudget {
+            l1_bytes: usize::MAX,
+            l2_bytes: usize::MAX,
+            l3_bytes: usize::MAX,
+            table_bytes: usize::MAX,
+        }
+    }
+
+    /// Splits `total_bytes` across levels. The table owns the BDDs themselves,
+    /// so it gets the largest share; the caches mostly hold ids.
+    pub fn from_total(total_bytes: usize) -> Self {
+        let share = total_bytes / 20;
+        CacheBudget {
+            l1_bytes: share * 3,
+            l2_bytes: share * 3,
+            l3_bytes: share * 6,
+            table_bytes: total_bytes - share * 12,
+        }
+    }
+
+    pub fn is_bounded(&self) -> bool {
+        *self != Self::unbounded()
+    }
+}
+
+impl Default for CacheBudget {
+    fn default() -> Self {
+        Self::unbounded()
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct CacheStats {
+    pub l1: CacheLevelStats,
+    pub l2: CacheLevelStats,
+    pub l3: CacheLevelStats,
+    pub table: CacheLevelStats,
+    pub table_flushes: usize,
+    /// Flushes skipped because the pinned sets alone exceed the table budget.
+    pub skipped_table_flushes: usize,
+}
+
+impl fmt::Display for CacheStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (name, level) in [
+            ("L1", &self.l1),
+            ("L2", &self.l2),
+            ("L3", &self.l3),
+            ("Table", &self.table),
+        ] {
+            writeln!(
+                f,
+                "{} cache: {} hits, {} misses, {} entries, {} bytes, {} evicted",
+                name, level.hits, level.misses, level.entries, level.bytes, level.evicted
+            )?;
+        }
+        write!(f, "Table flushes: {}", self.table_flushes)?;
+        if self.skipped_table_flushes > 0 {
+            write!(
+                f,
+                ", {} skipped because pinned sets alone exceed the table budget",
+                self.skipped_table_flushes
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// Session-owned symbolic context: variable layout, canonical BDD table and
+/// layered operation caches. Every `Simulator` owns its own instance.
+pub struct NPBDD {
+    engine: Arc<Engine>,
+    table: Arc<BDDTable>,
+    cache: LayerCache,
+    budget: CacheBudget,
+    table_flushes: AtomicUsize,
+    skipped_table_flushes: AtomicUsize,
+}
 
-pub struct NPBDD;
 impl NPBDD {
-    pub fn init(ip_bits_len: usize) {
-        Engine::init(ip_bits_len);
-        BDDTable::init();
-        LayerCache::init();
+    pub fn new(ip_bits_len: usize) -> Self {
+        Self::with_budget(ip_bits_len, CacheBudget::default())
+    }
+
+    pub fn with_budget(ip_bits_len: usize, budget: CacheBudget) -> Self {
+        Self::with_order(ip_bits_len, budget, VariableOrder::default())
+    }
+
+    pub fn with_order(ip_bits_len: usize, budget: CacheBudget, order: VariableOrder) -> Self {
+        let engine = Arc::new(Engine::new(ip_bits_len, order));
+        let table = Arc::new(BDDTable::new());
+        let cache = LayerCache::new(Arc::clone(&engine), Arc::clone(&table), &budget);
+        let none_id = table.insert_bdd(engine.make_none_space_bdd());
+        let all_id = table.insert_bdd(engine.make_all_space_bdd());
+        assert_eq!((none_id, all_id), (NONE_SPACE_ID, ALL_SPACE_ID));
+        table.pin(none_id);
+        table.pin(all_id);
+        NPBDD {
+            engine,
+            table,
+            cache,
+            budget,
+            table_flushes: AtomicUsize::new(0),
+            skipped_table_flushes: AtomicUsize::new(0),
+        }
+    }
+
+    pub fn ip_bits_len(&self) -> usize {
+        self.engine.ip_bits_len
+    }
+
+    pub fn variable_order(&self) -> VariableOrder {
+        self.engine.order
+    }
+
+    pub fn variable_set(&self) -> &BddVariableSet {
+        &self.engine.variable_set
+    }
+
+    pub fn ip_bit_variables(&self) -> &[BddVariable] {
+        &self.engine.ip_bit_variables
+    }
+
+    pub fn table(&self) -> &BDDTable {
+        &self.table
+    }
+
+    pub fn cache(&self) -> &LayerCache {
+        &self.cache
     }
 
-    pub fn make_none_space_bdd() -> Bdd {
-        Engine::make_none_space_bdd()
+    pub fn budget(&self) -> CacheBudget {
+        self.budget
     }
 
-    pub fn make_all_space_bdd() -> Bdd {
-        Engine::make_all_space_bdd()
+    /// Number of devices to encode between two calls to [`NPBDD::maintain`].
+    pub fn encode_chunk_size(&self, device_count: usize) -> usize {
+        if self.budget.is_bounded() {
+            (rayon::current_num_threads() * 4).max(1)
+        } else {
+            device_count.max(1)
+        }
+    }
+
+    /// Drops the BDD table together with every cache level once the table is
+    /// over budget. Cached values are table ids, so the levels can't outlive
+    /// the table; callers must only invoke this when no encoding is in flight.
+    /// Traversal state holds unpinned ids too, so verification never flushes.
+    /// A flush is skipped, and counted in [`CacheStats`], when the pinned sets
+    /// alone exceed the budget, since it could not bring the table back under.
+    pub fn maintain(&self) -> bool {
+        if self.table.bytes() <= self.budget.table_bytes {
+            return false;
+        }
+        if self.table.pinned_bytes() > self.budget.table_bytes {
+            self.skipped_table_flushes.fetch_add(1, Ordering::Relaxed);
+            return false;
+        }
+        self.cache.clear();
+        self.table.clear();
+        self.table_flushes.fetch_add(1, Ordering::Relaxed);
+        true
+    }
+
+    pub fn cache_stats(&self) -> CacheStats {
+        let [l1, l2, l3] = self.cache.level_stats();
+        CacheStats {
+            l1,
+            l2,
+            l3,
+            table: CacheLevelStats {
+                hits: 0,
+                misses: 0,
+                entries: self.table.get_id_num(),
+                bytes: self.table.bytes(),
+                limit_bytes: self.budget.table_bytes,
+                evicted: self.table.evicted(),
+            },
+            table_flushes: self.table_flushes.load(Ordering::Relaxed),
+            skipped_table_flushes: self.skipped_table_flushes.load(Ordering::Relaxed),
+        }
+    }
+
+    pub fn make_none_space_bdd(&self) -> PacketSpace {
+        self.engine.make_none_space_bdd()
+    }
+
+    pub fn intern(&self, space: PacketSpace) -> SpaceId {
+        self.table.insert_bdd(space)
+    }
+
+    /// Interns a set that outlives the current encoding chunk, so
+    /// [`NPBDD::maintain`] keeps it.
+    pub fn intern_pinned(&self, space: PacketSpace) -> SpaceId {
+        let id = self.table.insert_bdd(space);
+        self.table.pin(id);
+        id
+    }
+
+    pub fn pin(&self, id: SpaceId) {
+        self.table.pin(id);
+    }
+
+    pub fn space(&self, id: SpaceId) -> Arc<PacketSpace> {
+        self.table
+            .get_bdd_by_id(id)
+            .unwrap_or_else(|| panic!("unknown space id {}", id))
+    }
+
+    pub fn and(&self, a: SpaceId, b: SpaceId) -> SpaceId {
+        self.cache.l3_and(a, b)
     }
 
-    pub fn make_prefix_bdd(ip_address: &str, prefix_length: usize) -> Bdd {
-        Engine::encode_dst_ip_prefix_clause(ip_address, prefix_length)
+    pub fn or(&self, a: SpaceId, b: SpaceId) -> SpaceId {
+        self.cache.l3_or(a, b)
     }
 
-    pub fn make_src_device_bdd(src_device_id: usize) -> Bdd {
-        Engine::encode_src_device_constraint(src_device_id)
+    pub fn and_not(&self, a: SpaceId, b: SpaceId) -> SpaceId {
+        self.cache.l3_and_not(a, b)
+    }
+
+    pub fn is_subset(&self, a: SpaceId, b: SpaceId) -> bool {
+        self.and_not(a, b) == NONE_SPACE_ID
+    }
+
+    pub fn make_all_space_bdd(&self) -> PacketSpace {
+        self.engine.make_all_space_bdd()
+    }
+
+    pub fn make_prefix_bdd(&self, ip_address: &str, prefix_length: usize) -> PacketSpace {
+        self.engine
+            .encode_dst_ip_prefix_clause(ip_address, prefix_length)
+    }
+
+    /// Prefix given as its leading address bits, most significant first.
+    pub fn make_prefix_bdd_from_bits(&self, bits: &[bool]) -> PacketSpace {
+        self.engine.encode_dst_ip_prefix_bits(bits)
+    }
+
+    pub fn make_src_device_bdd(&self, src_device_id: usize) -> PacketSpace {
+        self.engine.encode_src_device_constraint(src_device_id)
     }
 }
 
 // Canonical representation of symbolic structures
-lazy_static! {
-    static ref NUM_TO_BDD_MAP: DashMap<u32, Bdd> = DashMap::new();
-    static ref BDD_TO_NUM_MAP: DashMap<Bdd, u32> = DashMap::new();
-    static ref BDD_TABLE_NEXT_ID: AtomicUsize = AtomicUsize::new(1);
-    static ref PREFIX_BDD_MAP: DashMap<String, u32> = DashMap::new();
+pub struct BDDTable {
+    num_to_bdd_map: DashMap<u32, Arc<PacketSpace>>,
+    bdd_to_num_map: DashMap<Arc<PacketSpace>, u32>,
+    next_id: AtomicUsize,
+    prefix_bdd_map: DashMap<String, u32>,
+    pinned: DashSet<u32>,
+    bytes: AtomicUsize,
+    pinned_bytes: AtomicUsize,
+    evicted: AtomicUsize,
+}
+
+impl Default for BDDTable {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
-pub struct BDDTable;
 impl BDDTable {
-    pub fn init() {}
+    pub fn new() -> Self {
+        BDDTable {
+            num_to_bdd_map: DashMap::default(),
+            bdd_to_num_map: DashMap::default(),
+            next_id: AtomicUsize::new(1),
+            prefix_bdd_map: DashMap::default(),
+            pinned: DashSet::default(),
+            bytes: AtomicUsize::new(0),
+            pinned_bytes: AtomicUsize::new(0),
+            evicted: AtomicUsize::new(0),
+        }
+    }
 
-    pub fn get_next_id() -> usize {
-        BDD_TABLE_NEXT_ID.fetch_add(1, Ordering::SeqCst)
+    pub fn get_next_id(&self) -> usize {
+        self.next_id.fetch_add(1, Ordering::SeqCst)
     }
 
-    pub fn get_id_num() -> usize {
-        NUM_TO_BDD_MAP.len()
+    pub fn get_id_num(&self) -> usize {
+        self.num_to_bdd_map.len()
     }
 
-    pub fn get_bdd_num() -> usize {
-        BDD_TO_NUM_MAP.len()
+    pub fn get_bdd_num(&self) -> usize {
+        self.bdd_to_num_map.len()
     }
 
-    pub fn insert_bdd(bdd: Bdd) -> u32 {
-        if let Some(existing_id) = BDD_TO_NUM_MAP.get(&bdd) {
+    pub fn insert_bdd(&self, bdd: PacketSpace) -> u32 {
+        if let Some(existing_id) = self.bdd_to_num_map.get(&bdd) {
             return *existing_id;
         }
-        let new_id = BDD_TABLE_NEXT_ID.fetch_add(1, Ordering::SeqCst);
-        let id_u32 = new_id.try_into().unwrap();
-        NUM_TO_BDD_MAP.insert(id_u32, bdd.clone());
-        BDD_TO_NUM_MAP.insert(bdd, id_u32);
-        id_u32
+        let bdd = Arc::new(bdd);
+        match self.bdd_to_num_map.entry(Arc::clone(&bdd)) {
+            Entry::Occupied(entry) => *entry.get(),
+            Entry::Vacant(entry) => {
+                let new_id = self.next_id.fetch_add(1, Ordering::SeqCst);
+                let id_u32 = new_id.try_into().unwrap();
+                self.bytes
+                    .fetch_add(bdd.weight() + TABLE_ENTRY_OVERHEAD, Ordering::Relaxed);
+                self.num_to_bdd_map.insert(id_u32, bdd);
+                entry.insert(id_u32);
+                id_u32
+            }
+        }
+    }
+
+    pub fn get_bdd_by_id(&self, id: u32) -> Option<Arc<PacketSpace>> {
+        self.num_to_bdd_map.get(&id).map(|bdd| Arc::clone(&bdd))
+    }
+
+    /// Keeps `id` across [`NPBDD::maintain`] flushes.
+    pub fn pin(&self, id: u32) {
+        if !self.pinned.insert(id) {
+            return;
+        }
+        if let Some(bdd) = self.num_to_bdd_map.get(&id) {
+            self.pinned_bytes
+                .fetch_add(bdd.weight() + TABLE_ENTRY_OVERHEAD, Ordering::Relaxed);
+        }
+    }
+
+    pub fn get_prefix_bdd_map(&self) -> &DashMap<String, u32> {
+        &self.prefix_bdd_map
+    }
+
+    pub fn bytes(&self) -> usize {
+        self.bytes.load(Ordering::Relaxed)
     }
 
-    pub fn get_bdd_by_id(id: u32) -> Option<Bdd> {
-        NUM_TO_BDD_MAP.get(&id).map(|bdd| bdd.clone())
+    /// Bytes held by pinned entries, which no flush releases.
+    pub fn pinned_bytes(&self) -> usize {
+        self.pinned_bytes.load(Ordering::Relaxed)
     }
 
-    pub fn get_prefix_bdd_map() -> &'static DashMap<String, u32> {
-        &PREFIX_BDD_MAP
+    pub fn evicted(&self) -> usize {
+        self.evicted.load(Ordering::Relaxed)
+    }
+
+    /// Every (id, BDD) pair currently in the table.
+    pub fn entries(&self) -> Vec<(u32, PacketSpace)> {
+        self.num_to_bdd_map
+            .iter()
+            .map(|entry| (*entry.key(), entry.value().as_ref().clone()))
+            .collect()
+    }
+
+    // Ids are never reused, so a stale id misses instead of aliasing a new BDD.
+    // Pinned entries are held by devices and survive.
+    fn clear(&self) {
+        let mut evicted = 0;
+        let mut evicted_bytes = 0;
+        self.num_to_bdd_map.retain(|id, bdd| {
+            let keep = self.pinned.contains(id);
+            if !keep {
+                evicted += 1;
+                evicted_bytes += bdd.weight() + TABLE_ENTRY_OVERHEAD;
+            }
+            keep
+        });
+        self.bdd_to_num_map.retain(|_, id| self.pinned.contains(id));
+        self.prefix_bdd_map.clear();
+        self.bytes.fetch_sub(evicted_bytes, Ordering::Relaxed);
+        self.evicted.fetch_add(evicted, Ordering::Relaxed);
     }
 }
 
+type L1Key = (String, u32, Vec<u32>);
+
+/// Contents of the L3 caches, which map prefixes and BDD operations to table ids.
+#[derive(Default)]
+pub struct L3Entries {
+    pub make: Vec<(String, u32)>,
+    pub and: Vec<((u32, u32), u32)>,
+    pub or: Vec<((u32, u32), u32)>,
+    pub not: Vec<(u32, u32)>,
+}
+
 // Hierarchical memoization across computation granularities
-lazy_static! {
-    static ref L3_MAKE_CACHE: DashMap<String, u32> = DashMap::new();
-    static ref L3_AND_CACHE: DashMap<(u32, u32), u32> = DashMap::new();
-    static ref L3_OR_CACHE: DashMap<(u32, u32), u32> = DashMap::new();
-    static ref L3_NOT_CACHE: DashMap<u32, u32> = DashMap::new();
-    static ref L2_ENCODE_RULE_CACHE: DashMap<(String, usize), u32> = DashMap::new();
-    static ref L2_CAL_HIT_CACHE: DashMap<(u32, u32), (u32, u32)> = DashMap::new();
+pub struct LayerCache {
+    engine: Arc<Engine>,
+    table: Arc<BDDTable>,
+    l3_make_cache: GenerationalCache<String, u32>,
+    l3_and_cache: GenerationalCache<(u32, u32), u32>,
+    l3_or_cache: GenerationalCache<(u32, u32), u32>,
+    l3_not_cache: GenerationalCache<u32, u32>,
+    // Only used by traversal, so it is not persisted with the other levels.
+    l3_and_not_cache: GenerationalCache<(u32, u32), u32>,
+    l2_encode_rule_cache: GenerationalCache<(String, usize), u32>,
+    l2_cal_hit_cache: GenerationalCache<(u32, u32), (u32, u32)>,
     // Key/value MUST preserve the caller-provided order; never sort or hash port lists.
-    static ref L2_MERGE_PORT_SPACE_CACHE: DashMap<(u32, Vec<u32>), Vec<u32>> = DashMap::new();
-    static ref L1_COMPLETE_RULE_CACHE: DashMap<(String, u32, Vec<u32>), (u32, Vec<u32>)> =
-        DashMap::new();
-    static ref L1_HIT_CNT: AtomicUsize = AtomicUsize::new(0);
-    static ref L2_HIT_CNT: AtomicUsize = AtomicUsize::new(0);
-    static ref L3_HIT_CNT: AtomicUsize = AtomicUsize::new(0);
-    static ref MISS_CNT: AtomicUsize = AtomicUsize::new(0);
+    l2_merge_port_space_cache: GenerationalCache<(u32, Vec<u32>), Vec<u32>>,
+    l1_complete_rule_cache: GenerationalCache<L1Key, (u32, Vec<u32>)>,
+    l1_hit_cnt: AtomicUsize,
+    l2_hit_cnt: AtomicUsize,
+    l3_hit_cnt: AtomicUsize,
+    l1_miss_cnt: AtomicUsize,
+    l2_miss_cnt: AtomicUsize,
+    miss_cnt: AtomicUsize,
 }
 
-pub struct LayerCache;
 impl LayerCache {
-    pub fn init() {}
+    fn new(engine: Arc<Engine>, table: Arc<BDDTable>, budget: &CacheBudget) -> Self {
+        let l3_share = budget.l3_bytes / 5;
+        let l2_share = budget.l2_bytes / 3;
+        LayerCache {
+            engine,
+            table,
+            l3_make_cache: GenerationalCache::new(l3_share),
+            l3_and_cache: GenerationalCache::new(l3_share),
+            l3_or_cache: GenerationalCache::new(l3_share),
+            l3_not_cache: GenerationalCache::new(l3_share),
+            l3_and_not_cache: GenerationalCache::new(l3_share),
+            l2_encode_rule_cache: GenerationalCache::new(l2_share),
+            l2_cal_hit_cache: GenerationalCache::new(l2_share),
+            l2_merge_port_space_cache: GenerationalCache::new(l2_share),
+            l1_complete_rule_cache: GenerationalCache::new(budget.l1_bytes),
+            l1_hit_cnt: AtomicUsize::new(0),
+            l2_hit_cnt: AtomicUsize::new(0),
+            l3_hit_cnt: AtomicUsize::new(0),
+            l1_miss_cnt: AtomicUsize::new(0),
+            l2_miss_cnt: AtomicUsize::new(0),
+            miss_cnt: AtomicUsize::new(0),
+        }
+    }
 
     // Atomic operations preserved in memory
-    pub fn l3_make(ip: &str, prefix_len: usize) -> u32 {
+    pub fn l3_make(&self, ip: &str, prefix_len: usize) -> u32 {
         let key = format!("{}/{}", ip, prefix_len);
 
-        if let Some(cached_id) = L3_MAKE_CACHE.get(&key) {
-            L3_HIT_CNT.fetch_add(1, Ordering::Relaxed);
-            return *cached_id;
+        if let Some(cached_id) = self.l3_make_cache.get(&key) {
+            self.l3_hit_cnt.fetch_add(1, Ordering::Relaxed);
+            return cached_id;
         }
 
-        MISS_CNT.fetch_add(1, Ordering::Relaxed);
-        let bdd = NPBDD::make_prefix_bdd(ip, prefix_len);
-        let bdd_id = BDDTable::insert_bdd(bdd);
-        L3_MAKE_CACHE.insert(key, bdd_id);
+        self.miss_cnt.fetch_add(1, Ordering::Relaxed);
+        let bdd = self.engine.encode_dst_ip_prefix_clause(ip, prefix_len);
+        let bdd_id = self.table.insert_bdd(bdd);
+        self.l3_make_cache.insert(key, bdd_id);
         bdd_id
     }
 
-    pub fn l3_and(a_id: u32, b_id: u32) -> u32 {
+    pub fn l3_and(&self, a_id: u32, b_id: u32) -> u32 {
         let key = (a_id, b_id);
 
-        if let Some(cached_id) = L3_AND_CACHE.get(&key) {
-            L3_HIT_CNT.fetch_add(1, Ordering::Relaxed);
-            return *cached_id;
+        if let Some(cached_id) = self.l3_and_cache.get(&key) {
+            self.l3_hit_cnt.fetch_add(1, Ordering::Relaxed);
+            return cached_id;
         }
 
-        MISS_CNT.fetch_add(1, Ordering::Relaxed);
-        let a = BDDTable::get_bdd_by_id(a_id).unwrap();
-        let b = BDDTable::get_bdd_by_id(b_id).unwrap();
-        let result_bdd = a.and(&b);
-        let result_id = BDDTable::insert_bdd(result_bdd);
-        L3_AND_CACHE.insert(key, result_id);
+        self.miss_cnt.fetch_add(1, Ordering::Relaxed);
+        let a = self.table.get_bdd_by_id(a_id).unwrap();
+        let b = self.table.get_bdd_by_id(b_id).unwrap();
+        let result_bdd = PacketSet::and(a.as_ref(), &b);
+        let result_id = self.table.insert_bdd(result_bdd);
+        self.l3_and_cache.insert(key, result_id);
         result_id
     }
 
-    pub fn l3_or(a_id: u32, b_id: u32) -> u32 {
+    pub fn l3_or(&self, a_id: u32, b_id: u32) -> u32 {
         let key = (a_id, b_id);
 
-        if let Some(cached_id) = L3_OR_CACHE.get(&key) {
-            L3_HIT_CNT.fetch_add(1, Ordering::Relaxed);
-            return *cached_id;
+        if let Some(cached_id) = self.l3_or_cache.get(&key) {
+            self.l3_hit_cnt.fetch_add(1, Ordering::Relaxed);
+            return cached_id;
         }
 
-        MISS_CNT.fetch_add(1, Ordering::Relaxed);
-        let a = BDDTable::get_bdd_by_id(a_id).unwrap();
-        let b = BDDTable::get_bdd_by_id(b_id).unwrap();
-        let result_bdd = a.or(&b);
-        let result_id = BDDTable::insert_bdd(result_bdd);
-        L3_OR_CACHE.insert(key, result_id);
+        self.miss_cnt.fetch_add(1, Ordering::Relaxed);
+        let a = self.table.get_bdd_by_id(a_id).unwrap();
+        let b = self.table.get_bdd_by_id(b_id).unwrap();
+        let result_bdd = PacketSet::or(a.as_ref(), &b);
+        let result_id = self.table.insert_bdd(result_bdd);
+        self.l3_or_cache.insert(key, result_id);
         result_id
     }
 
-    pub fn l3_not(id: u32) -> u32 {
-        if let Some(cached_id) = L3_NOT_CACHE.get(&id) {
-            L3_HIT_CNT.fetch_add(1, Ordering::Relaxed);
-            return *cached_id;
+    pub fn l3_not(&self, id: u32) -> u32 {
+        if let Some(cached_id) = self.l3_not_cache.get(&id) {
+            self.l3_hit_cnt.fetch_add(1, Ordering::Relaxed);
+            return cached_id;
         }
 
-        MISS_CNT.fetch_add(1, Ordering::Relaxed);
-        let bdd = BDDTable::get_bdd_by_id(id).unwrap();
-        let result_bdd = bdd.not();
-        let result_id = BDDTable::insert_bdd(result_bdd);
-        L3_NOT_CACHE.insert(id, result_id);
+        self.miss_cnt.fetch_add(1, Ordering::Relaxed);
+        let bdd = self.table.get_bdd_by_id(id).unwrap();
+        let result_bdd = PacketSet::not(bdd.as_ref());
+        let result_id = self.table.insert_bdd(result_bdd);
+        self.l3_not_cache.insert(id, result_id);
+        result_id
+    }
+
+    pub fn l3_and_not(&self, a_id: u32, b_id: u32) -> u32 {
+        let key = (a_id, b_id);
+
+        if let Some(cached_id) = self.l3_and_not_cache.get(&key) {
+            self.l3_hit_cnt.fetch_add(1, Ordering::Relaxed);
+            return cached_id;
+        }
+
+        self.miss_cnt.fetch_add(1, Ordering::Relaxed);
+        let a = self.table.get_bdd_by_id(a_id).unwrap();
+        let b = self.table.get_bdd_by_id(b_id).unwrap();
+        let result_bdd = PacketSet::and_not(a.as_ref(), &b);
+        let result_id = self.table.insert_bdd(result_bdd);
+        self.l3_and_not_cache.insert(key, result_id);
         result_id
     }
 
     // Compositional patterns captured in intermediate layers
-    pub fn l2_encode_rule(ip: &str, prefix_len: usize) -> u32 {
+    pub fn l2_encode_rule(&self, ip: &str, prefix_len: usize) -> u32 {
         let key = (ip.to_string(), prefix_len);
 
-        if let Some(cached_id) = L2_ENCODE_RULE_CACHE.get(&key) {
-            L2_HIT_CNT.fetch_add(1, Ordering::Relaxed);
-            return *cached_id;
+        if let Some(cached_id) = self.l2_encode_rule_cache.get(&key) {
+            self.l2_hit_cnt.fetch_add(1, Ordering::Relaxed);
+            return cached_id;
         }
 
-        let prefix_bdd_id = Self::l3_make(ip, prefix_len);
-        L2_ENCODE_RULE_CACHE.insert(key, prefix_bdd_id);
+        self.l2_miss_cnt.fetch_add(1, Ordering::Relaxed);
+        let prefix_bdd_id = self.l3_make(ip, prefix_len);
+        self.l2_encode_rule_cache.insert(key, prefix_bdd_id);
         prefix_bdd_id
     }
 
-    pub fn l2_cal_hit(prefix_bdd_id: u32, used_space_id: u32) -> (u32, u32) {
+    pub fn l2_cal_hit(&self, prefix_bdd_id: u32, used_space_id: u32) -> (u32, u32) {
         let key = (prefix_bdd_id, used_space_id);
 
-        if let Some(cached_result) = L2_CAL_HIT_CACHE.get(&key) {
-            L2_HIT_CNT.fetch_add(1, Ordering::Relaxed);
-            return *cached_result.value();
+        if let Some(cached_result) = self.l2_cal_hit_cache.get(&key) {
+            self.l2_hit_cnt.fetch_add(1, Ordering::Relaxed);
+            return cached_result;
         }
 
-        let not_used_space_id = Self::l3_not(used_space_id);
-        let hit_id = Self::l3_and(prefix_bdd_id, not_used_space_id);
-        let new_used_space_id = Self::l3_or(used_space_id, hit_id);
+        self.l2_miss_cnt.fetch_add(1, Ordering::Relaxed);
+        let not_used_space_id = self.l3_not(used_space_id);
+        let hit_id = self.l3_and(prefix_bdd_id, not_used_space_id);
+        let new_used_space_id = self.l3_or(used_space_id, hit_id);
 
         let result = (hit_id, new_used_space_id);
-        L2_CAL_HIT_CACHE.insert(key, result);
+        self.l2_cal_hit_cache.insert(key, result);
         result
     }
 
-    pub fn l2_merge_port_space(hit_id: u32, old_port_space_ids: &[u32]) -> Vec<u32> {
+    pub fn l2_merge_port_space(&self, hit_id: u32, old_port_space_ids: &[u32]) -> Vec<u32> {
         // IMPORTANT: order is semantically meaningful (caller zips results back to ports).
         let key = (hit_id, old_port_space_ids.to_vec());
 
-        if let Some(cached_result) = L2_MERGE_PORT_SPACE_CACHE.get(&key) {
-            L2_HIT_CNT.fetch_add(1, Ordering::Relaxed);
-            return cached_result.value().clone();
+        if let Some(cached_result) = self.l2_merge_port_space_cache.get(&key) {
+            self.l2_hit_cnt.fetch_add(1, Ordering::Relaxed);
+            return cached_result;
         }
 
+        self.l2_miss_cnt.fetch_add(1, Ordering::Relaxed);
         let new_port_space_ids: Vec<u32> = old_port_space_ids
             .iter()
-            .map(|&old_port_id| Self::l3_or(old_port_id, hit_id))
+            .map(|&old_port_id| self.l3_or(old_port_id, hit_id))
             .collect();
 
-        L2_MERGE_PORT_SPACE_CACHE.insert(key, new_port_space_ids.clone());
+        self.l2_merge_port_space_cache
+            .insert(key, new_port_space_ids.clone());
         new_port_space_ids
     }
 
     // Complete transformations as holistic units
     pub fn l1_complete_rule(
+        &self,
         rule: &Rule,
         used_space_id: u32,
         port_space_ids: &[u32],
@@ -220,16 +609,17 @@ impl LayerCache {
         // IMPORTANT: port_space_ids order is part of the key.
         let key = (rule_descriptor, used_space_id, port_space_ids.to_vec());
 
-        if let Some(cached_result) = L1_COMPLETE_RULE_CACHE.get(&key) {
-            L1_HIT_CNT.fetch_add(1, Ordering::Relaxed);
-            let (new_used_space_id, new_port_ids) = cached_result.value();
-            return Some((*new_used_space_id, new_port_ids.clone()));
+        let cached_result = self.l1_complete_rule_cache.get(&key);
+        if cached_result.is_some() {
+            self.l1_hit_cnt.fetch_add(1, Ordering::Relaxed);
+        } else {
+            self.l1_miss_cnt.fetch_add(1, Ordering::Relaxed);
         }
-
-        None
+        cached_result
     }
 
     pub fn l1_cache_result(
+        &self,
         rule: &Rule,
         used_space_id: u32,
         port_space_ids: &[u32],
@@ -239,45 +629,107 @@ impl LayerCache {
         let rule_descriptor = format!("{}/{}", rule.get_ip(), rule.get_prefix_len());
         let key = (rule_descriptor, used_space_id, port_space_ids.to_vec());
         let value = (new_used_space_id, new_port_space_ids.to_vec());
-        L1_COMPLETE_RULE_CACHE.insert(key, value);
+        self.l1_complete_rule_cache.insert(key, value);
     }
 
     // Legacy interface adaptations
-    pub fn cached_or(a_id: u32, b_id: u32) -> u32 {
-        Self::l3_or(a_id, b_id)
+    pub fn cached_or(&self, a_id: u32, b_id: u32) -> u32 {
+        self.l3_or(a_id, b_id)
     }
 
-    pub fn cached_prefix_match(all_bdd_id: u32, bdd_hit_id: u32) -> (u32, u32) {
-        let not_all_bdd_id = Self::l3_not(all_bdd_id);
-        let hit_id = Self::l3_and(bdd_hit_id, not_all_bdd_id);
-        let new_all_bdd_id = Self::l3_or(all_bdd_id, hit_id);
+    pub fn cached_prefix_match(&self, all_bdd_id: u32, bdd_hit_id: u32) -> (u32, u32) {
+        let not_all_bdd_id = self.l3_not(all_bdd_id);
+        let hit_id = self.l3_and(bdd_hit_id, not_all_bdd_id);
+        let new_all_bdd_id = self.l3_or(all_bdd_id, hit_id);
         (new_all_bdd_id, hit_id)
     }
 
-    pub fn cached_relevance(all_space_id: u32, bdd_match_id: u32) -> bool {
-        let intersection_id = Self::l3_and(all_space_id, bdd_match_id);
-        let intersection_bdd = BDDTable::get_bdd_by_id(intersection_id).unwrap();
-        !intersection_bdd.is_false()
+    pub fn cached_relevance(&self, all_space_id: u32, bdd_match_id: u32) -> bool {
+        self.l3_and(all_space_id, bdd_match_id) != NONE_SPACE_ID
     }
 
-    pub fn get_cache_stats() -> (usize, usize, usize, usize) {
+    pub fn get_cache_stats(&self) -> (usize, usize, usize, usize) {
         (
-            L1_HIT_CNT.load(Ordering::Relaxed),
-            L2_HIT_CNT.load(Ordering::Relaxed),
-            L3_HIT_CNT.load(Ordering::Relaxed),
-            MISS_CNT.load(Ordering::Relaxed),
+            self.l1_hit_cnt.load(Ordering::Relaxed),
+            self.l2_hit_cnt.load(Ordering::Relaxed),
+            self.l3_hit_cnt.load(Ordering::Relaxed),
+            self.miss_cnt.load(Ordering::Relaxed),
         )
     }
+
+    /// Size accounting for the L1, L2 and L3 levels, in that order.
+    pub fn level_stats(&self) -> [CacheLevelStats; 3] {
+        let mut l1 = self.l1_complete_rule_cache.stats();
+        l1.hits = self.l1_hit_cnt.load(Ordering::Relaxed);
+        l1.misses = self.l1_miss_cnt.load(Ordering::Relaxed);
+        let mut l2 = self.l2_encode_rule_cache.stats()
+            + self.l2_cal_hit_cache.stats()
+            + self.l2_merge_port_space_cache.stats();
+        l2.hits = self.l2_hit_cnt.load(Ordering::Relaxed);
+        l2.misses = self.l2_miss_cnt.load(Ordering::Relaxed);
+        let mut l3 = self.l3_make_cache.stats()
+            + self.l3_and_cache.stats()
+            + self.l3_or_cache.stats()
+            + self.l3_not_cache.stats()
+            + self.l3_and_not_cache.stats();
+        l3.hits = self.l3_hit_cnt.load(Ordering::Relaxed);
+        l3.misses = self.miss_cnt.load(Ordering::Relaxed);
+        [l1, l2, l3]
+    }
+
+    pub fn export_l3(&self) -> L3Entries {
+        L3Entries {
+            make: self.l3_make_cache.entries(),
+            and: self.l3_and_cache.entries(),
+            or: self.l3_or_cache.entries(),
+            not: self.l3_not_cache.entries(),
+        }
+    }
+
+    /// Seeds the L3 caches. The ids must refer to BDDs already in the table.
+    pub fn import_l3(&self, entries: L3Entries) {
+        for (key, id) in entries.make {
+            self.l3_make_cache.insert(key, id);
+        }
+        for (key, id) in entries.and {
+            self.l3_and_cache.insert(key, id);
+        }
+        for (key, id) in entries.or {
+            self.l3_or_cache.insert(key, id);
+        }
+        for (key, id) in entries.not {
+            self.l3_not_cache.insert(key, id);
+        }
+    }
+
+    fn clear(&self) {
+        self.l3_make_cache.clear();
+        self.l3_and_cache.clear();
+        self.l3_or_cache.clear();
+        self.l3_not_cache.clear();
+        self.l3_and_not_cache.clear();
+        self.l2_encode_rule_cache.clear();
+        self.l2_cal_hit_cache.clear();
+        self.l2_merge_port_space_cache.clear();
+        self.l1_complete_rule_cache.clear();
+    }
 }
 
 // Symbolic encoding of network semantics
-struct Engine;
-static IP_BITS_LEN: OnceCell<usize> = OnceCell::new();
-static IP_BIT_VARIABLES: OnceCell<Vec<BddVariable>> = OnceCell::new();
-static VARIABLE_SET: OnceCell<BddVariableSet> = OnceCell::new();
+struct Engine {
+    ip_bits_len: usize,
+    order: VariableOrder,
+    ip_bit_variables: Vec<BddVariable>,
+    // Field variables, most significant bit first. Intervals don't use them.
+    #[cfg_attr(feature = "interval_packets", allow(dead_code))]
+    dst_ip_variables: Vec<BddVariable>,
+    #[cfg_attr(feature = "interval_packets", allow(dead_code))]
+    device_id_variables: Vec<BddVariable>,
+    variable_set: BddVariableSet,
+}
+
 impl Engine {
-    fn init(ip_bits_len: usize) {
-        let _ = IP_BITS_LEN.set(ip_bits_len);
+    fn new(ip_bits_len: usize, order: VariableOrder) -> Self {
         let mut variable_builder = BddVariableSetBuilder::new();
         let mut ip_bit_variables = Vec::new();
 
@@ -287,87 +739,194 @@ impl Engine {
             ip_bit_variables.push(var);
         }
         let variable_set = variable_builder.build();
-        let _ = IP_BIT_VARIABLES.set(ip_bit_variables);
-        let _ = VARIABLE_SET.set(variable_set);
+        let layout = order.layout(ip_bits_len);
+        let variables_at = |positions: &[usize]| -> Vec<BddVariable> {
+            positions
+                .iter()
+                .filter_map(|&position| ip_bit_variables.get(position).copied())
+                .collect()
+        };
+        let dst_ip_variables = variables_at(&layout.dst_ip);
+        let device_id_variables = variables_at(&layout.device_id);
+        Engine {
+            ip_bits_len,
+            order,
+            ip_bit_variables,
+            dst_ip_variables,
+            device_id_variables,
+            variable_set,
+        }
     }
+}
 
-    fn ip_bits_len() -> usize {
-        *IP_BITS_LEN.get().expect("IP_BITS_LEN not initialized")
+#[cfg(not(feature = "interval_packets"))]
+impl Engine {
+    fn make_none_space_bdd(&self) -> Bdd {
+        self.variable_set.mk_false()
     }
 
-    fn ip_bit_variables() -> &'static [BddVariable] {
-        IP_BIT_VARIABLES
-            .get()
-            .expect("IP_BIT_VARIABLES not initialized")
+    fn make_all_space_bdd(&self) -> Bdd {
+        self.variable_set.mk_true()
     }
 
-    fn variable_set() -> &'static BddVariableSet {
-        VARIABLE_SET.get().expect("VARIABLE_SET not initialized")
+    fn encode_dst_ip_prefix_clause(&self, ip_address: &str, prefix_length: usize) -> Bdd {
+        let bits = prefix_bits(ip_address, prefix_length)
+            .unwrap_or_else(|| panic!("Invalid prefix {}/{}", ip_address, prefix_length));
+        self.encode_dst_ip_prefix_bits(&bits)
     }
 
-    fn make_none_space_bdd() -> Bdd {
-        VARIABLE_SET
-            .get()
-            .expect("VARIABLE_SET not initialized")
-            .mk_false()
+    fn encode_dst_ip_prefix_bits(&self, bits: &[bool]) -> Bdd {
+        if bits.len() > self.dst_ip_variables.len() {
+            panic!(
+                "Prefix of {} bits does not fit the {} destination variables",
+                bits.len(),
+                self.dst_ip_variables.len()
+            );
+        }
+        let values: Vec<(BddVariable, bool)> = self
+            .dst_ip_variables
+            .iter()
+            .copied()
+            .zip(bits.iter().copied())
+            .collect();
+        let clause = BddPartialValuation::from_values(&values);
+        self.variable_set.mk_conjunctive_clause(&clause)
     }
 
-    fn make_all_space_bdd() -> Bdd {
-        VARIABLE_SET
-            .get()
-            .expect("VARIABLE_SET not initialized")
-            .mk_true()
+    fn encode_src_device_constraint(&self, src_device_id: usize) -> Bdd {
+        let bits = self.device_id_variables.len();
+        let values: Vec<(BddVariable, bool)> = self
+            .device_id_variables
+            .iter()
+            .enumerate()
+            .map(|(i, &var)| (var, (src_device_id & (1 << (bits - 1 - i))) != 0))
+            .collect();
+        let clause = BddPartialValuation::from_values(&values);
+        self.variable_set.mk_conjunctive_clause(&clause)
     }
+}
 
-    fn encode_dst_ip_prefix_clause(ip_address: &str, prefix_length: usize) -> Bdd {
-        let _ip_bits_len = Self::ip_bits_len();
-        let variables = Self::ip_bit_variables();
-        let variable_set = Self::variable_set();
-
-        if prefix_length == 0 {
-            return variable_set.mk_true();
-        }
-
-        let ip_addr: IpAddr = ip_address.parse().unwrap();
-        let ip_bits: Vec<bool> = match ip_addr {
-            IpAddr::V4(ipv4) => ipv4
-                .octets()
-                .iter()
-                .flat_map(|&octet| (0..8).rev().map(move |i| (octet & (1 << i)) != 0))
-                .collect(),
-            IpAddr::V6(ipv6) => ipv6
-                .octets()
-                .iter()
-                .flat_map(|&octet| (0..8).rev().map(move |i| (octet & (1 << i)) != 0))
-                .collect(),
-        };
-
-        let mut values = Vec::new();
-        let range_variables = &variables[(144 - prefix_length)..144];
-        for (i, &var) in range_variables.iter().rev().enumerate() {
-            values.push((var, ip_bits[i]));
-        }
+#[cfg(feature = "interval_packets")]
+impl Engine {
+    fn make_none_space_bdd(&self) -> PacketSpace {
+        IntervalSet::empty()
+    }
 
-        let clause = BddPartialValuation::from_values(&values);
-        variable_set.mk_conjunctive_clause(&clause)
+    fn make_all_space_bdd(&self) -> PacketSpace {
+        IntervalSet::full()
     }
 
-    fn encode_src_device_constraint(src_device_id: usize) -> Bdd {
-        let variables = Self::ip_bit_variables();
-        let variable_set = Self::variable_set();
+    fn encode_dst_ip_prefix_clause(&self, ip_address: &str, prefix_length: usize) -> PacketSpace {
+        let bits = prefix_bits(ip_address, prefix_length)
+            .unwrap_or_else(|| panic!("Invalid prefix {}/{}", ip_address, prefix_length));
+        IntervalSet::prefix(&bits)
+    }
 
-        let device_bits: Vec<bool> = (0..16)
-            .rev()
-            .map(|i| (src_device_id & (1 << i)) != 0)
-            .collect();
+    fn encode_dst_ip_prefix_bits(&self, bits: &[bool]) -> PacketSpace {
+        IntervalSet::prefix(bits)
+    }
 
-        let mut values = Vec::new();
-        let range_variables = &variables[0..16];
-        for (i, &var) in range_variables.iter().rev().enumerate() {
-            values.push((var, device_bits[i]));
-        }
+    // Intervals have no source dimension, so the constraint keeps everything.
+    fn encode_src_device_constraint(&self, _src_device_id: usize) -> PacketSpace {
+        IntervalSet::full()
+    }
+}
 
-        let clause = BddPartialValuation::from_values(&values);
-        variable_set.mk_conjunctive_clause(&clause)
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn engine() -> Engine {
+        Engine::new(144, VariableOrder::default())
+    }
+
+    #[test]
+    fn prefix_clause_covers_its_more_specific_prefixes() {
+        let engine = engine();
+        let slash8 = engine.encode_dst_ip_prefix_clause("10.0.0.0", 8);
+        let slash24 = engine.encode_dst_ip_prefix_clause("10.1.2.0", 24);
+        let host = engine.encode_dst_ip_prefix_clause("10.1.2.3", 32);
+        let other = engine.encode_dst_ip_prefix_clause("11.0.0.0", 8);
+        assert!(slash24.is_subset(&slash8));
+        assert!(host.is_subset(&slash24));
+        assert!(!slash8.is_subset(&slash24));
+        assert!(slash8.and(&other).is_empty());
+    }
+
+    #[test]
+    fn prefix_clause_ignores_host_bits() {
+        let engine = engine();
+        assert!(engine.encode_dst_ip_prefix_clause("0.0.0.0", 0).is_full());
+        assert!(
+            engine.encode_dst_ip_prefix_clause("10.1.2.3", 24)
+                == engine.encode_dst_ip_prefix_clause("10.1.2.0", 24)
+        );
+        let v6 = engine.encode_dst_ip_prefix_clause("2001:db8::", 32);
+        assert!(engine
+            .encode_dst_ip_prefix_clause("2001:db8:1::", 48)
+            .is_subset(&v6));
+    }
+
+    #[test]
+    #[should_panic(expected = "Invalid prefix")]
+    fn prefix_clause_rejects_an_overlong_prefix() {
+        engine().encode_dst_ip_prefix_clause("10.0.0.0", 33);
+    }
+
+    #[test]
+    fn cal_hit_leaves_only_the_unmatched_part_of_a_prefix() {
+        let npbdd = NPBDD::new(144);
+        let cache = npbdd.cache();
+        let slash24 = cache.l2_encode_rule("10.0.1.0", 24);
+        let slash16 = cache.l2_encode_rule("10.0.0.0", 16);
+
+        // Rules come longest prefix first; the /24 is hit in full.
+        let (hit, used) = cache.l2_cal_hit(slash24, NONE_SPACE_ID);
+        assert_eq!((hit, used), (slash24, slash24));
+
+        // The /16 only hits what the /24 left, and covers the rest.
+        let (hit, used) = cache.l2_cal_hit(slash16, used);
+        assert_eq!(hit, npbdd.and_not(slash16, slash24));
+        assert_eq!(used, slash16);
+
+        // A prefix that is already used entirely is not hit at all.
+        let (hit, used) = cache.l2_cal_hit(slash24, used);
+        assert_eq!((hit, used), (NONE_SPACE_ID, slash16));
+    }
+
+    #[test]
+    fn cal_hit_is_memoized() {
+        let npbdd = NPBDD::new(144);
+        let cache = npbdd.cache();
+        let prefix = cache.l2_encode_rule("10.0.1.0", 24);
+        let first = cache.l2_cal_hit(prefix, NONE_SPACE_ID);
+        let hits = cache.level_stats()[1].hits;
+        assert_eq!(cache.l2_cal_hit(prefix, NONE_SPACE_ID), first);
+        assert_eq!(cache.level_stats()[1].hits, hits + 1);
+    }
+
+    #[test]
+    fn maintain_skips_flushes_that_cannot_reach_the_budget() {
+        let budget = |table_bytes| CacheBudget {
+            table_bytes,
+            ..CacheBudget::unbounded()
+        };
+        let pinned_bytes = NPBDD::new(144).table().pinned_bytes();
+
+        // The empty and full sets are pinned and already over a tiny budget.
+        let npbdd = NPBDD::with_budget(144, budget(pinned_bytes - 1));
+        npbdd.cache().l2_encode_rule("10.0.0.0", 8);
+        assert!(!npbdd.maintain());
+        let stats = npbdd.cache_stats();
+        assert_eq!((stats.table_flushes, stats.skipped_table_flushes), (0, 1));
+
+        // With room for the pinned sets, unpinned ones are flushed.
+        let npbdd = NPBDD::with_budget(144, budget(pinned_bytes));
+        let prefix = npbdd.cache().l2_encode_rule("10.0.0.0", 8);
+        assert!(npbdd.maintain());
+        assert!(npbdd.table().get_bdd_by_id(prefix).is_none());
+        assert_eq!(npbdd.table().bytes(), pinned_bytes);
+        let stats = npbdd.cache_stats();
+        assert_eq!((stats.table_flushes, stats.skipped_table_flushes), (1, 0));
     }
 }
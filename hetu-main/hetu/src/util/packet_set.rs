@@ -0,0 +1,380 @@
+//! Packet set representation. The verifier works on `PacketSpace`, which is
+//! picked at compile time: BDDs by default, or sorted address intervals with
+//! the `interval_packets` cargo feature. Intervals only cover destination
+//! addresses, so forward traversal, which constrains the source device, needs
+//! the BDD backend.
+
+use crate::util::bounded_cache::CacheWeight;
+use biodivine_lib_bdd::Bdd;
+use std::hash::Hash;
+use std::io;
+use std::mem;
+use std::net::IpAddr;
+
+#[cfg(not(feature = "interval_packets"))]
+pub type PacketSpace = Bdd;
+#[cfg(not(feature = "interval_packets"))]
+pub const PACKET_SET_NAME: &str = "bdd";
+
+#[cfg(feature = "interval_packets")]
+pub type PacketSpace = IntervalSet;
+#[cfg(feature = "interval_packets")]
+pub const PACKET_SET_NAME: &str = "interval";
+
+/// Set operations the verifier needs from a packet representation. Equal
+/// sets must compare and hash equal, since predicates are used as map keys.
+pub trait PacketSet: Clone + Eq + Hash + Send + Sync + CacheWeight + 'static {
+    /// Whether sets can constrain the source device as well as the
+    /// destination address.
+    const HAS_SOURCE: bool;
+
+    fn and(&self, other: &Self) -> Self;
+    fn or(&self, other: &Self) -> Self;
+    fn not(&self) -> Self;
+    fn and_not(&self, other: &Self) -> Self;
+    fn is_empty(&self) -> bool;
+    fn is_full(&self) -> bool;
+
+    fn is_subset(&self, other: &Self) -> bool {
+        self.and_not(other).is_empty()
+    }
+
+    /// Size of the representation: BDD nodes or interval count.
+    fn node_count(&self) -> usize;
+
+    /// Number of BDD variables the set is defined over, `None` for backends
+    /// without variables.
+    fn variable_count(&self) -> Option<usize>;
+
+    fn to_bytes(&self) -> Vec<u8>;
+    fn from_bytes(bytes: &[u8]) -> io::Result<Self>;
+}
+
+impl PacketSet for Bdd {
+    const HAS_SOURCE: bool = true;
+
+    fn and(&self, other: &Self) -> Self {
+        Bdd::and(self, other)
+    }
+
+    fn or(&self, other: &Self) -> Self {
+        Bdd::or(self, other)
+    }
+
+    fn not(&self) -> Self {
+        Bdd::not(self)
+    }
+
+    fn and_not(&self, other: &Self) -> Self {
+        Bdd::and_not(self, other)
+    }
+
+    fn is_empty(&self) -> bool {
+        self.is_false()
+    }
+
+    fn is_full(&self) -> bool {
+        self.is_true()
+    }
+
+    fn node_count(&self) -> usize {
+        self.size()
+    }
+
+    fn variable_count(&self) -> Option<usize> {
+        Some(self.num_vars() as usize)
+    }
+
+    fn to_bytes(&self) -> Vec<u8> {
+        Bdd::to_bytes(self)
+    }
+
+    fn from_bytes(mut bytes: &[u8]) -> io::Result<Self> {
+        if !bytes.len().is_multiple_of(10) {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "malformed BDD"));
+        }
+        Bdd::read_as_bytes(&mut bytes)
+    }
+}
+
+/// Leading `prefix_len` bits of `ip`, most significant first, in the order
+/// `NPBDD::make_prefix_bdd` assigns them to variables.
+pub fn prefix_bits(ip: &str, prefix_len: usize) -> Option<Vec<bool>> {
+    let octets = match ip.parse::<IpAddr>().ok()? {
+        IpAddr::V4(ipv4) => ipv4.octets().to_vec(),
+        IpAddr::V6(ipv6) => ipv6.octets().to_vec(),
+    };
+    if prefix_len > octets.len() * 8 {
+        return None;
+    }
+    Some(
+        (0..prefix_len)
+            .map(|bit| octets[bit / 8] & (0x80 >> (bit % 8)) != 0)
+            .collect(),
+    )
+}
+
+/// Set of destination addresses as sorted, disjoint, non-adjacent inclusive
+/// ranges over a 128-bit address space. Addresses are left-aligned, so an
+/// IPv4 address occupies the top 32 bits, as in the BDD encoding. The ranges
+/// are canonical, so equal sets have equal representations.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash)]
+pub struct IntervalSet {
+    ranges: Vec<(u128, u128)>,
+}
+
+impl IntervalSet {
+    pub fn empty() -> Self {
+        IntervalSet { ranges: Vec::new() }
+    }
+
+    pub fn full() -> Self {
+        IntervalSet {
+            ranges: vec![(0, u128::MAX)],
+        }
+    }
+
+    /// Addresses whose leading bits, most significant first, are `bits`.
+    pub fn prefix(bits: &[bool]) -> Self {
+        assert!(bits.len() <= 128, "prefix longer than 128 bits");
+        let low = bits
+            .iter()
+            .enumerate()
+            .filter(|(_, &bit)| bit)
+            .fold(0u128, |low, (i, _)| low | (1 << (127 - i)));
+        let high = low | u128::MAX.checked_shr(bits.len() as u32).unwrap_or(0);
+        IntervalSet {
+            ranges: vec![(low, high)],
+        }
+    }
+
+    pub fn ranges(&self) -> &[(u128, u128)] {
+        &self.ranges
+    }
+
+    /// Builds a canonical set from ranges in any order.
+    fn from_ranges(mut ranges: Vec<(u128, u128)>) -> Self {
+        ranges.sort_unstable();
+        let mut merged: Vec<(u128, u128)> = Vec::with_capacity(ranges.len());
+        for (low, high) in ranges {
+            match merged.last_mut() {
+                Some((_, last_high)) if low <= last_high.saturating_add(1) => {
+                    *last_high = (*last_high).max(high);
+                }
+                _ => merged.push((low, high)),
+            }
+        }
+        IntervalSet { ranges: merged }
+    }
+}
+
+impl PacketSet for IntervalSet {
+    const HAS_SOURCE: bool = false;
+
+    fn and(&self, other: &Self) -> Self {
+        let mut ranges = Vec::new();
+        let (mut i, mut j) = (0, 0);
+        while i < self.ranges.len() && j < other.ranges.len() {
+            let (a_low, a_high) = self.ranges[i];
+            let (b_low, b_high) = other.ranges[j];
+            let low = a_low.max(b_low);
+            let high = a_high.min(b_high);
+            if low <= high {
+                ranges.push((low, high));
+            }
+            if a_high < b_high {
+                i += 1;
+            } else {
+                j += 1;
+            }
+        }
+        IntervalSet { ranges }
+    }
+
+    fn or(&self, other: &Self) -> Self {
+        let mut ranges = Vec::with_capacity(self.ranges.len() + other.ranges.len());
+        ranges.extend_from_slice(&self.ranges);
+        ranges.extend_from_slice(&other.ranges);
+        IntervalSet::from_ranges(ranges)
+    }
+
+    fn not(&self) -> Self {
+        let mut ranges = Vec::with_capacity(self.ranges.len() + 1);
+        let mut next: Option<u128> = Some(0);
+        for &(low, high) in &self.ranges {
+            if let Some(start) = next {
+                if start < low {
+                    ranges.push((start, low - 1));
+                }
+            }
+            next = high.checked_add(1);
+        }
+        if let Some(start) = next {
+            ranges.push((start, u128::MAX));
+        }
+        IntervalSet { ranges }
+    }
+
+    fn and_not(&self, other: &Self) -> Self {
+        self.and(&other.not())
+    }
+
+    fn is_empty(&self) -> bool {
+        self.ranges.is_empty()
+    }
+
+    fn is_full(&self) -> bool {
+        self.ranges == [(0, u128::MAX)]
+    }
+
+    fn node_count(&self) -> usize {
+        self.ranges.len()
+    }
+
+    fn variable_count(&self) -> Option<usize> {
+        None
+    }
+
+    fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(self.ranges.len() * 32);
+        for (low, high) in &self.ranges {
+            bytes.extend_from_slice(&low.to_le_bytes());
+            bytes.extend_from_slice(&high.to_le_bytes());
+        }
+        bytes
+    }
+
+    fn from_bytes(bytes: &[u8]) -> io::Result<Self> {
+        let invalid = || io::Error::new(io::ErrorKind::InvalidData, "malformed interval set");
+        if !bytes.len().is_multiple_of(32) {
+            return Err(invalid());
+        }
+        let ranges: Vec<(u128, u128)> = bytes
+            .chunks_exact(32)
+            .map(|chunk| {
+                let low = u128::from_le_bytes(chunk[..16].try_into().unwrap());
+                let high = u128::from_le_bytes(chunk[16..].try_into().unwrap());
+                (low, high)
+            })
+            .collect();
+        let canonical = ranges.windows(2).all(|pair| {
+            pair[0]
+                .1
+                .checked_add(1)
+                .is_some_and(|next| next < pair[1].0)
+        });
+        if !canonical || ranges.iter().any(|(low, high)| low > high) {
+            return Err(invalid());
+        }
+        Ok(IntervalSet { ranges })
+    }
+}
+
+impl CacheWeight for IntervalSet {
+    fn weight(&self) -> usize {
+        mem::size_of::<IntervalSet>() + self.ranges.capacity() * mem::size_of::<(u128, u128)>()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TOP: u128 = 1 << 127;
+
+    fn bits(spec: &str) -> Vec<bool> {
+        spec.chars().map(|bit| bit == '1').collect()
+    }
+
+    fn prefix(spec: &str) -> IntervalSet {
+        IntervalSet::prefix(&bits(spec))
+    }
+
+    /// Sets built from a handful of prefixes, including overlapping and
+    /// adjacent ones.
+    fn samples() -> Vec<IntervalSet> {
+        let mut samples = vec![IntervalSet::empty(), IntervalSet::full()];
+        for spec in ["0", "1", "01", "011", "10", "0110"] {
+            samples.push(prefix(spec));
+        }
+        samples.push(prefix("00").or(&prefix("11")));
+        samples.push(prefix("010").or(&prefix("1")));
+        samples
+    }
+
+    #[test]
+    fn prefix_covers_its_aligned_range() {
+        assert_eq!(prefix("").ranges(), &[(0, u128::MAX)]);
+        assert_eq!(prefix("1").ranges(), &[(TOP, u128::MAX)]);
+        assert_eq!(prefix("01").ranges(), &[(TOP >> 1, TOP - 1)]);
+        let full_length = IntervalSet::prefix(&[true; 128]);
+        assert_eq!(full_length.ranges(), &[(u128::MAX, u128::MAX)]);
+    }
+
+    #[test]
+    fn prefix_bits_match_the_interval_encoding() {
+        let ten = prefix_bits("10.0.0.0", 8).unwrap();
+        assert_eq!(
+            IntervalSet::prefix(&ten).ranges(),
+            &[(10 << 120, (11 << 120) - 1)]
+        );
+    }
+
+    #[test]
+    fn or_merges_overlapping_and_adjacent_ranges() {
+        assert_eq!(prefix("00").or(&prefix("01")), prefix("0"));
+        assert_eq!(prefix("0").or(&prefix("011")), prefix("0"));
+        assert_eq!(prefix("0").or(&prefix("1")), IntervalSet::full());
+        assert_eq!(prefix("00").or(&prefix("11")).ranges().len(), 2);
+    }
+
+    #[test]
+    fn not_and_and_agree_with_set_laws() {
+        let empty = IntervalSet::empty();
+        let full = IntervalSet::full();
+        assert_eq!(empty.not(), full);
+        assert_eq!(full.not(), empty);
+        assert_eq!(prefix("0").not(), prefix("1"));
+        for a in samples() {
+            assert_eq!(a.not().not(), a);
+            assert!(a.and(&a.not()).is_empty());
+            assert!(a.or(&a.not()).is_full());
+            for b in samples() {
+                assert_eq!(a.and(&b), b.and(&a));
+                assert_eq!(a.and(&b).not(), a.not().or(&b.not()));
+                assert_eq!(a.and_not(&b), a.and(&b.not()));
+                assert_eq!(a.is_subset(&b), a.or(&b) == b);
+            }
+        }
+    }
+
+    #[test]
+    fn interval_sets_round_trip_through_bytes() {
+        for set in samples() {
+            assert_eq!(IntervalSet::from_bytes(&set.to_bytes()).unwrap(), set);
+        }
+    }
+
+    #[test]
+    fn from_bytes_rejects_non_canonical_ranges() {
+        let encode = |ranges: &[(u128, u128)]| {
+            let mut bytes = Vec::new();
+            for (low, high) in ranges {
+                bytes.extend_from_slice(&low.to_le_bytes());
+                bytes.extend_from_slice(&high.to_le_bytes());
+            }
+            bytes
+        };
+        let mut truncated = prefix("1").to_bytes();
+        truncated.pop();
+        for bytes in [
+            truncated,
+            encode(&[(5, 4)]),
+            encode(&[(0, 4), (5, 9)]),
+            encode(&[(6, 9), (0, 4)]),
+            encode(&[(0, 9), (5, 12)]),
+        ] {
+            assert!(IntervalSet::from_bytes(&bytes).is_err());
+        }
+    }
+}
@@ -0,0 +1,225 @@
+use std::fmt;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ProgressEvent {
+    /// Devices whose rules have been encoded in this run.
+    DevicesEncoded { done: usize, total: usize },
+    /// Neighborhoods verified, with the remaining time extrapolated from the
+    /// average so far.
+    NeighborhoodsVerified {
+        done: usize,
+        total: usize,
+        eta: Option<Duration>,
+    },
+}
+
+impl fmt::Display for ProgressEvent {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ProgressEvent::DevicesEncoded { done, total } => {
+                write!(f, "Encoded {}/{} devices", done, total)
+            }
+            ProgressEvent::NeighborhoodsVerified { done, total, eta } => {
+                write!(f, "Verified {}/{} neighborhoods", done, total)?;
+                match eta {
+                    Some(eta) => write!(f, ", ETA {:.1?}", eta),
+                    None => Ok(()),
+                }
+            }
+        }
+    }
+}
+
+/// Receives progress events; called from worker threads.
+pub type ProgressCallback = Arc<dyn Fn(ProgressEvent) + Send + Sync>;
+
+/// Stops a run between devices or neighborhoods. Clones share the flag, so
+/// another thread can cancel through its own clone. A token with a deadline
+/// also counts as cancelled once the deadline has passed.
+#[derive(Debug, Clone, Default)]
+pub struct CancellationToken {
+    cancelled: Arc<AtomicBool>,
+    deadline: Option<Instant>,
+}
+
+impl CancellationToken {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The same token, also cancelled `budget` from now.
+    pub fn with_budget(mut self, budget: Duration) -> Self {
+        let deadline = Instant::now() + budget;
+        self.deadline = Some(self.deadline.map_or(deadline, |old| old.min(deadline)));
+        self
+    }
+
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::Relaxed)
+            || self
+                .deadline
+                .is_some_and(|deadline| Instant::now() >= deadline)
+    }
+}
+
+/// Cancellation and progress reporting of one simulator.
+#[derive(Clone, Default)]
+pub struct RunControl {
+    pub cancellation: CancellationToken,
+    pub progress: Option<ProgressCallback>,
+}
+
+impl RunControl {
+    pub fn is_cancelled(&self) -> bool {
+        self.cancellation.is_cancelled()
+    }
+
+    /// Counter reporting devices encoded out of `total`.
+    pub fn encode_counter(&self, total: usize) -> ProgressCounter {
+        ProgressCounter::new(self.progress.clone(), total, |done, total, _| {
+            ProgressEvent::DevicesEncoded { done, total }
+        })
+    }
+
+    /// Counter reporting neighborhoods verified out of `total`.
+    pub fn verify_counter(&self, total: usize) -> ProgressCounter {
+        ProgressCounter::new(self.progress.clone(), total, |done, total, eta| {
+            ProgressEvent::NeighborhoodsVerified { done, total, eta }
+        })
+    }
+}
+
+type EventBuilder = fn(usize, usize, Option<Duration>) -> ProgressEvent;
+
+/// Counts finished items and reports about once per percent, plus the last.
+pub struct ProgressCounter {
+    callback: Option<ProgressCallback>,
+    total: usize,
+    step: usize,
+    done: AtomicUsize,
+    start: Instant,
+    event: EventBuilder,
+}
+
+impl ProgressCounter {
+    fn new(callback: Option<ProgressCallback>, total: usize, event: EventBuilder) -> Self {
+        ProgressCounter {
+            callback,
+            total,
+            step: (total / 100).max(1),
+            done: AtomicUsize::new(0),
+            start: Instant::now(),
+            event,
+        }
+    }
+
+    pub fn tick(&self) {
+        let Some(callback) = &self.callback else {
+            return;
+        };
+        let done = self.done.fetch_add(1, Ordering::Relaxed) + 1;
+        if !done.is_multiple_of(self.step) && done != self.total {
+            return;
+        }
+        let eta = (done < self.total).then(|| {
+            self.start
+                .elapsed()
+                .mul_f64((self.total - done) as f64 / done as f64)
+        });
+        callback((self.event)(done, self.total, eta));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+
+    #[test]
+    fn clones_share_cancellation() {
+        let token = CancellationToken::new();
+        let clone = token.clone();
+        assert!(!token.is_cancelled());
+        clone.cancel();
+        assert!(token.is_cancelled());
+    }
+
+    #[test]
+    fn budgets_keep_the_earliest_deadline() {
+        assert!(CancellationToken::new()
+            .with_budget(Duration::ZERO)
+            .is_cancelled());
+        let token = CancellationToken::new().with_budget(Duration::from_secs(3600));
+        assert!(!token.is_cancelled());
+        assert!(token.clone().with_budget(Duration::ZERO).is_cancelled());
+        assert!(CancellationToken::new()
+            .with_budget(Duration::ZERO)
+            .with_budget(Duration::from_secs(3600))
+            .is_cancelled());
+    }
+
+    #[test]
+    fn counters_report_about_once_per_percent_and_the_last_item() {
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let sink = events.clone();
+        let control = RunControl {
+            progress: Some(Arc::new(move |event| sink.lock().unwrap().push(event))),
+            ..RunControl::default()
+        };
+        let counter = control.encode_counter(250);
+        for _ in 0..250 {
+            counter.tick();
+        }
+        let done: Vec<usize> = events
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|event| match event {
+                ProgressEvent::DevicesEncoded { done, total } => {
+                    assert_eq!(*total, 250);
+                    *done
+                }
+                _ => panic!("unexpected event {:?}", event),
+            })
+            .collect();
+        let expected: Vec<usize> = (2..=250).step_by(2).collect();
+        assert_eq!(done, expected);
+    }
+
+    #[test]
+    fn the_last_verify_event_has_no_eta() {
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let sink = events.clone();
+        let control = RunControl {
+            progress: Some(Arc::new(move |event| sink.lock().unwrap().push(event))),
+            ..RunControl::default()
+        };
+        let counter = control.verify_counter(2);
+        counter.tick();
+        counter.tick();
+        let events = events.lock().unwrap();
+        assert!(matches!(
+            events[0],
+            ProgressEvent::NeighborhoodsVerified {
+                done: 1,
+                total: 2,
+                eta: Some(_)
+            }
+        ));
+        assert_eq!(
+            events[1],
+            ProgressEvent::NeighborhoodsVerified {
+                done: 2,
+                total: 2,
+                eta: None
+            }
+        );
+    }
+}
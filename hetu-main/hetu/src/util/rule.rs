@@ -21,9 +21,9 @@ impl Rule {
     pub fn new(prefix_len: usize, ip: String, forward_type: String, ports: Vec<String>) -> Self {
         let forward_action: ForwardAction = ForwardAction::new(forward_type, ports);
         Rule {
-            forward_action: forward_action,
-            prefix_len: prefix_len,
-            ip: ip,
+            forward_action,
+            prefix_len,
+            ip,
         }
     }
 
@@ -31,9 +31,9 @@ impl Rule {
         let forward_action: ForwardAction =
             ForwardAction::new("packet_space".to_string(), Vec::new());
         Rule {
-            forward_action: forward_action,
-            prefix_len: prefix_len,
-            ip: ip,
+            forward_action,
+            prefix_len,
+            ip,
         }
     }
 
@@ -52,6 +52,10 @@ impl Rule {
     pub fn get_forward_action(&self) -> ForwardAction {
         self.forward_action.clone()
     }
+
+    pub fn get_ports(&self) -> &Vec<String> {
+        self.forward_action.get_ports()
+    }
 }
 
 impl Hash for Rule {
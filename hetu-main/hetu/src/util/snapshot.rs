@@ -0,0 +1,238 @@
+use crate::util::codec::{content_hash, invalid_data, Decoder, Encoder};
+use crate::util::encoding_cache::EncodedDevice;
+use crate::util::network::Topology;
+use crate::util::packet_set::{prefix_bits, PacketSet, PacketSpace, PACKET_SET_NAME};
+use crate::util::var_order::VariableOrder;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+const MAGIC: &[u8; 4] = b"HTSN";
+const VERSION: u16 = 5;
+/// Length of the trailing checksum.
+const CHECKSUM_BYTES: usize = 8;
+
+pub struct SnapshotDevice {
+    pub name: String,
+    pub device_id: usize,
+    /// Packet-space entries owned by the device, as (prefix, prefix length).
+    pub packet_space: Vec<(String, usize)>,
+    pub subnet_space: PacketSpace,
+    pub encoding: EncodedDevice,
+}
+
+/// Fully encoded network, detached from the route files it was built from.
+///
+/// Layout (little-endian): magic `HTSN`, `u16` version, the packet backend
+/// name, then the fields below in declaration order. Strings, packet sets and
+/// lists are length-prefixed with a `u64`; packet sets use the backend's
+/// `PacketSet::to_bytes` encoding. A `u64` FNV-1a checksum of everything
+/// before it closes the file.
+pub struct NetworkSnapshot {
+    pub ip_bits: usize,
+    /// BDD variable layout the packet sets were encoded with.
+    pub variable_order: VariableOrder,
+    pub common_prefix: String,
+    pub edge_devices: Vec<String>,
+    pub dst_devices: Vec<String>,
+    pub links: Vec<Topology>,
+    pub devices: Vec<SnapshotDevice>,
+    pub packet_space_bdds: Vec<(String, PacketSpace)>,
+    pub all_space: Option<PacketSpace>,
+}
+
+impl NetworkSnapshot {
+    pub fn save(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        let mut encoder = Encoder::with_header(MAGIC, VERSION);
+        encoder.put_str(PACKET_SET_NAME);
+        encoder.put_u64(self.ip_bits as u64);
+        encoder.put_str(&self.variable_order.to_string());
+        encoder.put_str(&self.common_prefix);
+        for names in [&self.edge_devices, &self.dst_devices] {
+            encoder.put_len(names.len());
+            for name in names {
+                encoder.put_str(name);
+            }
+        }
+
+        encoder.put_len(self.links.len());
+        for link in self.links.iter() {
+            encoder.put_str(&link.src_node);
+            encoder.put_str(&link.src_port);
+            encoder.put_str(&link.dst_node);
+            encoder.put_str(&link.dst_port);
+        }
+
+        encoder.put_len(self.devices.len());
+        for device in self.devices.iter() {
+            encoder.put_str(&device.name);
+            encoder.put_u64(device.device_id as u64);
+            encoder.put_len(device.packet_space.len());
+            for (prefix, prefix_len) in device.packet_space.iter() {
+                encoder.put_str(prefix);
+                encoder.put_u64(*prefix_len as u64);
+            }
+            encoder.put_bdd(&device.subnet_space);
+            device.encoding.write(&mut encoder);
+        }
+
+        encoder.put_len(self.packet_space_bdds.len());
+        for (name, bdd) in self.packet_space_bdds.iter() {
+            encoder.put_str(name);
+            encoder.put_bdd(bdd);
+        }
+        match &self.all_space {
+            Some(bdd) => {
+                encoder.put_u8(1);
+                encoder.put_bdd(bdd);
+            }
+            None => encoder.put_u8(0),
+        }
+
+        let mut bytes = encoder.into_bytes();
+        let checksum = content_hash(&bytes);
+        bytes.extend_from_slice(&checksum.to_le_bytes());
+
+        let path = path.as_ref();
+        let tmp_path = path.with_extension("tmp");
+        fs::write(&tmp_path, bytes)?;
+        fs::rename(tmp_path, path)
+    }
+
+    /// Reads and validates a snapshot written by [`NetworkSnapshot::save`].
+    pub fn load(path: impl AsRef<Path>) -> io::Result<Self> {
+        let data = fs::read(path)?;
+        let version = Decoder::new(&data).expect_header(MAGIC)?;
+        if version != VERSION {
+            return Err(invalid_data(&format!(
+                "unsupported snapshot version {}",
+                version
+            )));
+        }
+        let (payload, checksum) = data.split_at(data.len().saturating_sub(CHECKSUM_BYTES));
+        if checksum.len() < CHECKSUM_BYTES
+            || u64::from_le_bytes(checksum.try_into().unwrap()) != content_hash(payload)
+        {
+            return Err(invalid_data("snapshot checksum mismatch"));
+        }
+        let mut decoder = Decoder::new(payload);
+        decoder.expect_header(MAGIC)?;
+        let backend = decoder.get_string()?;
+        if backend != PACKET_SET_NAME {
+            return Err(invalid_data(&format!(
+                "snapshot uses the {} packet backend, this build uses {}",
+                backend, PACKET_SET_NAME
+            )));
+        }
+        let ip_bits = decoder.get_u64()? as usize;
+        let variable_order: VariableOrder = decoder
+            .get_string()?
+            .parse()
+            .map_err(|err: String| invalid_data(&err))?;
+        let common_prefix = decoder.get_string()?;
+        let mut name_lists = [Vec::new(), Vec::new()];
+        for names in name_lists.iter_mut() {
+            let count = decoder.get_len()?;
+            for _ in 0..count {
+                names.push(decoder.get_string()?);
+            }
+        }
+        let [edge_devices, dst_devices] = name_lists;
+
+        let link_count = decoder.get_len()?;
+        let mut links = Vec::with_capacity(link_count);
+        for _ in 0..link_count {
+            links.push(Topology {
+                src_node: decoder.get_string()?,
+                src_port: decoder.get_string()?,
+                dst_node: decoder.get_string()?,
+                dst_port: decoder.get_string()?,
+            });
+        }
+
+        let device_count = decoder.get_len()?;
+        let mut devices = Vec::with_capacity(device_count);
+        for _ in 0..device_count {
+            let name = decoder.get_string()?;
+            let device_id = decoder.get_u64()? as usize;
+            let prefix_count = decoder.get_len()?;
+            let mut packet_space = Vec::with_capacity(prefix_count);
+            for _ in 0..prefix_count {
+                packet_space.push((decoder.get_string()?, decoder.get_u64()? as usize));
+            }
+            devices.push(SnapshotDevice {
+                name,
+                device_id,
+                packet_space,
+                subnet_space: decoder.get_bdd()?,
+                encoding: EncodedDevice::read(&mut decoder)?,
+            });
+        }
+
+        let space_count = decoder.get_len()?;
+        let mut packet_space_bdds = Vec::with_capacity(space_count);
+        for _ in 0..space_count {
+            packet_space_bdds.push((decoder.get_string()?, decoder.get_bdd()?));
+        }
+        let all_space = match decoder.get_u8()? {
+            0 => None,
+            _ => Some(decoder.get_bdd()?),
+        };
+        if !decoder.is_empty() {
+            return Err(invalid_data("trailing data after snapshot"));
+        }
+
+        let snapshot = NetworkSnapshot {
+            ip_bits,
+            variable_order,
+            common_prefix,
+            edge_devices,
+            dst_devices,
+            links,
+            devices,
+            packet_space_bdds,
+            all_space,
+        };
+        snapshot.validate().map_err(|err| invalid_data(&err))?;
+        Ok(snapshot)
+    }
+
+    /// Checks what `Simulator::from_snapshot` relies on: a variable count the
+    /// order can lay out, packet sets over exactly that many variables and
+    /// well-formed packet-space prefixes.
+    pub fn validate(&self) -> Result<(), String> {
+        if self.ip_bits == 0 || self.ip_bits > u16::MAX as usize {
+            return Err(format!("snapshot has {} BDD variables", self.ip_bits));
+        }
+        self.variable_order.check(self.ip_bits)?;
+
+        let mut sets: Vec<&PacketSpace> = Vec::new();
+        for device in self.devices.iter() {
+            for (prefix, prefix_len) in device.packet_space.iter() {
+                if prefix_bits(prefix, *prefix_len).is_none() {
+                    return Err(format!(
+                        "{} owns an invalid packet space {}/{}",
+                        device.name, prefix, prefix_len
+                    ));
+                }
+            }
+            sets.push(&device.subnet_space);
+            sets.extend(device.encoding.space_ports.iter().map(|(_, space)| space));
+            sets.push(&device.encoding.forwardable_space);
+        }
+        sets.extend(self.packet_space_bdds.iter().map(|(_, space)| space));
+        sets.extend(self.all_space.iter());
+        for set in sets {
+            match set.variable_count() {
+                Some(count) if count != self.ip_bits => {
+                    return Err(format!(
+                        "packet set over {} variables in a snapshot of {}",
+                        count, self.ip_bits
+                    ));
+                }
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+}
@@ -1,34 +1,34 @@
-use biodivine_lib_bdd::Bdd;
-use hashbrown::HashSet;
+use crate::util::hash_utils::HashSet;
+use crate::util::npbdd::SpaceId;
 use std::hash::{Hash, Hasher};
 
 #[derive(Debug, Clone)]
 pub struct SpacePort {
     space_id: i8,
-    space: Bdd,
-    cache_table: HashSet<Bdd>,
+    predicate: SpaceId,
+    cache_table: HashSet<SpaceId>,
 }
 
 impl Hash for SpacePort {
     fn hash<H: Hasher>(&self, state: &mut H) {
         self.space_id.hash(state);
-        self.space.hash(state);
+        self.predicate.hash(state);
     }
 }
 
 impl PartialEq for SpacePort {
     fn eq(&self, other: &Self) -> bool {
-        self.space_id == other.space_id && self.space == other.space
+        self.space_id == other.space_id && self.predicate == other.predicate
     }
 }
 
 impl Eq for SpacePort {}
 
 impl SpacePort {
-    pub fn new(space_id: i8, space: Bdd) -> SpacePort {
+    pub fn new(space_id: i8, predicate: SpaceId) -> SpacePort {
         SpacePort {
             space_id,
-            space,
+            predicate,
             cache_table: HashSet::default(),
         }
     }
@@ -37,15 +37,16 @@ impl SpacePort {
         self.space_id
     }
 
-    pub fn get_space(&self) -> &Bdd {
-        &self.space
+    /// Interned predicate of the port.
+    pub fn get_predicate(&self) -> SpaceId {
+        self.predicate
     }
 
-    pub fn check_cache_space(&self, cache_space: &Bdd) -> bool {
-        self.cache_table.contains(cache_space)
+    pub fn check_cache_space(&self, cache_space: SpaceId) -> bool {
+        self.cache_table.contains(&cache_space)
     }
 
-    pub fn insert_cache_space(&mut self, cache_space: &Bdd) {
-        self.cache_table.insert(cache_space.clone());
+    pub fn insert_cache_space(&mut self, cache_space: SpaceId) {
+        self.cache_table.insert(cache_space);
     }
 }
@@ -0,0 +1,243 @@
+use crate::util::hash_utils::HashSet;
+use crate::util::packet_set::{PacketSet, PacketSpace};
+use std::fmt;
+use std::str::FromStr;
+
+/// Width of the source-device field used by forward traversal.
+pub const DEVICE_ID_BITS: usize = 16;
+
+/// Position of every packet field bit in the BDD variable order. Variable 0
+/// is the root of every BDD.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum VariableOrder {
+    /// Original layout: device id on the first 16 variables, destination bits
+    /// reversed from the last variable up, so the address MSB is tested last.
+    /// Device-id and destination variables overlap when the address needs more
+    /// than `ip_bits - 16` bits.
+    #[default]
+    Reversed,
+    /// Device id first, then the destination address MSB first.
+    MsbFirst,
+    /// Device-id and destination bits alternate, MSB first, for the first 16
+    /// bits of each field; the rest of the address follows.
+    Interleaved,
+    /// Destination address MSB first, then the device id.
+    DeviceLast,
+}
+
+impl VariableOrder {
+    pub const ALL: [VariableOrder; 4] = [
+        VariableOrder::Reversed,
+        VariableOrder::MsbFirst,
+        VariableOrder::Interleaved,
+        VariableOrder::DeviceLast,
+    ];
+
+    /// Whether the order can lay out `ip_bits_len` variables. Every order but
+    /// `Reversed` keeps the device id apart from the destination address.
+    pub fn check(&self, ip_bits_len: usize) -> Result<(), String> {
+        if *self != VariableOrder::Reversed && ip_bits_len <= DEVICE_ID_BITS {
+            return Err(format!(
+                "{} order needs more than {} variables, got {}",
+                self, DEVICE_ID_BITS, ip_bits_len
+            ));
+        }
+        Ok(())
+    }
+
+    /// Variable positions of each field for `ip_bits_len` variables.
+    pub fn layout(&self, ip_bits_len: usize) -> VariableLayout {
+        if let Err(err) = self.check(ip_bits_len) {
+            panic!("{}", err);
+        }
+        if *self == VariableOrder::Reversed {
+            return VariableLayout {
+                dst_ip: (0..ip_bits_len).rev().collect(),
+                device_id: (0..DEVICE_ID_BITS).rev().collect(),
+            };
+        }
+        let dst_len = ip_bits_len - DEVICE_ID_BITS;
+        let (dst_ip, device_id) = match self {
+            VariableOrder::Reversed => unreachable!(),
+            VariableOrder::MsbFirst => (
+                (DEVICE_ID_BITS..ip_bits_len).collect(),
+                (0..DEVICE_ID_BITS).collect(),
+            ),
+            VariableOrder::DeviceLast => ((0..dst_len).collect(), (dst_len..ip_bits_len).collect()),
+            VariableOrder::Interleaved => {
+                let paired = dst_len.min(DEVICE_ID_BITS);
+                let position = |bit: usize, offset: usize| {
+                    if bit < paired {
+                        2 * bit + offset
+                    } else {
+                        paired + bit
+                    }
+                };
+                (
+                    (0..dst_len).map(|bit| position(bit, 1)).collect(),
+                    (0..DEVICE_ID_BITS).map(|bit| position(bit, 0)).collect(),
+                )
+            }
+        };
+        VariableLayout { dst_ip, device_id }
+    }
+}
+
+impl FromStr for VariableOrder {
+    type Err = String;
+
+    fn from_str(spec: &str) -> Result<Self, Self::Err> {
+        match spec {
+            "reversed" => Ok(VariableOrder::Reversed),
+            "msb-first" => Ok(VariableOrder::MsbFirst),
+            "interleaved" => Ok(VariableOrder::Interleaved),
+            "device-last" => Ok(VariableOrder::DeviceLast),
+            _ => Err(format!("unknown variable order: {}", spec)),
+        }
+    }
+}
+
+impl fmt::Display for VariableOrder {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            VariableOrder::Reversed => "reversed",
+            VariableOrder::MsbFirst => "msb-first",
+            VariableOrder::Interleaved => "interleaved",
+            VariableOrder::DeviceLast => "device-last",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// Variable positions of each field, most significant bit first.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VariableLayout {
+    pub dst_ip: Vec<usize>,
+    pub device_id: Vec<usize>,
+}
+
+/// Size of the encoded predicates under one variable order.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PredicateSizes {
+    pub order: VariableOrder,
+    pub port_predicates: usize,
+    pub distinct_predicates: usize,
+    /// Nodes summed over distinct port predicates.
+    pub port_nodes: usize,
+    pub max_port_nodes: usize,
+    /// Nodes summed over the destination packet spaces.
+    pub packet_space_nodes: usize,
+}
+
+impl PredicateSizes {
+    pub fn new<'a>(
+        order: VariableOrder,
+        port_predicates: impl IntoIterator<Item = &'a PacketSpace>,
+        packet_spaces: impl IntoIterator<Item = &'a PacketSpace>,
+    ) -> Self {
+        let mut sizes = PredicateSizes {
+            order,
+            ..PredicateSizes::default()
+        };
+        let mut distinct: HashSet<&PacketSpace> = HashSet::default();
+        for predicate in port_predicates {
+            sizes.port_predicates += 1;
+            if distinct.insert(predicate) {
+                let nodes = predicate.node_count();
+                sizes.port_nodes += nodes;
+                sizes.max_port_nodes = sizes.max_port_nodes.max(nodes);
+            }
+        }
+        sizes.distinct_predicates = distinct.len();
+        sizes.packet_space_nodes = packet_spaces
+            .into_iter()
+            .map(|space| space.node_count())
+            .sum();
+        sizes
+    }
+}
+
+impl fmt::Display for PredicateSizes {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Predicate sizes ({} order): {} port predicates, {} distinct, {} nodes, max {}; \
+             packet spaces: {} nodes",
+            self.order,
+            self.port_predicates,
+            self.distinct_predicates,
+            self.port_nodes,
+            self.max_port_nodes,
+            self.packet_space_nodes
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn orders_round_trip_through_their_names() {
+        for order in VariableOrder::ALL {
+            assert_eq!(order.to_string().parse::<VariableOrder>(), Ok(order));
+        }
+        assert!("msb".parse::<VariableOrder>().is_err());
+    }
+
+    #[test]
+    fn reversed_layout_tests_the_address_msb_last() {
+        let layout = VariableOrder::Reversed.layout(20);
+        assert_eq!(layout.dst_ip, (0..20).rev().collect::<Vec<_>>());
+        assert_eq!(layout.device_id, (0..16).rev().collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn other_layouts_place_each_field_as_documented() {
+        let msb_first = VariableOrder::MsbFirst.layout(20);
+        assert_eq!(msb_first.device_id, (0..16).collect::<Vec<_>>());
+        assert_eq!(msb_first.dst_ip, vec![16, 17, 18, 19]);
+
+        let device_last = VariableOrder::DeviceLast.layout(20);
+        assert_eq!(device_last.dst_ip, vec![0, 1, 2, 3]);
+        assert_eq!(device_last.device_id, (4..20).collect::<Vec<_>>());
+
+        let interleaved = VariableOrder::Interleaved.layout(20);
+        assert_eq!(interleaved.dst_ip, vec![1, 3, 5, 7]);
+        assert_eq!(
+            interleaved.device_id,
+            vec![0, 2, 4, 6, 8, 9, 10, 11, 12, 13, 14, 15, 16, 17, 18, 19]
+        );
+    }
+
+    #[test]
+    fn non_reversed_layouts_use_every_variable_once() {
+        for ip_bits_len in [17, 32, 48, 144] {
+            for order in &VariableOrder::ALL[1..] {
+                let layout = order.layout(ip_bits_len);
+                assert_eq!(layout.dst_ip.len(), ip_bits_len - DEVICE_ID_BITS);
+                assert_eq!(layout.device_id.len(), DEVICE_ID_BITS);
+                let mut positions: Vec<usize> = layout
+                    .dst_ip
+                    .iter()
+                    .chain(&layout.device_id)
+                    .copied()
+                    .collect();
+                positions.sort_unstable();
+                assert_eq!(
+                    positions,
+                    (0..ip_bits_len).collect::<Vec<_>>(),
+                    "{} with {} variables",
+                    order,
+                    ip_bits_len
+                );
+            }
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "needs more than 16 variables")]
+    fn non_reversed_layouts_need_room_for_the_device_id() {
+        VariableOrder::MsbFirst.layout(16);
+    }
+}
@@ -0,0 +1,264 @@
+use crate::util::codec::{invalid_data, Decoder, Encoder};
+use crate::verifier::report::ReachabilityReport;
+use crate::verifier::scheduler::ScheduledNeighborhood;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+const PLAN_MAGIC: &[u8; 4] = b"HTWP";
+const RESULT_MAGIC: &[u8; 4] = b"HTWR";
+const VERSION: u16 = 2;
+
+/// Neighborhood by device names, since space ids are only meaningful inside
+/// the process that interned them.
+pub struct PlannedNeighborhood {
+    pub marked_nodes: Vec<String>,
+    pub normal_nodes: Vec<String>,
+    pub weight: usize,
+}
+
+/// Neighborhoods of one distributed run and the ids each worker verifies.
+/// An id is an index into `neighborhoods`.
+///
+/// Layout (little-endian): magic `HTWP`, `u16` version, the neighborhoods as
+/// a list of (marked names, normal names, `u64` weight), then one `u32` id
+/// list per worker. Lists and strings are length-prefixed with a `u64`.
+pub struct WorkPlan {
+    pub neighborhoods: Vec<PlannedNeighborhood>,
+    pub assignments: Vec<Vec<u32>>,
+}
+
+impl WorkPlan {
+    /// Deals the plan out in order, so every worker gets a share of the
+    /// costliest neighborhoods.
+    pub fn new(plan: &[ScheduledNeighborhood], workers: usize) -> Self {
+        let neighborhoods = plan
+            .iter()
+            .map(|scheduled| {
+                let neighborhood = &scheduled.neighborhood;
+                let mut marked_nodes: Vec<String> =
+                    neighborhood.get_marked_nodes().keys().cloned().collect();
+                let mut normal_nodes: Vec<String> =
+                    neighborhood.get_normal_nodes().iter().cloned().collect();
+                marked_nodes.sort();
+                normal_nodes.sort();
+                PlannedNeighborhood {
+                    marked_nodes,
+                    normal_nodes,
+                    weight: neighborhood.weight(),
+                }
+            })
+            .collect();
+        let workers = workers.max(1);
+        let mut assignments = vec![Vec::new(); workers];
+        for id in 0..plan.len() {
+            assignments[id % workers].push(id as u32);
+        }
+        WorkPlan {
+            neighborhoods,
+            assignments,
+        }
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        let mut encoder = Encoder::with_header(PLAN_MAGIC, VERSION);
+        encoder.put_len(self.neighborhoods.len());
+        for neighborhood in &self.neighborhoods {
+            for names in [&neighborhood.marked_nodes, &neighborhood.normal_nodes] {
+                encoder.put_len(names.len());
+                for name in names {
+                    encoder.put_str(name);
+                }
+            }
+            encoder.put_u64(neighborhood.weight as u64);
+        }
+        encoder.put_len(self.assignments.len());
+        for ids in &self.assignments {
+            encoder.put_u32_slice(ids);
+        }
+        write_atomically(path.as_ref(), encoder.into_bytes())
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> io::Result<Self> {
+        let data = fs::read(path)?;
+        let mut decoder = Decoder::new(&data);
+        check_version(decoder.expect_header(PLAN_MAGIC)?, "work plan")?;
+        let count = decoder.get_len()?;
+        let mut neighborhoods = Vec::with_capacity(count);
+        for _ in 0..count {
+            let mut name_lists = [Vec::new(), Vec::new()];
+            for names in name_lists.iter_mut() {
+                let count = decoder.get_len()?;
+                for _ in 0..count {
+                    names.push(decoder.get_string()?);
+                }
+            }
+            let [marked_nodes, normal_nodes] = name_lists;
+            neighborhoods.push(PlannedNeighborhood {
+                marked_nodes,
+                normal_nodes,
+                weight: decoder.get_u64()? as usize,
+            });
+        }
+        let workers = decoder.get_len()?;
+        let mut assignments = Vec::with_capacity(workers);
+        for _ in 0..workers {
+            let ids = decoder.get_u32_vec()?;
+            if ids.iter().any(|&id| id as usize >= neighborhoods.len()) {
+                return Err(invalid_data("neighborhood id out of range"));
+            }
+            assignments.push(ids);
+        }
+        if !decoder.is_empty() {
+            return Err(invalid_data("trailing data after work plan"));
+        }
+        Ok(WorkPlan {
+            neighborhoods,
+            assignments,
+        })
+    }
+}
+
+/// Reports of the neighborhoods one worker verified, keyed by id.
+///
+/// Layout (little-endian): magic `HTWR`, `u16` version, `u64` worker index,
+/// then a list of (`u32` id, `u64` reachable, `u64` unreachable, `u64`
+/// traversal count, `u64` skipped neighborhoods).
+pub struct WorkResult {
+    pub worker: usize,
+    pub reports: Vec<(u32, ReachabilityReport)>,
+}
+
+impl WorkResult {
+    pub fn save(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        let mut encoder = Encoder::with_header(RESULT_MAGIC, VERSION);
+        encoder.put_u64(self.worker as u64);
+        encoder.put_len(self.reports.len());
+        for (id, report) in &self.reports {
+            encoder.put_u32(*id);
+            encoder.put_u64(report.reachable as u64);
+            encoder.put_u64(report.unreachable as u64);
+            encoder.put_u64(report.traversal_count as u64);
+            encoder.put_u64(report.skipped_neighborhoods as u64);
+        }
+        write_atomically(path.as_ref(), encoder.into_bytes())
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> io::Result<Self> {
+        let data = fs::read(path)?;
+        let mut decoder = Decoder::new(&data);
+        check_version(decoder.expect_header(RESULT_MAGIC)?, "work result")?;
+        let worker = decoder.get_u64()? as usize;
+        let count = decoder.get_len()?;
+        let mut reports = Vec::with_capacity(count);
+        for _ in 0..count {
+            let id = decoder.get_u32()?;
+            let reachable = decoder.get_u64()? as usize;
+            let unreachable = decoder.get_u64()? as usize;
+            let traversal_count = decoder.get_u64()? as usize;
+            let mut report = ReachabilityReport::new(reachable, unreachable, traversal_count);
+            report.skipped_neighborhoods = decoder.get_u64()? as usize;
+            reports.push((id, report));
+        }
+        if !decoder.is_empty() {
+            return Err(invalid_data("trailing data after work result"));
+        }
+        Ok(WorkResult { worker, reports })
+    }
+}
+
+fn check_version(version: u16, kind: &str) -> io::Result<()> {
+    if version != VERSION {
+        return Err(invalid_data(&format!(
+            "unsupported {} version {}",
+            kind, version
+        )));
+    }
+    Ok(())
+}
+
+/// Readers polling the directory never see a partially written file.
+fn write_atomically(path: &Path, bytes: Vec<u8>) -> io::Result<()> {
+    let tmp_path = path.with_extension("tmp");
+    fs::write(&tmp_path, bytes)?;
+    fs::rename(tmp_path, path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    fn temp_path(test: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("hetu-work-plan-{}-{}", test, std::process::id()))
+    }
+
+    fn plan() -> WorkPlan {
+        WorkPlan {
+            neighborhoods: vec![
+                PlannedNeighborhood {
+                    marked_nodes: vec!["leaf0".to_string(), "leaf1".to_string()],
+                    normal_nodes: vec!["spine".to_string()],
+                    weight: 7,
+                },
+                PlannedNeighborhood {
+                    marked_nodes: vec!["leaf2".to_string()],
+                    normal_nodes: Vec::new(),
+                    weight: 1,
+                },
+            ],
+            assignments: vec![vec![1], vec![0], Vec::new()],
+        }
+    }
+
+    #[test]
+    fn work_plans_round_trip() {
+        let path = temp_path("plan");
+        plan().save(&path).unwrap();
+        let loaded = WorkPlan::load(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(loaded.assignments, plan().assignments);
+        for (loaded, original) in loaded.neighborhoods.iter().zip(&plan().neighborhoods) {
+            assert_eq!(loaded.marked_nodes, original.marked_nodes);
+            assert_eq!(loaded.normal_nodes, original.normal_nodes);
+            assert_eq!(loaded.weight, original.weight);
+        }
+    }
+
+    #[test]
+    fn work_results_round_trip() {
+        let path = temp_path("result");
+        let result = WorkResult {
+            worker: 2,
+            reports: vec![
+                (0, ReachabilityReport::new(3, 1, 4)),
+                (5, ReachabilityReport::new(0, 0, 0)),
+            ],
+        };
+        result.save(&path).unwrap();
+        let loaded = WorkResult::load(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(loaded.worker, result.worker);
+        assert_eq!(loaded.reports, result.reports);
+    }
+
+    #[test]
+    fn damaged_work_plans_are_rejected() {
+        let path = temp_path("damaged");
+        let mut out_of_range = plan();
+        out_of_range.assignments[2].push(2);
+        out_of_range.save(&path).unwrap();
+        assert!(WorkPlan::load(&path).is_err());
+
+        plan().save(&path).unwrap();
+        let mut bytes = fs::read(&path).unwrap();
+        bytes.push(0);
+        fs::write(&path, &bytes).unwrap();
+        assert!(WorkPlan::load(&path).is_err());
+
+        bytes.truncate(bytes.len() - 2);
+        fs::write(&path, &bytes).unwrap();
+        assert!(WorkPlan::load(&path).is_err());
+        fs::remove_file(&path).unwrap();
+    }
+}
@@ -1,16 +1,16 @@
-use biodivine_lib_bdd::Bdd;
+use crate::util::packet_set::PacketSpace;
 #[derive(Clone)]
 pub struct Announcement {
-    predicate: Bdd,
+    predicate: PacketSpace,
     count: i32,
 }
 
 impl Announcement {
-    pub fn new(predicate: Bdd, count: i32) -> Self {
+    pub fn new(predicate: PacketSpace, count: i32) -> Self {
         Announcement { predicate, count }
     }
 
-    pub fn get_predicate(&self) -> &Bdd {
+    pub fn get_predicate(&self) -> &PacketSpace {
         &self.predicate
     }
 
@@ -0,0 +1,305 @@
+use crate::util::hash_utils::{HashMap, HashSet};
+use crate::util::npbdd::{SpaceId, NONE_SPACE_ID, NPBDD};
+use crate::util::packet_set::{PacketSet, PacketSpace};
+use crate::verifier::neighborhood::Neighborhood;
+use crate::verifier::report::ReachabilityReport;
+use crate::verifier::topology_index::TopologyIndex;
+use rayon::prelude::*;
+use std::collections::VecDeque;
+use std::fmt;
+use std::str::FromStr;
+
+/// Which engine `Simulator::verify_reachability` runs.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum VerificationEngine {
+    /// Propagates packet sets through `NPNet`.
+    #[default]
+    NpNet,
+    /// Propagates bitsets of atomic predicates.
+    Atomic,
+}
+
+impl FromStr for VerificationEngine {
+    type Err = String;
+
+    fn from_str(spec: &str) -> Result<Self, Self::Err> {
+        match spec {
+            "npnet" => Ok(VerificationEngine::NpNet),
+            "atomic" => Ok(VerificationEngine::Atomic),
+            _ => Err(format!("unknown verification engine: {}", spec)),
+        }
+    }
+}
+
+impl fmt::Display for VerificationEngine {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            VerificationEngine::NpNet => "npnet",
+            VerificationEngine::Atomic => "atomic",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// Set of atomic-predicate ids. Missing trailing words are zero, so sets
+/// that were never touched take no memory.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash)]
+pub struct AtomSet {
+    words: Vec<u64>,
+}
+
+impl AtomSet {
+    pub fn insert(&mut self, atom: usize) {
+        let word = atom / 64;
+        if self.words.len() <= word {
+            self.words.resize(word + 1, 0);
+        }
+        self.words[word] |= 1 << (atom % 64);
+    }
+
+    pub fn contains(&self, atom: usize) -> bool {
+        self.words
+            .get(atom / 64)
+            .is_some_and(|word| word & (1 << (atom % 64)) != 0)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.words.iter().all(|&word| word == 0)
+    }
+
+    pub fn len(&self) -> usize {
+        self.words
+            .iter()
+            .map(|word| word.count_ones() as usize)
+            .sum()
+    }
+
+    pub fn and(&self, other: &AtomSet) -> AtomSet {
+        AtomSet {
+            words: self
+                .words
+                .iter()
+                .zip(&other.words)
+                .map(|(a, b)| a & b)
+                .collect(),
+        }
+    }
+
+    pub fn and_not(&self, other: &AtomSet) -> AtomSet {
+        AtomSet {
+            words: self
+                .words
+                .iter()
+                .enumerate()
+                .map(|(i, a)| a & !other.words.get(i).copied().unwrap_or(0))
+                .collect(),
+        }
+    }
+
+    pub fn union_with(&mut self, other: &AtomSet) {
+        if self.words.len() < other.words.len() {
+            self.words.resize(other.words.len(), 0);
+        }
+        for (a, b) in self.words.iter_mut().zip(&other.words) {
+            *a |= b;
+        }
+    }
+
+    pub fn is_subset(&self, other: &AtomSet) -> bool {
+        self.words
+            .iter()
+            .enumerate()
+            .all(|(i, a)| a & !other.words.get(i).copied().unwrap_or(0) == 0)
+    }
+}
+
+/// The coarsest partition of the verified packet space in which every port
+/// predicate and every destination packet space is a union of parts. Each
+/// predicate is kept as the set of its parts, so traversal only needs bitset
+/// operations.
+pub struct AtomicPredicates {
+    atom_count: usize,
+    predicate_atoms: Vec<AtomSet>,
+    predicate_ids: HashMap<SpaceId, usize>,
+    /// Backward links of every device as (peer, predicate id).
+    backward_links: Vec<Vec<(usize, usize)>>,
+}
+
+impl AtomicPredicates {
+    /// Port predicates are restricted to `all_space`, the union of the
+    /// destination packet spaces, since nothing outside it is traversed.
+    pub fn compute(
+        index: &TopologyIndex,
+        all_space: SpaceId,
+        packet_spaces: &HashMap<String, SpaceId>,
+        npbdd: &NPBDD,
+    ) -> Self {
+        let mut predicates: Vec<SpaceId> = Vec::new();
+        let mut predicate_ids: HashMap<SpaceId, usize> = HashMap::default();
+        let mut intern = |space: SpaceId| -> usize {
+            *predicate_ids.entry(space).or_insert_with(|| {
+                predicates.push(space);
+                predicates.len() - 1
+            })
+        };
+
+        let mut port_predicates: Vec<HashMap<i8, usize>> = Vec::with_capacity(index.len());
+        for node in 0..index.len() {
+            let space_ports = index.device(node).get_space_id_to_space_port();
+            port_predicates.push(
+                space_ports
+                    .iter()
+                    .map(|(&space_id, space_port)| {
+                        (
+                            space_id,
+                            intern(npbdd.and(space_port.get_predicate(), all_space)),
+                        )
+                    })
+                    .collect(),
+            );
+        }
+        let mut names: Vec<&String> = packet_spaces.keys().collect();
+        names.sort();
+        for name in names {
+            intern(packet_spaces[name]);
+        }
+
+        let backward_links = (0..index.len())
+            .map(|node| {
+                index
+                    .links(node)
+                    .iter()
+                    .filter_map(|link| {
+                        let space_id = link.in_space_id?;
+                        Some((link.peer, port_predicates[link.peer][&space_id]))
+                    })
+                    .collect()
+            })
+            .collect();
+
+        // Each atom carries the ids of the predicates containing it.
+        let mut atoms: Vec<(PacketSpace, Vec<usize>)> =
+            vec![(npbdd.space(all_space).as_ref().clone(), Vec::new())];
+        for (predicate_id, &predicate) in predicates.iter().enumerate() {
+            if predicate == NONE_SPACE_ID {
+                continue;
+            }
+            let predicate = npbdd.space(predicate);
+            let predicate = predicate.as_ref();
+            atoms = atoms
+                .into_par_iter()
+                .flat_map_iter(|(atom, mut members)| {
+                    let inside = atom.and(predicate);
+                    if inside.is_empty() {
+                        return vec![(atom, members)];
+                    }
+                    if inside == atom {
+                        members.push(predicate_id);
+                        return vec![(atom, members)];
+                    }
+                    let outside = atom.and_not(predicate);
+                    let mut inside_members = members.clone();
+                    inside_members.push(predicate_id);
+                    vec![(inside, inside_members), (outside, members)]
+                })
+                .collect();
+        }
+
+        let mut predicate_atoms = vec![AtomSet::default(); predicates.len()];
+        for (atom, (_, members)) in atoms.iter().enumerate() {
+            for &predicate_id in members {
+                predicate_atoms[predicate_id].insert(atom);
+            }
+        }
+        AtomicPredicates {
+            atom_count: atoms.len(),
+            predicate_atoms,
+            predicate_ids,
+            backward_links,
+        }
+    }
+
+    pub fn atom_count(&self) -> usize {
+        self.atom_count
+    }
+
+    pub fn predicate_count(&self) -> usize {
+        self.predicate_atoms.len()
+    }
+
+    /// Atoms of a destination packet space given to `compute`.
+    fn packet_space_atoms(&self, space: SpaceId) -> &AtomSet {
+        let predicate_id = self
+            .predicate_ids
+            .get(&space)
+            .expect("packet space was not part of the atomic predicates");
+        &self.predicate_atoms[*predicate_id]
+    }
+
+    /// Backward reachability for the marked nodes of `neighborhood`, checked
+    /// like `NPNet::backward_check_reachability`.
+    pub fn verify(
+        &self,
+        index: &TopologyIndex,
+        neighborhood: &Neighborhood,
+        edge_devices: &HashSet<String>,
+    ) -> ReachabilityReport {
+        let mut verified = vec![AtomSet::default(); index.len()];
+        let mut pending = vec![AtomSet::default(); index.len()];
+        let mut queued = vec![false; index.len()];
+        let mut queue: VecDeque<usize> = VecDeque::new();
+        let mut marked_nodes: Vec<(&String, &AtomSet)> = neighborhood
+            .get_marked_nodes()
+            .iter()
+            .map(|(name, device)| (name, self.packet_space_atoms(device.dst_prefix_bdd)))
+            .collect();
+        marked_nodes.sort_by(|a, b| a.0.cmp(b.0));
+        for (name, atoms) in &marked_nodes {
+            let node = index.index_of(name).unwrap();
+            pending[node].union_with(atoms);
+            if !queued[node] {
+                queued[node] = true;
+                queue.push_back(node);
+            }
+        }
+
+        let mut traversal_count = 0;
+        while let Some(node) = queue.pop_front() {
+            queued[node] = false;
+            let delta = std::mem::take(&mut pending[node]).and_not(&verified[node]);
+            if delta.is_empty() {
+                continue;
+            }
+            verified[node].union_with(&delta);
+            traversal_count += self.backward_links[node].len();
+            for &(peer, predicate_id) in &self.backward_links[node] {
+                let intersection = delta.and(&self.predicate_atoms[predicate_id]);
+                if intersection.is_subset(&verified[peer]) {
+                    continue;
+                }
+                pending[peer].union_with(&intersection);
+                if !queued[peer] {
+                    queued[peer] = true;
+                    queue.push_back(peer);
+                }
+            }
+        }
+
+        let mut reachable = 0;
+        let mut unreachable = 0;
+        for src_name in edge_devices {
+            let src_verified = &verified[index.index_of(src_name).unwrap()];
+            for (dst_name, atoms) in &marked_nodes {
+                if *dst_name == src_name {
+                    continue;
+                }
+                if atoms.is_subset(src_verified) {
+                    reachable += 1;
+                } else {
+                    unreachable += 1;
+                }
+            }
+        }
+        ReachabilityReport::new(reachable, unreachable, traversal_count)
+    }
+}
@@ -1,21 +1,21 @@
-use biodivine_lib_bdd::Bdd;
-use hashbrown::{HashMap, HashSet};
+use crate::util::hash_utils::{HashMap, HashSet};
+use crate::util::packet_set::*;
 
 #[derive(Clone)]
 pub struct BddCache {
-    last_bdd_pair_and: Option<(Bdd, Bdd)>,
-    last_bdd_pair_or: Option<(Bdd, Bdd)>,
-    arrive_predicate: Bdd,
-    predicate_type_set: HashSet<Bdd>,
-    and_table: HashMap<Bdd, Bdd>,
+    last_bdd_pair_and: Option<(PacketSpace, PacketSpace)>,
+    last_bdd_pair_or: Option<(PacketSpace, PacketSpace)>,
+    arrive_predicate: PacketSpace,
+    predicate_type_set: HashSet<PacketSpace>,
+    and_table: HashMap<PacketSpace, PacketSpace>,
 }
 
 impl BddCache {
-    pub fn new(arrive_predicate: Bdd) -> Self {
+    pub fn new(arrive_predicate: PacketSpace) -> Self {
         BddCache {
             last_bdd_pair_and: None,
             last_bdd_pair_or: None,
-            arrive_predicate: arrive_predicate,
+            arrive_predicate,
             predicate_type_set: HashSet::default(),
             and_table: HashMap::default(),
         }
@@ -25,7 +25,7 @@ impl BddCache {
         self.predicate_type_set.len()
     }
 
-    pub fn get_intersection(&mut self, predicate: &Bdd) -> Bdd {
+    pub fn get_intersection(&mut self, predicate: &PacketSpace) -> PacketSpace {
         if let Some((last_pred, last_result_bdd)) = &self.last_bdd_pair_and {
             if last_pred == predicate {
                 return last_result_bdd.clone();
@@ -37,7 +37,7 @@ impl BddCache {
         intersection
     }
 
-    pub fn get_intersection_with_table(&mut self, predicate: &Bdd) -> Bdd {
+    pub fn get_intersection_with_table(&mut self, predicate: &PacketSpace) -> PacketSpace {
         if let Some(result) = self.and_table.get(predicate) {
             return result.clone();
         }
@@ -50,7 +50,7 @@ impl BddCache {
         intersection
     }
 
-    pub fn get_union(&mut self, predicate: &Bdd) -> Bdd {
+    pub fn get_union(&mut self, predicate: &PacketSpace) -> PacketSpace {
         if let Some((last_pred, last_result_bdd)) = &self.last_bdd_pair_or {
             if last_pred == predicate {
                 return last_result_bdd.clone();
@@ -1,16 +1,16 @@
-use biodivine_lib_bdd::Bdd;
+use crate::util::packet_set::*;
 #[derive(Clone)]
 pub struct CibTuple {
-    predicate: Bdd, //
+    predicate: PacketSpace, //
     count: i32,
 }
 
 impl CibTuple {
-    pub fn new(predicate: Bdd, count: i32) -> Self {
+    pub fn new(predicate: PacketSpace, count: i32) -> Self {
         CibTuple { predicate, count }
     }
 
-    pub fn keep_and_split(&mut self, pre: Bdd, count: i32) -> CibTuple {
+    pub fn keep_and_split(&mut self, pre: PacketSpace, count: i32) -> CibTuple {
         let new_pre = self.predicate.and(&pre);
         let not_new_pre = self.predicate.and_not(&pre);
         self.predicate = new_pre;
@@ -26,11 +26,11 @@ impl CibTuple {
         self.count
     }
 
-    pub fn set_predicate(&mut self, new_predicate: Bdd) {
+    pub fn set_predicate(&mut self, new_predicate: PacketSpace) {
         self.predicate = new_predicate
     }
 
-    pub fn get_predicate(&self) -> &Bdd {
+    pub fn get_predicate(&self) -> &PacketSpace {
         &self.predicate
     }
 }
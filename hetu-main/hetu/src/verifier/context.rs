@@ -1,5 +1,5 @@
+use crate::util::packet_set::PacketSpace;
 use crate::verifier::annoucement::Announcement;
-use biodivine_lib_bdd::Bdd;
 
 #[derive(Clone)]
 pub struct Ctx {
@@ -8,7 +8,7 @@ pub struct Ctx {
 }
 
 impl Ctx {
-    pub fn new(device_name: String, predicate: Bdd, count: i32) -> Self {
+    pub fn new(device_name: String, predicate: PacketSpace, count: i32) -> Self {
         let announcement = Announcement::new(predicate, count);
         Ctx {
             device_name,
@@ -1,29 +1,26 @@
 use super::lec::Lec;
 use crate::simulator::SubNet;
-use crate::util::hash_utils::{HashMap, HashSet};
-use crate::util::npbdd::NPBDD;
+use crate::util::encoding_cache::EncodedDevice;
+use crate::util::hash_utils::{DashSet, HashMap, HashSet};
+use crate::util::npbdd::{SpaceId, NONE_SPACE_ID, NPBDD};
+use crate::util::packet_set::PacketSpace;
 use crate::util::{rule::Rule, space_port::SpacePort};
-use biodivine_lib_bdd::Bdd;
-use dashmap::{DashMap, DashSet};
 use serde::{Deserialize, Serialize};
 use std::fs;
 use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
 
-lazy_static! {
-    pub static ref ALIVE_DEVICE_MAP: DashMap<String, DashSet<i16>> = DashMap::new();
-}
-
 #[derive(Clone)]
 pub struct Device {
     name: String,
     pub device_id: usize,
-    pub device_id_bdd: Bdd,
+    pub device_id_bdd: SpaceId,
     rules: Vec<Rule>,
-    packet_space: Option<Rule>,
+    packet_space: Vec<Rule>,
     port_name_to_space_id: HashMap<String, i8>,
     space_id_to_space_port: HashMap<i8, SpacePort>,
-    pub forwardable_space: Bdd,
-    pub subnet_space: Bdd,
+    pub forwardable_space: SpaceId,
+    pub subnet_space: SpaceId,
+    pub alive_space_ports: DashSet<i16>,
 }
 
 #[derive(Serialize, Deserialize)]
@@ -42,21 +39,25 @@ impl Device {
         Device {
             name,
             device_id: 0,
-            device_id_bdd: NPBDD::make_none_space_bdd(),
+            device_id_bdd: NONE_SPACE_ID,
             rules: Vec::new(),
-            packet_space: None,
+            packet_space: Vec::new(),
             port_name_to_space_id: HashMap::default(),
             space_id_to_space_port: HashMap::default(),
-            forwardable_space: NPBDD::make_none_space_bdd(),
-            subnet_space: NPBDD::make_none_space_bdd(),
+            forwardable_space: NONE_SPACE_ID,
+            subnet_space: NONE_SPACE_ID,
+            alive_space_ports: DashSet::default(),
         }
     }
 
     pub fn read_rules_file(&mut self, filename: &String) {
         let contents = fs::read_to_string(filename).expect("Error while reading the file");
+        self.read_rules_str(&contents);
+    }
 
+    pub fn read_rules_str(&mut self, contents: &str) {
         let records: Vec<Record> =
-            serde_json::from_str(&contents).expect("Error while parsing the JSON {}");
+            serde_json::from_str(contents).expect("Error while parsing the JSON {}");
 
         let record_count = records.len();
 
@@ -77,22 +78,18 @@ impl Device {
     }
 
     pub fn merge_lec_to_space_port(&mut self, tmp_lecs: HashSet<Lec>) {
-        let mut tmp_space_port: HashMap<Bdd, i8> = HashMap::default();
+        let mut tmp_space_port: HashMap<SpaceId, i8> = HashMap::default();
         let mut cur_space_id = 0;
-        ALIVE_DEVICE_MAP.insert(self.name.clone(), DashSet::new());
+        self.alive_space_ports.clear();
         for lec in tmp_lecs {
             if let Some(port_name) = lec.forward_action.get_ports().first() {
                 if let Some(&tmp_space_id) = tmp_space_port.get(&lec.predicate) {
                     self.port_name_to_space_id
                         .insert(port_name.to_string(), tmp_space_id);
                 } else {
-                    self.space_id_to_space_port.insert(
-                        cur_space_id,
-                        SpacePort::new(cur_space_id, lec.predicate.clone()),
-                    );
-                    if let Some(mut device_set) = ALIVE_DEVICE_MAP.get_mut(&self.name) {
-                        device_set.insert(cur_space_id as i16);
-                    }
+                    self.space_id_to_space_port
+                        .insert(cur_space_id, SpacePort::new(cur_space_id, lec.predicate));
+                    self.alive_space_ports.insert(cur_space_id as i16);
                     self.port_name_to_space_id
                         .insert(port_name.to_string(), cur_space_id);
                     tmp_space_port.insert(lec.predicate, cur_space_id);
@@ -105,19 +102,68 @@ impl Device {
         }
     }
 
-    pub fn cal_forwardable_space(&mut self, tmp_lecs: &HashSet<Lec>) {
+    /// Also pins the LEC predicates, which the device keeps after encoding.
+    pub fn cal_forwardable_space(&mut self, tmp_lecs: &HashSet<Lec>, npbdd: &NPBDD) {
         for lec in tmp_lecs {
-            self.forwardable_space = self.forwardable_space.or(&lec.predicate);
+            npbdd.pin(lec.predicate);
+            self.forwardable_space = npbdd.or(self.forwardable_space, lec.predicate);
         }
+        npbdd.pin(self.forwardable_space);
     }
 
-    pub fn check_intersection_at_port(&self, arrive_bdd: &Bdd, port_name: &str) -> bool {
+    /// Result of rule encoding, detached from the rules that produced it.
+    pub fn encoded_state(&self, npbdd: &NPBDD) -> EncodedDevice {
+        let mut port_space_ids: Vec<(String, i8)> = self
+            .port_name_to_space_id
+            .iter()
+            .map(|(port, &space_id)| (port.clone(), space_id))
+            .collect();
+        port_space_ids.sort();
+        let mut space_ports: Vec<(i8, PacketSpace)> = self
+            .space_id_to_space_port
+            .iter()
+            .map(|(&space_id, space_port)| {
+                (
+                    space_id,
+                    npbdd.space(space_port.get_predicate()).as_ref().clone(),
+                )
+            })
+            .collect();
+        space_ports.sort_by_key(|(space_id, _)| *space_id);
+        EncodedDevice {
+            port_space_ids,
+            space_ports,
+            forwardable_space: npbdd.space(self.forwardable_space).as_ref().clone(),
+        }
+    }
+
+    /// Interns and pins the sets of `state`.
+    pub fn restore_encoded_state(&mut self, state: EncodedDevice, npbdd: &NPBDD) {
+        self.port_name_to_space_id = state.port_space_ids.into_iter().collect();
+        self.alive_space_ports.clear();
+        self.space_id_to_space_port = state
+            .space_ports
+            .into_iter()
+            .map(|(space_id, space)| {
+                self.alive_space_ports.insert(space_id as i16);
+                (
+                    space_id,
+                    SpacePort::new(space_id, npbdd.intern_pinned(space)),
+                )
+            })
+            .collect();
+        self.forwardable_space = npbdd.intern_pinned(state.forwardable_space);
+    }
+
+    pub fn check_intersection_at_port(
+        &self,
+        arrive_space: SpaceId,
+        port_name: &str,
+        npbdd: &NPBDD,
+    ) -> bool {
         match self.has_space_bdd(port_name) {
             false => false,
-            true => {
-                let port_space_bdd = self.get_space_bdd(port_name);
-                arrive_bdd.and_not(port_space_bdd).is_false()
-            }
+            true => npbdd.is_subset(arrive_space, self.get_space_bdd(port_name)),
         }
     }
 
@@ -159,10 +205,10 @@ impl Device {
         }
     }
 
-    pub fn get_space_bdd(&self, port_name: &str) -> &Bdd {
+    pub fn get_space_bdd(&self, port_name: &str) -> SpaceId {
         let cur_id = self.port_name_to_space_id.get(port_name).unwrap();
         let cur_space_port = self.space_id_to_space_port.get(cur_id).unwrap();
-        cur_space_port.get_space()
+        cur_space_port.get_predicate()
     }
 
     pub fn get_space_id(&self, port_name: &String) -> i8 {
@@ -171,6 +217,17 @@ impl Device {
         cur_space_port.get_space_id()
     }
 
+    pub fn find_space_id(&self, port_name: &str) -> Option<i8> {
+        self.port_name_to_space_id.get(port_name).copied()
+    }
+
+    pub fn get_space_bdd_by_id(&self, space_id: i8) -> SpaceId {
+        self.space_id_to_space_port
+            .get(&space_id)
+            .unwrap()
+            .get_predicate()
+    }
+
     pub fn get_space_id_to_space_port(&self) -> &HashMap<i8, SpacePort> {
         &self.space_id_to_space_port
     }
@@ -179,11 +236,13 @@ impl Device {
         self.port_name_to_space_id.contains_key(port_name)
     }
 
-    pub fn set_packet_space_file(&mut self, packet_space: Rule) {
-        self.packet_space = Some(packet_space);
+    /// Adds one packet-space entry; the device owns the union of its
+    /// entries.
+    pub fn add_packet_space(&mut self, packet_space: Rule) {
+        self.packet_space.push(packet_space);
     }
 
-    pub fn get_packet_space(&self) -> &Option<Rule> {
+    pub fn get_packet_space(&self) -> &[Rule] {
         &self.packet_space
     }
 
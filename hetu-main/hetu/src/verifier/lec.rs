@@ -1,17 +1,17 @@
 use crate::util::forward_action::ForwardAction;
-use biodivine_lib_bdd::Bdd;
+use crate::util::npbdd::SpaceId;
 use std::hash::{Hash, Hasher};
 use std::sync::OnceLock;
 
 #[derive(Clone)]
 pub struct Lec {
     pub forward_action: ForwardAction,
-    pub predicate: Bdd,
+    pub predicate: SpaceId,
     exhausted: OnceLock<bool>,
 }
 
 impl Lec {
-    pub fn new(forward_action: ForwardAction, predicate: Bdd) -> Self {
+    pub fn new(forward_action: ForwardAction, predicate: SpaceId) -> Self {
         Lec {
             forward_action,
             predicate,
@@ -0,0 +1,247 @@
+use crate::util::packet_set::prefix_bits;
+use crate::util::rule::Rule;
+use std::fmt;
+use std::str::FromStr;
+
+/// How rule LECs are computed from a destination-prefix FIB.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum LecBuilder {
+    /// Longest-prefix match with BDD operations through the layer cache.
+    #[default]
+    Bdd,
+    /// Longest-prefix match on a binary trie; only the final per-port
+    /// prefix sets become packet sets.
+    Trie,
+    /// Runs both and reports every port whose predicates differ. The BDD
+    /// result is kept.
+    Checked,
+}
+
+impl FromStr for LecBuilder {
+    type Err = String;
+
+    fn from_str(spec: &str) -> Result<Self, Self::Err> {
+        match spec {
+            "bdd" => Ok(LecBuilder::Bdd),
+            "trie" => Ok(LecBuilder::Trie),
+            "check" => Ok(LecBuilder::Checked),
+            _ => Err(format!("unknown LEC builder: {}", spec)),
+        }
+    }
+}
+
+impl fmt::Display for LecBuilder {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            LecBuilder::Bdd => "bdd",
+            LecBuilder::Trie => "trie",
+            LecBuilder::Checked => "check",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+// Child index 0 means no child; the root is never anyone's child.
+struct TrieNode {
+    children: [usize; 2],
+    owner: Option<usize>,
+}
+
+/// Binary trie of prefixes, each owned by the first value inserted for it.
+pub struct PrefixTrie {
+    nodes: Vec<TrieNode>,
+}
+
+impl Default for PrefixTrie {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl PrefixTrie {
+    pub fn new() -> Self {
+        PrefixTrie {
+            nodes: vec![TrieNode {
+                children: [0, 0],
+                owner: None,
+            }],
+        }
+    }
+
+    /// Returns false when the prefix already has an owner.
+    pub fn insert(&mut self, bits: &[bool], owner: usize) -> bool {
+        let mut node = 0;
+        for &bit in bits {
+            let child = self.nodes[node].children[bit as usize];
+            node = if child != 0 {
+                child
+            } else {
+                self.nodes.push(TrieNode {
+                    children: [0, 0],
+                    owner: None,
+                });
+                let child = self.nodes.len() - 1;
+                self.nodes[node].children[bit as usize] = child;
+                child
+            };
+        }
+        if self.nodes[node].owner.is_some() {
+            return false;
+        }
+        self.nodes[node].owner = Some(owner);
+        true
+    }
+
+    /// Whether the prefix shares an address with any inserted prefix.
+    pub fn overlaps(&self, bits: &[bool]) -> bool {
+        let mut node = 0;
+        for &bit in bits {
+            if self.nodes[node].owner.is_some() {
+                return true;
+            }
+            node = self.nodes[node].children[bit as usize];
+            if node == 0 {
+                return false;
+            }
+        }
+        let node = &self.nodes[node];
+        node.owner.is_some() || node.children != [0, 0]
+    }
+
+    /// Owner of the longest prefix containing all of `bits`, and whether a
+    /// longer prefix covers only part of them.
+    pub fn longest_match(&self, bits: &[bool]) -> (Option<usize>, bool) {
+        let mut node = 0;
+        let mut owner = None;
+        for &bit in bits {
+            owner = self.nodes[node].owner.or(owner);
+            node = self.nodes[node].children[bit as usize];
+            if node == 0 {
+                return (owner, false);
+            }
+        }
+        let node = &self.nodes[node];
+        (node.owner.or(owner), node.children != [0, 0])
+    }
+
+    /// Splits the covered addresses into disjoint prefixes, each paired with
+    /// the owner of its longest matching prefix.
+    pub fn disjoint_prefixes(&self) -> Vec<(usize, Vec<bool>)> {
+        let mut prefixes = Vec::new();
+        let mut stack: Vec<(usize, Option<usize>, Vec<bool>)> = vec![(0, None, Vec::new())];
+        while let Some((node, inherited, path)) = stack.pop() {
+            let TrieNode { children, owner } = &self.nodes[node];
+            let owner = owner.or(inherited);
+            if *children == [0, 0] {
+                if let Some(owner) = owner {
+                    prefixes.push((owner, path));
+                }
+                continue;
+            }
+            for (bit, &child) in children.iter().enumerate() {
+                let mut child_path = path.clone();
+                child_path.push(bit == 1);
+                if child != 0 {
+                    stack.push((child, owner, child_path));
+                } else if let Some(owner) = owner {
+                    prefixes.push((owner, child_path));
+                }
+            }
+        }
+        prefixes
+    }
+}
+
+/// Trie of the destination packet spaces, used to drop rules that can never
+/// match a verified packet, as the BDD path does against the full space.
+pub fn packet_space_trie(packet_spaces: &[Rule]) -> PrefixTrie {
+    let mut trie = PrefixTrie::new();
+    for packet_space in packet_spaces {
+        if let Some(bits) = prefix_bits(packet_space.get_ip(), packet_space.get_prefix_len()) {
+            trie.insert(&bits, 0);
+        }
+    }
+    trie
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn bits(spec: &str) -> Vec<bool> {
+        spec.chars().map(|bit| bit == '1').collect()
+    }
+
+    fn sorted_prefixes(trie: &PrefixTrie) -> Vec<(usize, String)> {
+        let mut prefixes: Vec<(usize, String)> = trie
+            .disjoint_prefixes()
+            .into_iter()
+            .map(|(owner, path)| {
+                let path = path.iter().map(|&bit| if bit { '1' } else { '0' });
+                (owner, path.collect())
+            })
+            .collect();
+        prefixes.sort_by(|a, b| a.1.cmp(&b.1));
+        prefixes
+    }
+
+    #[test]
+    fn prefix_bits_reads_the_leading_bits() {
+        assert_eq!(prefix_bits("10.0.0.0", 8), Some(bits("00001010")));
+        assert_eq!(prefix_bits("192.168.0.0", 3), Some(bits("110")));
+        assert_eq!(prefix_bits("0.0.0.0", 0), Some(Vec::new()));
+        assert_eq!(prefix_bits("10.0.0.0", 33), None);
+        assert_eq!(prefix_bits("::", 128).map(|bits| bits.len()), Some(128));
+        assert_eq!(prefix_bits("not an ip", 8), None);
+    }
+
+    #[test]
+    fn insert_keeps_the_first_owner() {
+        let mut trie = PrefixTrie::new();
+        assert!(trie.insert(&bits("01"), 1));
+        assert!(!trie.insert(&bits("01"), 2));
+        assert!(trie.insert(&bits("0"), 3));
+        assert_eq!(
+            sorted_prefixes(&trie),
+            vec![(3, "00".into()), (1, "01".into())]
+        );
+    }
+
+    #[test]
+    fn overlaps_checks_both_shorter_and_longer_prefixes() {
+        let mut trie = PrefixTrie::new();
+        trie.insert(&bits("010"), 0);
+        assert!(trie.overlaps(&bits("01")));
+        assert!(trie.overlaps(&bits("010")));
+        assert!(trie.overlaps(&bits("0101")));
+        assert!(!trie.overlaps(&bits("011")));
+        assert!(!trie.overlaps(&bits("1")));
+        assert!(trie.overlaps(&[]));
+        assert!(!PrefixTrie::new().overlaps(&[]));
+    }
+
+    #[test]
+    fn disjoint_prefixes_follow_longest_match() {
+        let mut trie = PrefixTrie::new();
+        trie.insert(&[], 0);
+        trie.insert(&bits("1"), 1);
+        trie.insert(&bits("101"), 2);
+        assert_eq!(
+            sorted_prefixes(&trie),
+            vec![
+                (0, "0".into()),
+                (1, "100".into()),
+                (2, "101".into()),
+                (1, "11".into()),
+            ]
+        );
+    }
+
+    #[test]
+    fn disjoint_prefixes_skip_uncovered_addresses() {
+        let mut trie = PrefixTrie::new();
+        trie.insert(&bits("01"), 7);
+        assert_eq!(sorted_prefixes(&trie), vec![(7, "01".into())]);
+        assert!(PrefixTrie::new().disjoint_prefixes().is_empty());
+    }
+}
@@ -1,12 +1,21 @@
 pub mod annoucement;
+pub mod atomic;
 pub mod bdd_cache;
 pub mod cibtuple;
 pub mod context;
 pub mod device;
 pub mod lec;
+pub mod lec_trie;
 pub mod neighborhood;
 pub mod node;
 pub mod npnet;
+pub mod outer_summary;
+pub mod partition;
+pub mod reference;
+pub mod report;
 pub mod rule_bdd;
+pub mod scheduler;
 pub mod space_node;
+pub mod symmetry;
+pub mod topology_index;
 pub mod toponet;
@@ -1,15 +1,15 @@
 use crate::util::hash_utils::{HashMap, HashSet};
-use biodivine_lib_bdd::Bdd;
+use crate::util::npbdd::SpaceId;
 
 #[derive(Debug, Clone, Hash, PartialEq, Eq)]
 pub struct PacketSpaceAwareDevice {
     pub device_name: String,
-    pub dst_prefix_bdd: Bdd,
+    pub dst_prefix_bdd: SpaceId,
     pub device_id: usize,
 }
 
 impl PacketSpaceAwareDevice {
-    pub fn new(device_name: String, dst_prefix_bdd: Bdd, device_id: usize) -> Self {
+    pub fn new(device_name: String, dst_prefix_bdd: SpaceId, device_id: usize) -> Self {
         Self {
             device_name,
             dst_prefix_bdd,
@@ -22,6 +22,15 @@ impl PacketSpaceAwareDevice {
 pub struct Neighborhood {
     local_marked_nodes: HashMap<String, PacketSpaceAwareDevice>,
     local_normal_nodes: HashSet<String>,
+    /// Number of destinations each marked node stands for under symmetry
+    /// reduction.
+    weight: usize,
+}
+
+impl Default for Neighborhood {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl Neighborhood {
@@ -29,6 +38,7 @@ impl Neighborhood {
         Self {
             local_marked_nodes: HashMap::default(),
             local_normal_nodes: HashSet::default(),
+            weight: 1,
         }
     }
 
@@ -59,4 +69,16 @@ impl Neighborhood {
     pub fn get_marked_nodes(&self) -> &HashMap<String, PacketSpaceAwareDevice> {
         &self.local_marked_nodes
     }
+
+    pub fn get_normal_nodes(&self) -> &HashSet<String> {
+        &self.local_normal_nodes
+    }
+
+    pub fn weight(&self) -> usize {
+        self.weight
+    }
+
+    pub fn set_weight(&mut self, weight: usize) {
+        self.weight = weight;
+    }
 }
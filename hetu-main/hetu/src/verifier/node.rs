@@ -2,14 +2,14 @@ use super::annoucement::Announcement;
 use super::bdd_cache::BddCache;
 use super::context::Ctx;
 use super::device::Device;
-use super::device::ALIVE_DEVICE_MAP;
 use super::lec::Lec;
 use super::space_node::SpaceNode;
 use crate::util::hash_utils::{HashMap, HashSet};
 use crate::util::network::Network;
+use crate::util::npbdd::NPBDD;
+use crate::util::packet_set::{PacketSet, PacketSpace};
 use crate::util::space_port::SpacePort;
 use crate::verifier::cibtuple::CibTuple;
-use biodivine_lib_bdd::Bdd;
 use std::sync::Arc;
 
 #[derive(Clone)]
@@ -17,14 +17,13 @@ pub struct Node {
     pub name: Arc<String>,
     port_name_to_space_id: HashMap<String, i8>,
     space_id_to_space_port: HashMap<i8, SpacePort>,
-    space_id_to_conceptual_space: HashMap<i8, Bdd>,
+    space_id_to_conceptual_space: HashMap<i8, PacketSpace>,
     local_cib: HashMap<String, CibTuple>,
     port_cib: HashMap<String, Vec<CibTuple>>,
     locked_space_port: HashSet<i8>,
     device: Option<Arc<Device>>,
     pub port_arrive_cnt: HashMap<String, i32>,
-    round_space: Option<Bdd>,
-    pub verify_space: Option<Bdd>,
+    pub verify_space: Option<PacketSpace>,
 }
 
 impl Node {
@@ -39,7 +38,6 @@ impl Node {
             locked_space_port: HashSet::default(),
             device: None,
             port_arrive_cnt: HashMap::default(),
-            round_space: None,
             verify_space: None,
         }
     }
@@ -71,14 +69,15 @@ impl Node {
 
     pub fn init_cib_new(
         &mut self,
-        network: &Network,
-        packet_space_bdd: &Bdd,
+        _network: &Network,
+        packet_space_bdd: &PacketSpace,
         lecs: &HashSet<Lec>,
-        dst_node_name: String,
+        _dst_node_name: String,
+        npbdd: &NPBDD,
     ) {
         for lec in lecs {
-            let intersection_bdd = packet_space_bdd.and(&lec.predicate);
-            if intersection_bdd.is_false() {
+            let intersection_bdd = packet_space_bdd.and(&npbdd.space(lec.predicate));
+            if intersection_bdd.is_empty() {
                 continue;
             } else {
                 let ports = lec.forward_action.get_ports();
@@ -87,9 +86,7 @@ impl Node {
                     if let Some(vec) = self.port_cib.get_mut(port) {
                         vec.push(new_cibtuple);
                     } else {
-                        let mut vec: Vec<CibTuple> = Vec::new();
-                        vec.push(new_cibtuple);
-                        self.port_cib.insert(port.to_string(), vec);
+                        self.port_cib.insert(port.to_string(), vec![new_cibtuple]);
                     }
                 }
             }
@@ -99,34 +96,39 @@ impl Node {
     pub fn cal_theoretical_space(
         &mut self,
         space_id_to_space_port: &HashMap<i8, SpacePort>,
-        packet_space: &Bdd,
-        tmp_bdd_cache: &mut BddCache,
+        packet_space: &PacketSpace,
+        _tmp_bdd_cache: &mut BddCache,
+        npbdd: &NPBDD,
     ) {
         for (space_id, space_port) in space_id_to_space_port {
-            let space = space_port.get_space();
-            let result = packet_space.and(space);
-            if !result.is_false() {
+            let space = npbdd.space(space_port.get_predicate());
+            let result = packet_space.and(&space);
+            if !result.is_empty() {
                 self.space_id_to_conceptual_space.insert(*space_id, result);
             }
         }
     }
 
     pub fn check_alive_device_and_space_port(&self, space_id: i8) -> bool {
-        if let Some(alive_space_port_set) = ALIVE_DEVICE_MAP.get_mut(&self.name.to_string()) {
-            alive_space_port_set.contains(&(space_id as i16))
-        } else {
-            false
+        match &self.device {
+            Some(device) => device.alive_space_ports.contains(&(space_id as i16)),
+            None => false,
         }
     }
 
     pub fn update_alive_device_and_space_port(&self, space_id: i8) {
-        if let Some(alive_space_port_set) = ALIVE_DEVICE_MAP.get_mut(&self.name.to_string()) {
-            alive_space_port_set.remove(&(space_id as i16));
+        if let Some(device) = &self.device {
+            device.alive_space_ports.remove(&(space_id as i16));
         }
     }
 
-    pub fn init_cib_space_port(&mut self, packet_space_bdd: &Bdd, lecs: &HashSet<Lec>) {
-        let mut tmp_space_port: HashMap<Bdd, i8> = HashMap::default();
+    pub fn init_cib_space_port(
+        &mut self,
+        packet_space_bdd: &PacketSpace,
+        lecs: &HashSet<Lec>,
+        npbdd: &NPBDD,
+    ) {
+        let mut tmp_space_port: HashMap<PacketSpace, i8> = HashMap::default();
         let mut cur_space_id = 0;
 
         for lec in lecs {
@@ -134,13 +136,14 @@ impl Node {
                 continue;
             }
 
-            if *packet_space_bdd == lec.predicate {
+            let predicate = npbdd.space(lec.predicate);
+            if *packet_space_bdd == *predicate {
                 lec.set_exhausted();
             }
 
-            let intersection_bdd = packet_space_bdd.and(&lec.predicate);
+            let intersection_bdd = packet_space_bdd.and(&predicate);
 
-            if intersection_bdd.is_false() {
+            if intersection_bdd.is_empty() {
                 continue;
             } else {
                 let ports = lec.forward_action.get_ports();
@@ -155,7 +158,8 @@ impl Node {
                     self.port_name_to_space_id
                         .insert(port_name.to_string(), tmp_space_id);
                 } else {
-                    let new_space_port = SpacePort::new(cur_space_id, intersection_bdd.clone());
+                    let new_space_port =
+                        SpacePort::new(cur_space_id, npbdd.intern(intersection_bdd.clone()));
                     self.space_id_to_space_port
                         .insert(cur_space_id, new_space_port);
                     self.port_name_to_space_id
@@ -196,8 +200,10 @@ impl Node {
         &mut self,
         from_port_name: String,
         annoucement: Announcement,
+        npbdd: &NPBDD,
     ) -> bool {
         let arrive_predicate = annoucement.get_predicate();
+        let arrive_id = npbdd.intern(arrive_predicate.clone());
         let space_id = self.port_name_to_space_id.get(&from_port_name).unwrap();
         let space_port = self
             .space_id_to_space_port
@@ -208,15 +214,14 @@ impl Node {
                     space_id
                 )
             });
-        if !space_port.check_cache_space(arrive_predicate) {
-            let space = space_port.get_space();
-            let intersection_bdd = arrive_predicate.and(space);
+        if !space_port.check_cache_space(arrive_id) {
+            let space = npbdd.space(space_port.get_predicate());
+            let intersection_bdd = arrive_predicate.and(&space);
             let cibtuple = CibTuple::new(intersection_bdd, 1);
             self.local_cib.insert(from_port_name, cibtuple);
-            space_port.insert_cache_space(arrive_predicate);
-        } else {
+            space_port.insert_cache_space(arrive_id);
         }
-        return true;
+        true
     }
 
     pub fn update_loc_cib_with_forward_lock_checking(
@@ -224,8 +229,8 @@ impl Node {
         dst_device: &Device,
         from_port_name: String,
         annoucement: Announcement,
-        dst_packet_space_bdd: &Bdd,
-        bdd_cache: &mut BddCache,
+        _dst_packet_space_bdd: &PacketSpace,
+        _bdd_cache: &mut BddCache,
     ) -> bool {
         let space_id = dst_device.get_space_id(&from_port_name);
         let theoretical_space = match self.space_id_to_conceptual_space.get(&space_id) {
@@ -237,9 +242,6 @@ impl Node {
 
         let arrive_space = annoucement.get_predicate();
         if theoretical_space != arrive_space {
-            let extra_space = theoretical_space.and_not(arrive_space);
-            if !extra_space.is_false() {}
-
             if self.local_cib.contains_key(&from_port_name) {
                 let announce = self.local_cib.get(&from_port_name).unwrap();
                 let exsit_bdd = announce.get_predicate();
@@ -250,16 +252,16 @@ impl Node {
             } else {
                 let verified_cibtuple = CibTuple::new(theoretical_space.and(arrive_space), 1);
                 self.local_cib.insert(from_port_name, verified_cibtuple);
-                return true;
+                true
             }
         } else {
             if self.locked_space_port.contains(&space_id) {
-                return true;
+                true
             } else {
                 self.locked_space_port.insert(space_id);
                 let verified_cibtuple = CibTuple::new(theoretical_space.clone(), 1);
                 self.local_cib.insert(from_port_name, verified_cibtuple);
-                return true;
+                true
             }
         }
     }
@@ -270,11 +272,10 @@ impl Node {
         port_name: String,
         current_ctx: &Ctx,
         visited_devices: &HashSet<String>,
-        dst_packet_space_bdd: &Bdd,
+        dst_packet_space_bdd: &PacketSpace,
         bdd_cache: &mut BddCache,
     ) -> bool {
         let annoucement = current_ctx.get_announcement();
-        let from_device_name = current_ctx.get_device_name();
 
         if !dst_device.has_space_bdd(&port_name) {
             return false;
@@ -305,11 +306,10 @@ impl Node {
         port_name: String,
         current_ctx: &Ctx,
         visited_devices: &HashSet<String>,
-        dst_packet_space: &Bdd,
+        dst_packet_space: &PacketSpace,
         bdd_cache: &mut BddCache,
     ) -> bool {
         let annoucement = current_ctx.get_announcement();
-        let from_device_name = current_ctx.get_device_name();
 
         if !dst_device.has_space_bdd(&port_name) {
             return false;
@@ -338,11 +338,10 @@ impl Node {
         &mut self,
         port_name: String,
         current_ctx: &Ctx,
-        dst_packet_space: &Bdd,
+        dst_packet_space: &PacketSpace,
         bdd_cache: &mut BddCache,
     ) -> bool {
         let annoucement = current_ctx.get_announcement();
-        let from_device_name = current_ctx.get_device_name();
         let dst_device = self.get_device();
 
         if !dst_device.has_space_bdd(&port_name) {
@@ -361,18 +360,18 @@ impl Node {
         true
     }
 
+    #[allow(clippy::too_many_arguments)]
     pub fn count_check_with_dst_device(
         &mut self,
         dst_device: &Device,
         port_name: String,
         current_ctx: &Ctx,
         edge_devices: &HashSet<String>,
-        visited_devices: &HashSet<String>,
-        dst_packet_space_bdd: &Bdd,
+        _visited_devices: &HashSet<String>,
+        dst_packet_space_bdd: &PacketSpace,
         bdd_cache: &mut BddCache,
     ) -> bool {
         let annoucement = current_ctx.get_announcement();
-        let from_device_name = current_ctx.get_device_name();
 
         if !dst_device.has_space_bdd(&port_name) {
             return false;
@@ -382,7 +381,7 @@ impl Node {
         if self.space_id_to_conceptual_space.contains_key(&space_id) {
             let conceptual_space = self.space_id_to_conceptual_space.get(&space_id).unwrap();
             let arrive_space = annoucement.get_predicate();
-            if !conceptual_space.and(arrive_space).is_false() {
+            if !conceptual_space.and(arrive_space).is_empty() {
                 match self.port_arrive_cnt.get_mut(&port_name) {
                     Some(cnt) => {
                         *cnt += 1;
@@ -419,6 +418,7 @@ impl Node {
         current_ctx: &Ctx,
         edge_devices: &HashSet<String>,
         visited_devices: &HashSet<String>,
+        npbdd: &NPBDD,
     ) -> bool {
         let annoucement = current_ctx.get_announcement();
 
@@ -429,7 +429,7 @@ impl Node {
             return false;
         }
 
-        if self.update_loc_cib_new_by_space_port(port_name.clone(), annoucement) {
+        if self.update_loc_cib_new_by_space_port(port_name.clone(), annoucement, npbdd) {
             return true;
         }
 
@@ -440,7 +440,7 @@ impl Node {
     }
 
     pub fn get_cib_out(&mut self) -> Announcement {
-        let mut count_predicate: HashMap<i32, Bdd> = HashMap::default();
+        let mut count_predicate: HashMap<i32, PacketSpace> = HashMap::default();
         for cibtuple in self.local_cib.values() {
             let tmp_count = cibtuple.get_count();
             let tmp_bdd = cibtuple.get_predicate();
@@ -455,8 +455,8 @@ impl Node {
         annoucement_out
     }
 
-    pub fn get_cib_out_predicate(&mut self) -> Bdd {
-        let mut count_predicate: HashMap<i32, Bdd> = HashMap::default();
+    pub fn get_cib_out_predicate(&mut self) -> PacketSpace {
+        let mut count_predicate: HashMap<i32, PacketSpace> = HashMap::default();
         for cibtuple in self.local_cib.values() {
             let tmp_count = cibtuple.get_count();
             let tmp_bdd = cibtuple.get_predicate();
@@ -470,29 +470,25 @@ impl Node {
         count_predicate.get(&1).unwrap().clone()
     }
 
-    pub fn get_result(&mut self, dst_packet_space_bdd: &Bdd, dst_node_name: &str) -> bool {
-        if self.local_cib.len() == 0 {
+    pub fn get_result(&mut self, dst_packet_space_bdd: &PacketSpace, _dst_node_name: &str) -> bool {
+        if self.local_cib.is_empty() {
             false
         } else {
             let annoucement_final = self.get_cib_out();
             let packet_space_bdd_final = annoucement_final.get_predicate();
             let count_final = annoucement_final.get_count();
-            if count_final == 1 && packet_space_bdd_final == dst_packet_space_bdd {
-                true
-            } else {
-                false
-            }
+            count_final == 1 && packet_space_bdd_final == dst_packet_space_bdd
         }
     }
 
     pub fn get_result_toward_dst_space_region(
         &mut self,
         dst_space_region: &SpaceNode,
-        node_packet_space_table: &Arc<HashMap<String, Bdd>>,
+        node_packet_space_table: &Arc<HashMap<String, PacketSpace>>,
     ) -> (usize, usize) {
         let s0_device_set = dst_space_region.get_s0_nodes_table();
         let s0_len = s0_device_set.len();
-        if self.local_cib.len() == 0 {
+        if self.local_cib.is_empty() {
             (0, s0_len)
         } else {
             let mut reach_cnt = 0;
@@ -500,17 +496,16 @@ impl Node {
             let packet_space_bdd_final = annoucement_final.get_predicate();
             let dst_aggre_packet_space = dst_space_region.get_aggre_space().unwrap();
             if packet_space_bdd_final == dst_aggre_packet_space {
-                return (s0_len, 0);
+                (s0_len, 0)
             } else {
                 for s0_device_name in s0_device_set.keys() {
                     let s0_packets_space = node_packet_space_table.get(s0_device_name).unwrap();
                     let intersection = packet_space_bdd_final.and(s0_packets_space);
                     if intersection == *s0_packets_space {
                         reach_cnt += 1;
-                    } else {
                     }
                 }
-                return (reach_cnt, s0_len - reach_cnt);
+                (reach_cnt, s0_len - reach_cnt)
             }
         }
     }
@@ -1,226 +1,285 @@
-use super::{device::Device, toponet::Toponet};
+use super::toponet::Toponet;
 use crate::util::hash_utils::{HashMap, HashSet};
-use crate::util::npbdd::NPBDD;
-use crate::util::{device_port::DevicePort, network::Network};
+use crate::util::npbdd::{SpaceId, NONE_SPACE_ID, NPBDD};
+use crate::util::packet_set::*;
 use crate::verifier::neighborhood::Neighborhood;
-use crate::{EXIST_COUNT, NONEXIST_COUNT, TRAVERSAL_COUNT};
-use biodivine_lib_bdd::*;
-use std::sync::atomic::Ordering;
+use crate::verifier::outer_summary::OuterSummaryCache;
+use crate::verifier::report::ReachabilityReport;
+use crate::verifier::topology_index::{Link, TopologyIndex};
+use rayon::prelude::*;
+use std::collections::VecDeque;
+use std::fmt;
 use std::sync::Arc;
 
-struct Tunnel {
-    pub device_port: DevicePort,
-    pub arrive_spaces: Vec<Bdd>,
-    pub veriyied_space: Bdd,
-    pub arrive_cnt: u32,
+/// Iterations `iterative_traversal` runs before giving up. Verified spaces
+/// only grow, so the loop ends in theory; the cap stops pathological cases.
+pub const DEFAULT_MAX_ITERATIONS: usize = 1000;
+
+/// Tunnels named in a convergence diagnostic.
+const MAX_REPORTED_TUNNELS: usize = 8;
+
+pub struct Tunnel {
+    peer: usize,
+    pub arrive_spaces: Vec<SpaceId>,
+    pub veriyied_space: SpaceId,
+    /// Iterations in which new spaces crossed, and the last one.
+    crossings: usize,
+    last_crossing: usize,
 }
 
 impl Tunnel {
-    pub fn verified_space_prune(&mut self, predicate: &Bdd) -> Bdd {
-        let extra_space = predicate.and_not(&self.veriyied_space);
-        if extra_space.is_false() {
+    pub fn new(peer: usize) -> Self {
+        Self {
+            peer,
+            arrive_spaces: Vec::new(),
+            veriyied_space: NONE_SPACE_ID,
+            crossings: 0,
+            last_crossing: 0,
+        }
+    }
+
+    pub fn verified_space_prune(&mut self, predicate: SpaceId, npbdd: &NPBDD) -> SpaceId {
+        let extra_space = npbdd.and_not(predicate, self.veriyied_space);
+        if extra_space == NONE_SPACE_ID {
             return extra_space;
         }
-        self.veriyied_space = self.veriyied_space.or(&extra_space);
+        self.veriyied_space = npbdd.or(self.veriyied_space, extra_space);
         extra_space
     }
 
     pub fn trans_space_to_peer_node(
         &mut self,
-        inner_area: &mut HashMap<String, NPNetNode>,
-        outer_area: &mut HashMap<String, NPNetNode>,
+        npbdd: &NPBDD,
+        nodes: &mut HashMap<usize, NPNetNode>,
     ) {
         let aggre_space = self
             .arrive_spaces
             .iter()
-            .fold(NPBDD::make_none_space_bdd(), |acc, space| acc.or(space));
+            .fold(NONE_SPACE_ID, |acc, &space| npbdd.or(acc, space));
         self.arrive_spaces.clear();
-        let dst_node_name = self.device_port.get_peer_port().unwrap().0.clone();
-        if inner_area.contains_key(&dst_node_name) {
-            let dst_node = inner_area.get_mut(&dst_node_name).unwrap();
-            dst_node.arrive_spaces.push(aggre_space.clone());
-            dst_node.veriyied_space = dst_node.veriyied_space.or(&aggre_space);
-        } else if outer_area.contains_key(&dst_node_name) {
-            let dst_node = outer_area.get_mut(&dst_node_name).unwrap();
-            dst_node.arrive_spaces.push(aggre_space.clone());
-            dst_node.veriyied_space = dst_node.veriyied_space.or(&aggre_space);
-        }
+        let dst_node = nodes.entry(self.peer).or_default();
+        dst_node.arrive_spaces.push(aggre_space);
+        dst_node.veriyied_space = npbdd.or(dst_node.veriyied_space, aggre_space);
     }
+}
 
-    pub fn loop_detection_at_port(&mut self, max_arrive_cnt: u32) -> bool {
-        self.arrive_cnt += 1;
-        if self.arrive_cnt > max_arrive_cnt {
-            println!(
-                "Possible loop detected at port {} for device {}",
-                self.device_port.get_port_name(),
-                self.device_port.get_peer_port().unwrap().0
-            );
-            return true;
-        }
-        false
-    }
+/// Per-neighborhood traversal state of one device, created the first time a
+/// space arrives at it.
+pub struct NPNetNode {
+    arrive_spaces: Vec<SpaceId>,
+    veriyied_space: SpaceId,
+    queued: bool,
 }
 
-struct NPNetNode {
-    pub name: String,
-    device: Arc<Device>,
-    arrive_spaces: Vec<Bdd>,
-    veriyied_space: Bdd,
-    pub port_arrive_cnt: HashMap<String, u32>,
+impl Default for NPNetNode {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl NPNetNode {
-    pub fn new(name: String) -> Self {
+    pub fn new() -> Self {
         Self {
-            name: name.clone(),
-            device: Arc::new(Device::new(name)),
             arrive_spaces: Vec::new(),
-            veriyied_space: NPBDD::make_none_space_bdd(),
-            port_arrive_cnt: HashMap::default(),
+            veriyied_space: NONE_SPACE_ID,
+            queued: false,
         }
     }
 
-    pub fn get_forwardable_space(&self) -> Bdd {
-        self.device.forwardable_space.clone()
-    }
-
-    pub fn verified_space_prune(&mut self, predicate: &Bdd) -> Bdd {
-        let extra_space = predicate.and_not(&self.veriyied_space);
-        if extra_space.is_false() {
+    pub fn verified_space_prune(&mut self, predicate: SpaceId, npbdd: &NPBDD) -> SpaceId {
+        let extra_space = npbdd.and_not(predicate, self.veriyied_space);
+        if extra_space == NONE_SPACE_ID {
             return extra_space;
         }
-        self.veriyied_space = self.veriyied_space.or(&extra_space);
+        self.veriyied_space = npbdd.or(self.veriyied_space, extra_space);
         extra_space
     }
-    pub fn arrive_space_aggregate_and_verify(&mut self) -> Bdd {
+    pub fn arrive_space_aggregate_and_verify(&mut self, npbdd: &NPBDD) -> SpaceId {
         let aggre_sapce = self
             .arrive_spaces
             .iter()
-            .fold(NPBDD::make_none_space_bdd(), |acc, space| acc.or(space));
-        self.veriyied_space = self.veriyied_space.or(&aggre_sapce);
+            .fold(NONE_SPACE_ID, |acc, &space| npbdd.or(acc, space));
+        self.veriyied_space = npbdd.or(self.veriyied_space, aggre_sapce);
         self.arrive_spaces.clear();
         aggre_sapce
     }
+}
 
-    pub fn loop_detection_at_port(&mut self, port_name: String, max_arrive_cnt: u32) -> bool {
-        match self.port_arrive_cnt.get_mut(&port_name) {
-            Some(cnt) => {
-                *cnt += 1;
-                if *cnt > max_arrive_cnt {
-                    println!(
-                        "Possible loop detected at port {} for device {}",
-                        port_name, self.name
-                    );
-                    return true;
-                }
-                false
-            }
-            None => {
-                self.port_arrive_cnt.insert(port_name, 1);
-                false
-            }
-        }
+/// Result of expanding one frontier node: (device, link position, space)
+/// deliveries, spaces verified at devices passed over by a summary, and the
+/// number of links examined.
+struct Expansion {
+    deliveries: Vec<(usize, usize, SpaceId)>,
+    observed: Vec<(usize, SpaceId)>,
+    examined: usize,
+}
+
+/// Tunnels are keyed by the device and the position of the link in its
+/// `TopologyIndex::links`.
+type TunnelKey = (usize, usize);
+
+/// What one pass over an area and the following tunnel exchange changed.
+#[derive(Debug, Clone, Default)]
+pub struct IterationStats {
+    pub inner: bool,
+    /// Nodes whose verified space grew.
+    pub nodes_updated: usize,
+    /// Spaces handed to the other area, per tunnel as (device, peer) indices
+    /// into the `TopologyIndex`.
+    pub tunnel_spaces: Vec<((usize, usize), usize)>,
+}
+
+impl IterationStats {
+    pub fn spaces_crossed(&self) -> usize {
+        self.tunnel_spaces.iter().map(|(_, spaces)| spaces).sum()
     }
 }
 
-struct NPNetCtx {
-    pub device_name: String,
-    pub arrive_predicate: Bdd,
+/// `iterative_traversal` hit its iteration limit with spaces still crossing
+/// between the areas.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConvergenceError {
+    pub iterations: usize,
+    /// Tunnels that carried new spaces in the last two iterations, as
+    /// (device, peer, iterations with crossings), most active first.
+    pub oscillating: Vec<(String, String, usize)>,
+}
+
+impl fmt::Display for ConvergenceError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "traversal did not converge after {} iterations; tunnels still carrying new spaces: ",
+            self.iterations
+        )?;
+        let tunnels: Vec<String> = self
+            .oscillating
+            .iter()
+            .take(MAX_REPORTED_TUNNELS)
+            .map(|(device, peer, crossings)| {
+                format!("{} -> {} ({} crossings)", device, peer, crossings)
+            })
+            .collect();
+        write!(f, "{}", tunnels.join(", "))?;
+        if self.oscillating.len() > MAX_REPORTED_TUNNELS {
+            write!(
+                f,
+                " and {} more",
+                self.oscillating.len() - MAX_REPORTED_TUNNELS
+            )?;
+        }
+        Ok(())
+    }
 }
 
+impl std::error::Error for ConvergenceError {}
+
 pub struct NPNet {
     neighborhood: Neighborhood,
-    pub inner_area: HashMap<String, NPNetNode>,
-    pub outer_area: HashMap<String, NPNetNode>,
-    pub entrance: HashMap<DevicePort, Tunnel>,
-    devices: Arc<HashMap<String, Arc<Device>>>,
-    network: Arc<Network>,
-    all_subnet_space: Bdd,
-    map_device_packet_space_bdd: Arc<HashMap<String, Bdd>>,
+    index: Arc<TopologyIndex>,
+    inner_area: HashSet<usize>,
+    nodes: HashMap<usize, NPNetNode>,
+    tunnels: HashMap<TunnelKey, Tunnel>,
+    npbdd: Arc<NPBDD>,
+    all_subnet_space: SpaceId,
+    map_device_packet_space_bdd: Arc<HashMap<String, SpaceId>>,
+    traversal_count: usize,
+    parallel_frontier: Option<usize>,
+    outer_summaries: Option<Arc<OuterSummaryCache>>,
+    max_iterations: usize,
+    iteration_stats: Vec<IterationStats>,
 }
 
 impl NPNet {
     pub fn new_with_src_toponet(src_toponet: &Toponet, neighborhood: Neighborhood) -> Self {
-        let bdd = src_toponet.all_space_map.get("All").unwrap().clone();
-        let mut npnet = Self::gen_npnet(
+        let bdd = src_toponet.all_space_map["All"];
+        Self::gen_npnet(
             neighborhood,
-            src_toponet.devices.clone(),
-            src_toponet.network.clone(),
+            src_toponet.topology_index(),
+            src_toponet.npbdd.clone(),
             bdd,
             src_toponet.map_device_packet_space_bdd.clone(),
-        );
-        let nodes = npnet.gen_nodes();
-        npnet.partion_npnet(nodes);
-        npnet
+        )
     }
 
     fn gen_npnet(
         neighborhood: Neighborhood,
-        devices: Arc<HashMap<String, Arc<Device>>>,
-        network: Arc<Network>,
-        all_subnet_space: Bdd,
-        map_device_packet_space_bdd: Arc<HashMap<String, Bdd>>,
+        index: Arc<TopologyIndex>,
+        npbdd: Arc<NPBDD>,
+        all_subnet_space: SpaceId,
+        map_device_packet_space_bdd: Arc<HashMap<String, SpaceId>>,
     ) -> Self {
+        let inner_area = neighborhood
+            .get_marked_nodes()
+            .keys()
+            .chain(neighborhood.get_normal_nodes().iter())
+            .filter_map(|device_name| index.index_of(device_name))
+            .collect();
         Self {
             neighborhood,
-            inner_area: HashMap::default(),
-            outer_area: HashMap::default(),
-            entrance: HashMap::default(),
-            devices,
-            network,
+            index,
+            inner_area,
+            nodes: HashMap::default(),
+            tunnels: HashMap::default(),
+            npbdd,
             all_subnet_space,
             map_device_packet_space_bdd,
+            traversal_count: 0,
+            parallel_frontier: None,
+            outer_summaries: None,
+            max_iterations: DEFAULT_MAX_ITERATIONS,
+            iteration_stats: Vec::new(),
         }
     }
 
-    fn gen_nodes(&self) -> Vec<NPNetNode> {
-        let mut nodes = Vec::new();
-        for (device_name, device) in self.devices.iter() {
-            let mut node = NPNetNode::new(device_name.clone().into());
-            node.device = Arc::clone(device);
-            nodes.push(node);
-        }
-        nodes
+    /// Expands frontier levels with at least `min_len` nodes in parallel.
+    pub fn set_parallel_frontier(&mut self, min_len: Option<usize>) {
+        self.parallel_frontier = min_len;
     }
 
-    fn partion_npnet(&mut self, nodes: Vec<NPNetNode>) {
-        for node in nodes {
-            let device_name = node.name.as_ref();
-            if self.neighborhood.is_neighborhood_node(device_name) {
-                self.inner_area.insert(device_name.to_string(), node);
-            } else {
-                self.outer_area.insert(device_name.to_string(), node);
-            }
-        }
-        for (node_name, is_inner) in self
-            .inner_area
-            .keys()
-            .map(|n| (n, true))
-            .chain(self.outer_area.keys().map(|n| (n, false)))
-        {
-            let ports = self.network.get_device_ports().get(node_name);
-            if ports.is_none() {
-                continue;
-            }
-            for device_port in ports.unwrap() {
-                let (peer_name, _) = device_port.get_peer_port().unwrap();
-                if (is_inner && !self.inner_area.contains_key(peer_name))
-                    || (!is_inner && self.inner_area.contains_key(peer_name))
-                {
-                    let tunnel = Tunnel {
-                        device_port: device_port.clone(),
-                        arrive_spaces: Vec::new(),
-                        veriyied_space: NPBDD::make_none_space_bdd(),
-                        arrive_cnt: 0,
-                    };
-                    self.entrance.insert(device_port.clone(), tunnel);
-                }
-            }
+    /// Lets the outer-area traversal jump over the shared region with cached
+    /// summaries.
+    pub fn set_outer_summaries(&mut self, cache: Option<Arc<OuterSummaryCache>>) {
+        self.outer_summaries = cache;
+    }
+
+    /// Caps the passes over the inner and outer area, counted separately.
+    pub fn set_max_iterations(&mut self, max_iterations: usize) {
+        self.max_iterations = max_iterations;
+    }
+
+    pub fn iteration_stats(&self) -> &[IterationStats] {
+        &self.iteration_stats
+    }
+
+    /// One line per iteration: area, updated nodes and tunnel crossings.
+    pub fn iteration_summary(&self) -> String {
+        let mut summary = String::new();
+        for (iteration, stats) in self.iteration_stats.iter().enumerate() {
+            summary.push_str(&format!(
+                "  iteration {} ({} area): {} nodes updated, {} spaces over {} tunnels\n",
+                iteration + 1,
+                if stats.inner { "inner" } else { "outer" },
+                stats.nodes_updated,
+                stats.spaces_crossed(),
+                stats.tunnel_spaces.len()
+            ));
         }
+        summary
+    }
+
+    /// Space verified at `device_name`; devices never reached have none.
+    fn get_verified_space(&self, device_name: &str) -> Option<SpaceId> {
+        let node = self.index.index_of(device_name)?;
+        Some(
+            self.nodes
+                .get(&node)
+                .map_or(NONE_SPACE_ID, |node| node.veriyied_space),
+        )
     }
 }
 
-#[derive(Clone)]
+#[derive(Clone, PartialEq, Eq)]
 pub enum TraversalType {
     Forward,
     Backward,
@@ -231,274 +290,383 @@ pub enum InvariantType {
     Reachability,
 }
 
+/// Space a predicate must intersect to cross `link` from `node`.
+pub(crate) fn link_space(
+    index: &TopologyIndex,
+    node: usize,
+    link: &Link,
+    traversal_type: &TraversalType,
+) -> Option<SpaceId> {
+    match traversal_type {
+        TraversalType::Forward => link
+            .out_space_id
+            .map(|space_id| index.device(node).get_space_bdd_by_id(space_id)),
+        TraversalType::Backward => link
+            .in_space_id
+            .map(|space_id| index.device(link.peer).get_space_bdd_by_id(space_id)),
+    }
+}
+
 impl NPNet {
+    /// Alternates between the inner and the outer area until no space
+    /// crosses a tunnel, or fails after `max_iterations` passes.
     pub fn iterative_traversal(
         &mut self,
         traversal_type: TraversalType,
-        invariant_type: InvariantType,
-    ) {
+        _invariant_type: InvariantType,
+    ) -> Result<(), ConvergenceError> {
         self.init_marked_nodes_packet_space(traversal_type.clone());
-        let mut iteration = 0;
+        self.iteration_stats.clear();
+        let mut inner = true;
         loop {
-            iteration += 1;
-            self.traverse_inner_area(traversal_type.clone(), invariant_type.clone());
-            if self.entrace_check() {
-                break;
+            if self.iteration_stats.len() == self.max_iterations {
+                return Err(self.convergence_error());
             }
-            self.traverse_outer_area(traversal_type.clone(), invariant_type.clone());
-            if self.entrace_check() {
-                break;
+            let nodes_updated = self.traverse_area(inner, &traversal_type);
+            let tunnel_spaces = self.entrace_check();
+            let converged = tunnel_spaces.is_empty();
+            self.iteration_stats.push(IterationStats {
+                inner,
+                nodes_updated,
+                tunnel_spaces,
+            });
+            if converged {
+                return Ok(());
             }
+            inner = !inner;
+        }
+    }
+
+    fn convergence_error(&self) -> ConvergenceError {
+        let iterations = self.iteration_stats.len();
+        let mut oscillating: Vec<(&TunnelKey, &Tunnel)> = self
+            .tunnels
+            .iter()
+            .filter(|(_, tunnel)| tunnel.crossings > 0 && tunnel.last_crossing + 2 >= iterations)
+            .collect();
+        oscillating
+            .sort_unstable_by_key(|(key, tunnel)| (std::cmp::Reverse(tunnel.crossings), **key));
+        ConvergenceError {
+            iterations,
+            oscillating: oscillating
+                .into_iter()
+                .map(|((node, _), tunnel)| {
+                    (
+                        self.index.name(*node).to_string(),
+                        self.index.name(tunnel.peer).to_string(),
+                        tunnel.crossings,
+                    )
+                })
+                .collect(),
         }
     }
 
     fn init_marked_nodes_packet_space(&mut self, traversal_type: TraversalType) {
         let marked_nodes = self.neighborhood.get_marked_nodes();
         for (name, device) in marked_nodes {
-            let start_node = self.inner_area.get_mut(name).unwrap();
-            let device_bdd = NPBDD::make_src_device_bdd(device.device_id);
+            let node = self.index.index_of(name).unwrap();
+            let start_node = self.nodes.entry(node).or_default();
             let packet_space = match traversal_type {
-                TraversalType::Forward => device_bdd.and(&self.all_subnet_space),
-                TraversalType::Backward => device.dst_prefix_bdd.clone(),
+                TraversalType::Forward => {
+                    if !PacketSpace::HAS_SOURCE {
+                        panic!("forward traversal needs a packet backend with source devices");
+                    }
+                    let device_bdd = self
+                        .npbdd
+                        .intern(self.npbdd.make_src_device_bdd(device.device_id));
+                    self.npbdd.and(device_bdd, self.all_subnet_space)
+                }
+                TraversalType::Backward => device.dst_prefix_bdd,
             };
             start_node.arrive_spaces.push(packet_space);
         }
     }
 
-    fn traverse_inner_area(
+    fn is_tunnel(&self, node: usize, link: &Link) -> bool {
+        self.inner_area.contains(&node) != self.inner_area.contains(&link.peer)
+    }
+
+    /// Hands the new part of `intersection` to the link's tunnel or peer
+    /// node. Peers that received something are queued once in `touched`.
+    fn deliver(
         &mut self,
-        traversal_type: TraversalType,
-        invariant_type: InvariantType,
+        node: usize,
+        link_pos: usize,
+        intersection: SpaceId,
+        touched: &mut Vec<usize>,
     ) {
-        let mut queue = Vec::new();
-        let mut traversal_count = 0;
-        for (node_name, node) in &mut self.inner_area {
-            if node.arrive_spaces.is_empty() {
-                continue;
+        let link = &self.index.links(node)[link_pos];
+        if self.is_tunnel(node, link) {
+            let tunnel = self
+                .tunnels
+                .entry((node, link_pos))
+                .or_insert_with(|| Tunnel::new(link.peer));
+            let arrive_space = tunnel.verified_space_prune(intersection, &self.npbdd);
+            if arrive_space != NONE_SPACE_ID {
+                tunnel.arrive_spaces.push(arrive_space);
             }
-            queue.push(NPNetCtx {
-                device_name: node_name.clone(),
-                arrive_predicate: node.arrive_space_aggregate_and_verify(),
-            });
-        }
-
-        while !queue.is_empty() {
-            let mut indegree_check_set: HashSet<String> = HashSet::default();
-            let size = queue.len();
-            for _ in 0..size {
-                let current_ctx = queue.remove(0);
-                let current_node_name = current_ctx.device_name;
-                let arrive_predicate = current_ctx.arrive_predicate;
-                if let Some(ports) = self.network.get_device_ports().get(&current_node_name) {
-                    for port in ports {
-                        if let Some((dst_device_name, dst_port_name)) = port.get_peer_port() {
-                            traversal_count += 1;
-                            let dst_device = self.devices.get(dst_device_name).unwrap();
-                            let mut intersection = NPBDD::make_none_space_bdd();
-                            match traversal_type {
-                                TraversalType::Forward => {
-                                    let current_device =
-                                        self.devices.get(&current_node_name).unwrap();
-                                    if current_device.has_space_bdd(&port.get_port_name()) {
-                                        intersection = arrive_predicate.and(
-                                            &current_device.get_space_bdd(&port.get_port_name()),
-                                        );
-                                    } else {
-                                        continue;
-                                    }
-                                }
-                                TraversalType::Backward => {
-                                    if dst_device.has_space_bdd(dst_port_name) {
-                                        intersection = arrive_predicate
-                                            .and(&dst_device.get_space_bdd(dst_port_name));
-                                    } else {
-                                        continue;
-                                    }
-                                }
-                            }
-                            if intersection.is_false() {
-                                continue;
-                            }
-                            if self.inner_area.contains_key(dst_device_name) {
-                                let dst_node = self.inner_area.get_mut(dst_device_name).unwrap();
-                                let mut arrive_space = intersection.clone();
-                                match invariant_type {
-                                    _ => {
-                                        arrive_space = dst_node.verified_space_prune(&arrive_space);
-                                    }
-                                }
-                                if !arrive_space.is_false() {
-                                    indegree_check_set.insert(dst_device_name.clone());
-                                    dst_node.arrive_spaces.push(arrive_space);
-                                }
-                            }
-                            if self.entrance.contains_key(port) {
-                                if let Some(tunnel) = self.entrance.get_mut(port) {
-                                    let mut arrive_space = intersection;
-                                    match invariant_type {
-                                        _ => {
-                                            arrive_space =
-                                                tunnel.verified_space_prune(&arrive_space);
-                                        }
-                                    }
-                                    if !arrive_space.is_false() {
-                                        tunnel.arrive_spaces.push(arrive_space);
-                                    }
-                                }
-                            }
-                        }
-                    }
+        } else {
+            let dst_node = self.nodes.entry(link.peer).or_default();
+            let arrive_space = dst_node.verified_space_prune(intersection, &self.npbdd);
+            if arrive_space != NONE_SPACE_ID {
+                dst_node.arrive_spaces.push(arrive_space);
+                if !dst_node.queued {
+                    dst_node.queued = true;
+                    touched.push(link.peer);
                 }
             }
-            indegree_check_set.iter().for_each(|node_name| {
-                let node = self.inner_area.get_mut(node_name).unwrap();
-                let aggreated_space = node.arrive_space_aggregate_and_verify();
-                queue.push(NPNetCtx {
-                    device_name: node_name.clone(),
-                    arrive_predicate: aggreated_space,
-                });
-            });
         }
-        TRAVERSAL_COUNT.fetch_add(traversal_count, Ordering::SeqCst);
     }
 
-    fn entrace_check(&mut self) -> bool {
-        let mut space_convergence = true;
-        for tunnel in self.entrance.values_mut() {
-            if tunnel.arrive_spaces.is_empty() {
-                continue;
+    /// Intersections produced by moving `predicate` one step from `node`. A
+    /// node in the shared outer region jumps straight to the region's exits
+    /// through its cached summary.
+    fn expand(&self, node: usize, predicate: SpaceId, traversal_type: &TraversalType) -> Expansion {
+        if let Some(cache) = self
+            .outer_summaries
+            .as_ref()
+            .filter(|cache| cache.in_region(node) && !self.inner_area.contains(&node))
+            .filter(|cache| cache.traversal_type() == traversal_type)
+        {
+            let summary = cache.summary(node);
+            let restrict = |space: SpaceId| {
+                let intersection = self.npbdd.and(predicate, space);
+                (intersection != NONE_SPACE_ID).then_some(intersection)
+            };
+            return Expansion {
+                deliveries: summary
+                    .exits
+                    .iter()
+                    .filter_map(|(exit_node, link_pos, space)| {
+                        restrict(*space).map(|intersection| (*exit_node, *link_pos, intersection))
+                    })
+                    .collect(),
+                observed: summary
+                    .observed
+                    .iter()
+                    .filter_map(|(observed_node, space)| {
+                        restrict(*space).map(|intersection| (*observed_node, intersection))
+                    })
+                    .collect(),
+                examined: summary.exits.len(),
+            };
+        }
+        let links = self.index.links(node);
+        Expansion {
+            deliveries: links
+                .iter()
+                .enumerate()
+                .filter_map(|(link_pos, link)| {
+                    let space = link_space(&self.index, node, link, traversal_type)?;
+                    let intersection = self.npbdd.and(predicate, space);
+                    (intersection != NONE_SPACE_ID).then_some((node, link_pos, intersection))
+                })
+                .collect(),
+            observed: Vec::new(),
+            examined: links.len(),
+        }
+    }
+
+    fn apply_expansion(&mut self, expansion: Expansion, touched: &mut Vec<usize>) {
+        self.traversal_count += expansion.examined;
+        for (node, space) in expansion.observed {
+            self.observe(node, space);
+        }
+        for (node, link_pos, intersection) in expansion.deliveries {
+            self.deliver(node, link_pos, intersection, touched);
+        }
+    }
+
+    /// Records space verified at a region device a summary jumped over.
+    fn observe(&mut self, node: usize, space: SpaceId) {
+        let state = self.nodes.entry(node).or_default();
+        state.veriyied_space = self.npbdd.or(state.veriyied_space, space);
+    }
+
+    /// Propagates the spaces that arrived in one area, level by level, until
+    /// nothing new arrives. Spaces reaching a tunnel wait there for
+    /// `entrace_check` to hand them to the other area. Returns how many
+    /// times a node's verified space grew.
+    fn traverse_area(&mut self, inner: bool, traversal_type: &TraversalType) -> usize {
+        let mut started: Vec<usize> = self
+            .nodes
+            .iter()
+            .filter(|(node, state)| {
+                self.inner_area.contains(node) == inner && !state.arrive_spaces.is_empty()
+            })
+            .map(|(node, _)| *node)
+            .collect();
+        started.sort_unstable();
+        let mut nodes_updated = started.len();
+        let mut frontier: VecDeque<(usize, SpaceId)> = started
+            .into_iter()
+            .map(|node| {
+                let state = self.nodes.get_mut(&node).unwrap();
+                (node, state.arrive_space_aggregate_and_verify(&self.npbdd))
+            })
+            .collect();
+
+        let mut touched: Vec<usize> = Vec::new();
+        while !frontier.is_empty() {
+            let level_len = frontier.len();
+            if self
+                .parallel_frontier
+                .is_some_and(|min_len| level_len >= min_len)
+            {
+                self.expand_level_parallel(&mut frontier, traversal_type, &mut touched);
             } else {
-                space_convergence = false;
-                tunnel.trans_space_to_peer_node(&mut self.inner_area, &mut self.outer_area);
+                for _ in 0..level_len {
+                    let (node, predicate) = frontier.pop_front().unwrap();
+                    let expansion = self.expand(node, predicate, traversal_type);
+                    self.apply_expansion(expansion, &mut touched);
+                }
+            }
+            nodes_updated += touched.len();
+            for node in touched.drain(..) {
+                let state = self.nodes.get_mut(&node).unwrap();
+                state.queued = false;
+                frontier.push_back((node, state.arrive_space_aggregate_and_verify(&self.npbdd)));
             }
         }
-        space_convergence
+        nodes_updated
     }
 
-    fn traverse_outer_area(
+    /// Expands a whole level on the rayon pool. Intersections are computed
+    /// per link, collected per destination, and every destination prunes the
+    /// union of what it received, so each node and tunnel has one writer.
+    fn expand_level_parallel(
         &mut self,
-        traversal_type: TraversalType,
-        invariant_type: InvariantType,
+        frontier: &mut VecDeque<(usize, SpaceId)>,
+        traversal_type: &TraversalType,
+        touched: &mut Vec<usize>,
     ) {
-        let mut queue = Vec::new();
-        let mut traversal_count = 0;
-        for (node_name, node) in &mut self.outer_area {
-            if node.arrive_spaces.is_empty() {
-                continue;
+        let expansions: Vec<Expansion> = frontier
+            .make_contiguous()
+            .par_iter()
+            .map(|&(node, predicate)| self.expand(node, predicate, traversal_type))
+            .collect();
+        frontier.clear();
+
+        let mut node_inbox: HashMap<usize, Vec<SpaceId>> = HashMap::default();
+        let mut tunnel_inbox: HashMap<TunnelKey, Vec<SpaceId>> = HashMap::default();
+        let index = Arc::clone(&self.index);
+        for expansion in expansions {
+            self.traversal_count += expansion.examined;
+            for (node, space) in expansion.observed {
+                self.observe(node, space);
+            }
+            for (node, link_pos, intersection) in expansion.deliveries {
+                let link = &index.links(node)[link_pos];
+                if self.is_tunnel(node, link) {
+                    tunnel_inbox
+                        .entry((node, link_pos))
+                        .or_default()
+                        .push(intersection);
+                } else {
+                    node_inbox.entry(link.peer).or_default().push(intersection);
+                }
             }
-            queue.push(NPNetCtx {
-                device_name: node_name.clone(),
-                arrive_predicate: node.arrive_space_aggregate_and_verify(),
-            });
         }
 
-        while !queue.is_empty() {
-            let mut indegree_check_set: HashSet<String> = HashSet::default();
-            let size = queue.len();
-            for _ in 0..size {
-                let current_ctx = queue.remove(0);
-                let current_node_name = current_ctx.device_name;
-                let arrive_predicate = current_ctx.arrive_predicate;
-                if let Some(ports) = self.network.get_device_ports().get(&current_node_name) {
-                    for port in ports {
-                        if let Some((dst_device_name, dst_port_name)) = port.get_peer_port() {
-                            traversal_count += 1;
-                            let dst_device = self.devices.get(dst_device_name).unwrap();
-                            let mut intersection = NPBDD::make_none_space_bdd();
-                            match traversal_type {
-                                TraversalType::Forward => {
-                                    let current_device =
-                                        self.devices.get(&current_node_name).unwrap();
-                                    if current_device.has_space_bdd(&port.get_port_name()) {
-                                        intersection = arrive_predicate.and(
-                                            &current_device.get_space_bdd(&port.get_port_name()),
-                                        );
-                                    } else {
-                                        continue;
-                                    }
-                                }
-                                TraversalType::Backward => {
-                                    if dst_device.has_space_bdd(dst_port_name) {
-                                        intersection = arrive_predicate
-                                            .and(&dst_device.get_space_bdd(dst_port_name));
-                                    } else {
-                                        continue;
-                                    }
-                                }
-                            }
-                            if intersection.is_false() {
-                                continue;
-                            }
-                            if self.outer_area.contains_key(dst_device_name) {
-                                let dst_node = self.outer_area.get_mut(dst_device_name).unwrap();
-                                let mut arrive_space = intersection.clone();
-                                match invariant_type {
-                                    _ => {
-                                        arrive_space = dst_node.verified_space_prune(&arrive_space);
-                                    }
-                                }
-                                if !arrive_space.is_false() {
-                                    indegree_check_set.insert(dst_device_name.clone());
-                                    dst_node.arrive_spaces.push(arrive_space);
-                                }
-                            }
-                            if self.entrance.contains_key(port) {
-                                if let Some(tunnel) = self.entrance.get_mut(port) {
-                                    let mut arrive_space = intersection;
-                                    match invariant_type {
-                                        _ => {
-                                            arrive_space =
-                                                tunnel.verified_space_prune(&arrive_space);
-                                        }
-                                    }
-                                    if !arrive_space.is_false() {
-                                        tunnel.arrive_spaces.push(arrive_space);
-                                    }
-                                }
-                            }
-                        }
-                    }
+        let npbdd = Arc::clone(&self.npbdd);
+        let union = |spaces: &[SpaceId]| {
+            spaces
+                .iter()
+                .fold(NONE_SPACE_ID, |acc, &space| npbdd.or(acc, space))
+        };
+        // Move the receiving states out so each one is updated on its own thread.
+        let mut receiving_nodes: Vec<(usize, NPNetNode, Vec<SpaceId>)> = node_inbox
+            .into_iter()
+            .map(|(node, inbox)| {
+                let state = self.nodes.remove(&node).unwrap_or_default();
+                (node, state, inbox)
+            })
+            .collect();
+        receiving_nodes
+            .par_iter_mut()
+            .for_each(|(_, state, inbox)| {
+                let arrive_space = state.verified_space_prune(union(inbox), &npbdd);
+                if arrive_space != NONE_SPACE_ID {
+                    state.arrive_spaces.push(arrive_space);
                 }
+            });
+        receiving_nodes.sort_unstable_by_key(|(node, _, _)| *node);
+        for (node, state, _) in receiving_nodes {
+            if !state.arrive_spaces.is_empty() {
+                touched.push(node);
             }
-            indegree_check_set.iter().for_each(|node_name| {
-                let node = self.outer_area.get_mut(node_name).unwrap();
-                let aggreated_space = node.arrive_space_aggregate_and_verify();
-                queue.push(NPNetCtx {
-                    device_name: node_name.clone(),
-                    arrive_predicate: aggreated_space,
-                });
+            self.nodes.insert(node, state);
+        }
+
+        let mut receiving_tunnels: Vec<(TunnelKey, Tunnel, Vec<SpaceId>)> = tunnel_inbox
+            .into_iter()
+            .map(|((node, link_pos), inbox)| {
+                let tunnel = self
+                    .tunnels
+                    .remove(&(node, link_pos))
+                    .unwrap_or_else(|| Tunnel::new(index.links(node)[link_pos].peer));
+                ((node, link_pos), tunnel, inbox)
+            })
+            .collect();
+        receiving_tunnels
+            .par_iter_mut()
+            .for_each(|(_, tunnel, inbox)| {
+                let arrive_space = tunnel.verified_space_prune(union(inbox), &npbdd);
+                if arrive_space != NONE_SPACE_ID {
+                    tunnel.arrive_spaces.push(arrive_space);
+                }
             });
+        self.tunnels.extend(
+            receiving_tunnels
+                .into_iter()
+                .map(|(key, tunnel, _)| (key, tunnel)),
+        );
+    }
+
+    /// Hands waiting tunnel spaces to the other area and returns how many
+    /// crossed each tunnel; none means the traversal has converged.
+    fn entrace_check(&mut self) -> Vec<((usize, usize), usize)> {
+        let iteration = self.iteration_stats.len();
+        let mut tunnel_spaces = Vec::new();
+        for ((node, _), tunnel) in self.tunnels.iter_mut() {
+            if tunnel.arrive_spaces.is_empty() {
+                continue;
+            }
+            tunnel_spaces.push(((*node, tunnel.peer), tunnel.arrive_spaces.len()));
+            tunnel.crossings += 1;
+            tunnel.last_crossing = iteration;
+            tunnel.trans_space_to_peer_node(&self.npbdd, &mut self.nodes);
         }
-        TRAVERSAL_COUNT.fetch_add(traversal_count, Ordering::SeqCst);
+        tunnel_spaces.sort_unstable();
+        tunnel_spaces
     }
 }
 
 impl NPNet {
-    pub fn check_reachability(&self, pair_devices: HashSet<String>, traversal_type: TraversalType) {
+    pub fn check_reachability(
+        &self,
+        pair_devices: HashSet<String>,
+        traversal_type: TraversalType,
+    ) -> ReachabilityReport {
         match traversal_type {
             TraversalType::Backward => self.backward_check_reachability(pair_devices),
             TraversalType::Forward => self.forward_check_reachability(pair_devices),
         }
     }
 
-    pub fn backward_check_reachability(&self, pair_devices: HashSet<String>) {
+    pub fn backward_check_reachability(&self, pair_devices: HashSet<String>) -> ReachabilityReport {
         let mut arrive_count = 0;
         let mut unreachable_count = 0;
         for src_name in pair_devices {
-            let src_node;
-            match self.inner_area.get(&src_name) {
-                Some(node) => src_node = node,
-                None => src_node = self.outer_area.get(&src_name).unwrap(),
-            }
+            let src_verified_space = self.get_verified_space(&src_name).unwrap();
             let dst_nodes = self.neighborhood.get_marked_nodes();
             for (dst_node_name, device) in dst_nodes {
                 if *dst_node_name == src_name {
                     continue;
                 }
-                if device
-                    .dst_prefix_bdd
-                    .and_not(&src_node.veriyied_space)
-                    .is_false()
+                if self
+                    .npbdd
+                    .is_subset(device.dst_prefix_bdd, src_verified_space)
                 {
                     arrive_count += 1;
                 } else {
@@ -506,42 +674,61 @@ impl NPNet {
                 }
             }
         }
-        EXIST_COUNT.fetch_add(arrive_count, Ordering::SeqCst);
-        NONEXIST_COUNT.fetch_add(unreachable_count, Ordering::SeqCst);
+        ReachabilityReport::new(arrive_count, unreachable_count, self.traversal_count)
     }
 
-    pub fn forward_check_reachability(&self, pair_devices: HashSet<String>) {
+    pub fn forward_check_reachability(&self, pair_devices: HashSet<String>) -> ReachabilityReport {
         let mut reach_cnt = 0;
         let mut unreach_cnt = 0;
         let src_nodes = self.neighborhood.get_marked_nodes();
-        let dst_nodes: Vec<&NPNetNode> = pair_devices
+        let dst_nodes: Vec<(&String, SpaceId)> = pair_devices
             .iter()
-            .map(|device_name| match self.inner_area.get(device_name) {
-                Some(node) => node,
-                None => self.outer_area.get(device_name).unwrap(),
-            })
+            .map(|device_name| (device_name, self.get_verified_space(device_name).unwrap()))
             .collect();
 
-        dst_nodes.iter().for_each(|dst_node| {
-            let dst_node_name = dst_node.name.clone();
-            let Some(dst_node_subnet_space) = self.map_device_packet_space_bdd.get(&dst_node_name)
-            else {
-                return;
-            };
-            src_nodes.iter().for_each(|(src_node_name, src_node)| {
-                if dst_node_name == *src_node_name {
+        dst_nodes
+            .iter()
+            .for_each(|(dst_node_name, dst_verified_space)| {
+                let Some(dst_node_subnet_space) =
+                    self.map_device_packet_space_bdd.get(*dst_node_name)
+                else {
                     return;
-                }
-                let src_device_space = NPBDD::make_src_device_bdd(src_node.device_id);
-                let packet_space = src_device_space.and(dst_node_subnet_space);
-                if !packet_space.and_not(&dst_node.veriyied_space).is_false() {
-                    unreach_cnt += 1;
-                } else {
-                    reach_cnt += 1;
-                }
+                };
+                src_nodes.iter().for_each(|(src_node_name, src_node)| {
+                    if *dst_node_name == src_node_name {
+                        return;
+                    }
+                    let src_device_space = self
+                        .npbdd
+                        .intern(self.npbdd.make_src_device_bdd(src_node.device_id));
+                    let packet_space = self.npbdd.and(src_device_space, *dst_node_subnet_space);
+                    if !self.npbdd.is_subset(packet_space, *dst_verified_space) {
+                        unreach_cnt += 1;
+                    } else {
+                        reach_cnt += 1;
+                    }
+                });
             });
-        });
-        NONEXIST_COUNT.fetch_add(unreach_cnt, Ordering::SeqCst);
-        EXIST_COUNT.fetch_add(reach_cnt, Ordering::SeqCst);
+        ReachabilityReport::new(reach_cnt, unreach_cnt, self.traversal_count)
+    }
+
+    /// Whether every packet of `space` sent from `src_name` reaches the
+    /// marked node owning it.
+    pub fn delivers(&self, src_name: &str, space: SpaceId) -> Option<bool> {
+        let src_verified_space = self.get_verified_space(src_name)?;
+        Some(self.npbdd.is_subset(space, src_verified_space))
+    }
+
+    pub fn is_reachable(&self, src_name: &str, dst_name: &str) -> Option<bool> {
+        let src_verified_space = self.get_verified_space(src_name)?;
+        let dst_device = self.neighborhood.get_marked_nodes().get(dst_name)?;
+        Some(
+            self.npbdd
+                .is_subset(dst_device.dst_prefix_bdd, src_verified_space),
+        )
+    }
+
+    pub fn get_traversal_count(&self) -> usize {
+        self.traversal_count
     }
 }
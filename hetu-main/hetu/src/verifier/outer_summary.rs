@@ -0,0 +1,141 @@
+use crate::util::hash_utils::{DashMap, HashMap};
+use crate::util::npbdd::{SpaceId, ALL_SPACE_ID, NONE_SPACE_ID, NPBDD};
+use crate::verifier::npnet::{link_space, TraversalType};
+use crate::verifier::topology_index::TopologyIndex;
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+/// What happens to a space after it enters the shared region at one device.
+/// Both lists hold the predicate a space must intersect, so applying the
+/// summary to a space `S` is `S ∧ predicate` for every entry.
+pub struct EntrySummary {
+    /// (device, link position) of links that leave the region.
+    pub exits: Vec<(usize, usize, SpaceId)>,
+    /// Space verified at observed devices inside the region.
+    pub observed: Vec<(usize, SpaceId)>,
+}
+
+/// Cache of transfer summaries for the shared region: the devices outside
+/// every neighborhood's inner area, typically the spine and core layers.
+///
+/// Propagation distributes over union and intersection, so a summary built
+/// once from the full space holds for any space entering at the same device,
+/// whichever neighborhood it comes from.
+pub struct OuterSummaryCache {
+    index: Arc<TopologyIndex>,
+    npbdd: Arc<NPBDD>,
+    traversal_type: TraversalType,
+    in_region: Vec<bool>,
+    is_observed: Vec<bool>,
+    summaries: DashMap<usize, Arc<EntrySummary>>,
+    computed: AtomicUsize,
+    applied: AtomicUsize,
+}
+
+impl OuterSummaryCache {
+    /// `in_region` and `is_observed` are indexed like `index`. Observed
+    /// devices are the ones whose verified space is checked afterwards.
+    pub fn new(
+        index: Arc<TopologyIndex>,
+        npbdd: Arc<NPBDD>,
+        traversal_type: TraversalType,
+        in_region: Vec<bool>,
+        is_observed: Vec<bool>,
+    ) -> Self {
+        OuterSummaryCache {
+            index,
+            npbdd,
+            traversal_type,
+            in_region,
+            is_observed,
+            summaries: DashMap::default(),
+            computed: AtomicUsize::new(0),
+            applied: AtomicUsize::new(0),
+        }
+    }
+
+    pub fn in_region(&self, node: usize) -> bool {
+        self.in_region[node]
+    }
+
+    pub fn region_len(&self) -> usize {
+        self.in_region
+            .iter()
+            .filter(|&&in_region| in_region)
+            .count()
+    }
+
+    pub fn traversal_type(&self) -> &TraversalType {
+        &self.traversal_type
+    }
+
+    /// Summaries built so far and the number of times one was applied.
+    pub fn stats(&self) -> (usize, usize) {
+        (
+            self.computed.load(Ordering::Relaxed),
+            self.applied.load(Ordering::Relaxed),
+        )
+    }
+
+    pub fn summary(&self, entry: usize) -> Arc<EntrySummary> {
+        self.applied.fetch_add(1, Ordering::Relaxed);
+        if let Some(summary) = self.summaries.get(&entry) {
+            return Arc::clone(&summary);
+        }
+        // Built outside the map lock; a racing thread may build the same
+        // summary, and the first insert wins.
+        let summary = Arc::new(self.build(entry));
+        let summary = self.summaries.entry(entry).or_insert_with(|| {
+            self.computed.fetch_add(1, Ordering::Relaxed);
+            summary
+        });
+        Arc::clone(&summary)
+    }
+
+    /// Propagates the full space from `entry` through the region only.
+    fn build(&self, entry: usize) -> EntrySummary {
+        let mut reach: HashMap<usize, SpaceId> = HashMap::default();
+        let mut exits: HashMap<(usize, usize), SpaceId> = HashMap::default();
+        reach.insert(entry, ALL_SPACE_ID);
+        let mut frontier: VecDeque<(usize, SpaceId)> = VecDeque::new();
+        frontier.push_back((entry, ALL_SPACE_ID));
+        while let Some((node, predicate)) = frontier.pop_front() {
+            for (link_pos, link) in self.index.links(node).iter().enumerate() {
+                let Some(space) = link_space(&self.index, node, link, &self.traversal_type) else {
+                    continue;
+                };
+                let intersection = self.npbdd.and(predicate, space);
+                if intersection == NONE_SPACE_ID {
+                    continue;
+                }
+                if !self.in_region[link.peer] {
+                    exits
+                        .entry((node, link_pos))
+                        .and_modify(|exit| *exit = self.npbdd.or(*exit, intersection))
+                        .or_insert(intersection);
+                    continue;
+                }
+                let verified = reach.entry(link.peer).or_insert(NONE_SPACE_ID);
+                let extra_space = self.npbdd.and_not(intersection, *verified);
+                if extra_space == NONE_SPACE_ID {
+                    continue;
+                }
+                *verified = self.npbdd.or(*verified, extra_space);
+                frontier.push_back((link.peer, extra_space));
+            }
+        }
+
+        let mut exits: Vec<(usize, usize, SpaceId)> = exits
+            .into_iter()
+            .map(|((node, link_pos), space)| (node, link_pos, space))
+            .collect();
+        exits.sort_unstable_by_key(|(node, link_pos, _)| (*node, *link_pos));
+        let mut observed: Vec<(usize, SpaceId)> = reach
+            .into_iter()
+            .filter(|(node, _)| self.is_observed[*node])
+            .collect();
+        observed.sort_unstable_by_key(|(node, _)| *node);
+        EntrySummary { exits, observed }
+    }
+}
@@ -0,0 +1,642 @@
+use crate::util::hash_utils::{HashMap, HashSet};
+use crate::util::network::Network;
+use crate::verifier::neighborhood::{Neighborhood, PacketSpaceAwareDevice};
+use std::collections::VecDeque;
+use std::fmt;
+use std::str::FromStr;
+use std::sync::Arc;
+
+/// Splits the marked (packet-space owning) devices into neighborhoods that
+/// are verified independently.
+///
+/// Every marked node must end up in exactly one neighborhood, otherwise its
+/// pairs are counted twice or not at all. Normal nodes are optional; they only
+/// decide which devices are traversed as the neighborhood's inner area.
+pub trait Partitioner: Send + Sync {
+    fn name(&self) -> String;
+
+    fn partition(
+        &self,
+        network: &Network,
+        marked_nodes: HashMap<String, PacketSpaceAwareDevice>,
+    ) -> Vec<Neighborhood>;
+}
+
+/// Parses a `--partition` value: `bfs[:DEPTH[:MAX_MARKED]]`, `pod` or `mincut[:PARTS]`.
+pub fn parse_partitioner(spec: &str) -> Result<Arc<dyn Partitioner>, String> {
+    let mut fields = spec.split(':');
+    let kind = fields.next().unwrap_or_default();
+    match kind {
+        "bfs" => {
+            let mut partitioner = BfsMergePartitioner::default();
+            if let Some(depth) = parse_field::<i32>(fields.next(), "depth")? {
+                if depth < 1 {
+                    return Err(format!("partition depth must be at least 1, got {}", depth));
+                }
+                partitioner.proximity_depth = depth;
+            }
+            partitioner.max_marked_nodes = parse_field(fields.next(), "max marked nodes")?;
+            Ok(Arc::new(partitioner))
+        }
+        "pod" => Ok(Arc::new(PodPartitioner)),
+        "mincut" => {
+            let mut partitioner = MinCutPartitioner::default();
+            if let Some(parts) = parse_field::<usize>(fields.next(), "part count")? {
+                partitioner.parts = parts.max(1);
+            }
+            Ok(Arc::new(partitioner))
+        }
+        _ => Err(format!("unknown partition strategy: {}", spec)),
+    }
+}
+
+fn parse_field<T: FromStr>(value: Option<&str>, name: &str) -> Result<Option<T>, String> {
+    match value {
+        Some(value) => value
+            .parse()
+            .map(Some)
+            .map_err(|_| format!("invalid {} in partition spec: {}", name, value)),
+        None => Ok(None),
+    }
+}
+
+/// Grows a neighborhood from each unvisited marked node. The depth budget is
+/// reset whenever another marked node is reached, so marked nodes within
+/// `proximity_depth` hops of each other are merged.
+#[derive(Debug, Clone)]
+pub struct BfsMergePartitioner {
+    pub proximity_depth: i32,
+    pub max_marked_nodes: Option<usize>,
+}
+
+impl Default for BfsMergePartitioner {
+    fn default() -> Self {
+        BfsMergePartitioner {
+            proximity_depth: 2,
+            max_marked_nodes: None,
+        }
+    }
+}
+
+impl Partitioner for BfsMergePartitioner {
+    fn name(&self) -> String {
+        match self.max_marked_nodes {
+            Some(max_size) => format!("bfs(depth={}, max={})", self.proximity_depth, max_size),
+            None => format!("bfs(depth={})", self.proximity_depth),
+        }
+    }
+
+    fn partition(
+        &self,
+        network: &Network,
+        marked_nodes: HashMap<String, PacketSpaceAwareDevice>,
+    ) -> Vec<Neighborhood> {
+        let mut visited: HashMap<String, bool> = HashMap::default();
+        let mut components: Vec<Neighborhood> = Vec::new();
+        for (device_name, device) in marked_nodes.iter() {
+            match visited.get(device_name) {
+                Some(true) => continue,
+                _ => {
+                    let mut component = Neighborhood::new();
+                    self.bfs_explore(network, &marked_nodes, device, &mut visited, &mut component);
+                    components.push(component);
+                }
+            }
+        }
+        components
+    }
+}
+
+impl BfsMergePartitioner {
+    fn bfs_explore(
+        &self,
+        network: &Network,
+        marked_nodes: &HashMap<String, PacketSpaceAwareDevice>,
+        start_device: &PacketSpaceAwareDevice,
+        visited: &mut HashMap<String, bool>,
+        component: &mut Neighborhood,
+    ) {
+        // The start node is always part of its neighborhood.
+        let proximity_depth = self.proximity_depth.max(1);
+        let mut queue: Vec<(String, i32)> = Vec::new();
+        queue.push((start_device.device_name.clone(), proximity_depth));
+        let mut marked_count = 0;
+        while let Some((current_device_name, depth)) = queue.pop() {
+            if *visited.get(&current_device_name).unwrap_or(&false) || depth == 0 {
+                continue;
+            }
+            visited.insert(current_device_name.clone(), true);
+            if let Some(bdd) = marked_nodes.get(&current_device_name) {
+                component.add_marked_node(bdd.clone());
+            } else {
+                component.add_normal_node(current_device_name.clone());
+            }
+            if let Some(max_size) = self.max_marked_nodes {
+                if marked_nodes.contains_key(&current_device_name) {
+                    marked_count += 1;
+                    if marked_count >= max_size {
+                        break;
+                    }
+                }
+            }
+            if let Some(cur_ports) = network.get_device_ports().get(&current_device_name) {
+                for cur_port in cur_ports {
+                    if let Some((neighbor_name, _)) = cur_port.get_peer_port() {
+                        if !*visited.get(neighbor_name).unwrap_or(&false) {
+                            let new_depth = if marked_nodes.contains_key(neighbor_name) {
+                                proximity_depth
+                            } else {
+                                depth - 1
+                            };
+                            queue.push((neighbor_name.clone(), new_depth));
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// One neighborhood per pod from `Network::get_pods`. Marked nodes outside
+/// every pod become single-node neighborhoods.
+#[derive(Debug, Clone, Default)]
+pub struct PodPartitioner;
+
+impl Partitioner for PodPartitioner {
+    fn name(&self) -> String {
+        "pod".to_string()
+    }
+
+    fn partition(
+        &self,
+        network: &Network,
+        mut marked_nodes: HashMap<String, PacketSpaceAwareDevice>,
+    ) -> Vec<Neighborhood> {
+        let mut pods: Vec<_> = network.get_pods().iter().collect();
+        pods.sort_by_key(|(pod_id, _)| **pod_id);
+        let mut neighborhoods = Vec::new();
+        for (_, pod) in pods {
+            let mut neighborhood = Neighborhood::new();
+            for device_name in pod.get_devices() {
+                match marked_nodes.remove(&device_name) {
+                    Some(device) => neighborhood.add_marked_node(device),
+                    None => neighborhood.add_normal_node(device_name),
+                }
+            }
+            if !neighborhood.get_marked_nodes().is_empty() {
+                neighborhoods.push(neighborhood);
+            }
+        }
+        for (_, device) in marked_nodes {
+            let mut neighborhood = Neighborhood::new();
+            neighborhood.add_marked_node(device);
+            neighborhoods.push(neighborhood);
+        }
+        neighborhoods
+    }
+}
+
+/// Balanced k-way partition of the device graph that keeps few links between
+/// parts. Parts are grown from spread-out marked seeds, always extending the
+/// part with the fewest marked nodes, then refined by moving boundary nodes
+/// to the neighboring part they share the most links with, as long as no part
+/// exceeds its marked-node quota.
+#[derive(Debug, Clone)]
+pub struct MinCutPartitioner {
+    pub parts: usize,
+    pub refine_passes: usize,
+    /// Allowed overshoot of a part's marked-node quota, e.g. 0.1 for 10%.
+    pub imbalance: f64,
+}
+
+impl Default for MinCutPartitioner {
+    fn default() -> Self {
+        MinCutPartitioner {
+            parts: rayon::current_num_threads() * 2,
+            refine_passes: 8,
+            imbalance: 0.1,
+        }
+    }
+}
+
+impl Partitioner for MinCutPartitioner {
+    fn name(&self) -> String {
+        format!("mincut(parts={})", self.parts)
+    }
+
+    fn partition(
+        &self,
+        network: &Network,
+        mut marked_nodes: HashMap<String, PacketSpaceAwareDevice>,
+    ) -> Vec<Neighborhood> {
+        let graph = DeviceGraph::new(network, &marked_nodes);
+        let marked: Vec<bool> = graph
+            .names
+            .iter()
+            .map(|name| marked_nodes.contains_key(name))
+            .collect();
+        let parts = self.parts.clamp(1, marked_nodes.len().max(1));
+        let mut assignment = self.grow(&graph, &marked, parts);
+        self.refine(&graph, &marked, parts, &mut assignment);
+
+        let mut neighborhoods: Vec<Neighborhood> =
+            (0..parts).map(|_| Neighborhood::new()).collect();
+        for (node, name) in graph.names.iter().enumerate() {
+            let neighborhood = &mut neighborhoods[assignment[node]];
+            match marked_nodes.remove(name) {
+                Some(device) => neighborhood.add_marked_node(device),
+                None => neighborhood.add_normal_node(name.clone()),
+            }
+        }
+        // Marked nodes without any link never made it into the graph.
+        for (_, device) in marked_nodes {
+            let mut neighborhood = Neighborhood::new();
+            neighborhood.add_marked_node(device);
+            neighborhoods.push(neighborhood);
+        }
+        neighborhoods.retain(|neighborhood| !neighborhood.get_marked_nodes().is_empty());
+        neighborhoods
+    }
+}
+
+impl MinCutPartitioner {
+    fn quota(&self, marked: &[bool], parts: usize) -> usize {
+        let total = marked.iter().filter(|&&is_marked| is_marked).count();
+        let even = total.div_ceil(parts);
+        ((even as f64) * (1.0 + self.imbalance)).ceil() as usize
+    }
+
+    fn grow(&self, graph: &DeviceGraph, marked: &[bool], parts: usize) -> Vec<usize> {
+        let node_count = graph.names.len();
+        let mut assignment = vec![usize::MAX; node_count];
+        let seeds = graph.spread_seeds(marked, parts);
+        let mut frontiers: Vec<VecDeque<usize>> = vec![VecDeque::new(); parts];
+        let mut weights = vec![0usize; parts];
+        let mut sizes = vec![1usize; parts];
+        for (part, &seed) in seeds.iter().enumerate() {
+            assignment[seed] = part;
+            weights[part] += marked[seed] as usize;
+            frontiers[part].extend(graph.adjacency[seed].iter().copied());
+        }
+
+        let quota = self.quota(marked, parts);
+        loop {
+            // Extend the lightest part that still has room and somewhere to
+            // grow. Full parts stop claiming nodes so they do not wall off
+            // marked nodes from the others.
+            let next_part = (0..parts)
+                .filter(|&part| weights[part] < quota && !frontiers[part].is_empty())
+                .min_by_key(|&part| (weights[part], sizes[part]));
+            let Some(part) = next_part else { break };
+            while let Some(node) = frontiers[part].pop_front() {
+                if assignment[node] != usize::MAX {
+                    continue;
+                }
+                assignment[node] = part;
+                weights[part] += marked[node] as usize;
+                sizes[part] += 1;
+                frontiers[part].extend(
+                    graph.adjacency[node]
+                        .iter()
+                        .copied()
+                        .filter(|&peer| assignment[peer] == usize::MAX),
+                );
+                break;
+            }
+        }
+
+        // Nodes left behind by full parts join the lightest adjacent part.
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for node in 0..node_count {
+                if assignment[node] != usize::MAX {
+                    continue;
+                }
+                let lightest = graph.adjacency[node]
+                    .iter()
+                    .map(|&peer| assignment[peer])
+                    .filter(|&part| part != usize::MAX)
+                    .min_by_key(|&part| (weights[part], part));
+                if let Some(part) = lightest {
+                    assignment[node] = part;
+                    weights[part] += marked[node] as usize;
+                    changed = true;
+                }
+            }
+        }
+
+        // Components without a seed go to whichever part is lightest.
+        for node in 0..node_count {
+            if assignment[node] == usize::MAX {
+                let part = (0..parts).min_by_key(|&part| weights[part]).unwrap();
+                for member in graph.component(node) {
+                    assignment[member] = part;
+                    weights[part] += marked[member] as usize;
+                }
+            }
+        }
+        assignment
+    }
+
+    fn refine(&self, graph: &DeviceGraph, marked: &[bool], parts: usize, assignment: &mut [usize]) {
+        let quota = self.quota(marked, parts);
+        let mut weights = vec![0usize; parts];
+        for (node, &part) in assignment.iter().enumerate() {
+            weights[part] += marked[node] as usize;
+        }
+        let mut links_to: HashMap<usize, usize> = HashMap::default();
+        for _ in 0..self.refine_passes {
+            let mut moved = false;
+            for node in 0..graph.names.len() {
+                let own = assignment[node];
+                links_to.clear();
+                for &peer in graph.adjacency[node].iter() {
+                    *links_to.entry(assignment[peer]).or_default() += 1;
+                }
+                let own_links = links_to.get(&own).copied().unwrap_or(0);
+                let best = links_to
+                    .iter()
+                    .filter(|(&part, _)| part != own)
+                    .filter(|(&part, _)| !marked[node] || weights[part] < quota)
+                    // A part must keep at least one marked node.
+                    .filter(|_| !marked[node] || weights[own] > 1)
+                    .max_by_key(|(&part, &links)| (links, std::cmp::Reverse(part)));
+                if let Some((&part, &links)) = best {
+                    if links > own_links {
+                        assignment[node] = part;
+                        weights[own] -= marked[node] as usize;
+                        weights[part] += marked[node] as usize;
+                        moved = true;
+                    }
+                }
+            }
+            if !moved {
+                break;
+            }
+        }
+    }
+}
+
+/// Undirected device graph with dense indices, sorted by name so partitions
+/// are reproducible.
+struct DeviceGraph {
+    names: Vec<String>,
+    adjacency: Vec<Vec<usize>>,
+}
+
+impl DeviceGraph {
+    fn new(network: &Network, marked_nodes: &HashMap<String, PacketSpaceAwareDevice>) -> Self {
+        let mut name_set: HashSet<&String> = network.get_device_ports().keys().collect();
+        name_set.extend(marked_nodes.keys());
+        let mut names: Vec<String> = name_set.into_iter().cloned().collect();
+        names.sort();
+        let index: HashMap<&str, usize> = names
+            .iter()
+            .enumerate()
+            .map(|(i, name)| (name.as_str(), i))
+            .collect();
+        let mut adjacency = vec![Vec::new(); names.len()];
+        for (device_name, ports) in network.get_device_ports() {
+            let node = index[device_name.as_str()];
+            for port in ports {
+                if let Some((peer_name, _)) = port.get_peer_port() {
+                    if let Some(&peer) = index.get(peer_name.as_str()) {
+                        adjacency[node].push(peer);
+                    }
+                }
+            }
+        }
+        for peers in adjacency.iter_mut() {
+            peers.sort_unstable();
+            peers.dedup();
+        }
+        DeviceGraph { names, adjacency }
+    }
+
+    fn distances_from(&self, sources: &[usize]) -> Vec<usize> {
+        let mut distance = vec![usize::MAX; self.names.len()];
+        let mut queue = VecDeque::new();
+        for &source in sources {
+            distance[source] = 0;
+            queue.push_back(source);
+        }
+        while let Some(node) = queue.pop_front() {
+            for &peer in self.adjacency[node].iter() {
+                if distance[peer] == usize::MAX {
+                    distance[peer] = distance[node] + 1;
+                    queue.push_back(peer);
+                }
+            }
+        }
+        distance
+    }
+
+    /// Farthest-first traversal over marked nodes.
+    fn spread_seeds(&self, marked: &[bool], parts: usize) -> Vec<usize> {
+        let Some(first) = marked.iter().position(|&is_marked| is_marked) else {
+            return Vec::new();
+        };
+        let mut seeds = vec![first];
+        while seeds.len() < parts {
+            let distance = self.distances_from(&seeds);
+            let farthest = (0..self.names.len())
+                .filter(|&node| marked[node] && !seeds.contains(&node))
+                .max_by_key(|&node| (distance[node], std::cmp::Reverse(node)));
+            match farthest {
+                Some(node) => seeds.push(node),
+                None => break,
+            }
+        }
+        seeds
+    }
+
+    fn component(&self, start: usize) -> Vec<usize> {
+        let distance = self.distances_from(&[start]);
+        (0..self.names.len())
+            .filter(|&node| distance[node] != usize::MAX)
+            .collect()
+    }
+}
+
+/// Sizes of the neighborhoods produced by a partitioner.
+#[derive(Debug, Clone)]
+pub struct NeighborhoodReport {
+    pub partitioner: String,
+    /// (marked nodes, normal nodes) per neighborhood, largest first.
+    pub sizes: Vec<(usize, usize)>,
+}
+
+impl NeighborhoodReport {
+    pub fn new(partitioner: String, neighborhoods: &[Neighborhood]) -> Self {
+        let mut sizes: Vec<(usize, usize)> = neighborhoods
+            .iter()
+            .map(|neighborhood| {
+                (
+                    neighborhood.get_marked_nodes().len(),
+                    neighborhood.get_normal_nodes().len(),
+                )
+            })
+            .collect();
+        sizes.sort_by(|a, b| b.cmp(a));
+        NeighborhoodReport { partitioner, sizes }
+    }
+}
+
+impl fmt::Display for NeighborhoodReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "Partitioner {}: {} neighborhoods",
+            self.partitioner,
+            self.sizes.len()
+        )?;
+        if self.sizes.is_empty() {
+            return Ok(());
+        }
+        let mut marked: Vec<usize> = self.sizes.iter().map(|(marked, _)| *marked).collect();
+        marked.sort_unstable();
+        let total: usize = marked.iter().sum();
+        writeln!(
+            f,
+            "Marked nodes per neighborhood: min {}, median {}, max {}, mean {:.1}",
+            marked[0],
+            marked[marked.len() / 2],
+            marked[marked.len() - 1],
+            total as f64 / marked.len() as f64
+        )?;
+        writeln!(f, "{:>6} {:>8} {:>8}", "#", "marked", "normal")?;
+        for (i, (marked, normal)) in self.sizes.iter().enumerate() {
+            writeln!(f, "{:>6} {:>8} {:>8}", i, marked, normal)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::util::network::Topology;
+    use crate::util::npbdd::NONE_SPACE_ID;
+
+    fn parsed_name(spec: &str) -> String {
+        parse_partitioner(spec).unwrap().name()
+    }
+
+    #[test]
+    fn parse_partitioner_reads_every_strategy() {
+        assert_eq!(parsed_name("bfs"), "bfs(depth=2)");
+        assert_eq!(parsed_name("bfs:3"), "bfs(depth=3)");
+        assert_eq!(parsed_name("bfs:3:40"), "bfs(depth=3, max=40)");
+        assert_eq!(parsed_name("pod"), "pod");
+        assert_eq!(parsed_name("mincut:4"), "mincut(parts=4)");
+        assert_eq!(parsed_name("mincut:0"), "mincut(parts=1)");
+    }
+
+    #[test]
+    fn parse_partitioner_rejects_malformed_specs() {
+        for spec in [
+            "",
+            "dfs",
+            "bfs:two",
+            "bfs:0",
+            "bfs:-1",
+            "bfs:4294967298",
+            "bfs:2:-1",
+            "mincut:x",
+        ] {
+            assert!(parse_partitioner(spec).is_err(), "{}", spec);
+        }
+    }
+
+    /// a - x - b - y - z - c, with a, b and c marked.
+    fn chain() -> (Network, HashMap<String, PacketSpaceAwareDevice>) {
+        let names = ["a", "x", "b", "y", "z", "c"];
+        let links = names
+            .windows(2)
+            .map(|pair| Topology {
+                src_node: pair[0].to_string(),
+                src_port: format!("to_{}", pair[1]),
+                dst_node: pair[1].to_string(),
+                dst_port: format!("to_{}", pair[0]),
+            })
+            .collect();
+        let mut network = Network::new();
+        network.load_links(links);
+        let marked_nodes = ["a", "b", "c"]
+            .iter()
+            .enumerate()
+            .map(|(device_id, name)| {
+                let device =
+                    PacketSpaceAwareDevice::new(name.to_string(), NONE_SPACE_ID, device_id);
+                (name.to_string(), device)
+            })
+            .collect();
+        (network, marked_nodes)
+    }
+
+    /// Sorted marked and normal node names of every neighborhood.
+    fn components(neighborhoods: &[Neighborhood]) -> Vec<(Vec<String>, Vec<String>)> {
+        let mut components: Vec<(Vec<String>, Vec<String>)> = neighborhoods
+            .iter()
+            .map(|neighborhood| {
+                let mut marked: Vec<String> =
+                    neighborhood.get_marked_nodes().keys().cloned().collect();
+                let mut normal: Vec<String> =
+                    neighborhood.get_normal_nodes().iter().cloned().collect();
+                marked.sort();
+                normal.sort();
+                (marked, normal)
+            })
+            .collect();
+        components.sort();
+        components
+    }
+
+    fn names(names: &[&str]) -> Vec<String> {
+        names.iter().map(|name| name.to_string()).collect()
+    }
+
+    #[test]
+    fn bfs_merge_joins_marked_nodes_two_hops_apart() {
+        let (network, marked_nodes) = chain();
+        let neighborhoods = BfsMergePartitioner::default().partition(&network, marked_nodes);
+        assert_eq!(
+            components(&neighborhoods),
+            vec![
+                (names(&["a", "b"]), names(&["x", "y"])),
+                (names(&["c"]), names(&["z"])),
+            ]
+        );
+    }
+
+    #[test]
+    fn bfs_merge_depth_resets_at_every_marked_node() {
+        let (network, marked_nodes) = chain();
+        let partitioner = BfsMergePartitioner {
+            proximity_depth: 3,
+            max_marked_nodes: None,
+        };
+        assert_eq!(
+            components(&partitioner.partition(&network, marked_nodes)),
+            vec![(names(&["a", "b", "c"]), names(&["x", "y", "z"]))]
+        );
+    }
+
+    #[test]
+    fn bfs_merge_keeps_every_marked_node_at_depth_zero() {
+        let (network, marked_nodes) = chain();
+        let partitioner = BfsMergePartitioner {
+            proximity_depth: 0,
+            max_marked_nodes: None,
+        };
+        assert_eq!(
+            components(&partitioner.partition(&network, marked_nodes)),
+            vec![
+                (names(&["a"]), Vec::new()),
+                (names(&["b"]), Vec::new()),
+                (names(&["c"]), Vec::new()),
+            ]
+        );
+    }
+}
@@ -0,0 +1,282 @@
+use crate::util::address::{Address, PrefixTable};
+use crate::util::hash_utils::{HashMap, HashSet};
+use crate::util::network::Network;
+use crate::verifier::device::Device;
+use std::collections::VecDeque;
+use std::fmt;
+use std::net::IpAddr;
+use std::ops::AddAssign;
+use std::sync::Arc;
+
+/// Forwards one concrete destination address at a time, by longest-prefix
+/// match on `Device::get_rules` and the links of a `Network`, following
+/// every port of every matched rule. It shares no code with the encoders,
+/// so it can check their results.
+pub struct ReferenceForwarder {
+    names: Vec<String>,
+    index: HashMap<String, usize>,
+    tables: Vec<PrefixTable>,
+    /// Peer of every port of every rule, by device and rule index; `None`
+    /// is a port without a link.
+    next_hops: Vec<Vec<Vec<Option<usize>>>>,
+    /// Every distinct rule prefix, as its first address and length.
+    prefixes: Vec<(Address, usize)>,
+}
+
+/// Where the branches of one address went from a source.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Trace {
+    /// Some branch reached the destination.
+    pub delivered: bool,
+    /// Some branch was dropped: no rule matched, or a port has no link.
+    pub dropped: bool,
+    /// Devices visited, in breadth-first order from the source.
+    pub visited: Vec<String>,
+}
+
+impl ReferenceForwarder {
+    pub fn new(devices: &HashMap<String, Arc<Device>>, network: &Network) -> Self {
+        let mut names: Vec<String> = devices
+            .keys()
+            .chain(network.get_device_ports().keys())
+            .cloned()
+            .collect::<HashSet<String>>()
+            .into_iter()
+            .collect();
+        names.sort();
+        let index: HashMap<String, usize> = names
+            .iter()
+            .enumerate()
+            .map(|(position, name)| (name.clone(), position))
+            .collect();
+
+        let mut peers: HashMap<(usize, String), usize> = HashMap::default();
+        for (device_name, device_ports) in network.get_device_ports() {
+            for device_port in device_ports {
+                if let Some((peer_name, _)) = device_port.get_peer_port() {
+                    peers.insert(
+                        (index[device_name], device_port.get_port_name()),
+                        index[peer_name],
+                    );
+                }
+            }
+        }
+
+        let mut tables: Vec<PrefixTable> = names.iter().map(|_| PrefixTable::default()).collect();
+        let mut next_hops: Vec<Vec<Vec<Option<usize>>>> = vec![Vec::new(); names.len()];
+        let mut prefixes: HashSet<(Address, usize)> = HashSet::default();
+        for (device_name, device) in devices {
+            let position = index[device_name];
+            tables[position] = PrefixTable::new(
+                device
+                    .get_rules()
+                    .iter()
+                    .map(|rule| (rule.get_ip(), rule.get_prefix_len())),
+            );
+            next_hops[position] = device
+                .get_rules()
+                .iter()
+                .map(|rule| {
+                    rule.get_ports()
+                        .iter()
+                        .map(|port| peers.get(&(position, port.clone())).copied())
+                        .collect()
+                })
+                .collect();
+            for rule in device.get_rules() {
+                if let Some(address) = Address::parse(rule.get_ip()) {
+                    prefixes.insert((address.first(rule.get_prefix_len()), rule.get_prefix_len()));
+                }
+            }
+        }
+        let mut prefixes: Vec<(Address, usize)> = prefixes.into_iter().collect();
+        prefixes.sort_unstable();
+        ReferenceForwarder {
+            names,
+            index,
+            tables,
+            next_hops,
+            prefixes,
+        }
+    }
+
+    /// Sends `address` from `src_name` until every branch has been delivered
+    /// at `dst_name`, dropped, or has come back to a visited device. `None`
+    /// if either device is unknown.
+    pub fn forward(&self, src_name: &str, dst_name: &str, address: IpAddr) -> Option<Trace> {
+        let src = *self.index.get(src_name)?;
+        let dst = *self.index.get(dst_name)?;
+        let (delivered, dropped, visited) = self.walk(src, dst, Address::new(address));
+        Some(Trace {
+            delivered,
+            dropped,
+            visited: visited
+                .into_iter()
+                .map(|device| self.names[device].clone())
+                .collect(),
+        })
+    }
+
+    /// Whether some branch of `address` sent from `src_name` reaches
+    /// `dst_name`.
+    pub fn delivers(&self, src_name: &str, dst_name: &str, address: IpAddr) -> bool {
+        match (self.index.get(src_name), self.index.get(dst_name)) {
+            (Some(&src), Some(&dst)) => self.walk(src, dst, Address::new(address)).0,
+            _ => false,
+        }
+    }
+
+    /// Breadth-first walk of every branch: (delivered, dropped, visited).
+    fn walk(&self, src: usize, dst: usize, address: Address) -> (bool, bool, Vec<usize>) {
+        let mut delivered = false;
+        let mut dropped = false;
+        let mut seen = vec![false; self.names.len()];
+        let mut queue: VecDeque<usize> = VecDeque::from([src]);
+        seen[src] = true;
+        let mut visited = Vec::new();
+        while let Some(device) = queue.pop_front() {
+            visited.push(device);
+            if device == dst {
+                delivered = true;
+                continue;
+            }
+            let hops: &[Option<usize>] = match self.tables[device].lookup(address) {
+                Some(rule_index) => &self.next_hops[device][rule_index],
+                None => &[],
+            };
+            if hops.is_empty() {
+                dropped = true;
+            }
+            for hop in hops {
+                match *hop {
+                    Some(peer) if !seen[peer] => {
+                        seen[peer] = true;
+                        queue.push_back(peer);
+                    }
+                    Some(_) => {}
+                    None => dropped = true,
+                }
+            }
+        }
+        (delivered, dropped, visited)
+    }
+
+    /// Addresses of `prefix/prefix_len` to check: its first and last, the
+    /// first address of every more-specific rule prefix inside it and the
+    /// one past its end, and `spread` more spaced evenly across it. Rules
+    /// forward every range between consecutive samples alike, so the samples
+    /// cover every forwarding behavior of the space.
+    pub fn sample_addresses(&self, prefix: &str, prefix_len: usize, spread: usize) -> Vec<IpAddr> {
+        let Some(address) = Address::parse(prefix) else {
+            return Vec::new();
+        };
+        let first = address.first(prefix_len);
+        let last = address.last(prefix_len);
+        let mut samples = vec![first, last];
+        for &(rule_prefix, len) in &self.prefixes {
+            if len > prefix_len && rule_prefix.first(prefix_len) == first {
+                samples.push(rule_prefix);
+                let end = rule_prefix.last(len);
+                if end < last {
+                    samples.push(end.with_value(end.value() + 1));
+                }
+            }
+        }
+        let step = (last.value() - first.value()) / (spread as u128 + 1);
+        samples.extend((1..=spread as u128).map(|i| first.with_value(first.value() + step * i)));
+        samples.sort_unstable();
+        samples.dedup();
+        samples.into_iter().map(Address::to_ip).collect()
+    }
+}
+
+/// A verdict on which `NPNet` and `ReferenceForwarder` differ.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Disagreement {
+    pub src: String,
+    pub dst: String,
+    /// The sampled address, or `None` for the verdict on the whole packet
+    /// space of `dst`.
+    pub address: Option<IpAddr>,
+    pub npnet: bool,
+    pub reference: bool,
+}
+
+impl fmt::Display for Disagreement {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let verdict = |reachable: bool| {
+            if reachable {
+                "reachable"
+            } else {
+                "unreachable"
+            }
+        };
+        match self.address {
+            Some(address) => write!(f, "{} -> {} for {}", self.src, self.dst, address)?,
+            None => write!(f, "{} -> {} for its whole packet space", self.src, self.dst)?,
+        }
+        write!(
+            f,
+            ": NPNet says {}, the reference forwarder says {}",
+            verdict(self.npnet),
+            verdict(self.reference)
+        )
+    }
+}
+
+/// Outcome of `Simulator::cross_check`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CrossCheckReport {
+    pub addresses_checked: usize,
+    pub pairs_checked: usize,
+    /// Neighborhoods not checked because the run was cancelled or the
+    /// traversal did not converge.
+    pub skipped_neighborhoods: usize,
+    /// Sorted by source, destination and address.
+    pub disagreements: Vec<Disagreement>,
+}
+
+impl CrossCheckReport {
+    pub fn skipped() -> Self {
+        CrossCheckReport {
+            skipped_neighborhoods: 1,
+            ..Self::default()
+        }
+    }
+
+    pub fn is_consistent(&self) -> bool {
+        self.disagreements.is_empty()
+    }
+}
+
+impl AddAssign for CrossCheckReport {
+    fn add_assign(&mut self, other: Self) {
+        self.addresses_checked += other.addresses_checked;
+        self.pairs_checked += other.pairs_checked;
+        self.skipped_neighborhoods += other.skipped_neighborhoods;
+        self.disagreements.extend(other.disagreements);
+    }
+}
+
+impl fmt::Display for CrossCheckReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for disagreement in &self.disagreements {
+            writeln!(f, "MISMATCH {}", disagreement)?;
+        }
+        write!(
+            f,
+            "Cross-check: {} addresses and {} pairs checked, {} disagreements",
+            self.addresses_checked,
+            self.pairs_checked,
+            self.disagreements.len()
+        )?;
+        if self.skipped_neighborhoods > 0 {
+            write!(
+                f,
+                ", {} neighborhoods not checked",
+                self.skipped_neighborhoods
+            )?;
+        }
+        Ok(())
+    }
+}
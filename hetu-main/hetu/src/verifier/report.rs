@@ -0,0 +1,59 @@
+use std::ops::AddAssign;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ReachabilityReport {
+    pub reachable: usize,
+    pub unreachable: usize,
+    pub traversal_count: usize,
+    /// Neighborhoods not verified because the run was cancelled, ran out of
+    /// time or the traversal did not converge. Their pairs are missing from
+    /// the counts.
+    pub skipped_neighborhoods: usize,
+}
+
+impl ReachabilityReport {
+    pub fn new(reachable: usize, unreachable: usize, traversal_count: usize) -> Self {
+        ReachabilityReport {
+            reachable,
+            unreachable,
+            traversal_count,
+            skipped_neighborhoods: 0,
+        }
+    }
+
+    /// Report of one neighborhood that was never verified.
+    pub fn skipped() -> Self {
+        ReachabilityReport {
+            skipped_neighborhoods: 1,
+            ..Self::default()
+        }
+    }
+
+    pub fn is_complete(&self) -> bool {
+        self.skipped_neighborhoods == 0
+    }
+
+    /// Counts pairs `weight` times, for neighborhoods standing in for
+    /// symmetric ones. Traversals are not repeated, so their count is kept.
+    pub fn weighted(self, weight: usize) -> Self {
+        ReachabilityReport {
+            reachable: self.reachable * weight,
+            unreachable: self.unreachable * weight,
+            traversal_count: self.traversal_count,
+            skipped_neighborhoods: self.skipped_neighborhoods,
+        }
+    }
+
+    pub fn total_pairs(&self) -> usize {
+        self.reachable + self.unreachable
+    }
+}
+
+impl AddAssign for ReachabilityReport {
+    fn add_assign(&mut self, other: Self) {
+        self.reachable += other.reachable;
+        self.unreachable += other.unreachable;
+        self.traversal_count += other.traversal_count;
+        self.skipped_neighborhoods += other.skipped_neighborhoods;
+    }
+}
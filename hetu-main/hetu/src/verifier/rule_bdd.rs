@@ -1,15 +1,15 @@
-use biodivine_lib_bdd::*;
+use crate::util::packet_set::*;
 
 #[derive(Clone)]
 pub struct RuleBDD {
-    hit: Bdd,
-    tmatch: Bdd,
+    hit: PacketSpace,
+    tmatch: PacketSpace,
     lec_index: i32,
-    black_list: Vec<Bdd>,
+    black_list: Vec<PacketSpace>,
 }
 
 impl RuleBDD {
-    pub fn new(hit: Bdd, tmatch: Bdd, lec_index: i32) -> Self {
+    pub fn new(hit: PacketSpace, tmatch: PacketSpace, lec_index: i32) -> Self {
         RuleBDD {
             hit,
             tmatch,
@@ -19,27 +19,30 @@ impl RuleBDD {
     }
 
     pub fn compare_with_other_rule_bdd(&self, o_rule_bdd: &RuleBDD) -> bool {
-        let result = self.hit.xor(o_rule_bdd.get_hit());
-        result.is_false()
+        self.hit == *o_rule_bdd.get_hit()
     }
 
-    pub fn get_hit(&self) -> &Bdd {
+    pub fn get_hit(&self) -> &PacketSpace {
         &self.hit
     }
 
-    pub fn get_match(&self) -> &Bdd {
+    pub fn get_lec_index(&self) -> i32 {
+        self.lec_index
+    }
+
+    pub fn get_match(&self) -> &PacketSpace {
         &self.tmatch
     }
 
-    pub fn get_blacklist(&self) -> &Vec<Bdd> {
+    pub fn get_blacklist(&self) -> &Vec<PacketSpace> {
         &self.black_list
     }
 
-    pub fn set_hit(&mut self, bdd: Bdd) {
+    pub fn set_hit(&mut self, bdd: PacketSpace) {
         self.hit = bdd;
     }
 
-    pub fn add_blacklist(&mut self, bdd: Bdd) {
+    pub fn add_blacklist(&mut self, bdd: PacketSpace) {
         self.black_list.push(bdd);
     }
 }
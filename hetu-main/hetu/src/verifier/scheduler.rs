@@ -0,0 +1,368 @@
+use crate::util::hash_utils::HashMap;
+use crate::util::network::Network;
+use crate::util::progress::RunControl;
+use crate::verifier::device::Device;
+use crate::verifier::neighborhood::Neighborhood;
+use rayon::prelude::*;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// Weights of the linear cost estimate. Only the ratios matter; the estimate
+/// is in arbitrary units and is compared against `Scheduler::max_cost`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CostModel {
+    /// Each marked node floods its packet space over the whole network.
+    pub per_marked_node: f64,
+    /// Rules of the inner-area devices, which are traversed first.
+    pub per_rule: f64,
+    /// Ports between the inner and the outer area, where spaces are
+    /// exchanged once per iteration.
+    pub per_tunnel: f64,
+}
+
+impl Default for CostModel {
+    fn default() -> Self {
+        CostModel {
+            per_marked_node: 1.0,
+            per_rule: 0.001,
+            per_tunnel: 0.1,
+        }
+    }
+}
+
+impl CostModel {
+    /// Parses `MARKED,RULE,TUNNEL`, e.g. `1,0.001,0.1`. Weights must be
+    /// finite and non-negative.
+    pub fn parse(spec: &str) -> Option<Self> {
+        let weights: Vec<f64> = spec
+            .split(',')
+            .map(|weight| weight.trim().parse().ok())
+            .collect::<Option<_>>()?;
+        if weights
+            .iter()
+            .any(|weight| !weight.is_finite() || *weight < 0.0)
+        {
+            return None;
+        }
+        match weights[..] {
+            [per_marked_node, per_rule, per_tunnel] => Some(CostModel {
+                per_marked_node,
+                per_rule,
+                per_tunnel,
+            }),
+            _ => None,
+        }
+    }
+
+    pub fn estimate(
+        &self,
+        network: &Network,
+        devices: &HashMap<String, Arc<Device>>,
+        neighborhood: &Neighborhood,
+    ) -> NeighborhoodCost {
+        let inner_nodes = neighborhood
+            .get_marked_nodes()
+            .keys()
+            .chain(neighborhood.get_normal_nodes().iter());
+        let mut rules = 0;
+        let mut boundary_tunnels = 0;
+        for device_name in inner_nodes {
+            if let Some(device) = devices.get(device_name) {
+                // Devices restored from a snapshot keep their LECs but not their rules.
+                rules += device
+                    .get_rules()
+                    .len()
+                    .max(device.get_space_id_to_space_port().len());
+            }
+            if let Some(ports) = network.get_device_ports().get(device_name) {
+                boundary_tunnels += ports
+                    .iter()
+                    .filter_map(|port| port.get_peer_port())
+                    .filter(|(peer_name, _)| !neighborhood.is_neighborhood_node(peer_name))
+                    .count();
+            }
+        }
+        let marked_nodes = neighborhood.get_marked_nodes().len();
+        NeighborhoodCost {
+            marked_nodes,
+            rules,
+            boundary_tunnels,
+            estimate: self.per_marked_node * marked_nodes as f64
+                + self.per_rule * rules as f64
+                + self.per_tunnel * boundary_tunnels as f64,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct NeighborhoodCost {
+    pub marked_nodes: usize,
+    pub rules: usize,
+    pub boundary_tunnels: usize,
+    pub estimate: f64,
+}
+
+pub struct ScheduledNeighborhood {
+    pub neighborhood: Neighborhood,
+    pub cost: NeighborhoodCost,
+}
+
+/// Orders neighborhoods by estimated cost, largest first, so a single large
+/// neighborhood does not start last and dominate the wall time.
+#[derive(Debug, Clone, Default)]
+pub struct Scheduler {
+    pub cost_model: CostModel,
+    /// Neighborhoods estimated above this are split by marked nodes.
+    pub max_cost: Option<f64>,
+    /// Prints cost and elapsed time of every neighborhood.
+    pub log_timings: bool,
+}
+
+impl Scheduler {
+    pub fn plan(
+        &self,
+        network: &Network,
+        devices: &HashMap<String, Arc<Device>>,
+        neighborhoods: Vec<Neighborhood>,
+    ) -> Vec<ScheduledNeighborhood> {
+        let mut plan: Vec<ScheduledNeighborhood> = neighborhoods
+            .into_par_iter()
+            .flat_map_iter(|neighborhood| {
+                let cost = self.cost_model.estimate(network, devices, &neighborhood);
+                self.split(neighborhood, cost)
+                    .into_iter()
+                    .map(|neighborhood| {
+                        let cost = self.cost_model.estimate(network, devices, &neighborhood);
+                        ScheduledNeighborhood { neighborhood, cost }
+                    })
+            })
+            .collect();
+        plan.sort_by(|a, b| b.cost.estimate.total_cmp(&a.cost.estimate));
+        plan
+    }
+
+    /// Estimates the cost of every neighborhood without splitting or
+    /// reordering them, for neighborhoods that were planned elsewhere.
+    pub fn schedule_as_given(
+        &self,
+        network: &Network,
+        devices: &HashMap<String, Arc<Device>>,
+        neighborhoods: Vec<Neighborhood>,
+    ) -> Vec<ScheduledNeighborhood> {
+        neighborhoods
+            .into_par_iter()
+            .map(|neighborhood| {
+                let cost = self.cost_model.estimate(network, devices, &neighborhood);
+                ScheduledNeighborhood { neighborhood, cost }
+            })
+            .collect()
+    }
+
+    /// Splits the marked nodes into name-ordered chunks; every chunk keeps
+    /// the original normal nodes as its inner area.
+    fn split(&self, neighborhood: Neighborhood, cost: NeighborhoodCost) -> Vec<Neighborhood> {
+        let Some(max_cost) = self.max_cost else {
+            return vec![neighborhood];
+        };
+        if cost.estimate <= max_cost || cost.marked_nodes < 2 {
+            return vec![neighborhood];
+        }
+        // A non-positive or NaN `max_cost` yields 0 here; keep at least one part.
+        let parts = ((cost.estimate / max_cost).ceil() as usize).clamp(1, cost.marked_nodes);
+        let mut marked: Vec<_> = neighborhood.get_marked_nodes().values().cloned().collect();
+        marked.sort_by(|a, b| a.device_name.cmp(&b.device_name));
+        marked
+            .chunks(cost.marked_nodes.div_ceil(parts))
+            .map(|chunk| {
+                let mut part = Neighborhood::new();
+                part.set_weight(neighborhood.weight());
+                for device in chunk {
+                    part.add_marked_node(device.clone());
+                }
+                for device_name in neighborhood.get_normal_nodes() {
+                    part.add_normal_node(device_name.clone());
+                }
+                part
+            })
+            .collect()
+    }
+
+    /// Runs `verify` over the plan on the rayon pool. Workers pull the next
+    /// neighborhood from a shared counter, so they start in plan order.
+    /// Results are returned in plan order; neighborhoods not started before
+    /// `control` was cancelled are `None`.
+    pub fn run<T, F>(
+        &self,
+        plan: &[ScheduledNeighborhood],
+        control: &RunControl,
+        verify: F,
+    ) -> Vec<Option<T>>
+    where
+        T: Send,
+        F: Fn(&Neighborhood) -> T + Sync,
+    {
+        let next = AtomicUsize::new(0);
+        let progress = control.verify_counter(plan.len());
+        let mut results: Vec<(usize, Option<T>)> = (0..rayon::current_num_threads())
+            .into_par_iter()
+            .flat_map_iter(|_| {
+                let mut local = Vec::new();
+                loop {
+                    let index = next.fetch_add(1, Ordering::Relaxed);
+                    let Some(scheduled) = plan.get(index) else {
+                        break;
+                    };
+                    if control.is_cancelled() {
+                        local.push((index, None));
+                        continue;
+                    }
+                    let start = Instant::now();
+                    local.push((index, Some(verify(&scheduled.neighborhood))));
+                    if self.log_timings {
+                        log_timing(index, &scheduled.cost, start.elapsed());
+                    }
+                    progress.tick();
+                }
+                local
+            })
+            .collect();
+        results.sort_by_key(|(index, _)| *index);
+        results.into_iter().map(|(_, result)| result).collect()
+    }
+}
+
+fn log_timing(index: usize, cost: &NeighborhoodCost, elapsed: Duration) {
+    println!(
+        "Neighborhood {}: marked {}, rules {}, tunnels {}, estimate {:.2}, time {:?}",
+        index, cost.marked_nodes, cost.rules, cost.boundary_tunnels, cost.estimate, elapsed
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::util::npbdd::NONE_SPACE_ID;
+    use crate::verifier::neighborhood::PacketSpaceAwareDevice;
+
+    /// One neighborhood with `marked` marked nodes m0, m1, ... and the
+    /// normal node `n`.
+    fn neighborhood(marked: usize) -> Neighborhood {
+        let mut neighborhood = Neighborhood::new();
+        for device_id in 0..marked {
+            let name = format!("m{}", device_id);
+            neighborhood.add_marked_node(PacketSpaceAwareDevice::new(
+                name,
+                NONE_SPACE_ID,
+                device_id,
+            ));
+        }
+        neighborhood.add_normal_node("n".to_string());
+        neighborhood
+    }
+
+    /// Costs one unit per marked node.
+    fn scheduler(max_cost: Option<f64>) -> Scheduler {
+        Scheduler {
+            cost_model: CostModel {
+                per_marked_node: 1.0,
+                per_rule: 0.0,
+                per_tunnel: 0.0,
+            },
+            max_cost,
+            log_timings: false,
+        }
+    }
+
+    fn plan(scheduler: &Scheduler, sizes: &[usize]) -> Vec<ScheduledNeighborhood> {
+        let neighborhoods = sizes.iter().map(|&size| neighborhood(size)).collect();
+        scheduler.plan(&Network::new(), &HashMap::default(), neighborhoods)
+    }
+
+    fn sorted_marked_nodes(plan: &[ScheduledNeighborhood]) -> Vec<String> {
+        let mut names: Vec<String> = plan
+            .iter()
+            .flat_map(|scheduled| scheduled.neighborhood.get_marked_nodes().keys().cloned())
+            .collect();
+        names.sort();
+        names
+    }
+
+    #[test]
+    fn cost_model_parses_three_weights() {
+        assert_eq!(
+            CostModel::parse("2, 0.5,0"),
+            Some(CostModel {
+                per_marked_node: 2.0,
+                per_rule: 0.5,
+                per_tunnel: 0.0,
+            })
+        );
+        for spec in [
+            "", "1,2", "1,2,3,4", "1,x,3", "1,-0.5,0", "NaN,1,1", "1,inf,1",
+        ] {
+            assert_eq!(CostModel::parse(spec), None, "{}", spec);
+        }
+    }
+
+    #[test]
+    fn plan_orders_neighborhoods_by_cost() {
+        let plan = plan(&scheduler(None), &[1, 3, 2]);
+        let estimates: Vec<f64> = plan.iter().map(|s| s.cost.estimate).collect();
+        assert_eq!(estimates, vec![3.0, 2.0, 1.0]);
+    }
+
+    #[test]
+    fn split_keeps_every_marked_node_exactly_once() {
+        let plan = plan(&scheduler(Some(2.0)), &[5]);
+        let sizes: Vec<usize> = plan.iter().map(|s| s.cost.marked_nodes).collect();
+        assert_eq!(sizes, vec![2, 2, 1]);
+        assert_eq!(
+            sorted_marked_nodes(&plan),
+            vec!["m0", "m1", "m2", "m3", "m4"]
+        );
+        for scheduled in &plan {
+            assert!(scheduled.neighborhood.get_normal_nodes().contains("n"));
+        }
+    }
+
+    #[test]
+    fn split_leaves_cheap_and_single_node_neighborhoods_alone() {
+        assert_eq!(plan(&scheduler(Some(3.0)), &[3]).len(), 1);
+        assert_eq!(plan(&scheduler(Some(0.5)), &[1]).len(), 1);
+        assert_eq!(plan(&scheduler(None), &[50]).len(), 1);
+    }
+
+    #[test]
+    fn split_survives_a_max_cost_that_is_not_positive() {
+        for max_cost in [0.0, -1.0, f64::NAN] {
+            let plan = plan(&scheduler(Some(max_cost)), &[4]);
+            assert_eq!(
+                sorted_marked_nodes(&plan),
+                vec!["m0", "m1", "m2", "m3"],
+                "{}",
+                max_cost
+            );
+        }
+    }
+
+    #[test]
+    fn run_returns_results_in_plan_order() {
+        let scheduler = scheduler(None);
+        let plan = plan(&scheduler, &[2, 4, 1, 3]);
+        let sizes = scheduler.run(&plan, &RunControl::default(), |neighborhood| {
+            neighborhood.get_marked_nodes().len()
+        });
+        assert_eq!(sizes, vec![Some(4), Some(3), Some(2), Some(1)]);
+    }
+
+    #[test]
+    fn a_cancelled_run_starts_no_neighborhood() {
+        let scheduler = scheduler(None);
+        let plan = plan(&scheduler, &[2, 1]);
+        let control = RunControl::default();
+        control.cancellation.cancel();
+        let results = scheduler.run(&plan, &control, |_| panic!("verified after cancel"));
+        assert_eq!(results, vec![None::<()>, None]);
+    }
+}
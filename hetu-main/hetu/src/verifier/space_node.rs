@@ -1,8 +1,8 @@
 use super::device::Device;
 use super::{annoucement::Announcement, cibtuple::CibTuple, context::Ctx, node::Node};
 use crate::util::hash_utils::{HashMap, HashSet};
+use crate::util::packet_set::*;
 use crate::util::{device_port::DevicePort, pod::Pod};
-use biodivine_lib_bdd::Bdd;
 use std::sync::Arc;
 
 #[derive(Clone)]
@@ -11,7 +11,7 @@ pub struct SpaceNode {
     interfaces: HashSet<DevicePort>,
     s1_nodes_table: HashMap<String, Node>,
     s0_nodes_table: HashMap<String, Node>,
-    aggre_space: Option<Bdd>,
+    aggre_space: Option<PacketSpace>,
     local_s1_cib: HashMap<String, CibTuple>,
 }
 
@@ -29,10 +29,10 @@ impl SpaceNode {
 
     pub fn aggregate_packet_space(
         &mut self,
-        packet_space_map: &HashMap<String, Bdd>,
-        regional_dst_node_bdd_table: &mut HashMap<String, Bdd>,
+        packet_space_map: &HashMap<String, PacketSpace>,
+        regional_dst_node_bdd_table: &mut HashMap<String, PacketSpace>,
     ) {
-        let mut aggre_packet_space: Option<Bdd> = None;
+        let mut aggre_packet_space: Option<PacketSpace> = None;
         for s0_device_name in self.s0_nodes_table.keys() {
             if let Some(packet_space) = packet_space_map.get(s0_device_name) {
                 regional_dst_node_bdd_table
@@ -41,7 +41,6 @@ impl SpaceNode {
                     Some(existing_space) => Some(existing_space.or(packet_space)),
                     None => Some(packet_space.clone()),
                 };
-            } else {
             }
         }
 
@@ -58,7 +57,7 @@ impl SpaceNode {
         }
     }
 
-    pub fn get_aggre_space(&self) -> Option<&Bdd> {
+    pub fn get_aggre_space(&self) -> Option<&PacketSpace> {
         self.aggre_space.as_ref()
     }
 
@@ -88,7 +87,7 @@ impl SpaceNode {
     }
 
     pub fn set_interfaces(&mut self, device_ports: &HashMap<String, HashSet<DevicePort>>) {
-        for (s1_device_name, _node) in &self.s1_nodes_table {
+        for s1_device_name in self.s1_nodes_table.keys() {
             if let Some(ports) = device_ports.get(s1_device_name) {
                 for port in ports {
                     if let Some((peer_device_name, _peer_port_name)) = port.get_peer_port() {
@@ -105,7 +104,7 @@ impl SpaceNode {
         self.local_s1_cib.contains_key(s1_device_name)
     }
 
-    pub fn get_s1_arrive_space(&self, s1_device_name: &String) -> &Bdd {
+    pub fn get_s1_arrive_space(&self, s1_device_name: &String) -> &PacketSpace {
         let cibtuple = self.local_s1_cib.get(s1_device_name).unwrap();
         cibtuple.get_predicate()
     }
@@ -126,7 +125,7 @@ impl SpaceNode {
     }
 
     pub fn get_cib_out(&self) -> Announcement {
-        let mut count_predicate: HashMap<i32, Bdd> = HashMap::default();
+        let mut count_predicate: HashMap<i32, PacketSpace> = HashMap::default();
         for cibtuple in self.local_s1_cib.values() {
             let tmp_count = cibtuple.get_count();
             let tmp_bdd = cibtuple.get_predicate();
@@ -0,0 +1,421 @@
+use crate::util::hash_utils::{HashMap, HashSet};
+use crate::util::network::Network;
+use crate::util::packet_set::prefix_bits;
+use crate::verifier::lec_trie::PrefixTrie;
+use crate::verifier::topology_index::TopologyIndex;
+use rayon::prelude::*;
+use std::fmt;
+
+/// Where every device sends the packet space of every owner, read from the
+/// rule tables with the same longest-prefix match as the encoders. It is only
+/// built when no rule splits an owner's space, so reachability of an owner is
+/// plain graph reachability over these next hops.
+struct OwnerForwarding {
+    owners: Vec<usize>,
+    /// Position in `owners` of every device that owns a space.
+    owner_pos: Vec<Option<usize>>,
+    /// Distinct sorted next-hop sets of every device's rules.
+    hop_sets: Vec<Vec<Vec<usize>>>,
+    /// Hop set each device uses for each owner position, or `NO_HOPS` when
+    /// no rule matches.
+    owner_hops: Vec<Vec<u32>>,
+    is_edge: Vec<bool>,
+}
+
+const NO_HOPS: u32 = u32::MAX;
+
+impl OwnerForwarding {
+    fn build(
+        index: &TopologyIndex,
+        network: &Network,
+        owners: &[(String, Vec<bool>)],
+        edge_devices: &HashSet<String>,
+        common_prefix: &str,
+    ) -> Result<Self, String> {
+        let mut owner_trie = PrefixTrie::new();
+        let mut owner_nodes = Vec::with_capacity(owners.len());
+        let mut owner_pos = vec![None; index.len()];
+        for (pos, (name, bits)) in owners.iter().enumerate() {
+            if owner_trie.overlaps(bits) {
+                return Err(format!("the packet space of {} overlaps another one", name));
+            }
+            owner_trie.insert(bits, pos);
+            let node = index
+                .index_of(name)
+                .ok_or_else(|| format!("unknown destination {}", name))?;
+            owner_nodes.push(node);
+            owner_pos[node] = Some(pos);
+        }
+
+        let tables = (0..index.len())
+            .into_par_iter()
+            .map(|node| {
+                let name = index.name(node);
+                let device = index.device(node);
+                let rules = device.get_rules();
+                if rules.is_empty() && !device.get_space_id_to_space_port().is_empty() {
+                    return Err("rule tables are not loaded".to_string());
+                }
+                let mut peers: HashMap<String, usize> = HashMap::default();
+                if let Some(ports) = network.get_device_ports().get(name) {
+                    for device_port in ports {
+                        let Some((peer_name, _)) = device_port.get_peer_port() else {
+                            continue;
+                        };
+                        if let Some(peer) = index.index_of(peer_name) {
+                            peers.insert(device_port.get_port_name(), peer);
+                        }
+                    }
+                }
+                // Rules share few distinct port lists, so each one is resolved
+                // to peers once.
+                let mut port_lists: Vec<&Vec<String>> = Vec::new();
+                let mut hop_sets: Vec<Vec<usize>> = Vec::new();
+                let mut trie = PrefixTrie::new();
+                for rule in rules {
+                    if !rule.get_ip().starts_with(common_prefix) && rule.get_prefix_len() != 0 {
+                        continue;
+                    }
+                    let bits = prefix_bits(rule.get_ip(), rule.get_prefix_len())
+                        .ok_or_else(|| format!("invalid prefix {}", rule.get_name()))?;
+                    let ports = rule.get_ports();
+                    let set = match port_lists.iter().position(|&list| list == ports) {
+                        Some(set) => set,
+                        None => {
+                            let mut hops: Vec<usize> = ports
+                                .iter()
+                                .filter_map(|port| peers.get(port).copied())
+                                .collect();
+                            hops.sort_unstable();
+                            hops.dedup();
+                            port_lists.push(ports);
+                            hop_sets.push(hops);
+                            hop_sets.len() - 1
+                        }
+                    };
+                    trie.insert(&bits, set);
+                }
+                let owner_hops = owners
+                    .iter()
+                    .map(|(owner_name, bits)| match trie.longest_match(bits) {
+                        (_, true) => Err(format!(
+                            "{} splits the packet space of {}",
+                            name, owner_name
+                        )),
+                        (set, false) => Ok(set.map_or(NO_HOPS, |set| set as u32)),
+                    })
+                    .collect::<Result<Vec<u32>, String>>()?;
+                Ok((hop_sets, owner_hops))
+            })
+            .collect::<Result<Vec<_>, String>>()?;
+        let (hop_sets, owner_hops): (Vec<_>, Vec<_>) = tables.into_iter().unzip();
+
+        Ok(OwnerForwarding {
+            owners: owner_nodes,
+            owner_pos,
+            hop_sets,
+            owner_hops,
+            is_edge: (0..index.len())
+                .map(|node| edge_devices.contains(index.name(node)))
+                .collect(),
+        })
+    }
+
+    fn len(&self) -> usize {
+        self.hop_sets.len()
+    }
+
+    fn next_hops(&self, node: usize, pos: usize) -> &[usize] {
+        match self.owner_hops[node][pos] {
+            NO_HOPS => &[],
+            set => &self.hop_sets[node][set as usize],
+        }
+    }
+
+    /// Permutation exchanging the devices of `a` and `b` and fixing every
+    /// other device, paired by color refinement. Owners are paired up front
+    /// by the order of their prefixes. `None` when the refinement tells the
+    /// two sides apart; the result still has to pass `is_automorphism`.
+    fn swap_candidate(&self, a: &[usize], b: &[usize]) -> Option<Vec<usize>> {
+        let region: Vec<usize> = a.iter().chain(b).copied().collect();
+        let in_a: HashSet<usize> = a.iter().copied().collect();
+        let mut colors: Vec<u64> = (0..self.len()).map(|node| mix(0, node as u64)).collect();
+        for side in [a, b] {
+            let mut side_owners: Vec<(usize, usize)> = side
+                .iter()
+                .filter_map(|&node| self.owner_pos[node].map(|pos| (pos, node)))
+                .collect();
+            side_owners.sort_unstable();
+            for &node in side {
+                colors[node] = mix(1, self.is_edge[node] as u64);
+            }
+            for (rank, (_, node)) in side_owners.into_iter().enumerate() {
+                colors[node] = mix(mix(2, self.is_edge[node] as u64), rank as u64);
+            }
+        }
+
+        let mut picks = 0;
+        loop {
+            self.refine(&region, &mut colors);
+            let mut classes: HashMap<u64, (Vec<usize>, Vec<usize>)> = HashMap::default();
+            for &node in &region {
+                let class = classes.entry(colors[node]).or_default();
+                if in_a.contains(&node) {
+                    class.0.push(node);
+                } else {
+                    class.1.push(node);
+                }
+            }
+            if classes
+                .values()
+                .any(|(left, right)| left.len() != right.len())
+            {
+                return None;
+            }
+            // Pair the first devices of the earliest tied class and refine again.
+            let tied = classes
+                .values()
+                .filter(|(left, _)| left.len() > 1)
+                .min_by_key(|(left, _)| left.iter().min().copied());
+            let Some((left, right)) = tied else {
+                let mut permutation: Vec<usize> = (0..self.len()).collect();
+                for (left, right) in classes.values() {
+                    permutation[left[0]] = right[0];
+                    permutation[right[0]] = left[0];
+                }
+                return Some(permutation);
+            };
+            picks += 1;
+            let (left, right) = (*left.iter().min().unwrap(), *right.iter().min().unwrap());
+            let color = mix(mix(3, colors[left]), picks);
+            colors[left] = color;
+            colors[right] = color;
+        }
+    }
+
+    /// Recolors `region` until its partition is stable. Every new color
+    /// includes the old one, so the partition only ever gets finer.
+    fn refine(&self, region: &[usize], colors: &mut [u64]) {
+        let count = |colors: &[u64]| {
+            region
+                .iter()
+                .map(|&node| colors[node])
+                .collect::<HashSet<u64>>()
+                .len()
+        };
+        let mut distinct = count(colors);
+        loop {
+            let next: Vec<u64> = region
+                .par_iter()
+                .map(|&node| self.signature(node, colors))
+                .collect();
+            for (&node, color) in region.iter().zip(next) {
+                colors[node] = color;
+            }
+            let refined = count(colors);
+            if refined == distinct {
+                return;
+            }
+            distinct = refined;
+        }
+    }
+
+    /// Hashes the node's color with where it forwards each owner. Lists are
+    /// hashed as multisets by summing the hashes of their entries.
+    fn signature(&self, node: usize, colors: &[u64]) -> u64 {
+        let sent = (0..self.owners.len())
+            .map(|pos| (pos, self.next_hops(node, pos)))
+            .filter(|(_, hops)| !hops.is_empty())
+            .fold(0u64, |sum, (pos, hops)| {
+                let hop_colors = hops
+                    .iter()
+                    .fold(0u64, |sum, &hop| sum.wrapping_add(mix(colors[hop], 0)));
+                sum.wrapping_add(mix(colors[self.owners[pos]], hop_colors))
+            });
+        mix(colors[node], sent)
+    }
+
+    /// Whether `permutation` keeps edge devices, owners and every owner's
+    /// next hops. Reachability from `s` to owner `o` then equals reachability
+    /// from `permutation[s]` to `permutation[o]`.
+    fn is_automorphism(&self, permutation: &[usize]) -> bool {
+        // Sorted and free of duplicates on both sides, so equal lengths and
+        // containment make the sets equal.
+        let maps_to = |hops: &[usize], image_hops: &[usize]| {
+            hops.len() == image_hops.len()
+                && hops
+                    .iter()
+                    .all(|&hop| image_hops.binary_search(&permutation[hop]).is_ok())
+        };
+        let all_owners: Vec<usize> = (0..self.owners.len()).collect();
+        let moved_owners: Vec<usize> = all_owners
+            .iter()
+            .copied()
+            .filter(|&pos| permutation[self.owners[pos]] != self.owners[pos])
+            .collect();
+        (0..self.len()).into_par_iter().all(|node| {
+            let image = permutation[node];
+            if self.is_edge[node] != self.is_edge[image]
+                || self.owner_pos[node].is_some() != self.owner_pos[image].is_some()
+            {
+                return false;
+            }
+            // A fixed device forwarding a fixed owner needs a hop set that
+            // maps onto itself, which is checked once per set. When every set
+            // does, only the moved owners are left to check.
+            let invariant: Vec<bool> = if node == image {
+                self.hop_sets[node]
+                    .iter()
+                    .map(|hops| maps_to(hops, hops))
+                    .collect()
+            } else {
+                Vec::new()
+            };
+            let positions = if node == image && !invariant.contains(&false) {
+                &moved_owners
+            } else {
+                &all_owners
+            };
+            positions.iter().all(|&pos| {
+                let owner = self.owners[pos];
+                let image_owner = permutation[owner];
+                let set = self.owner_hops[node][pos];
+                if node == image && owner == image_owner {
+                    return set == NO_HOPS || invariant[set as usize];
+                }
+                let Some(image_pos) = self.owner_pos[image_owner] else {
+                    return false;
+                };
+                if node == image && set == self.owner_hops[node][image_pos] {
+                    return set == NO_HOPS || invariant[set as usize];
+                }
+                maps_to(self.next_hops(node, pos), self.next_hops(image, image_pos))
+            })
+        })
+    }
+}
+
+/// SplitMix64 finalizer over the pair.
+fn mix(a: u64, b: u64) -> u64 {
+    let mut z = a
+        .wrapping_mul(0x9e37_79b9_7f4a_7c15)
+        .wrapping_add(b)
+        .wrapping_add(0x9e37_79b9_7f4a_7c15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^ (z >> 31)
+}
+
+/// Classes of pods that are images of each other under a forwarding
+/// symmetry. Only the first pod of each class is verified; its destinations
+/// stand for the matching destinations of every other pod in the class.
+pub struct SymmetryReduction {
+    /// Verified destinations and the number of destinations each one stands
+    /// for, itself included.
+    weights: HashMap<String, usize>,
+    owner_count: usize,
+    pod_count: usize,
+    class_count: usize,
+}
+
+impl SymmetryReduction {
+    /// `owners` are the destinations with their packet-space prefix bits.
+    /// Fails when some rule splits a destination's packet space or the
+    /// packet spaces overlap, since results then no longer follow devices.
+    pub fn detect(
+        index: &TopologyIndex,
+        network: &Network,
+        owners: &[(String, Vec<bool>)],
+        edge_devices: &HashSet<String>,
+        common_prefix: &str,
+    ) -> Result<Self, String> {
+        let forwarding =
+            OwnerForwarding::build(index, network, owners, edge_devices, common_prefix)?;
+        let mut pods: Vec<Vec<usize>> = network
+            .get_pods()
+            .values()
+            .map(|pod| {
+                let mut nodes: Vec<usize> = pod
+                    .get_devices()
+                    .iter()
+                    .filter_map(|name| index.index_of(name))
+                    .collect();
+                nodes.sort_unstable();
+                nodes
+            })
+            .filter(|nodes| {
+                nodes
+                    .iter()
+                    .any(|&node| forwarding.owner_pos[node].is_some())
+            })
+            .collect();
+        pods.sort();
+
+        // Each class is (representative pod, number of pods).
+        let mut classes: Vec<(usize, usize)> = Vec::new();
+        for (pod, nodes) in pods.iter().enumerate() {
+            let class = classes.iter_mut().find(|(representative, _)| {
+                forwarding
+                    .swap_candidate(&pods[*representative], nodes)
+                    .is_some_and(|permutation| forwarding.is_automorphism(&permutation))
+            });
+            match class {
+                Some((_, size)) => *size += 1,
+                None => classes.push((pod, 1)),
+            }
+        }
+
+        let mut weights: HashMap<String, usize> =
+            owners.iter().map(|(name, _)| (name.clone(), 1)).collect();
+        for (pod, nodes) in pods.iter().enumerate() {
+            let weight = classes
+                .iter()
+                .find(|(representative, _)| *representative == pod)
+                .map_or(0, |(_, size)| *size);
+            for &node in nodes {
+                if forwarding.owner_pos[node].is_some() {
+                    let name = index.name(node);
+                    if weight == 0 {
+                        weights.remove(name);
+                    } else {
+                        weights.insert(name.to_string(), weight);
+                    }
+                }
+            }
+        }
+        Ok(SymmetryReduction {
+            weights,
+            owner_count: owners.len(),
+            pod_count: pods.len(),
+            class_count: classes.len(),
+        })
+    }
+
+    /// Destinations to verify, grouped by weight.
+    pub fn groups(&self) -> Vec<(usize, HashSet<String>)> {
+        let mut groups: HashMap<usize, HashSet<String>> = HashMap::default();
+        for (name, weight) in &self.weights {
+            groups.entry(*weight).or_default().insert(name.clone());
+        }
+        let mut groups: Vec<(usize, HashSet<String>)> = groups.into_iter().collect();
+        groups.sort_by_key(|(weight, _)| *weight);
+        groups
+    }
+
+    pub fn verified_count(&self) -> usize {
+        self.weights.len()
+    }
+}
+
+impl fmt::Display for SymmetryReduction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Symmetry: {} pods in {} classes, verifying {} of {} destinations",
+            self.pod_count,
+            self.class_count,
+            self.verified_count(),
+            self.owner_count
+        )
+    }
+}
@@ -0,0 +1,90 @@
+use crate::util::hash_utils::HashMap;
+use crate::util::network::Network;
+use crate::verifier::device::Device;
+use std::sync::Arc;
+
+/// Link from a device to its peer. Port names are resolved to space ids once,
+/// so traversal never looks anything up by name.
+pub struct Link {
+    pub peer: usize,
+    /// Space id of the port on the current device, used by forward traversal.
+    pub out_space_id: Option<i8>,
+    /// Space id of the peer port on the peer device, used by backward traversal.
+    pub in_space_id: Option<i8>,
+}
+
+/// Immutable, index-based view of the encoded network, built once and shared
+/// by every `NPNet`. Devices are numbered in name order.
+pub struct TopologyIndex {
+    names: Vec<String>,
+    devices: Vec<Arc<Device>>,
+    node_index: HashMap<String, usize>,
+    links: Vec<Vec<Link>>,
+}
+
+impl TopologyIndex {
+    pub fn new(devices: &HashMap<String, Arc<Device>>, network: &Network) -> Self {
+        let mut names: Vec<String> = devices.keys().cloned().collect();
+        names.sort();
+        let node_index: HashMap<String, usize> = names
+            .iter()
+            .enumerate()
+            .map(|(index, name)| (name.clone(), index))
+            .collect();
+        let devices: Vec<Arc<Device>> = names
+            .iter()
+            .map(|name| Arc::clone(&devices[name]))
+            .collect();
+        let links = names
+            .iter()
+            .enumerate()
+            .map(|(node, name)| {
+                let Some(ports) = network.get_device_ports().get(name) else {
+                    return Vec::new();
+                };
+                ports
+                    .iter()
+                    .filter_map(|device_port| {
+                        let (peer_name, peer_port_name) = device_port.get_peer_port()?;
+                        let peer = *node_index.get(peer_name)?;
+                        Some(Link {
+                            peer,
+                            out_space_id: devices[node].find_space_id(&device_port.get_port_name()),
+                            in_space_id: devices[peer].find_space_id(peer_port_name),
+                        })
+                    })
+                    .collect()
+            })
+            .collect();
+        TopologyIndex {
+            names,
+            devices,
+            node_index,
+            links,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.names.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.names.is_empty()
+    }
+
+    pub fn index_of(&self, device_name: &str) -> Option<usize> {
+        self.node_index.get(device_name).copied()
+    }
+
+    pub fn name(&self, node: usize) -> &str {
+        &self.names[node]
+    }
+
+    pub fn device(&self, node: usize) -> &Arc<Device> {
+        &self.devices[node]
+    }
+
+    pub fn links(&self, node: usize) -> &[Link] {
+        &self.links[node]
+    }
+}
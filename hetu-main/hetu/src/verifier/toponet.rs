@@ -1,70 +1,60 @@
 use super::space_node::SpaceNode;
 use crate::util::device_port::DevicePort;
 use crate::util::forward_action::ForwardAction;
-use crate::util::hash_utils::{HashMap, HashSet};
+use crate::util::hash_utils::{DashMap, HashMap, HashSet};
 use crate::util::network::Network;
-use crate::util::npbdd::{BDDTable, LayerCache, NPBDD};
+use crate::util::npbdd::{SpaceId, NONE_SPACE_ID, NPBDD};
+use crate::util::packet_set::{prefix_bits, PacketSet, PacketSpace};
 use crate::util::pod::Pod;
+use crate::util::progress::RunControl;
 use crate::util::rule::Rule;
 use crate::verifier::device::Device;
 use crate::verifier::lec::Lec;
+use crate::verifier::lec_trie::{packet_space_trie, LecBuilder, PrefixTrie};
 use crate::verifier::node::Node;
 use crate::verifier::rule_bdd::RuleBDD;
-use biodivine_lib_bdd::*;
+use crate::verifier::topology_index::TopologyIndex;
+use biodivine_lib_bdd::{BddVariable, BddVariableSet};
 use rayon::prelude::*;
 use std::mem;
-use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, OnceLock};
 
 #[derive(Clone)]
 pub struct Toponet {
     pub ip_bits_len: usize,
-    variables_dst_ip: Arc<Vec<BddVariable>>,
-    pub variable_set: Arc<BddVariableSet>,
+    pub npbdd: Arc<NPBDD>,
     map_device_rule_bdd: Arc<HashMap<String, HashMap<Rule, RuleBDD>>>,
-    map_device_lecs: Arc<HashMap<String, HashSet<Lec>>>,
-    pub map_device_packet_space_bdd: Arc<HashMap<String, Bdd>>,
-    pub all_space_map: Arc<HashMap<String, Bdd>>,
+    pub map_device_packet_space_bdd: Arc<HashMap<String, SpaceId>>,
+    pub all_space_map: Arc<HashMap<String, SpaceId>>,
+    packet_spaces: Vec<Rule>,
     pub devices: Arc<HashMap<String, Arc<Device>>>,
-    device_space_table: HashMap<String, String>,
-    regional_dst_device_bdd_table: HashMap<String, Bdd>,
+    regional_dst_device_bdd_table: HashMap<String, PacketSpace>,
     pub network: Arc<Network>,
     dst_node_name: String,
     dst_space_node_name: String,
     nodes_table: HashMap<Arc<String>, Node>,
     space_nodes_table: HashMap<String, SpaceNode>,
-    space_node_connection: HashMap<String, Bdd>,
+    topology_index: OnceLock<Arc<TopologyIndex>>,
 }
 
 impl Toponet {
-    pub fn new(ip_bits: usize) -> Self {
-        let mut variable_builder = BddVariableSetBuilder::new();
-        let mut variables_dst_ip = Vec::new();
-
-        for i in 0..ip_bits {
-            let var_name: String = format!("x{}", i + 1);
-            let var = variable_builder.make_variable(&var_name);
-            variables_dst_ip.push(var);
-        }
-
-        let variable_set = variable_builder.build();
-
+    pub fn new(npbdd: Arc<NPBDD>) -> Self {
         Toponet {
-            ip_bits_len: ip_bits,
-            variables_dst_ip: Arc::new(variables_dst_ip),
-            variable_set: Arc::new(variable_set),
+            ip_bits_len: npbdd.ip_bits_len(),
+            npbdd,
             map_device_rule_bdd: Arc::new(HashMap::default()),
-            map_device_lecs: Arc::new(HashMap::default()),
             map_device_packet_space_bdd: Arc::new(HashMap::default()),
             all_space_map: Arc::new(HashMap::default()),
+            packet_spaces: Vec::new(),
             devices: Arc::new(HashMap::default()),
-            device_space_table: HashMap::default(),
             regional_dst_device_bdd_table: HashMap::default(),
             network: Arc::new(Network::new()),
             dst_node_name: String::new(),
             nodes_table: HashMap::default(),
             dst_space_node_name: String::new(),
-            space_node_connection: HashMap::default(),
             space_nodes_table: HashMap::default(),
+            topology_index: OnceLock::new(),
         }
     }
 
@@ -84,12 +74,12 @@ impl Toponet {
         }
     }
 
-    pub fn get_variables_dst_ip(&self) -> &Vec<BddVariable> {
-        &self.variables_dst_ip
+    pub fn get_variables_dst_ip(&self) -> &[BddVariable] {
+        self.npbdd.ip_bit_variables()
     }
 
     pub fn get_variable_set(&self) -> &BddVariableSet {
-        &self.variable_set
+        self.npbdd.variable_set()
     }
 
     pub fn get_space_node_name(&self) -> String {
@@ -105,11 +95,21 @@ impl Toponet {
     }
 
     pub fn set_arc_devices(&mut self, arc_devices: &Arc<HashMap<String, Arc<Device>>>) {
-        self.devices = Arc::clone(&arc_devices);
+        self.devices = Arc::clone(arc_devices);
+        self.topology_index = OnceLock::new();
     }
 
     pub fn set_arc_network(&mut self, arc_network: &Arc<Network>) {
         self.network = Arc::clone(arc_network);
+        self.topology_index = OnceLock::new();
+    }
+
+    /// Index of the current devices and links, built on first use.
+    pub fn topology_index(&self) -> Arc<TopologyIndex> {
+        Arc::clone(
+            self.topology_index
+                .get_or_init(|| Arc::new(TopologyIndex::new(&self.devices, &self.network))),
+        )
     }
 
     pub fn encode_rule_stepwise(
@@ -118,13 +118,17 @@ impl Toponet {
         common_prefix: &String,
         network: &Network,
     ) {
-        let all_space = self.all_space_map.values().next().unwrap();
-        let all_space_id = BDDTable::insert_bdd(all_space.clone());
+        let table = self.npbdd.table();
+        let cache = self.npbdd.cache();
+        let all_space_id = *self.all_space_map.values().next().unwrap();
         let device_ports_topo = network.get_device_ports();
 
-        tmp_devices
-            .par_iter_mut()
-            .for_each(|(device_name, device)| {
+        // Encode in chunks so the table can be flushed between them when bounded.
+        let mut entries: Vec<(&String, &mut Device)> = tmp_devices.iter_mut().collect();
+        let chunk_size = self.npbdd.encode_chunk_size(entries.len());
+        for chunk in entries.chunks_mut(chunk_size) {
+            chunk.par_iter_mut().for_each(|(device_name, device)| {
+                let device_name: &String = device_name;
                 let mut port_predicate: HashMap<String, usize> = HashMap::default();
                 let topo_ports = match device_ports_topo.get(device_name) {
                     Some(ports) => ports,
@@ -136,8 +140,8 @@ impl Toponet {
                 if tmp_rules.is_empty() {
                     return;
                 }
-                let mut all_bdd = self.variable_set.mk_false();
-                let mut all_bdd_id = BDDTable::insert_bdd(all_bdd);
+                let all_bdd = self.npbdd.make_none_space_bdd();
+                let mut all_bdd_id = table.insert_bdd(all_bdd);
                 let last_longest_prefix_len = tmp_rules.first().unwrap().get_prefix_len();
                 for rule in tmp_rules.iter() {
                     let rule_ip = rule.get_ip();
@@ -147,19 +151,22 @@ impl Toponet {
                     }
 
                     let prefix_key = format!("{}/{}", rule_ip, rule_prefix_len);
-                    let entry = BDDTable::get_prefix_bdd_map()
+                    let entry = table
+                        .get_prefix_bdd_map()
                         .get(&prefix_key)
                         .map(|cached| *cached.value());
                     let bdd_match_id = if let Some(cached_id) = entry {
                         cached_id
                     } else {
-                        let result = NPBDD::make_prefix_bdd(rule.get_ip(), rule.get_prefix_len());
-                        let new_bdd_id = BDDTable::insert_bdd(result.clone());
-                        BDDTable::get_prefix_bdd_map().insert(prefix_key, new_bdd_id);
+                        let result = self
+                            .npbdd
+                            .make_prefix_bdd(rule.get_ip(), rule.get_prefix_len());
+                        let new_bdd_id = table.insert_bdd(result.clone());
+                        table.get_prefix_bdd_map().insert(prefix_key, new_bdd_id);
                         new_bdd_id
                     };
 
-                    let is_relevant = LayerCache::cached_relevance(all_space_id, bdd_match_id);
+                    let is_relevant = cache.cached_relevance(all_space_id, bdd_match_id);
                     if !is_relevant {
                         continue;
                     }
@@ -167,13 +174,13 @@ impl Toponet {
                     // 2. calculate hit
                     let mut bdd_hit_id = bdd_match_id;
                     if rule.get_prefix_len() == last_longest_prefix_len {
-                        all_bdd_id = LayerCache::cached_or(all_bdd_id, bdd_hit_id);
+                        all_bdd_id = cache.cached_or(all_bdd_id, bdd_hit_id);
                     } else {
                         (all_bdd_id, bdd_hit_id) =
-                            LayerCache::cached_prefix_match(all_bdd_id, bdd_hit_id);
+                            cache.cached_prefix_match(all_bdd_id, bdd_hit_id);
                     }
 
-                    if BDDTable::get_bdd_by_id(bdd_hit_id).unwrap().is_false() {
+                    if table.get_bdd_by_id(bdd_hit_id).unwrap().is_empty() {
                         continue;
                     }
 
@@ -198,13 +205,13 @@ impl Toponet {
                                         Some((last_old_predicate_id, last_result_predicate_id));
                                 } else {
                                     let new_predicate_id =
-                                        LayerCache::cached_or(old_predicate_id_u32, bdd_hit_id);
+                                        cache.cached_or(old_predicate_id_u32, bdd_hit_id);
                                     last_calculation_pair =
                                         Some((old_predicate_id_u32, new_predicate_id));
                                 }
                             } else {
                                 let new_predicate_id =
-                                    LayerCache::cached_or(old_predicate_id_u32, bdd_hit_id);
+                                    cache.cached_or(old_predicate_id_u32, bdd_hit_id);
                                 last_calculation_pair =
                                     Some((old_predicate_id_u32, new_predicate_id));
                             }
@@ -221,104 +228,297 @@ impl Toponet {
                 for (port, predicate_id) in port_predicate.into_iter() {
                     tmp_lecs.insert(Lec::new(
                         ForwardAction::new("ALL".to_owned(), vec![port.clone()]),
-                        BDDTable::get_bdd_by_id(predicate_id.try_into().unwrap()).unwrap(),
+                        predicate_id.try_into().unwrap(),
                     ));
                 }
-                device.cal_forwardable_space(&tmp_lecs);
+                device.cal_forwardable_space(&tmp_lecs, &self.npbdd);
                 device.merge_lec_to_space_port(tmp_lecs);
             });
+            self.npbdd.maintain();
+        }
     }
 
     pub fn encode_rule_npbdd(
         &mut self,
         tmp_devices: &mut HashMap<String, Device>,
-        common_prefix: &String,
+        common_prefix: &str,
         network: &Network,
-    ) {
-        let all_space = self.all_space_map.values().next().unwrap();
-        let all_space_id = BDDTable::insert_bdd(all_space.clone());
-        let device_ports_topo = network.get_device_ports();
+        control: &RunControl,
+    ) -> bool {
+        self.encode_rules(
+            tmp_devices,
+            common_prefix,
+            network,
+            LecBuilder::Bdd,
+            control,
+        )
+        .is_some()
+    }
 
-        tmp_devices
-            .par_iter_mut()
-            .for_each(|(device_name, device)| {
-                let mut port_predicate: HashMap<String, u32> = HashMap::default();
+    /// Returns the number of ports on which `LecBuilder::Checked` found the
+    /// builders to disagree, 0 for the other builders. `None` if `control`
+    /// was cancelled before every device was encoded; the devices not
+    /// reached keep no LECs.
+    pub fn encode_rules(
+        &mut self,
+        tmp_devices: &mut HashMap<String, Device>,
+        common_prefix: &str,
+        network: &Network,
+        lec_builder: LecBuilder,
+        control: &RunControl,
+    ) -> Option<usize> {
+        let all_space_id = *self.all_space_map.values().next().unwrap();
+        let relevance = packet_space_trie(&self.packet_spaces);
+        let device_ports_topo = network.get_device_ports();
+        let mismatches = AtomicUsize::new(0);
+
+        // Encode in chunks so the table can be flushed between them when bounded.
+        let mut entries: Vec<(&String, &mut Device)> = tmp_devices.iter_mut().collect();
+        let chunk_size = self.npbdd.encode_chunk_size(entries.len());
+        let progress = control.encode_counter(entries.len());
+        for chunk in entries.chunks_mut(chunk_size) {
+            if control.is_cancelled() {
+                return None;
+            }
+            // Trie unions are memoized per chunk, so the memo is dropped
+            // along with whatever `maintain` flushes.
+            let unions: DashMap<Vec<Vec<bool>>, SpaceId> = DashMap::default();
+            chunk.par_iter_mut().for_each(|(device_name, device)| {
+                if control.is_cancelled() {
+                    return;
+                }
+                let device_name: &String = device_name;
                 let topo_ports = match device_ports_topo.get(device_name) {
                     Some(ports) => ports,
                     None => {
+                        progress.tick();
                         return;
                     }
                 };
-                let tmp_rules = device.get_rules();
-                if tmp_rules.is_empty() {
+                if device.get_rules().is_empty() {
+                    progress.tick();
                     return;
                 }
-                // used_space (fwded) 初始化为 false (⊥)，并复用 false_id 以避免重复构造。
-                let false_id = BDDTable::insert_bdd(self.variable_set.mk_false());
-                let mut used_space_id = false_id;
-
-                for rule in tmp_rules.iter() {
-                    let rule_ip = rule.get_ip();
-                    let rule_prefix_len = rule.get_prefix_len();
-                    if !rule_ip.starts_with(common_prefix) && rule_prefix_len != 0 {
-                        continue;
+                let port_predicate = match lec_builder {
+                    LecBuilder::Bdd => self.npbdd_port_predicates(
+                        device_name,
+                        device,
+                        topo_ports,
+                        common_prefix,
+                        all_space_id,
+                    ),
+                    LecBuilder::Trie => self.trie_port_predicates(
+                        device_name,
+                        device,
+                        topo_ports,
+                        common_prefix,
+                        &relevance,
+                        &unions,
+                    ),
+                    LecBuilder::Checked => {
+                        let bdd_predicate = self.npbdd_port_predicates(
+                            device_name,
+                            device,
+                            topo_ports,
+                            common_prefix,
+                            all_space_id,
+                        );
+                        let trie_predicate = self.trie_port_predicates(
+                            device_name,
+                            device,
+                            topo_ports,
+                            common_prefix,
+                            &relevance,
+                            &unions,
+                        );
+                        let ports: HashSet<&String> =
+                            bdd_predicate.keys().chain(trie_predicate.keys()).collect();
+                        for port in ports {
+                            if bdd_predicate.get(port) != trie_predicate.get(port) {
+                                println!("LEC mismatch on {} port {}", device_name, port);
+                                mismatches.fetch_add(1, Ordering::Relaxed);
+                            }
+                        }
+                        bdd_predicate
                     }
+                };
 
-                    let prefix_bdd_id = LayerCache::l2_encode_rule(rule_ip, rule_prefix_len);
+                let mut tmp_lecs = HashSet::default();
+                for (port, predicate) in port_predicate.into_iter() {
+                    tmp_lecs.insert(Lec::new(
+                        ForwardAction::new("ALL".to_owned(), vec![port]),
+                        predicate,
+                    ));
+                }
+                device.cal_forwardable_space(&tmp_lecs, &self.npbdd);
+                device.merge_lec_to_space_port(tmp_lecs);
+                progress.tick();
+            });
+            self.npbdd.maintain();
+        }
+        if control.is_cancelled() {
+            return None;
+        }
+        let mismatches = mismatches.into_inner();
+        if lec_builder == LecBuilder::Checked {
+            println!(
+                "LEC cross-check: {} devices, {} mismatched ports",
+                tmp_devices.len(),
+                mismatches
+            );
+        }
+        Some(mismatches)
+    }
 
-                    let is_relevant = LayerCache::cached_relevance(all_space_id, prefix_bdd_id);
-                    if !is_relevant {
-                        continue;
-                    }
+    /// Per-port predicates of one device by longest-prefix match with BDD
+    /// operations through the layer cache.
+    fn npbdd_port_predicates(
+        &self,
+        device_name: &str,
+        device: &Device,
+        topo_ports: &HashSet<DevicePort>,
+        common_prefix: &str,
+        all_space_id: SpaceId,
+    ) -> HashMap<String, SpaceId> {
+        let cache = self.npbdd.cache();
+        let mut port_predicate: HashMap<String, u32> = HashMap::default();
+        // used_space (fwded) 初始化为 false (⊥)，并复用 false_id 以避免重复构造。
+        let false_id = NONE_SPACE_ID;
+        let mut used_space_id = false_id;
+
+        for rule in device.get_rules().iter() {
+            let rule_ip = rule.get_ip();
+            let rule_prefix_len = rule.get_prefix_len();
+            if !rule_ip.starts_with(common_prefix) && rule_prefix_len != 0 {
+                continue;
+            }
 
-                    let (hit_id, new_used_space_id) =
-                        LayerCache::l2_cal_hit(prefix_bdd_id, used_space_id);
-                    used_space_id = new_used_space_id;
+            let prefix_bdd_id = cache.l2_encode_rule(rule_ip, rule_prefix_len);
 
-                    if BDDTable::get_bdd_by_id(hit_id).unwrap().is_false() {
-                        continue;
-                    }
+            let is_relevant = cache.cached_relevance(all_space_id, prefix_bdd_id);
+            if !is_relevant {
+                continue;
+            }
 
-                    let forward_action = rule.get_forward_action();
-                    let tmp_ports = forward_action.get_ports();
-                    let mut port_ids_to_update: Vec<u32> = Vec::new();
-                    let mut ports_to_update: Vec<String> = Vec::new();
+            let (hit_id, new_used_space_id) = cache.l2_cal_hit(prefix_bdd_id, used_space_id);
+            used_space_id = new_used_space_id;
 
-                    for port in tmp_ports {
-                        let tmp_device_port =
-                            DevicePort::new(device_name.to_string(), port.to_string());
-                        if !topo_ports.contains(&tmp_device_port) {
-                            continue;
-                        }
-                        ports_to_update.push(port.clone());
-                        if let Some(&old_port_id) = port_predicate.get(port) {
-                            port_ids_to_update.push(old_port_id);
-                        } else {
-                            // 新端口，初始化为 false，然后与 hit 合并
-                            port_ids_to_update.push(false_id);
-                        }
-                    }
+            if hit_id == NONE_SPACE_ID {
+                continue;
+            }
 
-                    if !port_ids_to_update.is_empty() {
-                        let new_port_ids =
-                            LayerCache::l2_merge_port_space(hit_id, &port_ids_to_update);
-                        for (port, &new_port_id) in ports_to_update.iter().zip(new_port_ids.iter())
-                        {
-                            port_predicate.insert(port.clone(), new_port_id);
-                        }
-                    }
+            let forward_action = rule.get_forward_action();
+            let tmp_ports = forward_action.get_ports();
+            let mut port_ids_to_update: Vec<u32> = Vec::new();
+            let mut ports_to_update: Vec<String> = Vec::new();
+
+            for port in tmp_ports {
+                let tmp_device_port = DevicePort::new(device_name.to_string(), port.to_string());
+                if !topo_ports.contains(&tmp_device_port) {
+                    continue;
                 }
+                ports_to_update.push(port.clone());
+                if let Some(&old_port_id) = port_predicate.get(port) {
+                    port_ids_to_update.push(old_port_id);
+                } else {
+                    // 新端口，初始化为 false，然后与 hit 合并
+                    port_ids_to_update.push(false_id);
+                }
+            }
 
-                let mut tmp_lecs = HashSet::default();
-                for (port, predicate_id) in port_predicate.into_iter() {
-                    tmp_lecs.insert(Lec::new(
-                        ForwardAction::new("ALL".to_owned(), vec![port.clone()]),
-                        BDDTable::get_bdd_by_id(predicate_id).unwrap(),
+            if !port_ids_to_update.is_empty() {
+                let new_port_ids = cache.l2_merge_port_space(hit_id, &port_ids_to_update);
+                for (port, &new_port_id) in ports_to_update.iter().zip(new_port_ids.iter()) {
+                    port_predicate.insert(port.clone(), new_port_id);
+                }
+            }
+        }
+
+        port_predicate
+    }
+
+    /// Per-port predicates of one device by longest-prefix match on a binary
+    /// trie. Each port's disjoint prefixes are turned into one BDD at the end.
+    fn trie_port_predicates(
+        &self,
+        device_name: &str,
+        device: &Device,
+        topo_ports: &HashSet<DevicePort>,
+        common_prefix: &str,
+        relevance: &PrefixTrie,
+        unions: &DashMap<Vec<Vec<bool>>, SpaceId>,
+    ) -> HashMap<String, SpaceId> {
+        let rules = device.get_rules();
+        let mut trie = PrefixTrie::new();
+        for (rule_index, rule) in rules.iter().enumerate() {
+            let rule_ip = rule.get_ip();
+            let rule_prefix_len = rule.get_prefix_len();
+            if !rule_ip.starts_with(common_prefix) && rule_prefix_len != 0 {
+                continue;
+            }
+            let bits = prefix_bits(rule_ip, rule_prefix_len)
+                .unwrap_or_else(|| panic!("Invalid prefix {}", rule.get_name()));
+            if relevance.overlaps(&bits) {
+                trie.insert(&bits, rule_index);
+            }
+        }
+
+        // Rules with the same ports share one union, which is also shared
+        // across devices through `unions`.
+        let mut group_prefixes: HashMap<Vec<String>, Vec<Vec<bool>>> = HashMap::default();
+        for (rule_index, bits) in trie.disjoint_prefixes() {
+            let mut ports: Vec<String> = rules[rule_index]
+                .get_forward_action()
+                .get_ports()
+                .iter()
+                .filter(|port| {
+                    topo_ports.contains(&DevicePort::new(device_name.to_string(), port.to_string()))
+                })
+                .cloned()
+                .collect();
+            if ports.is_empty() {
+                continue;
+            }
+            ports.sort();
+            ports.dedup();
+            group_prefixes.entry(ports).or_default().push(bits);
+        }
+
+        let mut port_predicate: HashMap<String, SpaceId> = HashMap::default();
+        for (ports, mut prefixes) in group_prefixes {
+            prefixes.sort();
+            let union = match unions.get(&prefixes) {
+                Some(union) => *union,
+                None => {
+                    let union = self.npbdd.intern(union_balanced(
+                        prefixes
+                            .iter()
+                            .map(|bits| self.npbdd.make_prefix_bdd_from_bits(bits))
+                            .collect(),
                     ));
+                    unions.insert(prefixes, union);
+                    union
                 }
-                device.cal_forwardable_space(&tmp_lecs);
-                device.merge_lec_to_space_port(tmp_lecs);
-            });
+            };
+            for port in ports {
+                port_predicate
+                    .entry(port)
+                    .and_modify(|predicate| *predicate = self.npbdd.or(*predicate, union))
+                    .or_insert(union);
+            }
+        }
+        port_predicate
+    }
+
+    /// Union of a device's packet-space entries, or `None` if it has none.
+    fn packet_space_union(&self, packet_spaces: &[Rule]) -> Option<PacketSpace> {
+        packet_spaces
+            .iter()
+            .map(|packet_space| {
+                self.npbdd
+                    .make_prefix_bdd(packet_space.get_ip(), packet_space.get_prefix_len())
+            })
+            .reduce(|union, bdd| union.or(&bdd))
     }
 
     pub fn encode_packet_space_group(
@@ -326,46 +526,41 @@ impl Toponet {
         tmp_devices: &mut HashMap<String, Device>,
         dst_devices: &HashSet<String>,
     ) {
-        let tmp_map_device_subnet_bdd: HashMap<String, Bdd> = dst_devices
+        let tmp_map_device_subnet_bdd: HashMap<String, SpaceId> = dst_devices
             .par_iter()
             .filter_map(|dst_device_name| {
-                let edge_device = tmp_devices.get(dst_device_name).expect(&format!(
-                    "Failed to find device with name: {}",
-                    dst_device_name
-                ));
-                if let Some(packet_space) = edge_device.get_packet_space() {
-                    let bdd = NPBDD::make_prefix_bdd(
-                        packet_space.get_ip(),
-                        packet_space.get_prefix_len(),
-                    );
-                    Some((dst_device_name.clone(), bdd))
-                } else {
-                    None
-                }
+                let edge_device = tmp_devices.get(dst_device_name).unwrap_or_else(|| {
+                    panic!("Failed to find device with name: {}", dst_device_name)
+                });
+                let bdd = self.packet_space_union(edge_device.get_packet_space())?;
+                Some((dst_device_name.clone(), self.npbdd.intern_pinned(bdd)))
             })
             .collect();
+        self.packet_spaces = tmp_map_device_subnet_bdd
+            .keys()
+            .flat_map(|dst_device_name| tmp_devices[dst_device_name].get_packet_space())
+            .cloned()
+            .collect();
 
         tmp_devices
             .par_iter_mut()
-            .for_each(|(device_name, device)| {
-                if let Some(packet_space) = device.get_packet_space() {
-                    device.subnet_space = NPBDD::make_prefix_bdd(
-                        packet_space.get_ip(),
-                        packet_space.get_prefix_len(),
-                    );
+            .for_each(|(_device_name, device)| {
+                if let Some(bdd) = self.packet_space_union(device.get_packet_space()) {
+                    device.subnet_space = self.npbdd.intern_pinned(bdd);
                 }
             });
 
-        if let Some((_, bdd)) = tmp_map_device_subnet_bdd.iter().next() {
+        if let Some((_, &bdd)) = tmp_map_device_subnet_bdd.iter().next() {
+            let bdd = self.npbdd.space(bdd).as_ref().clone();
             let all_space = tmp_map_device_subnet_bdd
                 .values()
-                .cloned()
+                .map(|&space| self.npbdd.space(space).as_ref().clone())
                 .collect::<Vec<_>>()
                 .into_par_iter()
                 .reduce(|| bdd.clone(), |acc, cur| acc.or(&cur));
 
-            let mut all_space_map: HashMap<String, Bdd> = HashMap::default();
-            all_space_map.insert("All".to_string(), all_space);
+            let mut all_space_map: HashMap<String, SpaceId> = HashMap::default();
+            all_space_map.insert("All".to_string(), self.npbdd.intern_pinned(all_space));
             self.all_space_map = Arc::new(all_space_map);
         } else {
             println!("HashMap is empty");
@@ -373,27 +568,6 @@ impl Toponet {
         self.map_device_packet_space_bdd = Arc::new(tmp_map_device_subnet_bdd);
     }
 
-    fn get_tmatch(&self, device_name: &str, rule: &Rule) -> Option<Bdd> {
-        self.map_device_rule_bdd
-            .get(device_name)?
-            .get(rule)
-            .map(|rule_bdd| rule_bdd.get_match().clone())
-    }
-
-    fn get_packet_space(&self) -> &Bdd {
-        self.map_device_packet_space_bdd
-            .get(&self.dst_node_name)
-            .unwrap()
-    }
-
-    fn get_space_node_bdd(&self) -> &Bdd {
-        let space_node = self
-            .space_nodes_table
-            .get(&self.dst_space_node_name)
-            .unwrap();
-        space_node.get_aggre_space().unwrap()
-    }
-
     pub fn gen_topo_node(&mut self, devices_name: &Vec<Arc<String>>) {
         for device_name in devices_name {
             let mut node = Node::new(device_name.clone());
@@ -428,10 +602,18 @@ impl Toponet {
                 .insert(pod_id.to_string(), cur_space_node);
         }
         if let Some(dst_space_node) = self.space_nodes_table.get_mut(&self.dst_space_node_name) {
-            dst_space_node.aggregate_packet_space(
-                &self.map_device_packet_space_bdd,
-                &mut self.regional_dst_device_bdd_table,
-            );
+            let packet_spaces: HashMap<String, PacketSpace> = self
+                .map_device_packet_space_bdd
+                .iter()
+                .map(|(device_name, &space)| {
+                    (
+                        device_name.clone(),
+                        self.npbdd.space(space).as_ref().clone(),
+                    )
+                })
+                .collect();
+            dst_space_node
+                .aggregate_packet_space(&packet_spaces, &mut self.regional_dst_device_bdd_table);
         };
     }
 
@@ -461,3 +643,19 @@ impl Toponet {
         &self.map_device_rule_bdd
     }
 }
+
+/// Pairwise union, which keeps the intermediate BDDs small compared to
+/// folding prefixes into one accumulator.
+fn union_balanced(mut bdds: Vec<PacketSpace>) -> PacketSpace {
+    while bdds.len() > 1 {
+        bdds = bdds
+            .chunks(2)
+            .map(|pair| match pair {
+                [a, b] => a.or(b),
+                [a] => a.clone(),
+                _ => unreachable!(),
+            })
+            .collect();
+    }
+    bdds.pop().unwrap()
+}
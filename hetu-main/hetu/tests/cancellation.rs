@@ -0,0 +1,94 @@
+//! Cancellation and time budgets on the datasets in `tests/fixtures`.
+
+use hetu::verifier::atomic::VerificationEngine;
+use hetu::{CancellationToken, ReachabilityReport, SessionError, VerificationSession};
+use std::fs;
+use std::path::PathBuf;
+use std::time::Duration;
+
+fn fixture_dir(name: &str) -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/fixtures")
+        .join(name)
+}
+
+fn assert_all_skipped(report: &ReachabilityReport, planned: usize) {
+    assert!(!report.is_complete(), "{:?}", report);
+    assert_eq!(report.skipped_neighborhoods, planned, "{:?}", report);
+    assert_eq!(report.total_pairs(), 0, "{:?}", report);
+}
+
+#[test]
+fn cancelling_before_encode_latches_the_session() {
+    let token = CancellationToken::new();
+    let mut session = VerificationSession::builder()
+        .data_dir(fixture_dir("fattree4"))
+        .cancellation(token.clone())
+        .build()
+        .unwrap();
+    session.load().unwrap();
+    token.cancel();
+    assert_eq!(session.encode(), Err(SessionError::Cancelled));
+
+    // A cancelled session stays cancelled.
+    assert_eq!(session.encode(), Err(SessionError::Cancelled));
+    assert_eq!(session.load(), Err(SessionError::Cancelled));
+    assert_eq!(session.verify(), Err(SessionError::Cancelled));
+    assert_eq!(
+        session.query("leaf0", "leaf1"),
+        Err(SessionError::Cancelled)
+    );
+}
+
+#[test]
+fn cancelling_before_verify_skips_every_neighborhood() {
+    for engine in [VerificationEngine::NpNet, VerificationEngine::Atomic] {
+        for outer_summaries in [false, true] {
+            let token = CancellationToken::new();
+            let mut session = VerificationSession::builder()
+                .data_dir(fixture_dir("fattree4"))
+                .engine(engine)
+                .outer_summaries(outer_summaries)
+                .cancellation(token.clone())
+                .build()
+                .unwrap();
+            session.load().unwrap();
+            session.encode().unwrap();
+            let planned = session.plan_verification().unwrap().len();
+            assert!(planned > 0);
+            token.cancel();
+            assert_all_skipped(&session.verify().unwrap(), planned);
+        }
+    }
+}
+
+#[test]
+fn zero_time_budget_cancels_encoding() {
+    let mut session = VerificationSession::builder()
+        .data_dir(fixture_dir("fattree4"))
+        .time_budget(Duration::ZERO)
+        .build()
+        .unwrap();
+    assert_eq!(session.run(), Err(SessionError::Cancelled));
+    assert_eq!(session.verify(), Err(SessionError::Cancelled));
+}
+
+#[test]
+fn zero_time_budget_skips_every_neighborhood() {
+    let path = std::env::temp_dir().join(format!("hetu-cancellation-{}.htsn", std::process::id()));
+    let mut session = VerificationSession::builder()
+        .data_dir(fixture_dir("fattree4"))
+        .build()
+        .unwrap();
+    session.run().unwrap();
+    session.save_snapshot(&path).unwrap();
+    let planned = session.plan_verification().unwrap().len();
+
+    // A snapshot is already encoded, so the budget only runs out in verify.
+    let session = VerificationSession::builder()
+        .time_budget(Duration::ZERO)
+        .open_snapshot(&path)
+        .unwrap();
+    fs::remove_file(&path).unwrap();
+    assert_all_skipped(&session.verify().unwrap(), planned);
+}
@@ -0,0 +1,104 @@
+//! Small datasets written to a temporary directory, for tests that change
+//! files between runs.
+
+use serde_json::{json, Value};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// A leaf/spine network: leaf `i` owns `10.0.i.0/24` behind port `host0`
+/// and reaches the single spine over `up0`; the spine reaches leaf `i`
+/// over `down{i}`. Removed when dropped.
+pub struct Dataset {
+    dir: PathBuf,
+    leaves: usize,
+}
+
+impl Dataset {
+    pub fn leaf_spine(test: &str, leaves: usize) -> Self {
+        let dir = std::env::temp_dir().join(format!("hetu-{}-{}", test, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(dir.join("routes")).unwrap();
+        let dataset = Dataset { dir, leaves };
+        let mut links = Vec::new();
+        let mut subnets = Vec::new();
+        let mut spine_routes = Vec::new();
+        for leaf in 0..leaves {
+            let routes: Vec<Value> = (0..leaves)
+                .map(|dst| {
+                    let port = if dst == leaf { "host0" } else { "up0" };
+                    route(&subnet(dst), 24, &[port])
+                })
+                .collect();
+            dataset.write_routes(&leaf_name(leaf), &routes);
+            links.push(json!({
+                "src_node": leaf_name(leaf),
+                "src_port": "up0",
+                "dst_node": "spine",
+                "dst_port": format!("down{}", leaf),
+            }));
+            subnets.push(json!({
+                "prefix": subnet(leaf),
+                "prefix_len": 24,
+                "host_name": leaf_name(leaf),
+            }));
+            spine_routes.push(route(&subnet(leaf), 24, &[&format!("down{}", leaf)]));
+        }
+        dataset.write_routes("spine", &spine_routes);
+        dataset.write_json("topology.json", &Value::Array(links));
+        dataset.write_json("packet_space.json", &Value::Array(subnets));
+        let edge_devices: Vec<String> = (0..leaves).map(leaf_name).collect();
+        dataset.write_edge_devices(&edge_devices);
+        dataset
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// Pairs of distinct leaves.
+    pub fn pairs(&self) -> usize {
+        self.leaves * (self.leaves - 1)
+    }
+
+    pub fn write_routes(&self, device: &str, routes: &[Value]) {
+        self.write_json(
+            &format!("routes/{}", device),
+            &Value::Array(routes.to_vec()),
+        );
+    }
+
+    pub fn write_edge_devices(&self, devices: &[String]) {
+        fs::write(self.dir.join("edge_devices"), devices.join("\n")).unwrap();
+    }
+
+    pub fn write_json(&self, name: &str, value: &Value) {
+        fs::write(
+            self.dir.join(name),
+            serde_json::to_string_pretty(value).unwrap(),
+        )
+        .unwrap();
+    }
+}
+
+impl Drop for Dataset {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.dir);
+    }
+}
+
+pub fn leaf_name(leaf: usize) -> String {
+    format!("leaf{}", leaf)
+}
+
+pub fn subnet(leaf: usize) -> String {
+    format!("10.0.{}.0", leaf)
+}
+
+pub fn route(prefix: &str, prefix_len: usize, ports: &[&str]) -> Value {
+    json!({
+        "action": "fwd",
+        "prefix": prefix,
+        "prefix_len": prefix_len,
+        "nexthop_infs": ports,
+    })
+}
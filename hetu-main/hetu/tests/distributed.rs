@@ -0,0 +1,121 @@
+//! Coordinator and workers on the datasets in `tests/fixtures`: merged
+//! results have to match verifying the same session in one process.
+
+use hetu::distributed::{self, result_path};
+use hetu::util::work_plan::{WorkPlan, WorkResult};
+use hetu::{Coordinator, DistributedError, VerificationSession};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+const WORKERS: usize = 3;
+
+fn fixture_dir(name: &str) -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/fixtures")
+        .join(name)
+}
+
+/// A fresh work directory, unique to this process and test.
+fn work_dir(test: &str) -> PathBuf {
+    let dir =
+        std::env::temp_dir().join(format!("hetu-distributed-{}-{}", test, std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    dir
+}
+
+fn encoded_session(name: &str) -> VerificationSession {
+    let mut session = VerificationSession::builder()
+        .data_dir(fixture_dir(name))
+        .build()
+        .expect("fixture directory exists");
+    session.load().expect("load failed");
+    session.encode().expect("encode failed");
+    session
+}
+
+/// Prepares `dir` for `name` and runs every worker in this process.
+fn prepare_and_run_workers(name: &str, dir: &Path) -> (Coordinator, WorkPlan) {
+    let session = encoded_session(name);
+    let coordinator = Coordinator::new(dir, WORKERS);
+    let plan = coordinator.prepare(&session).unwrap();
+    assert_eq!(plan.assignments.len(), WORKERS);
+    for worker in 0..WORKERS {
+        let result = distributed::run_worker(VerificationSession::builder(), dir, worker).unwrap();
+        assert_eq!(result.worker, worker);
+        assert_eq!(result.reports.len(), plan.assignments[worker].len());
+    }
+    (coordinator, plan)
+}
+
+#[test]
+fn merged_worker_results_match_local_verification() {
+    for name in ["leaf_spine", "fattree4", "leaf_spine_loop"] {
+        let dir = work_dir(name);
+        let (coordinator, plan) = prepare_and_run_workers(name, &dir);
+        let merged = coordinator.merge(&plan).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+        assert!(merged.is_complete(), "{}: {:?}", name, merged);
+        assert_eq!(merged, encoded_session(name).verify().unwrap(), "{}", name);
+    }
+}
+
+#[test]
+fn worker_processes_match_local_verification() {
+    let dir = work_dir("processes");
+    let session = encoded_session("fattree4");
+    let coordinator = Coordinator::new(&dir, WORKERS);
+    let plan = coordinator.prepare(&session).unwrap();
+    coordinator
+        .run_local_workers(Path::new(env!("CARGO_BIN_EXE_Hetu")), &[])
+        .unwrap();
+    let merged = coordinator.merge(&plan).unwrap();
+    fs::remove_dir_all(&dir).unwrap();
+    assert_eq!(merged, session.verify().unwrap());
+}
+
+#[test]
+fn merge_rejects_a_missing_result() {
+    let dir = work_dir("missing");
+    let (coordinator, plan) = prepare_and_run_workers("leaf_spine", &dir);
+    let missing = result_path(&dir, 1);
+    fs::remove_file(&missing).unwrap();
+    let merged = coordinator.merge(&plan);
+    fs::remove_dir_all(&dir).unwrap();
+    assert!(
+        matches!(&merged, Err(DistributedError::Io(path, _)) if *path == missing),
+        "{:?}",
+        merged
+    );
+}
+
+#[test]
+fn merge_rejects_a_result_copied_to_another_worker() {
+    let dir = work_dir("copied");
+    let (coordinator, plan) = prepare_and_run_workers("leaf_spine", &dir);
+    fs::copy(result_path(&dir, 0), result_path(&dir, 1)).unwrap();
+    let merged = coordinator.merge(&plan);
+    fs::remove_dir_all(&dir).unwrap();
+    assert!(
+        matches!(merged, Err(DistributedError::Incomplete(_))),
+        "{:?}",
+        merged
+    );
+}
+
+#[test]
+fn merge_rejects_a_neighborhood_reported_twice() {
+    let dir = work_dir("duplicated");
+    let (coordinator, plan) = prepare_and_run_workers("leaf_spine", &dir);
+    let path = result_path(&dir, 0);
+    let mut result = WorkResult::load(&path).unwrap();
+    let first = result.reports[0];
+    result.reports.push(first);
+    result.save(&path).unwrap();
+    let merged = coordinator.merge(&plan);
+    fs::remove_dir_all(&dir).unwrap();
+    assert!(
+        matches!(merged, Err(DistributedError::Incomplete(_))),
+        "{:?}",
+        merged
+    );
+}
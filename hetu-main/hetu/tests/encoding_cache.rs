@@ -0,0 +1,127 @@
+//! Runs with `--encoding-cache` have to match cold runs after any input
+//! file changes.
+
+mod common;
+
+use common::{leaf_name, route, subnet, Dataset};
+use hetu::util::encoding_cache::EncodingCache;
+use hetu::{ReachabilityReport, VerificationSession};
+use serde_json::json;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+fn run(data_dir: &Path, cache: Option<&Path>) -> ReachabilityReport {
+    let mut builder = VerificationSession::builder().data_dir(data_dir);
+    if let Some(path) = cache {
+        builder = builder.encoding_cache(path);
+    }
+    builder.build().unwrap().run().unwrap()
+}
+
+/// Runs `dataset` with its cache and checks the result against a cold run.
+fn assert_cached_matches_cold(dataset: &Dataset) -> ReachabilityReport {
+    let cache = dataset.dir().join("encoding.cache");
+    let cached = run(dataset.dir(), Some(&cache));
+    assert_eq!(cached, run(dataset.dir(), None));
+    cached
+}
+
+fn device_keys(dataset: &Dataset) -> Vec<(String, u64)> {
+    let cache = EncodingCache::load(dataset.dir().join("encoding.cache")).unwrap();
+    let mut keys: Vec<(String, u64)> = cache
+        .devices
+        .iter()
+        .map(|(name, (key, _))| (name.clone(), *key))
+        .collect();
+    keys.sort();
+    keys
+}
+
+#[test]
+fn cached_runs_match_cold_runs() {
+    let dataset = Dataset::leaf_spine("cache-reuse", 3);
+    let first = assert_cached_matches_cold(&dataset);
+    assert_eq!((first.reachable, first.unreachable), (dataset.pairs(), 0));
+    let keys = device_keys(&dataset);
+    assert_eq!(keys.len(), 4);
+
+    // Nothing changed, so the second run reuses every device.
+    assert_eq!(assert_cached_matches_cold(&dataset), first);
+    assert_eq!(device_keys(&dataset), keys);
+}
+
+#[test]
+fn a_changed_route_file_is_reencoded() {
+    let dataset = Dataset::leaf_spine("cache-routes", 3);
+    assert_cached_matches_cold(&dataset);
+    let keys = device_keys(&dataset);
+
+    // leaf0 now drops traffic to leaf1.
+    dataset.write_routes(
+        &leaf_name(0),
+        &[
+            route(&subnet(0), 24, &["host0"]),
+            route(&subnet(2), 24, &["up0"]),
+        ],
+    );
+    let report = assert_cached_matches_cold(&dataset);
+    assert_eq!(report.unreachable, 1);
+    let changed: Vec<String> = device_keys(&dataset)
+        .into_iter()
+        .zip(keys)
+        .filter(|(new, old)| new != old)
+        .map(|(new, _)| new.0)
+        .collect();
+    assert_eq!(changed, vec![leaf_name(0)]);
+}
+
+#[test]
+fn a_changed_packet_space_invalidates_the_cache() {
+    let dataset = Dataset::leaf_spine("cache-space", 3);
+    assert_cached_matches_cold(&dataset);
+
+    // leaf2 now owns a subnet no device routes.
+    let subnets: Vec<_> = (0..3)
+        .map(|leaf| {
+            let prefix = if leaf == 2 {
+                "10.0.9.0".to_string()
+            } else {
+                subnet(leaf)
+            };
+            json!({"prefix": prefix, "prefix_len": 24, "host_name": leaf_name(leaf)})
+        })
+        .collect();
+    dataset.write_json("packet_space.json", &json!(subnets));
+    let report = assert_cached_matches_cold(&dataset);
+    assert_eq!(report.unreachable, 2);
+}
+
+#[test]
+fn changed_edge_devices_invalidate_the_cache() {
+    let dataset = Dataset::leaf_spine("cache-edges", 4);
+    dataset.write_edge_devices(&[leaf_name(0), leaf_name(1)]);
+    let first = assert_cached_matches_cold(&dataset);
+    assert_eq!((first.reachable, first.unreachable), (2, 0));
+
+    let edge_devices: Vec<String> = (0..4).map(leaf_name).collect();
+    dataset.write_edge_devices(&edge_devices);
+    let report = assert_cached_matches_cold(&dataset);
+    assert_eq!((report.reachable, report.unreachable), (dataset.pairs(), 0));
+}
+
+#[test]
+fn a_cache_with_trailing_bytes_is_rejected() {
+    let dataset = Dataset::leaf_spine("cache-trailing", 2);
+    assert_cached_matches_cold(&dataset);
+    let path = dataset.dir().join("encoding.cache");
+    let mut bytes = fs::read(&path).unwrap();
+    bytes.push(0);
+    fs::write(&path, bytes).unwrap();
+    let err = EncodingCache::load(&path).err().unwrap();
+    assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+
+    // The run ignores the unreadable cache and rewrites it.
+    assert_cached_matches_cold(&dataset);
+    assert!(EncodingCache::load(&path).is_ok());
+}
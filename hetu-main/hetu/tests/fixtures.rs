@@ -0,0 +1,257 @@
+//! End-to-end checks of the verifier on the datasets in `tests/fixtures`,
+//! against the golden results in each dataset's `expected.json`.
+
+use hetu::util::fabric::{ClosSpec, Fabric};
+use hetu::util::fault::{FaultKind, FaultManifest, Violations};
+use hetu::verifier::atomic::VerificationEngine;
+use hetu::verifier::lec_trie::LecBuilder;
+use hetu::{ReachabilityReport, Simulator, VerificationSession};
+use serde::Deserialize;
+use std::fs;
+use std::path::PathBuf;
+
+const FIXTURES: [&str; 5] = [
+    "leaf_spine",
+    "fattree4",
+    "leaf_spine_loop",
+    "leaf_spine_blackhole",
+    "leaf_spine_hijack",
+];
+
+/// Sorted (source, destination) pairs, as in `Violations`.
+type Pairs = Vec<(String, String)>;
+
+#[derive(Debug, Deserialize)]
+struct Golden {
+    reachable: usize,
+    unreachable: usize,
+    unreachable_pairs: Pairs,
+    looping_pairs: Pairs,
+    blackholed_pairs: Pairs,
+}
+
+fn fixture_dir(name: &str) -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/fixtures")
+        .join(name)
+}
+
+fn golden(name: &str) -> Golden {
+    let path = fixture_dir(name).join("expected.json");
+    let contents = fs::read(&path).unwrap_or_else(|err| panic!("{}: {}", path.display(), err));
+    serde_json::from_slice(&contents).unwrap_or_else(|err| panic!("{}: {}", path.display(), err))
+}
+
+fn encoded_session(name: &str, engine: VerificationEngine, symmetry: bool) -> VerificationSession {
+    let mut session = VerificationSession::builder()
+        .data_dir(fixture_dir(name))
+        .engine(engine)
+        .symmetry(symmetry)
+        .build()
+        .expect("fixture directory exists");
+    session.load().expect("load failed");
+    session.encode().expect("encode failed");
+    session
+}
+
+fn assert_counts(name: &str, report: &ReachabilityReport) {
+    let golden = golden(name);
+    assert!(report.is_complete(), "{}: {:?}", name, report);
+    assert_eq!(
+        (report.reachable, report.unreachable),
+        (golden.reachable, golden.unreachable),
+        "{}",
+        name
+    );
+}
+
+#[test]
+fn npnet_matches_golden_counts() {
+    for name in FIXTURES {
+        let mut simulator = Simulator::new(144);
+        simulator.set_file_dir(fixture_dir(name).to_str().unwrap());
+        simulator.build();
+        assert_counts(name, &simulator.verify_reachability());
+
+        // The same neighborhoods verified one by one add up to the same report.
+        let plan = simulator.plan_verification();
+        let total = simulator.verify_plan(&plan).into_iter().fold(
+            ReachabilityReport::default(),
+            |mut acc, cur| {
+                acc += cur;
+                acc
+            },
+        );
+        assert_counts(name, &total);
+    }
+}
+
+#[test]
+fn atomic_engine_matches_golden_counts() {
+    for name in FIXTURES {
+        let session = encoded_session(name, VerificationEngine::Atomic, false);
+        assert_counts(name, &session.verify().unwrap());
+    }
+}
+
+#[test]
+fn symmetry_reduction_keeps_fat_tree_counts() {
+    let session = encoded_session("fattree4", VerificationEngine::NpNet, true);
+    assert_counts("fattree4", &session.verify().unwrap());
+}
+
+#[test]
+fn lec_builders_agree_on_every_port() {
+    for name in FIXTURES {
+        let mut session = VerificationSession::builder()
+            .data_dir(fixture_dir(name))
+            .lec_builder(LecBuilder::Checked)
+            .build()
+            .unwrap();
+        assert_counts(name, &session.run().unwrap());
+        assert_eq!(session.lec_mismatches(), Ok(0), "{}", name);
+    }
+}
+
+#[test]
+fn repeated_verification_does_not_accumulate_metrics() {
+    let session = encoded_session("fattree4", VerificationEngine::NpNet, false);
+    session.verify().unwrap();
+    let first = session.metrics();
+    session.verify().unwrap();
+    let second = session.metrics();
+    assert!(first.traversal_count > 0);
+    assert_eq!(second.traversal_count, first.traversal_count);
+    assert_eq!(second.neighborhoods, first.neighborhoods);
+}
+
+#[test]
+fn queries_match_golden_pairs() {
+    for name in FIXTURES {
+        let session = encoded_session(name, VerificationEngine::NpNet, false);
+        let fabric = Fabric::load(fixture_dir(name)).unwrap();
+        let mut unreachable = Vec::new();
+        for src in &fabric.edge_devices {
+            for subnet in fabric.subnets.iter().filter(|subnet| subnet.host != *src) {
+                if !session.query(src, &subnet.host).unwrap() {
+                    unreachable.push((src.clone(), subnet.host.clone()));
+                }
+            }
+        }
+        unreachable.sort();
+        assert_eq!(unreachable, golden(name).unreachable_pairs, "{}", name);
+    }
+}
+
+#[test]
+fn ground_truth_matches_golden_pairs() {
+    for name in FIXTURES {
+        let golden = golden(name);
+        let violations = Violations::evaluate(&Fabric::load(fixture_dir(name)).unwrap());
+        assert_eq!(
+            violations,
+            Violations {
+                reachability: golden.unreachable_pairs,
+                loop_freedom: golden.looping_pairs,
+                blackhole_freedom: golden.blackholed_pairs,
+            },
+            "{}",
+            name
+        );
+    }
+}
+
+#[test]
+fn reference_forwarder_agrees_with_npnet() {
+    for name in FIXTURES {
+        let session = encoded_session(name, VerificationEngine::NpNet, false);
+        let report = session.cross_check(4).unwrap();
+        assert!(report.is_consistent(), "{}:\n{}", name, report);
+        assert!(report.addresses_checked > 0, "{}", name);
+    }
+}
+
+#[test]
+fn injected_faults_match_manifest() {
+    let out_dir = std::env::temp_dir().join(format!("hetu-fixture-faults-{}", std::process::id()));
+    let _ = fs::remove_dir_all(&out_dir);
+    let mut fabric = Fabric::load(fixture_dir("fattree4")).unwrap();
+    let manifest = FaultManifest::inject(&mut fabric, &FaultKind::ALL, "fattree4", 1).unwrap();
+    assert_eq!(manifest.faults.len(), FaultKind::ALL.len());
+    assert!(manifest.baseline.is_empty());
+    fabric.write(&out_dir).unwrap();
+
+    let mut session = VerificationSession::builder()
+        .data_dir(&out_dir)
+        .build()
+        .unwrap();
+    let report = session.run().unwrap();
+    fs::remove_dir_all(&out_dir).unwrap();
+    assert_eq!(report.total_pairs(), manifest.total_pairs);
+    assert_eq!(report.unreachable, manifest.expected_unreachable_pairs);
+}
+
+#[test]
+fn every_subnet_of_a_tor_is_verified() {
+    let out_dir = std::env::temp_dir().join(format!("hetu-fixture-subnets-{}", std::process::id()));
+    let _ = fs::remove_dir_all(&out_dir);
+    let mut fabric = ClosSpec::parse("2,2,1,1")
+        .unwrap()
+        .with_subnets_per_tor(2)
+        .unwrap()
+        .build();
+    // p0_S0_1 loses the first of p0_S0_0's two subnets.
+    fabric
+        .routes_mut("p0_S0_1")
+        .unwrap()
+        .retain(|route| route.prefix != "10.0.0.0");
+    let expected = Violations::evaluate(&fabric);
+    assert_eq!(
+        expected.reachability,
+        vec![("p0_S0_1".to_string(), "p0_S0_0".to_string())]
+    );
+    fabric.write(&out_dir).unwrap();
+
+    let mut session = VerificationSession::builder()
+        .data_dir(&out_dir)
+        .build()
+        .unwrap();
+    let report = session.run().unwrap();
+    let cross_check = session.cross_check(0).unwrap();
+    fs::remove_dir_all(&out_dir).unwrap();
+    assert_eq!((report.reachable, report.unreachable), (11, 1));
+    assert!(cross_check.is_consistent(), "{}", cross_check);
+}
+
+#[test]
+fn the_first_of_duplicate_routes_wins() {
+    let out_dir =
+        std::env::temp_dir().join(format!("hetu-fixture-duplicates-{}", std::process::id()));
+    let _ = fs::remove_dir_all(&out_dir);
+    let mut fabric = ClosSpec::parse("2,2,1,1").unwrap().build();
+    let subnet = fabric
+        .subnets
+        .iter()
+        .find(|subnet| subnet.host == "p0_S0_0")
+        .unwrap()
+        .clone();
+    // p0_S0_1 lists a route to p0_S0_0 out of an unlinked port before the
+    // working one.
+    fabric
+        .routes_mut("p0_S0_1")
+        .unwrap()
+        .insert(0, subnet.route(vec!["unlinked".to_string()]));
+    let expected = Violations::evaluate(&fabric);
+    let pair = vec![("p0_S0_1".to_string(), "p0_S0_0".to_string())];
+    assert_eq!(expected.reachability, pair);
+    assert_eq!(expected.blackhole_freedom, pair);
+    fabric.write(&out_dir).unwrap();
+
+    let mut session = VerificationSession::builder()
+        .data_dir(&out_dir)
+        .build()
+        .unwrap();
+    let report = session.run().unwrap();
+    fs::remove_dir_all(&out_dir).unwrap();
+    assert_eq!(report.unreachable, 1);
+}
@@ -0,0 +1,127 @@
+//! Snapshots have to verify like the session they were saved from, and
+//! damaged files have to be rejected.
+
+mod common;
+
+use common::{leaf_name, route, subnet, Dataset};
+use hetu::util::snapshot::NetworkSnapshot;
+use hetu::util::var_order::VariableOrder;
+use hetu::{SessionError, VerificationSession};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// An encoded session of `dataset`, saved next to it.
+fn save_snapshot(dataset: &Dataset) -> (VerificationSession, PathBuf) {
+    let mut session = VerificationSession::builder()
+        .data_dir(dataset.dir())
+        .build()
+        .unwrap();
+    session.load().unwrap();
+    session.encode().unwrap();
+    let path = dataset.dir().join("network.htsn");
+    session.save_snapshot(&path).unwrap();
+    (session, path)
+}
+
+fn assert_rejected(path: &Path) {
+    match VerificationSession::from_snapshot(path) {
+        Err(SessionError::Snapshot(_)) => {}
+        Err(err) => panic!("unexpected error: {}", err),
+        Ok(_) => panic!("{} was accepted", path.display()),
+    }
+}
+
+#[test]
+fn a_snapshot_verifies_like_its_session() {
+    let dataset = Dataset::leaf_spine("snapshot-round-trip", 3);
+    // leaf0 drops traffic to leaf1, so both verdicts occur.
+    dataset.write_routes(
+        &leaf_name(0),
+        &[
+            route(&subnet(0), 24, &["host0"]),
+            route(&subnet(2), 24, &["up0"]),
+        ],
+    );
+    let (session, path) = save_snapshot(&dataset);
+    let restored = VerificationSession::from_snapshot(&path).unwrap();
+    let report = restored.verify().unwrap();
+    assert_eq!(report, session.verify().unwrap());
+    assert_eq!(
+        (report.reachable, report.unreachable),
+        (dataset.pairs() - 1, 1)
+    );
+    for src in 0..3 {
+        for dst in 0..3 {
+            let (src, dst) = (leaf_name(src), leaf_name(dst));
+            assert_eq!(
+                restored.query(&src, &dst),
+                session.query(&src, &dst),
+                "{} -> {}",
+                src,
+                dst
+            );
+        }
+    }
+}
+
+#[test]
+fn damaged_snapshots_are_rejected() {
+    let dataset = Dataset::leaf_spine("snapshot-damaged", 2);
+    let (_, path) = save_snapshot(&dataset);
+    let bytes = fs::read(&path).unwrap();
+    let damaged = dataset.dir().join("damaged.htsn");
+
+    let mut bad_magic = bytes.clone();
+    bad_magic[0] = b'X';
+    let mut bad_version = bytes.clone();
+    bad_version[4] = bad_version[4].wrapping_add(1);
+    let mut trailing = bytes.clone();
+    trailing.push(0);
+    let mut flipped = bytes.clone();
+    let middle = flipped.len() / 2;
+    flipped[middle] ^= 0x01;
+    for contents in [
+        bad_magic,
+        bad_version,
+        bytes[..bytes.len() - 1].to_vec(),
+        bytes[..bytes.len() / 2].to_vec(),
+        trailing,
+        flipped,
+        Vec::new(),
+    ] {
+        fs::write(&damaged, contents).unwrap();
+        assert_rejected(&damaged);
+    }
+    assert_rejected(&dataset.dir().join("missing.htsn"));
+}
+
+#[test]
+fn inconsistent_snapshots_are_rejected() {
+    let dataset = Dataset::leaf_spine("snapshot-inconsistent", 2);
+    let (_, path) = save_snapshot(&dataset);
+    let damaged = dataset.dir().join("inconsistent.htsn");
+    let rewrite = |edit: &dyn Fn(&mut NetworkSnapshot)| {
+        let mut snapshot = NetworkSnapshot::load(&path).unwrap();
+        edit(&mut snapshot);
+        snapshot.save(&damaged).unwrap();
+        assert_rejected(&damaged);
+    };
+
+    rewrite(&|snapshot| snapshot.ip_bits = 0);
+    rewrite(&|snapshot| {
+        snapshot.ip_bits = 16;
+        snapshot.variable_order = VariableOrder::MsbFirst;
+    });
+    rewrite(&|snapshot| {
+        let device = snapshot
+            .devices
+            .iter_mut()
+            .find(|device| !device.packet_space.is_empty())
+            .unwrap();
+        device.packet_space[0].1 = 33;
+    });
+    // Interval sets carry no variables to compare against.
+    if cfg!(not(feature = "interval_packets")) {
+        rewrite(&|snapshot| snapshot.ip_bits += 1);
+    }
+}